@@ -0,0 +1,16 @@
+#![no_std]
+
+#[inline(always)]
+fn square(x: u32) -> u32 {
+    x.wrapping_mul(x)
+}
+
+#[inline(always)]
+fn sum_of_squares(a: u32, b: u32) -> u32 {
+    square(a).wrapping_add(square(b))
+}
+
+#[no_mangle]
+pub extern "C" fn compute(a: u32, b: u32) -> u32 {
+    sum_of_squares(a, b) ^ 0x5a
+}
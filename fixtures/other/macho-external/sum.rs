@@ -0,0 +1,12 @@
+#![no_std]
+
+#[no_mangle]
+pub extern "C" fn sum_to(n: u32) -> u32 {
+    let mut sum = 0u32;
+    let mut i = 0;
+    while i < n {
+        sum = sum.wrapping_add(i);
+        i += 1;
+    }
+    sum
+}
@@ -0,0 +1,14 @@
+#include <stdio.h>
+
+int compute_sum(int n);
+
+static inline __attribute__((always_inline)) int square(int x) {
+  return x * x;
+}
+
+int main(int argc, char **argv) {
+  int total = square(argc);
+  total += compute_sum(argc * 10);
+  printf("%d\n", total);
+  return 0;
+}
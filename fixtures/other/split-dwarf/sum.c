@@ -0,0 +1,11 @@
+static inline __attribute__((always_inline)) int add_one(int x) {
+  return x + 1;
+}
+
+__attribute__((noinline)) int compute_sum(int n) {
+  int sum = 0;
+  for (int i = 0; i < n; i++) {
+    sum += add_one(i) * i;
+  }
+  return sum;
+}
@@ -14,4 +14,7 @@ pub enum Error {
 
     #[error("Malformed request JSON: {0}")]
     ParseRequestErrorContents(&'static str),
+
+    #[error("Symbolication was cancelled")]
+    Cancelled,
 }
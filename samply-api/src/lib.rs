@@ -138,18 +138,34 @@
 //!     fn location_for_breakpad_symindex(&self) -> Option<Self> {
 //!         Some(Self(self.0.with_extension("symindex")))
 //!     }
+//!
+//!     fn location_for_dwp(&self) -> Option<Self> {
+//!         let mut path = self.0.clone().into_os_string();
+//!         path.push(".dwp");
+//!         Some(Self(path.into()))
+//!     }
+//!
+//!     fn location_for_dwo(&self, dwo_path: &str) -> Option<Self> {
+//!         Some(Self(dwo_path.into()))
+//!     }
 //! }
 //! ```
 
+pub use error::Error;
 pub use samply_symbols;
 pub use samply_symbols::debugid;
 use samply_symbols::{FileAndPathHelper, SymbolManager};
+use std::sync::atomic::AtomicBool;
+pub use symbolicate::looked_up_addresses::{AddressResult, LibSymbolicationInfo};
+pub use symbolicate::{ExternalFileBudget, ProgressCallback};
 
 use asm::AsmApi;
 use debugid::DebugId;
 use serde_json::json;
 use source::SourceApi;
+use symbolicate::request_json::Lib;
 use symbolicate::SymbolicateApi;
+use text_stream::TextStreamApi;
 
 mod api_file_path;
 mod asm;
@@ -157,21 +173,98 @@ mod error;
 mod hex;
 mod source;
 mod symbolicate;
+mod text_stream;
 
+/// Parses a breakpad ID. Mach-O and ELF binaries have no age, so a bare UUID
+/// without the trailing age digit is also accepted, and treated as having age 0.
 pub(crate) fn to_debug_id(breakpad_id: &str) -> Result<DebugId, samply_symbols::Error> {
-    DebugId::from_breakpad(breakpad_id)
-        .map_err(|_| samply_symbols::Error::InvalidBreakpadId(breakpad_id.to_string()))
+    let debug_id = if breakpad_id.len() == 32 {
+        breakpad_id.parse::<DebugId>()
+    } else {
+        DebugId::from_breakpad(breakpad_id)
+    };
+    debug_id.map_err(|_| samply_symbols::Error::InvalidBreakpadId(breakpad_id.to_string()))
 }
 
 #[derive(Clone, Copy)]
 pub struct Api<'a, 'h: 'a, H: FileAndPathHelper<'h>> {
     symbol_manager: &'a SymbolManager<'h, H>,
+    cancellation_flag: Option<&'a AtomicBool>,
+    progress_callback: Option<&'a ProgressCallback>,
+    symbols_only: bool,
+    symbol_table_names: bool,
+    max_inline_depth: Option<usize>,
+    external_file_budget: ExternalFileBudget,
 }
 
 impl<'a, 'h: 'a, H: FileAndPathHelper<'h>> Api<'a, 'h, H> {
     /// Create a [`Api`] instance which uses the provided [`SymbolManager`].
     pub fn new(symbol_manager: &'a SymbolManager<'h, H>) -> Self {
-        Self { symbol_manager }
+        Self {
+            symbol_manager,
+            cancellation_flag: None,
+            progress_callback: None,
+            symbols_only: false,
+            symbol_table_names: false,
+            max_inline_depth: None,
+            external_file_budget: ExternalFileBudget::default(),
+        }
+    }
+
+    /// Lets `/symbolicate/v5` requests stop early once `cancellation_flag` is set to
+    /// `true`. A cancelled request returns an error response.
+    pub fn with_cancellation_flag(mut self, cancellation_flag: &'a AtomicBool) -> Self {
+        self.cancellation_flag = Some(cancellation_flag);
+        self
+    }
+
+    /// Calls `progress_callback` with `(objects_processed, objects_remaining)` while
+    /// a `/symbolicate/v5` request loads the external object files of a library, for
+    /// example the `.o` files and archive members referenced by a macOS binary.
+    /// The counts start over for every library.
+    pub fn with_progress_callback(mut self, progress_callback: &'a ProgressCallback) -> Self {
+        self.progress_callback = Some(progress_callback);
+        self
+    }
+
+    /// Makes `/symbolicate/v5` requests return only the function name of each
+    /// address, without file, line or inline information. This is much faster
+    /// because no line information is parsed and no external object files are loaded.
+    pub fn with_symbols_only(mut self) -> Self {
+        self.symbols_only = true;
+        self
+    }
+
+    /// Makes `/symbolicate/v5` requests return the function name from the symbol
+    /// table in an extra `symbol_table_function` field. The `function` field has the
+    /// name from the debug info, if there is debug info for the address. The two names
+    /// usually match; comparing them helps with diagnosing symbol tables that disagree
+    /// with the debug info. This is off by default.
+    pub fn with_symbol_table_names(mut self) -> Self {
+        self.symbol_table_names = true;
+        self
+    }
+
+    /// Makes `/symbolicate/v5` requests return at most `max_inline_depth` inlined
+    /// frames per address, keeping the innermost ones. The frame for the function
+    /// which contains the address is always returned. By default, all inlined frames
+    /// are returned.
+    pub fn with_max_inline_depth(mut self, max_inline_depth: usize) -> Self {
+        self.max_inline_depth = Some(max_inline_depth);
+        self
+    }
+
+    /// Limits the external object files which `/symbolicate/v5` requests load, for
+    /// example the `.o` files and archives referenced by a macOS binary, by number,
+    /// total size and time. This protects against runaway requests for binaries with
+    /// huge object maps. External files which are skipped because the budget was
+    /// exceeded are reported as `ExternalFileBudgetExceeded` errors in the
+    /// response's `external_file_errors`, or in
+    /// [`LibSymbolicationInfo::external_file_errors`], and the affected addresses
+    /// only get symbol information. By default, there are no limits.
+    pub fn with_external_file_budget(mut self, external_file_budget: ExternalFileBudget) -> Self {
+        self.external_file_budget = external_file_budget;
+        self
     }
 
     /// This is the main API of this crate.
@@ -184,15 +277,18 @@ impl<'a, 'h: 'a, H: FileAndPathHelper<'h>> Api<'a, 'h, H> {
     ///
     /// The following "URLs" are supported:
     ///  - `/symbolicate/v5`: This API is documented at <https://tecken.readthedocs.io/en/latest/symbolication.html>.
-    ///    The returned data has two extra fields: inlines (per address) and module_errors (per job).
+    ///    The returned data has three extra fields: inlines (per address), and module_errors and
+    ///    external_file_errors (per job). external_file_errors lists the external object files of
+    ///    found modules which could not be loaded.
     ///  - `/source/v1`: Experimental API. Symbolicates an address and lets you read one of the files in the
     ///    symbol information for that address.
     ///  - `/asm/v1`: Experimental API. Symbolicates an address and lets you read one of the files in the
     ///    symbol information for that address.
     pub async fn query_api(self, request_url: &str, request_json_data: &str) -> String {
         if request_url == "/symbolicate/v5" {
-            let symbolicate_api = SymbolicateApi::new(self.symbol_manager);
-            symbolicate_api.query_api_json(request_json_data).await
+            self.symbolicate_api()
+                .query_api_json(request_json_data)
+                .await
         } else if request_url == "/source/v1" {
             let source_api = SourceApi::new(self.symbol_manager);
             source_api.query_api_json(request_json_data).await
@@ -203,4 +299,93 @@ impl<'a, 'h: 'a, H: FileAndPathHelper<'h>> Api<'a, 'h, H> {
             json!({ "error": format!("Unrecognized URL {request_url}") }).to_string()
         }
     }
+
+    /// Symbolicates `addresses` in the library with the given debug name and breakpad
+    /// ID, and calls `callback` with the result for each address as soon as it is
+    /// known, instead of collecting all results before returning. This lets callers
+    /// which symbolicate a very large number of addresses, for example all the
+    /// addresses in a big profile, write out the results incrementally.
+    ///
+    /// `callback` is called once for every distinct address, with `None` if no symbol
+    /// was found. The calls are not in address order: addresses whose debug info is in
+    /// an external object file are reported after that file has been loaded.
+    ///
+    /// The options of this `Api`, such as [`Api::with_symbols_only`], apply as for
+    /// `/symbolicate/v5` requests. If the symbolication is cancelled, this returns
+    /// [`Error::Cancelled`], but `callback` may already have been called for some
+    /// addresses.
+    pub async fn symbolicate_addresses_with_callback(
+        self,
+        debug_name: &str,
+        breakpad_id: &str,
+        addresses: Vec<u32>,
+        callback: impl FnMut(u32, Option<AddressResult>),
+    ) -> Result<LibSymbolicationInfo, Error> {
+        let lib = Lib {
+            debug_name: debug_name.to_string(),
+            breakpad_id: breakpad_id.to_string(),
+        };
+        self.symbolicate_api()
+            .symbolicate_addresses_with_callback(&lib, addresses, callback)
+            .await
+    }
+
+    fn symbolicate_api(self) -> SymbolicateApi<'a, 'h, H> {
+        let mut symbolicate_api = SymbolicateApi::new(self.symbol_manager);
+        if let Some(cancellation_flag) = self.cancellation_flag {
+            symbolicate_api = symbolicate_api.with_cancellation_flag(cancellation_flag);
+        }
+        if let Some(progress_callback) = self.progress_callback {
+            symbolicate_api = symbolicate_api.with_progress_callback(progress_callback);
+        }
+        if self.symbols_only {
+            symbolicate_api = symbolicate_api.with_symbols_only();
+        }
+        if self.symbol_table_names {
+            symbolicate_api = symbolicate_api.with_symbol_table_names();
+        }
+        if let Some(max_inline_depth) = self.max_inline_depth {
+            symbolicate_api = symbolicate_api.with_max_inline_depth(max_inline_depth);
+        }
+        symbolicate_api.with_external_file_budget(self.external_file_budget)
+    }
+
+    /// Symbolicate a text stream of addresses, for use in command line pipelines.
+    ///
+    /// Each input line has the form `<debug_name> <breakpad_id> <address>`, for example
+    /// `firefox.pdb AA152DEB2D9B76084C4C44205044422E1 0x31fe8`. The address is a hex
+    /// number relative to the image base address, with an optional `0x` prefix.
+    ///
+    /// For each input line, a line of the form `0x<address> <function> <file>:<line>` is
+    /// written to `output`, followed by one indented line per inlined frame. Addresses
+    /// are grouped by library before lookup, so each library's symbols are only loaded once.
+    /// Lines which cannot be parsed are copied to `output` unchanged.
+    pub async fn symbolicate_text_stream<R: std::io::BufRead, W: std::io::Write>(
+        self,
+        input: R,
+        output: W,
+    ) -> std::io::Result<()> {
+        let text_stream_api = TextStreamApi::new(self.symbolicate_api());
+        text_stream_api.symbolicate_text_stream(input, output).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bare_uuid_breakpad_id() {
+        let with_age = to_debug_id("B993FABD8143361AB199F7DE9DF7E4360").unwrap();
+        assert_eq!(
+            to_debug_id("B993FABD8143361AB199F7DE9DF7E436").unwrap(),
+            with_age
+        );
+        assert_eq!(
+            to_debug_id("b993fabd8143361ab199f7de9df7e436").unwrap(),
+            with_age
+        );
+        assert!(to_debug_id("B993FABD8143361AB199F7DE9DF7E43").is_err());
+        assert!(to_debug_id("B993FABD-8143-361A-B199-F7DE9DF7E436").is_err());
+    }
 }
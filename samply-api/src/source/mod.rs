@@ -1,6 +1,7 @@
 use crate::{api_file_path::to_api_file_path, to_debug_id};
 use samply_symbols::{
-    FileAndPathHelper, FileAndPathHelperError, FramesLookupResult, LibraryInfo, SymbolManager,
+    DwoRef, FileAndPathHelper, FileAndPathHelperError, FramesLookupResult, LibraryInfo,
+    SymbolManager,
 };
 use serde_json::json;
 
@@ -61,19 +62,42 @@ impl<'a, 'h: 'a, H: FileAndPathHelper<'h>> SourceApi<'a, 'h, H> {
         let debug_id = to_debug_id(debug_id)?;
 
         // Look up the address to see which file paths we are allowed to read.
-        let (debug_file_location, frames) = {
-            let info = LibraryInfo {
-                debug_name: Some(debug_name.to_string()),
-                debug_id: Some(debug_id),
-                ..Default::default()
-            };
-            let symbol_map = self.symbol_manager.load_symbol_map(&info).await?;
-            let debug_file_location = symbol_map.debug_file_location().clone();
-            let frames = match symbol_map.lookup_relative_address(*module_offset) {
-                Some(address_info) => address_info.frames,
-                None => FramesLookupResult::Unavailable,
+        // If the address is in a split DWARF unit whose .dwo file hasn't been
+        // loaded yet, load the .dwo file and look up the address again in a fresh
+        // symbol map. The symbol map isn't kept across the await so that this
+        // future stays Send.
+        let info = LibraryInfo {
+            debug_name: Some(debug_name.to_string()),
+            debug_id: Some(debug_id),
+            ..Default::default()
+        };
+        let mut dwo_files: Vec<(DwoRef, Option<Vec<u8>>)> = Vec::new();
+        let (debug_file_location, frames) = loop {
+            let (debug_file_location, frames) = {
+                let symbol_map = self.symbol_manager.load_symbol_map(&info).await?;
+                for (dwo_ref, contents) in &dwo_files {
+                    symbol_map.add_dwo_file(dwo_ref, contents.clone());
+                }
+                let debug_file_location = symbol_map.debug_file_location().clone();
+                let frames = match symbol_map.lookup_relative_address(*module_offset) {
+                    Some(address_info) => address_info.frames,
+                    None => FramesLookupResult::Unavailable,
+                };
+                (debug_file_location, frames)
             };
-            (debug_file_location, frames)
+            match frames {
+                FramesLookupResult::NeedDwo(dwo_ref)
+                    if !dwo_files.iter().any(|(r, _)| *r == dwo_ref) =>
+                {
+                    let contents = self
+                        .symbol_manager
+                        .load_dwo_file(&debug_file_location, &dwo_ref)
+                        .await
+                        .ok();
+                    dwo_files.push((dwo_ref, contents));
+                }
+                frames => break (debug_file_location, frames),
+            }
         };
 
         let frames = match frames {
@@ -88,7 +112,9 @@ impl<'a, 'h: 'a, H: FileAndPathHelper<'h>> SourceApi<'a, 'h, H> {
                     None => return Err(SourceError::NoDebugInfo),
                 }
             }
-            FramesLookupResult::Unavailable => return Err(SourceError::NoDebugInfo),
+            FramesLookupResult::Unavailable | FramesLookupResult::NeedDwo(_) => {
+                return Err(SourceError::NoDebugInfo)
+            }
         };
 
         // Find the SourceFilePath whose "api file path" matches the requested file.
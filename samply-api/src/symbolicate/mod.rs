@@ -1,24 +1,282 @@
 use crate::to_debug_id;
 use crate::{api_file_path::to_api_file_path, error::Error};
-use samply_symbols::{FileAndPathHelper, FramesLookupResult, LibraryInfo, SymbolManager};
-use std::collections::HashMap;
+use futures_util::stream::{self, StreamExt};
+use samply_symbols::{
+    DwoRef, ExternalFileAddressInFileRef, ExternalFileIdentity, ExternalFileRef,
+    ExternalFileSymbolMap, FileAndPathHelper, FrameDebugInfo, FramesLookupResult, LibraryInfo,
+    SymbolManager,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 pub mod looked_up_addresses;
 pub mod request_json;
 pub mod response_json;
 
-use looked_up_addresses::{AddressResults, LookedUpAddresses};
+use looked_up_addresses::{AddressResult, AddressResults, LibSymbolicationInfo, LookedUpAddresses};
 use request_json::Lib;
 use serde_json::json;
 
+/// The maximum number of external object files which are loaded at the same time
+/// when resolving `FramesLookupResult::External` addresses.
+const MAX_CONCURRENT_EXTERNAL_FILE_LOADS: usize = 8;
+
+/// The debug info for each address which was looked up in one external file, and
+/// the error if the file couldn't be loaded.
+type ExternalFileResult = (
+    Vec<(u32, Option<Vec<FrameDebugInfo>>)>,
+    Option<(String, samply_symbols::Error)>,
+);
+
 pub struct SymbolicateApi<'a, 'h: 'a, H: FileAndPathHelper<'h>> {
     symbol_manager: &'a SymbolManager<'h, H>,
+    cancellation_flag: Option<&'a AtomicBool>,
+    progress_callback: Option<&'a ProgressCallback>,
+    symbols_only: bool,
+    raw_names: bool,
+    symbol_table_names: bool,
+    max_inline_depth: Option<usize>,
+    external_file_budget: ExternalFileBudget,
+}
+
+/// Limits for the work which is done on external object files during one
+/// symbolication request, see
+/// [`Api::with_external_file_budget`](crate::Api::with_external_file_budget).
+///
+/// The limits are checked before each external file is loaded, and the size and
+/// time limits are checked again once the file is loaded. A file which exceeds them
+/// at that point is not used, so no debug info is parsed from it. Loads which are
+/// in progress are not interrupted, so the time spent can exceed the timeout by the
+/// time it takes to load the files which are loaded concurrently.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExternalFileBudget {
+    /// The maximum number of external objects to load. Every archive member counts
+    /// as its own object.
+    pub max_objects: Option<usize>,
+    /// The maximum total size, in bytes, of the external files to load. Archives
+    /// count with their full size, even if only some members are used.
+    pub max_bytes: Option<u64>,
+    /// The maximum time to spend on the request before no more external files are
+    /// loaded, measured from the start of the request.
+    pub timeout: Option<Duration>,
+}
+
+/// How much of the [`ExternalFileBudget`] has been used up during one request.
+pub(crate) struct ExternalFileBudgetUsage {
+    start: Option<Instant>,
+    object_count: AtomicUsize,
+    byte_count: AtomicU64,
+}
+
+impl ExternalFileBudgetUsage {
+    fn new(budget: &ExternalFileBudget) -> Self {
+        Self {
+            // Only query the clock if needed; Instant::now() is not available on
+            // all targets, for example wasm32-unknown-unknown.
+            start: budget.timeout.map(|_| Instant::now()),
+            object_count: AtomicUsize::new(0),
+            byte_count: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns `Err` with the reason if loading an external file with
+    /// `object_count` objects would exceed the budget.
+    fn check(&self, budget: &ExternalFileBudget, object_count: usize) -> Result<(), String> {
+        if let Some(max_objects) = budget.max_objects {
+            let used = self.object_count.load(Ordering::Relaxed);
+            if used + object_count > max_objects {
+                return Err(format!(
+                    "{used} objects were already loaded, and the limit is {max_objects}"
+                ));
+            }
+        }
+        if let Some(max_bytes) = budget.max_bytes {
+            let used = self.byte_count.load(Ordering::Relaxed);
+            if used >= max_bytes {
+                return Err(format!(
+                    "{used} bytes were already loaded, and the limit is {max_bytes}"
+                ));
+            }
+        }
+        self.check_timeout(budget)
+    }
+
+    /// Adds a file of `file_size` bytes which was just loaded to the usage. Returns
+    /// `Err` with the reason if the file must not be used, because it brought the
+    /// total size over the limit or because the timeout was reached while it was
+    /// being loaded.
+    fn add_loaded_file(&self, budget: &ExternalFileBudget, file_size: u64) -> Result<(), String> {
+        let used = self.byte_count.fetch_add(file_size, Ordering::Relaxed) + file_size;
+        if let Some(max_bytes) = budget.max_bytes {
+            if used > max_bytes {
+                return Err(format!(
+                    "a file with {file_size} bytes brought the total to {used} bytes, and the limit is {max_bytes}"
+                ));
+            }
+        }
+        self.check_timeout(budget)
+    }
+
+    fn check_timeout(&self, budget: &ExternalFileBudget) -> Result<(), String> {
+        if let (Some(timeout), Some(start)) = (budget.timeout, self.start) {
+            if start.elapsed() >= timeout {
+                return Err(format!("the timeout of {timeout:?} was reached"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Called with `(objects_processed, objects_remaining)`, see
+/// [`Api::with_progress_callback`](crate::Api::with_progress_callback).
+pub type ProgressCallback = dyn Fn(usize, usize) + Send + Sync;
+
+/// The maximum number of archives which are kept loaded during one symbolication
+/// request, see [`LoadedArchives`].
+const MAX_LOADED_ARCHIVES: usize = 8;
+
+/// The archives (`.a` files) which have been loaded during one symbolication
+/// request. The same static library can be linked into multiple of the requested
+/// libraries, and opening and indexing a big archive is expensive, so archives are
+/// kept loaded for the rest of the request.
+///
+/// Entries are keyed by the debug file location of the library which referred to
+/// the archive and by the archive's `ExternalFileRef`, because the same path can
+/// resolve to different files for different debug file locations. At most
+/// [`MAX_LOADED_ARCHIVES`] archives are kept; inserting another one evicts the
+/// least recently used one.
+pub(crate) struct LoadedArchives<T = Arc<ExternalFileSymbolMap>> {
+    inner: Mutex<LoadedArchivesInner<T>>,
+}
+
+struct LoadedArchivesInner<T> {
+    /// The archive and the time of its last use, for each key.
+    entries: HashMap<(String, ExternalFileRef), (T, u64)>,
+    clock: u64,
+}
+
+impl<T> Default for LoadedArchives<T> {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(LoadedArchivesInner {
+                entries: HashMap::new(),
+                clock: 0,
+            }),
+        }
+    }
+}
+
+impl<T: Clone> LoadedArchives<T> {
+    fn get(&self, debug_file_location: &impl Display, file_ref: &ExternalFileRef) -> Option<T> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        let key = (debug_file_location.to_string(), file_ref.clone());
+        let (archive, last_use) = inner.entries.get_mut(&key)?;
+        *last_use = clock;
+        Some(archive.clone())
+    }
+
+    fn insert(&self, debug_file_location: &impl Display, file_ref: &ExternalFileRef, archive: T) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        let key = (debug_file_location.to_string(), file_ref.clone());
+        if inner.entries.len() >= MAX_LOADED_ARCHIVES && !inner.entries.contains_key(&key) {
+            let least_recently_used = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_use))| *last_use)
+                .map(|(key, _)| key.clone());
+            if let Some(least_recently_used) = least_recently_used {
+                inner.entries.remove(&least_recently_used);
+            }
+        }
+        inner.entries.insert(key, (archive, clock));
+    }
 }
 
 impl<'a, 'h: 'a, H: FileAndPathHelper<'h>> SymbolicateApi<'a, 'h, H> {
     /// Create a [`SymbolicateApi`] instance which uses the provided [`SymbolManager`].
     pub fn new(symbol_manager: &'a SymbolManager<'h, H>) -> Self {
-        Self { symbol_manager }
+        Self {
+            symbol_manager,
+            cancellation_flag: None,
+            progress_callback: None,
+            symbols_only: false,
+            raw_names: false,
+            symbol_table_names: false,
+            max_inline_depth: None,
+            external_file_budget: ExternalFileBudget::default(),
+        }
+    }
+
+    /// Makes the symbolication stop early once `cancellation_flag` is set to `true`.
+    /// The flag is checked between libraries and between external object files.
+    /// A cancelled request fails with [`Error::Cancelled`]; the results which were
+    /// gathered up to that point are discarded.
+    pub fn with_cancellation_flag(mut self, cancellation_flag: &'a AtomicBool) -> Self {
+        self.cancellation_flag = Some(cancellation_flag);
+        self
+    }
+
+    /// Calls `progress_callback` while the external object files of a library (see
+    /// [`FramesLookupResult::External`]) are processed, with the number of objects
+    /// processed so far and the number of objects remaining. Every archive member
+    /// counts as its own object. The counts start over for every library.
+    pub fn with_progress_callback(mut self, progress_callback: &'a ProgressCallback) -> Self {
+        self.progress_callback = Some(progress_callback);
+        self
+    }
+
+    /// Only looks up the function name of each address, without any file, line or
+    /// inline information. No DWARF or PDB line information is parsed and no external
+    /// object files are loaded, which makes this much faster for large requests.
+    pub fn with_symbols_only(mut self) -> Self {
+        self.symbols_only = true;
+        self
+    }
+
+    /// Adds a `symbol_table_function` field to each symbolicated frame, with the
+    /// name of the function at the address according to the symbol table. The
+    /// `function` field has the name of the outermost function according to the
+    /// debug info (DWARF or PDB) if there is debug info, which can be different,
+    /// for example if the symbol table has an alias.
+    pub fn with_symbol_table_names(mut self) -> Self {
+        self.symbol_table_names = true;
+        self
+    }
+
+    /// Keeps only the innermost `max_inline_depth` inlined frames of each address,
+    /// see `samply_symbols::SymbolMap::set_max_inline_depth`. This also applies to
+    /// the frames from external object files.
+    pub fn with_max_inline_depth(mut self, max_inline_depth: usize) -> Self {
+        self.max_inline_depth = Some(max_inline_depth);
+        self
+    }
+
+    /// Limits the number and total size of the external object files which are
+    /// loaded, and the time spent on loading them. External files which are not
+    /// loaded because of the budget are reported with
+    /// `samply_symbols::Error::ExternalFileBudgetExceeded`, and the addresses whose
+    /// debug info is in them only get symbol information.
+    pub fn with_external_file_budget(mut self, external_file_budget: ExternalFileBudget) -> Self {
+        self.external_file_budget = external_file_budget;
+        self
+    }
+
+    fn report_progress(&self, objects_processed: usize, objects_remaining: usize) {
+        if let Some(progress_callback) = self.progress_callback {
+            progress_callback(objects_processed, objects_remaining);
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation_flag
+            .map_or(false, |flag| flag.load(Ordering::Relaxed))
     }
 
     pub async fn query_api_json(&self, request_json: &str) -> String {
@@ -39,31 +297,114 @@ impl<'a, 'h: 'a, H: FileAndPathHelper<'h>> SymbolicateApi<'a, 'h, H> {
         request: &request_json::Request,
     ) -> Result<response_json::Response, Error> {
         let requested_addresses = gather_requested_addresses(request)?;
-        let symbolicated_addresses = self
+        // Raw names are only looked up if a job asks for them, see `Job::raw_names`.
+        let raw_names = request.jobs().any(|job| job.raw_names);
+        let symbolicate_api = SymbolicateApi { raw_names, ..*self };
+        let symbolicated_addresses = symbolicate_api
             .symbolicate_requested_addresses(requested_addresses)
-            .await;
-        Ok(create_response(request, symbolicated_addresses))
+            .await?;
+        Ok(create_response(
+            request,
+            symbolicated_addresses,
+            self.symbol_table_names,
+        ))
     }
 
-    async fn symbolicate_requested_addresses(
+    /// See [`Api::symbolicate_addresses_with_callback`](crate::Api::symbolicate_addresses_with_callback).
+    pub async fn symbolicate_addresses_with_callback(
+        &self,
+        lib: &Lib,
+        addresses: Vec<u32>,
+        callback: impl FnMut(u32, Option<AddressResult>),
+    ) -> Result<LibSymbolicationInfo, Error> {
+        let loaded_archives = LoadedArchives::default();
+        let budget_usage = ExternalFileBudgetUsage::new(&self.external_file_budget);
+        let info = self
+            .for_each_address_result_for_lib(
+                lib,
+                addresses,
+                &loaded_archives,
+                &budget_usage,
+                callback,
+            )
+            .await?;
+        if self.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        Ok(info)
+    }
+
+    pub(crate) async fn symbolicate_requested_addresses(
         &self,
         requested_addresses: HashMap<Lib, Vec<u32>>,
-    ) -> HashMap<Lib, Result<LookedUpAddresses, samply_symbols::Error>> {
+    ) -> Result<HashMap<Lib, Result<LookedUpAddresses, samply_symbols::Error>>, Error> {
         let mut symbolicated_addresses = HashMap::new();
+        let loaded_archives = LoadedArchives::default();
+        let budget_usage = ExternalFileBudgetUsage::new(&self.external_file_budget);
         for (lib, addresses) in requested_addresses.into_iter() {
+            if self.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
             let address_results = self
-                .symbolicate_requested_addresses_for_lib(&lib, addresses)
+                .symbolicate_requested_addresses_for_lib(
+                    &lib,
+                    addresses,
+                    &loaded_archives,
+                    &budget_usage,
+                )
                 .await;
+            // If the flag was set during the lookup, address_results may be missing
+            // the debug info from external files. Don't return it as if it was complete.
+            if self.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
             symbolicated_addresses.insert(lib, address_results);
         }
-        symbolicated_addresses
+        Ok(symbolicated_addresses)
     }
 
     async fn symbolicate_requested_addresses_for_lib(
         &self,
         lib: &Lib,
-        mut addresses: Vec<u32>,
+        addresses: Vec<u32>,
+        loaded_archives: &LoadedArchives,
+        budget_usage: &ExternalFileBudgetUsage,
     ) -> Result<LookedUpAddresses, samply_symbols::Error> {
+        let mut address_results = AddressResults::new();
+        let info = self
+            .for_each_address_result_for_lib(
+                lib,
+                addresses,
+                loaded_archives,
+                budget_usage,
+                |address, result| {
+                    address_results.insert(address, result);
+                },
+            )
+            .await?;
+        Ok(LookedUpAddresses {
+            address_results,
+            external_file_errors: info.external_file_errors,
+        })
+    }
+
+    /// Looks up `addresses` in the library `lib`, and calls `callback` with the
+    /// result for each address as soon as that result is complete, so that the
+    /// caller doesn't need to keep all results in memory.
+    ///
+    /// `callback` is called once for every distinct address, with `None` if no
+    /// symbol was found for it. The calls are not in address order: addresses whose
+    /// debug info is in an external object file are reported once that file has
+    /// been loaded, after the other addresses. If the lookup is cancelled, the
+    /// remaining addresses are reported without debug info from external files.
+    async fn for_each_address_result_for_lib(
+        &self,
+        lib: &Lib,
+        mut addresses: Vec<u32>,
+        loaded_archives: &LoadedArchives,
+        budget_usage: &ExternalFileBudgetUsage,
+        mut callback: impl FnMut(u32, Option<AddressResult>),
+    ) -> Result<LibSymbolicationInfo, samply_symbols::Error> {
         // Sort the addresses before the lookup, to have a higher chance of hitting
         // the same external file for subsequent addresses.
         addresses.sort_unstable();
@@ -71,64 +412,325 @@ impl<'a, 'h: 'a, H: FileAndPathHelper<'h>> SymbolicateApi<'a, 'h, H> {
 
         let debug_id = to_debug_id(&lib.breakpad_id)?;
 
-        let mut symbolication_result = LookedUpAddresses::for_addresses(&addresses);
-        let mut external_addresses = Vec::new();
+        // The symbol information for the addresses whose debug info is in an
+        // external file, until that file has been loaded.
+        let mut pending_results: HashMap<u32, AddressResult> = HashMap::new();
+        let mut external_addresses: BTreeMap<
+            ExternalFileRef,
+            Vec<(u32, ExternalFileAddressInFileRef)>,
+        > = BTreeMap::new();
+        // The addresses whose debug info is in a .dwo file which hasn't been loaded.
+        let mut dwo_addresses: BTreeMap<DwoRef, Vec<u32>> = BTreeMap::new();
         let debug_file_location;
+        let symbol_count;
 
         // Do the synchronous work first, and keep the symbol_map in a scope without
         // any other await calls so that the Rust compiler can see that the symbol
         // map does not exist across any await calls. This makes it so that the
         // future defined by this async function is Send even if the symbol map is
         // not Send.
+        let info = LibraryInfo {
+            debug_name: Some(lib.debug_name.to_string()),
+            debug_id: Some(debug_id),
+            ..Default::default()
+        };
         {
-            let info = LibraryInfo {
-                debug_name: Some(lib.debug_name.to_string()),
-                debug_id: Some(debug_id),
-                ..Default::default()
-            };
-            let symbol_map = self.symbol_manager.load_symbol_map(&info).await?;
+            let mut symbol_map = self.symbol_manager.load_symbol_map(&info).await?;
+            if let Some(err) = symbol_map.lookup_error() {
+                return Err(err);
+            }
+            symbol_map.set_max_inline_depth(self.max_inline_depth);
             debug_file_location = symbol_map.debug_file_location().clone();
-
-            symbolication_result.set_total_symbol_count(symbol_map.symbol_count() as u32);
+            symbol_count = symbol_map.symbol_count() as u32;
 
             for &address in &addresses {
-                if let Some(address_info) = symbol_map.lookup_relative_address(address) {
-                    symbolication_result.add_address_symbol(
-                        address,
-                        address_info.symbol.address,
-                        address_info.symbol.name,
-                        address_info.symbol.size,
-                    );
-                    match address_info.frames {
-                        FramesLookupResult::Available(frames) => {
-                            symbolication_result.add_address_debug_info(address, frames)
+                if self.symbols_only {
+                    let result = symbol_map.lookup_symbol(address).map(|symbol| {
+                        let mut result =
+                            AddressResult::new(symbol.address, symbol.name, symbol.size);
+                        if self.raw_names {
+                            result.raw_name = symbol_map.lookup_raw_symbol_name(address);
+                        }
+                        result
+                    });
+                    callback(address, result);
+                    continue;
+                }
+                let address_info = match symbol_map.lookup_relative_address(address) {
+                    Some(address_info) => address_info,
+                    None => {
+                        callback(address, None);
+                        continue;
+                    }
+                };
+                let mut result = AddressResult::new(
+                    address_info.symbol.address,
+                    address_info.symbol.name,
+                    address_info.symbol.size,
+                );
+                if self.raw_names {
+                    result.raw_name = symbol_map.lookup_raw_symbol_name(address);
+                }
+                match address_info.frames {
+                    FramesLookupResult::Available(frames) => {
+                        result.set_debug_info(frames);
+                        callback(address, Some(result));
+                    }
+                    FramesLookupResult::External(ext_address) => {
+                        external_addresses
+                            .entry(ext_address.file_ref)
+                            .or_default()
+                            .push((address, ext_address.address_in_file));
+                        pending_results.insert(address, result);
+                    }
+                    FramesLookupResult::NeedDwo(dwo_ref) => {
+                        dwo_addresses.entry(dwo_ref).or_default().push(address);
+                        pending_results.insert(address, result);
+                    }
+                    FramesLookupResult::Unavailable => callback(address, Some(result)),
+                }
+            }
+        }
+
+        // Look up any addresses whose debug info is in a .dwo file. The symbol map
+        // can't be kept across the awaits for loading the .dwo files, so it is
+        // loaded again afterwards, and the .dwo files are added to the new map.
+        if !dwo_addresses.is_empty() {
+            let mut dwo_files = Vec::with_capacity(dwo_addresses.len());
+            for dwo_ref in dwo_addresses.keys() {
+                let contents = if self.is_cancelled() {
+                    None
+                } else {
+                    self.symbol_manager
+                        .load_dwo_file(&debug_file_location, dwo_ref)
+                        .await
+                        .ok()
+                };
+                dwo_files.push(contents);
+            }
+            let mut symbol_map = self.symbol_manager.load_symbol_map(&info).await?;
+            symbol_map.set_max_inline_depth(self.max_inline_depth);
+            for ((dwo_ref, addresses), contents) in dwo_addresses.into_iter().zip(dwo_files) {
+                symbol_map.add_dwo_file(&dwo_ref, contents);
+                for address in addresses {
+                    let frames = symbol_map
+                        .lookup_relative_address(address)
+                        .map(|address_info| address_info.frames);
+                    match frames {
+                        Some(FramesLookupResult::External(ext_address)) => {
+                            external_addresses
+                                .entry(ext_address.file_ref)
+                                .or_default()
+                                .push((address, ext_address.address_in_file));
                         }
-                        FramesLookupResult::External(ext_address) => {
-                            external_addresses.push((address, ext_address));
+                        frames => {
+                            let mut result = pending_results.remove(&address);
+                            if let (Some(result), Some(FramesLookupResult::Available(frames))) =
+                                (&mut result, frames)
+                            {
+                                result.set_debug_info(frames);
+                            }
+                            callback(address, result);
                         }
-                        FramesLookupResult::Unavailable => {}
                     }
                 }
             }
         }
 
         // Look up any addresses whose debug info is in an external file.
-        // The symbol_manager caches the most recent external file.
-        // Since our addresses are sorted, they usually happen to be grouped by external
-        // file, so in practice we don't do much (if any) repeated reading of the same
-        // external file.
-
-        for (address, ext_address) in external_addresses {
-            if let Some(frames) = self
-                .symbol_manager
-                .lookup_external(&debug_file_location, &ext_address)
-                .await
-            {
-                symbolication_result.add_address_debug_info(address, frames);
+        // The addresses have been grouped by external file, so that each external
+        // file only needs to be loaded once. Multiple external files are loaded
+        // concurrently. `buffered` yields the results in the same order as the
+        // input, so the outcome does not depend on which file finishes loading first.
+        let debug_file_location = &debug_file_location;
+        let object_count_for_file = |addresses: &[(u32, ExternalFileAddressInFileRef)]| {
+            let mut members: Vec<_> = addresses
+                .iter()
+                .map(|(_, address_in_file)| &address_in_file.name_in_archive)
+                .collect();
+            members.sort_unstable();
+            members.dedup();
+            members.len()
+        };
+        let total_object_count: usize = external_addresses
+            .values()
+            .map(|addresses| object_count_for_file(addresses))
+            .sum();
+        let processed_object_count = AtomicUsize::new(0);
+        let processed_object_count = &processed_object_count;
+        if total_object_count != 0 {
+            self.report_progress(0, total_object_count);
+        }
+        let external_results = stream::iter(external_addresses)
+            .map(|(file_ref, addresses)| async move {
+                let addresses_without_frames =
+                    |addresses: Vec<(u32, ExternalFileAddressInFileRef)>| {
+                        addresses
+                            .into_iter()
+                            .map(|(address, _)| (address, None))
+                            .collect()
+                    };
+                if self.is_cancelled() {
+                    let result: ExternalFileResult = (addresses_without_frames(addresses), None);
+                    return result;
+                }
+                let object_count = object_count_for_file(&addresses);
+                let external_file = match (
+                    budget_usage.check(&self.external_file_budget, object_count),
+                    loaded_archives.get(debug_file_location, &file_ref),
+                ) {
+                    (Err(reason), _) => {
+                        Err(samply_symbols::Error::ExternalFileBudgetExceeded(reason))
+                    }
+                    (Ok(()), Some(archive)) => Ok(archive),
+                    (Ok(()), None) => self
+                        .symbol_manager
+                        .load_external_file(debug_file_location, &file_ref)
+                        .await
+                        .and_then(|external_file| {
+                            budget_usage
+                                .add_loaded_file(
+                                    &self.external_file_budget,
+                                    external_file.file_size(),
+                                )
+                                .map_err(samply_symbols::Error::ExternalFileBudgetExceeded)?;
+                            let external_file = Arc::new(external_file);
+                            if external_file.is_archive() {
+                                loaded_archives.insert(
+                                    debug_file_location,
+                                    &file_ref,
+                                    Arc::clone(&external_file),
+                                );
+                            }
+                            Ok(external_file)
+                        }),
+                };
+                if external_file.is_ok() {
+                    budget_usage
+                        .object_count
+                        .fetch_add(object_count, Ordering::Relaxed);
+                }
+                let result: ExternalFileResult = match external_file {
+                    Ok(external_file) => {
+                        self.lookup_in_external_file(
+                            debug_file_location,
+                            &file_ref,
+                            &external_file,
+                            addresses,
+                            budget_usage,
+                        )
+                        .await
+                    }
+                    Err(err) => (
+                        addresses_without_frames(addresses),
+                        Some((file_ref.file_name, err)),
+                    ),
+                };
+                let processed = processed_object_count.fetch_add(object_count, Ordering::Relaxed)
+                    + object_count;
+                self.report_progress(processed, total_object_count - processed);
+                result
+            })
+            .buffered(MAX_CONCURRENT_EXTERNAL_FILE_LOADS);
+        futures_util::pin_mut!(external_results);
+
+        let mut external_file_errors = Vec::new();
+        while let Some((frames_for_addresses, error)) = external_results.next().await {
+            for (address, frames) in frames_for_addresses {
+                if let Some(mut result) = pending_results.remove(&address) {
+                    if let Some(frames) = frames {
+                        result.set_debug_info(frames);
+                    }
+                    callback(address, Some(result));
+                }
+            }
+            if let Some((file_name, err)) = error {
+                external_file_errors.push((file_name, err));
             }
         }
 
-        Ok(symbolication_result)
+        Ok(LibSymbolicationInfo {
+            symbol_count,
+            external_file_errors,
+        })
+    }
+
+    /// Looks up `addresses` in `external_file`, which was loaded for `file_ref`.
+    ///
+    /// GNU thin archives only contain the paths of their members, so the members are
+    /// loaded from their own paths, once per member. The returned error is the error
+    /// of the first member which couldn't be loaded.
+    async fn lookup_in_external_file(
+        &self,
+        debug_file_location: &H::FL,
+        file_ref: &ExternalFileRef,
+        external_file: &ExternalFileSymbolMap,
+        addresses: Vec<(u32, ExternalFileAddressInFileRef)>,
+        budget_usage: &ExternalFileBudgetUsage,
+    ) -> ExternalFileResult {
+        let mut results = Vec::with_capacity(addresses.len());
+        let mut addresses_by_member_path: BTreeMap<String, Vec<_>> = BTreeMap::new();
+        for (address, address_in_file) in addresses {
+            let member_path = address_in_file
+                .name_in_archive
+                .as_deref()
+                .and_then(|name| external_file.thin_archive_member_path(name));
+            match member_path {
+                Some(member_path) => {
+                    let address_in_member = ExternalFileAddressInFileRef {
+                        name_in_archive: None,
+                        ..address_in_file
+                    };
+                    addresses_by_member_path
+                        .entry(member_path.to_owned())
+                        .or_default()
+                        .push((address, address_in_member));
+                }
+                None => results.push((address, external_file.lookup(&address_in_file))),
+            }
+        }
+
+        let mut first_error = None;
+        for (member_path, addresses) in addresses_by_member_path {
+            let member_file_ref = ExternalFileRef {
+                file_name: member_path,
+                arch: file_ref.arch.clone(),
+                identity: addresses
+                    .first()
+                    .map(|(_, address_in_member)| ExternalFileIdentity {
+                        name_in_archive: None,
+                        symbol_name: address_in_member.symbol_name.clone(),
+                    }),
+            };
+            // The members were already counted as objects of the archive.
+            let member_file = match budget_usage.check(&self.external_file_budget, 0) {
+                Ok(()) => self
+                    .symbol_manager
+                    .load_external_file(debug_file_location, &member_file_ref)
+                    .await
+                    .and_then(|member_file| {
+                        budget_usage
+                            .add_loaded_file(&self.external_file_budget, member_file.file_size())
+                            .map_err(samply_symbols::Error::ExternalFileBudgetExceeded)?;
+                        Ok(member_file)
+                    }),
+                Err(reason) => Err(samply_symbols::Error::ExternalFileBudgetExceeded(reason)),
+            };
+            let member_file = match member_file {
+                Ok(member_file) => Some(member_file),
+                Err(err) => {
+                    first_error.get_or_insert((member_file_ref.file_name, err));
+                    None
+                }
+            };
+            results.extend(addresses.into_iter().map(|(address, address_in_member)| {
+                let frames = member_file
+                    .as_ref()
+                    .and_then(|member_file| member_file.lookup(&address_in_member));
+                (address, frames)
+            }));
+        }
+        (results, first_error)
     }
 }
 
@@ -162,15 +764,18 @@ fn gather_requested_addresses(
 fn create_response(
     request: &request_json::Request,
     symbolicated_addresses: HashMap<Lib, Result<LookedUpAddresses, samply_symbols::Error>>,
+    symbol_table_names: bool,
 ) -> response_json::Response {
     use response_json::{DebugInfo, FrameDebugInfo, Response, Stack, StackFrame, Symbol};
 
     fn result_for_job(
         job: &request_json::Job,
         symbolicated_addresses: &HashMap<Lib, Result<LookedUpAddresses, samply_symbols::Error>>,
+        symbol_table_names: bool,
     ) -> response_json::Result {
         let mut found_modules = HashMap::new();
         let mut module_errors = HashMap::new();
+        let mut external_file_errors = HashMap::new();
         let mut symbols_by_module_index = HashMap::new();
         for (module_index, lib) in job.memory_map.iter().enumerate() {
             if let Some(symbol_result) = symbolicated_addresses.get(lib) {
@@ -179,6 +784,20 @@ fn create_response(
                     Ok(symbols) => {
                         symbols_by_module_index
                             .insert(module_index as u32, &symbols.address_results);
+                        if !symbols.external_file_errors.is_empty() {
+                            // The module was found, but some of its debug info is
+                            // missing. Say why, without reporting a module error.
+                            let errors: Vec<response_json::Error> = symbols
+                                .external_file_errors
+                                .iter()
+                                .map(|(file_name, err)| {
+                                    let mut err = response_json::Error::from(err);
+                                    err.filename = Some(file_name.clone());
+                                    err
+                                })
+                                .collect();
+                            external_file_errors.insert(module_key.clone(), errors);
+                        }
                     }
                     Err(err) => {
                         module_errors.insert(module_key.clone(), vec![err.into()]);
@@ -189,13 +808,20 @@ fn create_response(
         }
 
         let stacks = job.stacks.iter().map(|stack| {
-            response_stack_for_request_stack(stack, &job.memory_map, &symbols_by_module_index)
+            response_stack_for_request_stack(
+                stack,
+                &job.memory_map,
+                &symbols_by_module_index,
+                symbol_table_names,
+                job.raw_names,
+            )
         });
 
         response_json::Result {
             stacks: stacks.collect(),
             found_modules,
             module_errors,
+            external_file_errors,
         }
     }
 
@@ -203,6 +829,8 @@ fn create_response(
         stack: &request_json::Stack,
         memory_map: &[Lib],
         symbols_by_module_index: &HashMap<u32, &AddressResults>,
+        symbol_table_names: bool,
+        raw_names: bool,
     ) -> Stack {
         let frames = stack.0.iter().enumerate().map(|(frame_index, frame)| {
             response_frame_for_request_frame(
@@ -210,6 +838,8 @@ fn create_response(
                 frame_index as u32,
                 memory_map,
                 symbols_by_module_index,
+                symbol_table_names,
+                raw_names,
             )
         });
         Stack(frames.collect())
@@ -220,6 +850,8 @@ fn create_response(
         frame_index: u32,
         memory_map: &[Lib],
         symbols_by_module_index: &HashMap<u32, &AddressResults>,
+        symbol_table_names: bool,
+        raw_names: bool,
     ) -> StackFrame {
         let symbol = symbols_by_module_index
             .get(&frame.module_index)
@@ -232,6 +864,16 @@ fn create_response(
                     .as_ref()
                     .map(|address_result| Symbol {
                         function: address_result.symbol_name.clone(),
+                        symbol_table_function: if symbol_table_names {
+                            Some(address_result.symbol_table_name().to_string())
+                        } else {
+                            None
+                        },
+                        raw_name: if raw_names {
+                            address_result.raw_name.clone()
+                        } else {
+                            None
+                        },
                         function_offset: frame.address - address_result.symbol_address,
                         function_size: address_result.function_size,
                         debug_info: address_result.inline_frames.as_ref().map(|frames| {
@@ -264,7 +906,70 @@ fn create_response(
     Response {
         results: request
             .jobs()
-            .map(|job| result_for_job(job, &symbolicated_addresses))
+            .map(|job| result_for_job(job, &symbolicated_addresses, symbol_table_names))
             .collect(),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn external_file_budget() {
+        let unlimited = ExternalFileBudget::default();
+        let usage = ExternalFileBudgetUsage::new(&unlimited);
+        usage.object_count.store(1_000_000, Ordering::Relaxed);
+        usage.byte_count.store(u64::MAX, Ordering::Relaxed);
+        assert!(usage.check(&unlimited, 1).is_ok());
+
+        let budget = ExternalFileBudget {
+            max_objects: Some(10),
+            max_bytes: Some(1000),
+            timeout: None,
+        };
+        let usage = ExternalFileBudgetUsage::new(&budget);
+        assert!(usage.check(&budget, 10).is_ok());
+        assert!(usage.check(&budget, 11).is_err());
+        usage.object_count.store(8, Ordering::Relaxed);
+        assert!(usage.check(&budget, 2).is_ok());
+        assert!(usage.check(&budget, 3).is_err());
+        usage.byte_count.store(1000, Ordering::Relaxed);
+        assert!(usage.check(&budget, 1).is_err());
+
+        let usage = ExternalFileBudgetUsage::new(&budget);
+        assert!(usage.add_loaded_file(&budget, 600).is_ok());
+        assert!(usage.add_loaded_file(&budget, 400).is_ok());
+        assert!(usage.add_loaded_file(&budget, 1).is_err());
+
+        let budget = ExternalFileBudget {
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let usage = ExternalFileBudgetUsage::new(&budget);
+        assert!(usage.check(&budget, 1).is_err());
+        assert!(usage.add_loaded_file(&budget, 1).is_err());
+    }
+
+    #[test]
+    fn loaded_archives_are_bounded() {
+        let file_ref = |i: usize| ExternalFileRef {
+            file_name: format!("/path/to/lib{i}.a"),
+            arch: None,
+            identity: None,
+        };
+        let loaded_archives = LoadedArchives::<usize>::default();
+        for i in 0..MAX_LOADED_ARCHIVES {
+            loaded_archives.insert(&"a.so", &file_ref(i), i);
+        }
+        // Use the first archive, so that the second one is the least recently used.
+        assert_eq!(loaded_archives.get(&"a.so", &file_ref(0)), Some(0));
+        loaded_archives.insert(&"a.so", &file_ref(MAX_LOADED_ARCHIVES), MAX_LOADED_ARCHIVES);
+        assert_eq!(loaded_archives.get(&"a.so", &file_ref(0)), Some(0));
+        assert_eq!(loaded_archives.get(&"a.so", &file_ref(1)), None);
+        assert_eq!(loaded_archives.get(&"a.so", &file_ref(2)), Some(2));
+
+        // The same path can refer to a different file for a different library.
+        assert_eq!(loaded_archives.get(&"b.so", &file_ref(0)), None);
+    }
+}
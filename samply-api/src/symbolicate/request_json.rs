@@ -21,6 +21,10 @@ impl Request {
 pub struct Job {
     pub memory_map: Vec<Lib>,
     pub stacks: Vec<Stack>,
+    /// Whether to return the undemangled symbol name of each frame in a `raw_name`
+    /// field, next to `function`. Off by default.
+    #[serde(default)]
+    pub raw_names: bool,
 }
 
 #[derive(Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
@@ -14,6 +14,12 @@ pub struct Result {
 
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     pub module_errors: HashMap<String, Vec<Error>>,
+
+    /// Errors from the external object files of found modules which could not be
+    /// loaded, with the name of the file in `filename`. The addresses whose debug
+    /// info is in these files have no file and line information.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub external_file_errors: HashMap<String, Vec<Error>>,
 }
 
 #[derive(Serialize, Debug)]
@@ -37,6 +43,12 @@ pub struct StackFrame {
 pub struct Symbol {
     pub function: String,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol_table_function: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_name: Option<String>,
+
     #[serde(serialize_with = "crate::hex::as_hex_string")]
     pub function_offset: u32,
 
@@ -115,6 +127,8 @@ mod test {
                         module: String::from("xul.pdb"),
                         symbol: Some(response_json::Symbol {
                             function: String::from("sctp_send_initiate"),
+                            symbol_table_function: None,
+                            raw_name: None,
                             function_offset: 0x4ca,
                             function_size: None,
                             debug_info: None,
@@ -135,6 +149,7 @@ mod test {
                 .cloned()
                 .collect(),
                 module_errors: HashMap::new(),
+                external_file_errors: HashMap::new(),
             }],
         };
         let response = serde_json::to_string_pretty(&response)?;
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use samply_symbols::FileAndPathHelper;
+
+use crate::api_file_path::to_api_file_path;
+use crate::symbolicate::looked_up_addresses::AddressResult;
+use crate::symbolicate::request_json::Lib;
+use crate::symbolicate::SymbolicateApi;
+
+pub struct TextStreamApi<'a, 'h: 'a, H: FileAndPathHelper<'h>> {
+    symbolicate_api: SymbolicateApi<'a, 'h, H>,
+}
+
+impl<'a, 'h: 'a, H: FileAndPathHelper<'h>> TextStreamApi<'a, 'h, H> {
+    /// Create a [`TextStreamApi`] instance which looks up the addresses with the
+    /// provided [`SymbolicateApi`], and with its options.
+    pub fn new(symbolicate_api: SymbolicateApi<'a, 'h, H>) -> Self {
+        Self { symbolicate_api }
+    }
+
+    /// Reads lines of the form `<debug_name> <breakpad_id> <address>` from `input`,
+    /// and writes one symbolicated line per input line to `output`, in input order.
+    ///
+    /// The address is a hex number with an optional `0x` prefix, relative to the
+    /// image base address of the module. The output lines have the form
+    /// `0x<address> <function> <file>:<line>`. If the address is inside inlined
+    /// code, each inlined frame is written on its own indented line after it.
+    ///
+    /// Lines which cannot be parsed are copied to the output unchanged.
+    pub async fn symbolicate_text_stream<R: BufRead, W: Write>(
+        &self,
+        input: R,
+        mut output: W,
+    ) -> std::io::Result<()> {
+        let mut lines = Vec::new();
+        let mut requested_addresses: HashMap<Lib, Vec<u32>> = HashMap::new();
+        for line in input.lines() {
+            let line = line?;
+            let parsed_line = parse_line(&line);
+            if let Some((lib, address)) = &parsed_line {
+                requested_addresses
+                    .entry(lib.clone())
+                    .or_default()
+                    .push(*address);
+            }
+            lines.push((line, parsed_line));
+        }
+
+        let symbolicated_addresses = self
+            .symbolicate_api
+            .symbolicate_requested_addresses(requested_addresses)
+            .await
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err.to_string()))?;
+
+        for (line, parsed_line) in lines {
+            let (lib, address) = match parsed_line {
+                Some(parsed_line) => parsed_line,
+                None => {
+                    writeln!(output, "{line}")?;
+                    continue;
+                }
+            };
+            let address_result = match symbolicated_addresses.get(&lib) {
+                Some(Ok(looked_up_addresses)) => looked_up_addresses
+                    .address_results
+                    .get(&address)
+                    .and_then(Option::as_ref),
+                _ => None,
+            };
+            match address_result {
+                Some(address_result) => write_address_result(&mut output, address, address_result)?,
+                None => writeln!(output, "0x{address:x} ??")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_line(line: &str) -> Option<(Lib, u32)> {
+    let mut parts = line.split_whitespace();
+    let debug_name = parts.next()?;
+    let breakpad_id = parts.next()?;
+    let address = parts.next()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    let address = address
+        .strip_prefix("0x")
+        .or_else(|| address.strip_prefix("0X"))
+        .unwrap_or(address);
+    let address = u32::from_str_radix(address, 16).ok()?;
+    let lib = Lib {
+        debug_name: debug_name.to_string(),
+        breakpad_id: breakpad_id.to_string(),
+    };
+    Some((lib, address))
+}
+
+fn write_address_result(
+    output: &mut impl Write,
+    address: u32,
+    address_result: &AddressResult,
+) -> std::io::Result<()> {
+    write!(output, "0x{address:x} {}", address_result.symbol_name)?;
+    let frames = match address_result.inline_frames.as_deref() {
+        Some(frames) => frames,
+        None => return writeln!(output),
+    };
+    let (outer, inlines) = match frames.split_last() {
+        Some(split) => split,
+        None => return writeln!(output),
+    };
+    write_file_and_line(output, outer.file_path.as_ref(), outer.line_number)?;
+    writeln!(output)?;
+    for inline_frame in inlines {
+        let function = inline_frame.function.as_deref().unwrap_or("??");
+        write!(output, "    inlined {function}")?;
+        write_file_and_line(
+            output,
+            inline_frame.file_path.as_ref(),
+            inline_frame.line_number,
+        )?;
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
+fn write_file_and_line(
+    output: &mut impl Write,
+    file_path: Option<&samply_symbols::SourceFilePath>,
+    line_number: Option<u32>,
+) -> std::io::Result<()> {
+    match (file_path, line_number) {
+        (Some(file_path), Some(line)) => write!(output, " {}:{line}", to_api_file_path(file_path)),
+        (Some(file_path), None) => write!(output, " {}", to_api_file_path(file_path)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_text_stream_line() {
+        let (lib, address) =
+            parse_line("firefox.pdb AA152DEB2D9B76084C4C44205044422E1 0x31fe8").unwrap();
+        assert_eq!(lib.debug_name, "firefox.pdb");
+        assert_eq!(lib.breakpad_id, "AA152DEB2D9B76084C4C44205044422E1");
+        assert_eq!(address, 0x31fe8);
+        assert_eq!(
+            parse_line("  xul.pdb 44E4EC8C2F41492B9369D6B9A059577C2   1a2b ").map(|(_, a)| a),
+            Some(0x1a2b)
+        );
+        assert!(parse_line("firefox.pdb AA152DEB2D9B76084C4C44205044422E1").is_none());
+        assert!(parse_line("firefox.pdb AA152DEB2D9B76084C4C44205044422E1 0xzz").is_none());
+        assert!(parse_line("a b 0x10 extra").is_none());
+        assert!(parse_line("").is_none());
+    }
+}
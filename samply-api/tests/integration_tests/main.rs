@@ -1,7 +1,7 @@
 use assert_json_diff::assert_json_eq;
 pub use samply_api::debugid::DebugId;
 use samply_api::samply_symbols;
-use samply_api::Api;
+use samply_api::{Api, ExternalFileBudget};
 use samply_symbols::{
     CandidatePathInfo, FileAndPathHelper, FileAndPathHelperResult, FileLocation, LibraryInfo,
     OptionallySendFuture, SymbolManager,
@@ -11,6 +11,7 @@ use std::fs::File;
 use std::io::{Read, Write};
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::time::Duration;
 
 pub async fn query_api(request_url: &str, request_json: &str, symbol_directory: PathBuf) -> String {
     let helper = Helper { symbol_directory };
@@ -205,6 +206,16 @@ impl FileLocation for FileLocationType {
     fn location_for_breakpad_symindex(&self) -> Option<Self> {
         Some(Self(self.0.with_extension("symindex")))
     }
+
+    fn location_for_dwp(&self) -> Option<Self> {
+        let mut path = self.0.clone().into_os_string();
+        path.push(".dwp");
+        Some(Self(path.into()))
+    }
+
+    fn location_for_dwo(&self, dwo_path: &str) -> Option<Self> {
+        Some(Self(dwo_path.into()))
+    }
 }
 
 fn fixtures_dir() -> PathBuf {
@@ -437,3 +448,392 @@ fn asm_x86_64() {
         "output-asm_x86_64.txt",
     )
 }
+
+#[test]
+fn win64_ci_text_stream() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let api = Api::new(&symbol_manager);
+    let input = "firefox.pdb AA152DEB2D9B76084C4C44205044422E1 0x31fe8\nnot an address line\n";
+    let mut output = Vec::new();
+    futures::executor::block_on(api.symbolicate_text_stream(input.as_bytes(), &mut output))
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("0x31fe8 sandbox::ProcessMitigationsWin32KDispatcher::EnumDisplayMonitors(sandbox::IPCInfo*, sandbox::CountedBuffer*) hg:hg.mozilla.org/mozilla-central:security/sandbox/chromium/sandbox/win/src/process_mitigations_win32k_dispatcher.cc:"));
+    assert!(lines[0].ends_with(":274"));
+    assert_eq!(lines[1], "not an address line");
+}
+
+#[test]
+fn cancelled_text_stream() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let cancellation_flag = std::sync::atomic::AtomicBool::new(true);
+    let api = Api::new(&symbol_manager).with_cancellation_flag(&cancellation_flag);
+    let input = "firefox.pdb AA152DEB2D9B76084C4C44205044422E1 0x31fe8\n";
+    let mut output = Vec::new();
+    let result =
+        futures::executor::block_on(api.symbolicate_text_stream(input.as_bytes(), &mut output));
+    assert!(result.is_err());
+    assert!(output.is_empty());
+}
+
+#[test]
+fn cancelled_symbolication() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let cancellation_flag = std::sync::atomic::AtomicBool::new(true);
+    let api = Api::new(&symbol_manager).with_cancellation_flag(&cancellation_flag);
+    let request_json = r#"{
+        "memoryMap": [["firefox.pdb", "AA152DEB2D9B76084C4C44205044422E1"]],
+        "stacks": [[[0, 204776]]]
+    }"#;
+    let response = futures::executor::block_on(api.query_api("/symbolicate/v5", request_json));
+    let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+    assert_eq!(response["error"], "Symbolication was cancelled");
+}
+
+#[test]
+fn symbols_only_symbolication() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let request_json = r#"{
+        "memoryMap": [["firefox.pdb", "AA152DEB2D9B76084C4C44205044422E1"]],
+        "stacks": [[[0, 204776]]]
+    }"#;
+    let full_response = futures::executor::block_on(
+        Api::new(&symbol_manager).query_api("/symbolicate/v5", request_json),
+    );
+    let symbols_only_response = futures::executor::block_on(
+        Api::new(&symbol_manager)
+            .with_symbols_only()
+            .query_api("/symbolicate/v5", request_json),
+    );
+    let full_response: serde_json::Value = serde_json::from_str(&full_response).unwrap();
+    let symbols_only_response: serde_json::Value =
+        serde_json::from_str(&symbols_only_response).unwrap();
+    let full_frame = &full_response["results"][0]["stacks"][0][0];
+    let symbols_only_frame = &symbols_only_response["results"][0]["stacks"][0][0];
+    assert_eq!(symbols_only_frame["function"], full_frame["function"]);
+    assert_eq!(
+        symbols_only_frame["function_offset"],
+        full_frame["function_offset"]
+    );
+    assert_eq!(
+        symbols_only_frame["function_size"],
+        full_frame["function_size"]
+    );
+    assert!(full_frame.get("file").is_some());
+    assert!(symbols_only_frame.get("file").is_none());
+    assert!(symbols_only_frame.get("inlines").is_none());
+}
+
+#[test]
+fn symbol_table_names_symbolication() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("macos-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    // 0x1544 is in the static initializer of SSE.cpp. The symbol table calls it
+    // _GLOBAL__sub_I_SSE.cpp, and the debug info calls it GLOBAL__sub_I_SSE.cpp.
+    let request_json = r#"{
+        "memoryMap": [["libmozglue.dylib", "64EC2645330C3A0BA6E4EBCD28A1B5940"]],
+        "stacks": [[[0, 5444]]]
+    }"#;
+    let default_response = futures::executor::block_on(
+        Api::new(&symbol_manager).query_api("/symbolicate/v5", request_json),
+    );
+    let symbol_table_names_response = futures::executor::block_on(
+        Api::new(&symbol_manager)
+            .with_symbol_table_names()
+            .query_api("/symbolicate/v5", request_json),
+    );
+    let default_response: serde_json::Value = serde_json::from_str(&default_response).unwrap();
+    let symbol_table_names_response: serde_json::Value =
+        serde_json::from_str(&symbol_table_names_response).unwrap();
+    let default_frame = &default_response["results"][0]["stacks"][0][0];
+    let frame = &symbol_table_names_response["results"][0]["stacks"][0][0];
+    assert!(default_frame.get("symbol_table_function").is_none());
+    assert_eq!(default_frame["function"], "GLOBAL__sub_I_SSE.cpp");
+    assert_eq!(frame["function"], "GLOBAL__sub_I_SSE.cpp");
+    assert_eq!(frame["symbol_table_function"], "_GLOBAL__sub_I_SSE.cpp");
+}
+
+#[test]
+fn symbolicate_addresses_with_callback() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let api = Api::new(&symbol_manager);
+    let mut results = Vec::new();
+    let info = futures::executor::block_on(api.symbolicate_addresses_with_callback(
+        "firefox.pdb",
+        "AA152DEB2D9B76084C4C44205044422E1",
+        vec![0x31fe8, 0x31fe8, 0x1],
+        |address, result| results.push((address, result)),
+    ))
+    .unwrap();
+    assert!(info.symbol_count > 0);
+    assert!(info.external_file_errors.is_empty());
+
+    // Every distinct address is reported exactly once.
+    results.sort_by_key(|(address, _)| *address);
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].0, 0x1);
+    assert!(results[0].1.is_none());
+    assert_eq!(results[1].0, 0x31fe8);
+    let result = results[1].1.as_ref().unwrap();
+    assert_eq!(result.symbol_name, "sandbox::ProcessMitigationsWin32KDispatcher::EnumDisplayMonitors(sandbox::IPCInfo*, sandbox::CountedBuffer*)");
+    let frames = result.inline_frames.as_ref().unwrap();
+    assert_eq!(frames.last().unwrap().line_number, Some(274));
+}
+
+#[test]
+fn max_inline_depth_symbolication() {
+    // The debug info of libexternal.dylib is in the object files next to it.
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other").join("macho-external"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let request_json = r#"{
+        "memoryMap": [["libexternal.dylib", "4C4C446C55553144A1A9D5D659F57D350"]],
+        "stacks": [[[0, 580]]]
+    }"#;
+    let default_response = futures::executor::block_on(
+        Api::new(&symbol_manager).query_api("/symbolicate/v5", request_json),
+    );
+    let limited_response = futures::executor::block_on(
+        Api::new(&symbol_manager)
+            .with_max_inline_depth(1)
+            .query_api("/symbolicate/v5", request_json),
+    );
+    let default_response: serde_json::Value = serde_json::from_str(&default_response).unwrap();
+    let limited_response: serde_json::Value = serde_json::from_str(&limited_response).unwrap();
+    let inline_functions = |response: &serde_json::Value| -> Vec<String> {
+        let frame = &response["results"][0]["stacks"][0][0];
+        assert_eq!(frame["function"], "compute");
+        assert_eq!(frame["line"], 15);
+        frame["inlines"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|inline| inline["function"].as_str().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(
+        inline_functions(&default_response),
+        vec![
+            "<u32>::wrapping_mul",
+            "square::square",
+            "square::sum_of_squares"
+        ]
+    );
+    assert_eq!(
+        inline_functions(&limited_response),
+        vec!["<u32>::wrapping_mul"]
+    );
+}
+
+#[test]
+fn missing_external_file_symbolication() {
+    // Only square.o is next to the binary, sum.o is missing.
+    let symbol_directory = std::env::temp_dir().join("samply-api-missing-external-file");
+    std::fs::create_dir_all(&symbol_directory).unwrap();
+    for file_name in ["libexternal.dylib", "square.o"] {
+        std::fs::copy(
+            fixtures_dir()
+                .join("other")
+                .join("macho-external")
+                .join(file_name),
+            symbol_directory.join(file_name),
+        )
+        .unwrap();
+    }
+    let request_json = r#"{
+        "memoryMap": [["libexternal.dylib", "4C4C446C55553144A1A9D5D659F57D350"]],
+        "stacks": [[[0, 580], [0, 608]]]
+    }"#;
+    let response = futures::executor::block_on(query_api(
+        "/symbolicate/v5",
+        request_json,
+        symbol_directory.clone(),
+    ));
+    std::fs::remove_dir_all(&symbol_directory).unwrap();
+    let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+
+    // The module was found, both addresses have a symbol, and only the address in
+    // square.o has debug info.
+    let result = &response["results"][0];
+    let module_key = "libexternal.dylib/4C4C446C55553144A1A9D5D659F57D350";
+    assert_eq!(result["found_modules"][module_key], true);
+    assert!(result.get("module_errors").is_none());
+    let frames = &result["stacks"][0];
+    assert_eq!(frames[0]["function"], "compute");
+    assert_eq!(frames[0]["line"], 15);
+    assert_eq!(frames[1]["function"], "sum_to");
+    assert!(frames[1].get("line").is_none());
+
+    let errors = result["external_file_errors"][module_key]
+        .as_array()
+        .unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0]["filename"], "sum.o");
+    assert_eq!(errors[0]["name"], "HelperErrorDuringOpenFile");
+}
+
+#[test]
+fn external_file_budget_skips_files() {
+    // The debug info for compute (0x244) is in square.o, and the debug info for
+    // sum_to (0x260) is in sum.o.
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other").join("macho-external"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let symbolicate = |budget: ExternalFileBudget| {
+        let mut results = Vec::new();
+        let info = futures::executor::block_on(
+            Api::new(&symbol_manager)
+                .with_external_file_budget(budget)
+                .symbolicate_addresses_with_callback(
+                    "libexternal.dylib",
+                    "4C4C446C55553144A1A9D5D659F57D350",
+                    vec![0x244, 0x260],
+                    |address, result| results.push((address, result.unwrap())),
+                ),
+        )
+        .unwrap();
+        let with_debug_info = results
+            .iter()
+            .filter(|(_, result)| result.inline_frames.is_some())
+            .count();
+        let skipped: Vec<String> = info
+            .external_file_errors
+            .iter()
+            .map(|(file_name, err)| {
+                assert!(
+                    matches!(err, samply_symbols::Error::ExternalFileBudgetExceeded(_)),
+                    "{err:?}"
+                );
+                file_name.clone()
+            })
+            .collect();
+        (results.len(), with_debug_info, skipped)
+    };
+
+    let (result_count, with_debug_info, skipped) = symbolicate(ExternalFileBudget::default());
+    assert_eq!((result_count, with_debug_info), (2, 2));
+    assert!(skipped.is_empty());
+
+    // Only one of the two object files is loaded.
+    let (result_count, with_debug_info, skipped) = symbolicate(ExternalFileBudget {
+        max_objects: Some(1),
+        ..Default::default()
+    });
+    assert_eq!((result_count, with_debug_info), (2, 1));
+    assert_eq!(skipped.len(), 1);
+
+    // Each object file is bigger than the limit, so neither of them is used, even
+    // though the limit isn't reached before the first one is loaded.
+    let (result_count, with_debug_info, mut skipped) = symbolicate(ExternalFileBudget {
+        max_bytes: Some(1000),
+        ..Default::default()
+    });
+    skipped.sort();
+    assert_eq!((result_count, with_debug_info), (2, 0));
+    assert_eq!(skipped, vec!["square.o", "sum.o"]);
+
+    let (result_count, with_debug_info, skipped) = symbolicate(ExternalFileBudget {
+        timeout: Some(Duration::ZERO),
+        ..Default::default()
+    });
+    assert_eq!((result_count, with_debug_info), (2, 0));
+    assert_eq!(skipped.len(), 2);
+}
+
+#[test]
+fn external_file_budget_json() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other").join("macho-external"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let request_json = r#"{
+        "memoryMap": [["libexternal.dylib", "4C4C446C55553144A1A9D5D659F57D350"]],
+        "stacks": [[[0, 580], [0, 608]]]
+    }"#;
+    let response = futures::executor::block_on(
+        Api::new(&symbol_manager)
+            .with_external_file_budget(ExternalFileBudget {
+                max_objects: Some(1),
+                ..Default::default()
+            })
+            .query_api("/symbolicate/v5", request_json),
+    );
+    let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+
+    // The skipped object file is reported, but the module counts as found.
+    let result = &response["results"][0];
+    let module_key = "libexternal.dylib/4C4C446C55553144A1A9D5D659F57D350";
+    assert_eq!(result["found_modules"][module_key], true);
+    assert!(result.get("module_errors").is_none());
+    let errors = result["external_file_errors"][module_key]
+        .as_array()
+        .unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0]["name"], "ExternalFileBudgetExceeded");
+    let lines: Vec<_> = result["stacks"][0]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|frame| frame.get("line").is_some())
+        .collect();
+    assert_eq!(lines.len(), 1);
+}
+
+#[test]
+fn raw_names_symbolication() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-local"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    // Raw names are only returned for the jobs which ask for them.
+    let request_json = r#"{
+        "jobs": [
+            {
+                "memoryMap": [["mozglue.pdb", "B3CC644ECC086E044C4C44205044422E1"]],
+                "stacks": [[[0, 214640]]],
+                "rawNames": true
+            },
+            {
+                "memoryMap": [["mozglue.pdb", "B3CC644ECC086E044C4C44205044422E1"]],
+                "stacks": [[[0, 214640]]]
+            }
+        ]
+    }"#;
+    let response = futures::executor::block_on(
+        Api::new(&symbol_manager).query_api("/symbolicate/v5", request_json),
+    );
+    let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+    let raw_names_frame = &response["results"][0]["stacks"][0][0];
+    let default_frame = &response["results"][1]["stacks"][0][0];
+    assert_eq!(
+        raw_names_frame["function"],
+        "mozilla::baseprofiler::profiler_get_profile(double, bool, bool)"
+    );
+    assert_eq!(
+        raw_names_frame["raw_name"],
+        "?profiler_get_profile@baseprofiler@mozilla@@YA?AV?$UniquePtr@$$BY0A@DV?$DefaultDelete@$$BY0A@D@mozilla@@@2@N_N0@Z"
+    );
+    assert_eq!(default_frame["function"], raw_names_frame["function"]);
+    assert!(default_frame.get("raw_name").is_none());
+}
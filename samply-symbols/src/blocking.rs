@@ -0,0 +1,224 @@
+use std::future::{ready, Future, Ready};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use crate::{
+    CandidatePathInfo, ElfBuildId, Error, ExternalFileAddressRef, FileAndPathHelper,
+    FileAndPathHelperResult, FileContents, FileLocation, FrameDebugInfo, LibraryInfo,
+    SymbolManager, SymbolMap, VerificationReport,
+};
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs `future` to completion on the current thread.
+///
+/// This is a minimal executor: it polls the future, and parks the thread until
+/// the future's waker is called. It has no I/O or timer support, so it's meant
+/// for futures which don't depend on a runtime, such as the futures returned by
+/// [`SymbolManager`] when it's used with a [`BlockingHelper`].
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// A synchronous variant of [`FileAndPathHelper`], for the common case where all
+/// files are on the local file system and can be opened directly.
+///
+/// Wrap the implementation in a [`BlockingHelper`] to pass it to a [`SymbolManager`].
+pub trait BlockingFileAndPathHelper {
+    type F: FileContents + 'static;
+    type FL: FileLocation + 'static;
+
+    /// See [`FileAndPathHelper::get_candidate_paths_for_debug_file`].
+    fn get_candidate_paths_for_debug_file(
+        &self,
+        info: &LibraryInfo,
+    ) -> FileAndPathHelperResult<Vec<CandidatePathInfo<Self::FL>>>;
+
+    /// See [`FileAndPathHelper::get_candidate_paths_for_binary`].
+    fn get_candidate_paths_for_binary(
+        &self,
+        info: &LibraryInfo,
+    ) -> FileAndPathHelperResult<Vec<CandidatePathInfo<Self::FL>>>;
+
+    /// See [`FileAndPathHelper::get_dyld_shared_cache_paths`].
+    fn get_dyld_shared_cache_paths(
+        &self,
+        _arch: Option<&str>,
+    ) -> FileAndPathHelperResult<Vec<Self::FL>> {
+        Ok(Vec::new())
+    }
+
+    /// See [`FileAndPathHelper::get_candidate_paths_for_gnu_debug_link_dest`].
+    fn get_candidate_paths_for_gnu_debug_link_dest(
+        &self,
+        _original_file_location: &Self::FL,
+        _debug_link_name: &str,
+    ) -> FileAndPathHelperResult<Vec<Self::FL>> {
+        Ok(Vec::new())
+    }
+
+    /// See [`FileAndPathHelper::get_candidate_paths_for_supplementary_debug_file`].
+    fn get_candidate_paths_for_supplementary_debug_file(
+        &self,
+        _original_file_path: &Self::FL,
+        _supplementary_file_path: &str,
+        _supplementary_file_build_id: &ElfBuildId,
+    ) -> FileAndPathHelperResult<Vec<Self::FL>> {
+        Ok(Vec::new())
+    }
+
+    /// See [`FileAndPathHelper::get_candidate_paths_for_external_object_file`].
+    fn get_candidate_paths_for_external_object_file(
+        &self,
+        _original_file_location: &Self::FL,
+        _object_file: &str,
+    ) -> FileAndPathHelperResult<Vec<Self::FL>> {
+        Ok(Vec::new())
+    }
+
+    /// Opens the file at `location` and returns its contents. Unlike
+    /// [`FileAndPathHelper::load_file`], this returns directly.
+    fn load_file_blocking(&self, location: Self::FL) -> FileAndPathHelperResult<Self::F>;
+}
+
+/// Adapts a [`BlockingFileAndPathHelper`] to the [`FileAndPathHelper`] trait.
+///
+/// The futures returned by `load_file` are always ready, so a `SymbolManager`
+/// which uses this helper can be driven with [`block_on`].
+pub struct BlockingHelper<H>(pub H);
+
+impl<'h, H: BlockingFileAndPathHelper> FileAndPathHelper<'h> for BlockingHelper<H> {
+    type F = H::F;
+    type FL = H::FL;
+    type OpenFileFuture = Ready<FileAndPathHelperResult<H::F>>;
+
+    fn get_candidate_paths_for_debug_file(
+        &self,
+        info: &LibraryInfo,
+    ) -> FileAndPathHelperResult<Vec<CandidatePathInfo<Self::FL>>> {
+        self.0.get_candidate_paths_for_debug_file(info)
+    }
+
+    fn get_candidate_paths_for_binary(
+        &self,
+        info: &LibraryInfo,
+    ) -> FileAndPathHelperResult<Vec<CandidatePathInfo<Self::FL>>> {
+        self.0.get_candidate_paths_for_binary(info)
+    }
+
+    fn get_dyld_shared_cache_paths(
+        &self,
+        arch: Option<&str>,
+    ) -> FileAndPathHelperResult<Vec<Self::FL>> {
+        self.0.get_dyld_shared_cache_paths(arch)
+    }
+
+    fn get_candidate_paths_for_gnu_debug_link_dest(
+        &self,
+        original_file_location: &Self::FL,
+        debug_link_name: &str,
+    ) -> FileAndPathHelperResult<Vec<Self::FL>> {
+        self.0
+            .get_candidate_paths_for_gnu_debug_link_dest(original_file_location, debug_link_name)
+    }
+
+    fn get_candidate_paths_for_supplementary_debug_file(
+        &self,
+        original_file_path: &Self::FL,
+        supplementary_file_path: &str,
+        supplementary_file_build_id: &ElfBuildId,
+    ) -> FileAndPathHelperResult<Vec<Self::FL>> {
+        self.0.get_candidate_paths_for_supplementary_debug_file(
+            original_file_path,
+            supplementary_file_path,
+            supplementary_file_build_id,
+        )
+    }
+
+    fn get_candidate_paths_for_external_object_file(
+        &self,
+        original_file_location: &Self::FL,
+        object_file: &str,
+    ) -> FileAndPathHelperResult<Vec<Self::FL>> {
+        self.0
+            .get_candidate_paths_for_external_object_file(original_file_location, object_file)
+    }
+
+    fn load_file(&'h self, location: Self::FL) -> Self::OpenFileFuture {
+        ready(self.0.load_file_blocking(location))
+    }
+}
+
+impl<'h, H: BlockingFileAndPathHelper> SymbolManager<'h, BlockingHelper<H>> {
+    /// The blocking version of [`SymbolManager::load_symbol_map`].
+    pub fn load_symbol_map_blocking(
+        &self,
+        library_info: &LibraryInfo,
+    ) -> Result<SymbolMap<H::FL>, Error> {
+        block_on(self.load_symbol_map(library_info))
+    }
+
+    /// The blocking version of [`SymbolManager::lookup_external`].
+    pub fn lookup_external_blocking(
+        &self,
+        debug_file_location: &H::FL,
+        address: &ExternalFileAddressRef,
+    ) -> Option<Vec<FrameDebugInfo>> {
+        block_on(self.lookup_external(debug_file_location, address))
+    }
+
+    /// The blocking version of [`SymbolManager::verify_symbols`].
+    pub fn verify_symbols_blocking(
+        &self,
+        library_info: &LibraryInfo,
+    ) -> Result<VerificationReport<H::FL>, Error> {
+        block_on(self.verify_symbols(library_info))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A future which returns `Pending` once, after waking itself from another thread.
+    struct WakeOnce(bool);
+
+    impl Future for WakeOnce {
+        type Output = u32;
+
+        fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+            if self.0 {
+                return Poll::Ready(42);
+            }
+            self.0 = true;
+            let waker = cx.waker().clone();
+            thread::spawn(move || waker.wake());
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn block_on_waits_for_wakeup() {
+        assert_eq!(block_on(ready(7)), 7);
+        assert_eq!(block_on(WakeOnce(false)), 42);
+    }
+}
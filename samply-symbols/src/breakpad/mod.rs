@@ -1,9 +1,11 @@
 mod index;
+mod sym_sink;
 mod symbol_map;
 
 pub use index::{
     BreakpadIndex, BreakpadIndexParser, BreakpadParseError, BreakpadSymindexParseError,
 };
+pub use sym_sink::BreakpadSymSink;
 pub use symbol_map::get_symbol_map_for_breakpad_sym;
 
 use crate::{FileContents, FileContentsWrapper};
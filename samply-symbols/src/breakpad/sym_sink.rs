@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use debugid::DebugId;
+
+use crate::{FileLocation, FrameDebugInfo, FramesLookupResult, SourceFilePath, SymbolMap};
+
+/// Accumulates `FUNC`, `LINE`, `INLINE` and `PUBLIC` records, and serializes them in
+/// the Breakpad `.sym` text format.
+///
+/// Any [`SymbolMap`] can be added with [`add_symbol_map`](Self::add_symbol_map),
+/// regardless of whether its information comes from DWARF, a PDB file, or something
+/// else. Symbols with debug info become `FUNC` records with `LINE` and `INLINE`
+/// records, all other symbols become `PUBLIC` records. Functions from other sources,
+/// for example from the frames returned by the symbolication API, can be added with
+/// [`add_function`](Self::add_function) and
+/// [`add_public_symbol`](Self::add_public_symbol).
+#[derive(Debug, Clone)]
+pub struct BreakpadSymSink {
+    module_line: String,
+    files: Vec<String>,
+    file_ids: HashMap<String, u32>,
+    inline_origins: Vec<String>,
+    inline_origin_ids: HashMap<String, u32>,
+    funcs: Vec<FuncRecord>,
+    publics: Vec<(u32, String)>,
+}
+
+#[derive(Debug, Clone)]
+struct FuncRecord {
+    address: u32,
+    size: u32,
+    name: String,
+    inlines: Vec<InlineRecord>,
+    lines: Vec<LineRecord>,
+}
+
+#[derive(Debug, Clone)]
+struct InlineRecord {
+    depth: u32,
+    call_line: u32,
+    call_file_id: u32,
+    origin_id: u32,
+    ranges: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, Clone)]
+struct LineRecord {
+    address: u32,
+    size: u32,
+    line: u32,
+    file_id: u32,
+}
+
+impl BreakpadSymSink {
+    /// Creates an empty sink. The arguments are written into the `MODULE` line, for
+    /// example `BreakpadSymSink::new("windows", "x86_64", debug_id, "firefox.pdb")`.
+    pub fn new(os: &str, arch: &str, debug_id: DebugId, debug_name: &str) -> Self {
+        Self {
+            module_line: format!("MODULE {os} {arch} {} {debug_name}", debug_id.breakpad()),
+            files: Vec::new(),
+            file_ids: HashMap::new(),
+            inline_origins: Vec::new(),
+            inline_origin_ids: HashMap::new(),
+            funcs: Vec::new(),
+            publics: Vec::new(),
+        }
+    }
+
+    /// Adds records for all symbols of `symbol_map`.
+    pub fn add_symbol_map<FL: FileLocation>(&mut self, symbol_map: &SymbolMap<FL>) {
+        let symbols: Vec<(u32, Option<u32>, String)> = symbol_map
+            .iter_symbols_with_size()
+            .map(|(address, size, name)| (address, size, name.into_owned()))
+            .collect();
+        let mut symbol_addresses: Vec<u32> = symbols.iter().map(|(address, ..)| *address).collect();
+        symbol_addresses.sort_unstable();
+        symbol_addresses.dedup();
+
+        for (address, size, name) in symbols {
+            let size = size.or_else(|| {
+                let next_index = symbol_addresses.partition_point(|a| *a <= address);
+                let next_address = symbol_addresses.get(next_index)?;
+                next_address.checked_sub(address)
+            });
+            match size {
+                Some(size) if size > 0 => self.add_symbol(symbol_map, address, size, name),
+                _ => self.add_public_symbol(address, name),
+            }
+        }
+    }
+
+    fn add_symbol<FL: FileLocation>(
+        &mut self,
+        symbol_map: &SymbolMap<FL>,
+        address: u32,
+        size: u32,
+        name: String,
+    ) {
+        let end = address.saturating_add(size);
+        let entries = symbol_map.lookup_relative_address_range(address, end);
+        let entries = entries.iter().map(|(entry_address, info)| {
+            let frames: &[FrameDebugInfo] = match &info.frames {
+                // Entries which belong to a different symbol that overlaps this one
+                // are gaps in this function.
+                FramesLookupResult::Available(frames) if info.symbol.address == address => frames,
+                _ => &[],
+            };
+            (*entry_address, frames)
+        });
+        self.add_function(address, size, name, entries);
+    }
+
+    /// Adds a `PUBLIC` record.
+    pub fn add_public_symbol(&mut self, address: u32, name: impl Into<String>) {
+        self.publics.push((address, name.into()));
+    }
+
+    /// Adds a `FUNC` record with the `LINE` and `INLINE` records for the function at
+    /// `address..address + size`.
+    ///
+    /// `entries` are `(address, frames)` pairs sorted by address. Each entry covers
+    /// the addresses up to the next entry, or up to the end of the function for the
+    /// last entry. The frames are ordered innermost first, like in
+    /// [`FramesLookupResult::Available`]; entries with no frames are gaps without
+    /// line information. If no entry has frames, a `PUBLIC` record is added instead.
+    pub fn add_function<'a>(
+        &mut self,
+        address: u32,
+        size: u32,
+        name: impl Into<String>,
+        entries: impl IntoIterator<Item = (u32, &'a [FrameDebugInfo])>,
+    ) {
+        let name = name.into();
+        let end = address.saturating_add(size);
+        let entries: Vec<(u32, &[FrameDebugInfo])> = entries
+            .into_iter()
+            .filter(|(entry_address, _)| (address..end).contains(entry_address))
+            .collect();
+        let mut func = FuncRecord {
+            address,
+            size: end - address,
+            name,
+            inlines: Vec::new(),
+            lines: Vec::new(),
+        };
+
+        // The indexes into func.inlines of the inline records which cover the end of
+        // the previous entry, outermost first.
+        let mut open_inlines: Vec<usize> = Vec::new();
+        let mut has_frames = false;
+        for (i, (entry_address, frames)) in entries.iter().enumerate() {
+            let entry_end = entries.get(i + 1).map_or(end, |(next, _)| *next);
+            if frames.is_empty() {
+                open_inlines.clear();
+                continue;
+            }
+            has_frames = true;
+            let range = (*entry_address, entry_end - entry_address);
+
+            let innermost = &frames[0];
+            if let (Some(path), Some(line)) = (&innermost.file_path, innermost.line_number) {
+                func.lines.push(LineRecord {
+                    address: range.0,
+                    size: range.1,
+                    line,
+                    file_id: self.file_id(path),
+                });
+            }
+
+            // frames is ordered innermost first. Every frame except the outermost one
+            // is an inlined call; its call site is stored in the frame outside of it.
+            let mut still_open = true;
+            let mut new_open_inlines = Vec::new();
+            for (depth, pair) in frames.windows(2).rev().enumerate() {
+                let (callee, caller) = (&pair[0], &pair[1]);
+                let depth = depth as u32;
+                let call_line = caller.line_number.unwrap_or(0);
+                let call_file_id = match &caller.file_path {
+                    Some(path) => self.file_id(path),
+                    None => 0,
+                };
+                let origin_id = self.inline_origin_id(callee);
+                let continued = open_inlines
+                    .get(depth as usize)
+                    .filter(|_| still_open)
+                    .copied()
+                    .filter(|index| {
+                        let record = &func.inlines[*index];
+                        record.call_line == call_line
+                            && record.call_file_id == call_file_id
+                            && record.origin_id == origin_id
+                    });
+                match continued {
+                    Some(index) => {
+                        let last_range = func.inlines[index].ranges.last_mut().unwrap();
+                        if last_range.0 + last_range.1 == range.0 {
+                            last_range.1 += range.1;
+                        } else {
+                            func.inlines[index].ranges.push(range);
+                        }
+                        new_open_inlines.push(index);
+                    }
+                    None => {
+                        still_open = false;
+                        func.inlines.push(InlineRecord {
+                            depth,
+                            call_line,
+                            call_file_id,
+                            origin_id,
+                            ranges: vec![range],
+                        });
+                        new_open_inlines.push(func.inlines.len() - 1);
+                    }
+                }
+            }
+            open_inlines = new_open_inlines;
+        }
+
+        if has_frames {
+            self.funcs.push(func);
+        } else {
+            self.publics.push((func.address, func.name));
+        }
+    }
+
+    fn file_id(&mut self, path: &SourceFilePath) -> u32 {
+        // Mapped paths are written in their "special path" form, which the Breakpad
+        // reader turns back into a mapped path.
+        let path = match path.mapped_path() {
+            Some(mapped_path) => mapped_path.to_special_path_str(),
+            None => path.raw_path().to_string(),
+        };
+        let files = &mut self.files;
+        *self.file_ids.entry(path).or_insert_with_key(|path| {
+            files.push(path.clone());
+            (files.len() - 1) as u32
+        })
+    }
+
+    fn inline_origin_id(&mut self, frame: &FrameDebugInfo) -> u32 {
+        let name = frame.function.as_deref().unwrap_or("<unknown>").to_string();
+        let inline_origins = &mut self.inline_origins;
+        *self
+            .inline_origin_ids
+            .entry(name)
+            .or_insert_with_key(|name| {
+                inline_origins.push(name.clone());
+                (inline_origins.len() - 1) as u32
+            })
+    }
+
+    /// Serializes the accumulated records into the contents of a `.sym` file.
+    pub fn to_sym_string(&self) -> String {
+        let mut s = String::new();
+        writeln!(s, "{}", self.module_line).unwrap();
+        for (id, file) in self.files.iter().enumerate() {
+            writeln!(s, "FILE {id} {file}").unwrap();
+        }
+        for (id, name) in self.inline_origins.iter().enumerate() {
+            writeln!(s, "INLINE_ORIGIN {id} {name}").unwrap();
+        }
+
+        enum Record<'a> {
+            Func(&'a FuncRecord),
+            Public(u32, &'a str),
+        }
+        let mut records: Vec<(u32, Record)> = self
+            .funcs
+            .iter()
+            .map(|func| (func.address, Record::Func(func)))
+            .chain(
+                self.publics
+                    .iter()
+                    .map(|(address, name)| (*address, Record::Public(*address, name))),
+            )
+            .collect();
+        records.sort_by_key(|(address, _)| *address);
+
+        for (_, record) in records {
+            match record {
+                Record::Func(func) => {
+                    writeln!(s, "FUNC {:x} {:x} 0 {}", func.address, func.size, func.name).unwrap();
+                    for inline in &func.inlines {
+                        write!(
+                            s,
+                            "INLINE {} {} {} {}",
+                            inline.depth, inline.call_line, inline.call_file_id, inline.origin_id
+                        )
+                        .unwrap();
+                        for (address, size) in &inline.ranges {
+                            write!(s, " {address:x} {size:x}").unwrap();
+                        }
+                        writeln!(s).unwrap();
+                    }
+                    for line in &func.lines {
+                        writeln!(
+                            s,
+                            "{:x} {:x} {} {}",
+                            line.address, line.size, line.line, line.file_id
+                        )
+                        .unwrap();
+                    }
+                }
+                Record::Public(address, name) => {
+                    writeln!(s, "PUBLIC {address:x} 0 {name}").unwrap();
+                }
+            }
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn add_function_with_inlined_call() {
+        let mut sink = BreakpadSymSink::new("Linux", "x86_64", DebugId::nil(), "libfoo.so");
+        let path = || Some(SourceFilePath::new("foo.c".into(), None));
+        let outer = |line| FrameDebugInfo::new(Some("outer".into()), path(), Some(line));
+        let mut inner = FrameDebugInfo::new(Some("inner".into()), path(), Some(3));
+        inner.is_inlined = true;
+        let entries: Vec<(u32, Vec<FrameDebugInfo>)> = vec![
+            (0x1000, vec![outer(10)]),
+            (0x1004, vec![inner, outer(11)]),
+            (0x1008, vec![]),
+            (0x100c, vec![outer(12)]),
+        ];
+        sink.add_function(
+            0x1000,
+            0x10,
+            "outer",
+            entries
+                .iter()
+                .map(|(address, frames)| (*address, &frames[..])),
+        );
+        sink.add_public_symbol(0x2000, "bar");
+        assert_eq!(
+            sink.to_sym_string(),
+            "MODULE Linux x86_64 000000000000000000000000000000000 libfoo.so\n\
+             FILE 0 foo.c\n\
+             INLINE_ORIGIN 0 inner\n\
+             FUNC 1000 10 0 outer\n\
+             INLINE 0 11 0 0 1004 4\n\
+             1000 4 10 0\n\
+             1004 4 3 0\n\
+             100c 4 12 0\n\
+             PUBLIC 2000 0 bar\n"
+        );
+    }
+}
@@ -51,10 +51,27 @@ impl<T: FileContents> SymbolMapTrait for BreakpadSymbolMap<T> {
         self.0.get().0.iter_symbols()
     }
 
+    fn iter_symbols_with_size(
+        &self,
+    ) -> Box<dyn Iterator<Item = (u32, Option<u32>, Cow<'_, str>)> + '_> {
+        self.0.get().0.iter_symbols_with_size()
+    }
+
+    fn lookup_symbol(&self, address: u32) -> Option<SymbolInfo> {
+        self.0.get().0.lookup_symbol(address)
+    }
+
     fn lookup_relative_address(&self, address: u32) -> Option<AddressInfo> {
         self.0.get().0.lookup_relative_address(address)
     }
 
+    fn boundaries_in_relative_address_range(&self, start: u32, end: u32) -> Vec<u32> {
+        self.0
+            .get()
+            .0
+            .boundaries_in_relative_address_range(start, end)
+    }
+
     fn lookup_svma(&self, svma: u64) -> Option<AddressInfo> {
         self.0.get().0.lookup_svma(svma)
     }
@@ -253,6 +270,67 @@ impl<'a, T: FileContents> SymbolMapTrait for BreakpadSymbolMapInner<'a, T> {
         Box::new(iter)
     }
 
+    fn iter_symbols_with_size(
+        &self,
+    ) -> Box<dyn Iterator<Item = (u32, Option<u32>, Cow<'_, str>)> + '_> {
+        let iter = (0..self.symbol_count()).filter_map(move |i| {
+            let address = self.index.symbol_addresses[i];
+            let mut cache = self.cache.lock().unwrap();
+            let (size, name) = match &self.index.symbol_offsets[i] {
+                super::index::BreakpadSymbolType::Public(public) => {
+                    let public_info = cache.symbols.get_public_info(public, self.data).ok()?;
+                    // PUBLIC records don't have a size. Use the distance to the next symbol.
+                    let size = self
+                        .index
+                        .symbol_addresses
+                        .get(i + 1)
+                        .and_then(|next_address| next_address.checked_sub(address));
+                    (size, public_info.name)
+                }
+                super::index::BreakpadSymbolType::Func(func) => {
+                    let func_info = cache.symbols.get_func_info(func, self.data).ok()?;
+                    (Some(func_info.size), func_info.name)
+                }
+            };
+            Some((address, size, Cow::Borrowed(name)))
+        });
+        Box::new(iter)
+    }
+
+    fn boundaries_in_relative_address_range(&self, start: u32, end: u32) -> Vec<u32> {
+        let first_index = match self.index.symbol_addresses.binary_search(&start) {
+            Ok(i) => i,
+            Err(0) => 0,
+            Err(i) => i - 1,
+        };
+        let mut boundaries = Vec::new();
+        let mut cache = self.cache.lock().unwrap();
+        for i in first_index..self.index.symbol_addresses.len() {
+            let symbol_address = self.index.symbol_addresses[i];
+            if symbol_address >= end {
+                break;
+            }
+            boundaries.push(symbol_address);
+            if let BreakpadSymbolType::Func(func) = &self.index.symbol_offsets[i] {
+                let info = match cache.symbols.get_func_info(func, self.data) {
+                    Ok(info) => info,
+                    Err(_) => continue,
+                };
+                boundaries.push(symbol_address.saturating_add(info.size));
+                for line in &info.lines {
+                    boundaries.push(line.address);
+                    boundaries.push(line.address.saturating_add(line.size));
+                }
+                for inlinee in &info.inlinees {
+                    boundaries.push(inlinee.address);
+                    boundaries.push(inlinee.address.saturating_add(inlinee.size));
+                }
+            }
+        }
+        boundaries.retain(|address| start < *address && *address < end);
+        boundaries
+    }
+
     fn lookup_relative_address(&self, address: u32) -> Option<AddressInfo> {
         let index = match self.index.symbol_addresses.binary_search(&address) {
             Ok(i) => i,
@@ -279,6 +357,7 @@ impl<'a, T: FileContents> SymbolMapTrait for BreakpadSymbolMapInner<'a, T> {
                         name: info.name.to_string(),
                     },
                     frames: FramesLookupResult::Unavailable,
+                    section_name: None,
                 })
             }
             BreakpadSymbolType::Func(func) => {
@@ -300,6 +379,8 @@ impl<'a, T: FileContents> SymbolMapTrait for BreakpadSymbolMapInner<'a, T> {
                         function: name,
                         file_path: file.map(SourceFilePath::from_breakpad_path),
                         line_number: Some(inlinee.call_line),
+                        compiland: None,
+                        is_inlined: depth != 0,
                     });
                     let inline_origin = inline_origins
                         .get_str(inlinee.origin_id)
@@ -319,6 +400,8 @@ impl<'a, T: FileContents> SymbolMapTrait for BreakpadSymbolMapInner<'a, T> {
                     function: name,
                     file_path: file.map(SourceFilePath::from_breakpad_path),
                     line_number,
+                    compiland: None,
+                    is_inlined: depth != 0,
                 });
                 frames.reverse();
 
@@ -329,6 +412,7 @@ impl<'a, T: FileContents> SymbolMapTrait for BreakpadSymbolMapInner<'a, T> {
                         name: info.name.to_string(),
                     },
                     frames: FramesLookupResult::Available(frames),
+                    section_name: None,
                 })
             }
         }
@@ -452,7 +536,9 @@ mod test {
             FrameDebugInfo {
                 function: Some("WriteRelease64(long long*, long long)".into()),
                 file_path: Some(SourceFilePath::new("/builds/worker/workspace/obj-build/browser/app/d:/agent/_work/2/s/src/externalapis/windows/10/sdk/inc/winnt.h".into(), None)),
-                line_number: Some(7729)
+                line_number: Some(7729),
+                compiland: None,
+                is_inlined: true,
             }
         );
         assert_eq!(
@@ -460,7 +546,9 @@ mod test {
             FrameDebugInfo {
                 function: Some("WritePointerRelease(void**, void*)".into()),
                 file_path: Some(SourceFilePath::new("/builds/worker/workspace/obj-build/browser/app/d:/agent/_work/2/s/src/externalapis/windows/10/sdk/inc/winnt.h".into(), None)),
-                line_number: Some(8358)
+                line_number: Some(8358),
+                compiland: None,
+                is_inlined: true,
             }
         );
         assert_eq!(
@@ -468,7 +556,9 @@ mod test {
             FrameDebugInfo {
                 function: Some("DloadUnlock()".into()),
                 file_path: Some(SourceFilePath::new("/builds/worker/workspace/obj-build/browser/app/d:/agent/_work/2/s/src/vctools/delayimp/dloadsup.h".into(), None)),
-                line_number: Some(345)
+                line_number: Some(345),
+                compiland: None,
+                is_inlined: true,
             }
         );
         assert_eq!(
@@ -476,8 +566,52 @@ mod test {
             FrameDebugInfo {
                 function: Some("DloadAcquireSectionWriteAccess()".into()),
                 file_path: Some(SourceFilePath::new("/builds/worker/workspace/obj-build/browser/app/d:/agent/_work/2/s/src/vctools/delayimp/dloadsup.h".into(), None)),
-                line_number: Some(665)
+                line_number: Some(665),
+                compiland: None,
+                is_inlined: false,
             }
         );
     }
+
+    #[test]
+    fn sym_sink_roundtrip() {
+        let data_slices: &[&[u8]] = &[
+            b"MODULE windows x86_64 F1E853FD662672044C4C44205044422E1 firefox.pdb\nIN",
+            b"FO CODE_ID 63C036DBA7000 firefox.exe\nINFO GENERATOR mozilla/dump_syms ",
+            b"2.1.1\nFILE 0 /builds/worker/workspace/obj-build/browser/app/d:/agent/_",
+            b"work/2/s/src/vctools/delayimp/dloadsup.h\nFILE 1 /builds/worker/workspa",
+            b"ce/obj-build/browser/app/d:/agent/_work/2/s/src/externalapis/windows/10",
+            b"/sdk/inc/winnt.h\nINLINE_ORIGIN 0 DloadLock()\nINLINE_ORIGIN 1 DloadUnl",
+            b"ock()\nINLINE_ORIGIN 2 WritePointerRelease(void**, void*)\nINLINE_ORIGI",
+            b"N 3 WriteRelease64(long long*, long long)\nFUNC 2b754 aa 0 DloadAcquire",
+            b"SectionWriteAccess()\nINLINE 0 658 0 0 2b76a 3d\nINLINE 0 665 0 1 2b7ca",
+            b" 17 2b7e6 12\nINLINE 1 345 0 2 2b7ed b\nINLINE 2 8358 1 3 2b7ed b\n2b75",
+            b"4 6 644 0\n2b75a 10 650 0\n2b76a e 299 0\n2b778 14 300 0\n2b78c 2 301 0",
+            b"\n2b78e 2 306 0\n2b790 c 305 0\n2b79c b 309 0\n2b7a7 10 660 0\n2b7b7 2 ",
+            b"661 0\n2b7b9 11 662 0\n2b7ca 9 340 0\n2b7d3 e 341 0\n2b7e1 c 668 0\n2b7",
+            b"ed b 7729 1\n2b7f8 6 668 0",
+        ];
+        let sym_fc = FileContentsWrapper::new(data_slices.concat());
+        let symbol_map = get_symbol_map_for_breakpad_sym(sym_fc, DummyLocation, None).unwrap();
+
+        let mut sink =
+            crate::BreakpadSymSink::new("windows", "x86_64", symbol_map.debug_id(), "firefox.pdb");
+        sink.add_symbol_map(&symbol_map);
+        let sym = sink.to_sym_string();
+        assert!(sym
+            .starts_with("MODULE windows x86_64 F1E853FD662672044C4C44205044422E1 firefox.pdb\n"));
+        assert!(sym.contains("\nFUNC 2b754 aa 0 DloadAcquireSectionWriteAccess()\n"));
+
+        let roundtrip_fc = FileContentsWrapper::new(sym.into_bytes());
+        let roundtrip_map =
+            get_symbol_map_for_breakpad_sym(roundtrip_fc, DummyLocation, None).unwrap();
+        assert_eq!(roundtrip_map.debug_id(), symbol_map.debug_id());
+        for address in 0x2b750..0x2b800 {
+            assert_eq!(
+                roundtrip_map.lookup_relative_address(address),
+                symbol_map.lookup_relative_address(address),
+                "Mismatch at address 0x{address:x}"
+            );
+        }
+    }
 }
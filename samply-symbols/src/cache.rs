@@ -38,6 +38,67 @@ pub trait FileByteSource: Send + Sync {
     ) -> FileAndPathHelperResult<()>;
 }
 
+/// A [`FileByteSource`] which obtains the bytes from a callback.
+///
+/// The callback is called with the byte range to read, and must return exactly the
+/// bytes in that range. This makes it easy to back a [`FileContentsWithChunkedCaching`]
+/// with something like HTTP range requests: only the chunks which are actually
+/// touched during parsing are fetched, and each chunk is only fetched once. For
+/// example, reading the UUID of a large Mach-O file only fetches the first chunk.
+///
+/// The callback is synchronous; if the bytes come from an async source, the callback
+/// needs to block on it.
+pub struct CallbackFileByteSource<F>(pub F);
+
+impl<F> CallbackFileByteSource<F> {
+    fn read_bytes_into_with(
+        buffer: &mut Vec<u8>,
+        offset: u64,
+        size: usize,
+        callback: impl FnOnce(Range<u64>) -> FileAndPathHelperResult<Vec<u8>>,
+    ) -> FileAndPathHelperResult<()> {
+        let bytes = callback(offset..(offset + size as u64))?;
+        if bytes.len() != size {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "CallbackFileByteSource callback returned the wrong number of bytes",
+            )));
+        }
+        buffer.extend_from_slice(&bytes);
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "send_futures"))]
+impl<F> FileByteSource for CallbackFileByteSource<F>
+where
+    F: Fn(Range<u64>) -> FileAndPathHelperResult<Vec<u8>>,
+{
+    fn read_bytes_into(
+        &self,
+        buffer: &mut Vec<u8>,
+        offset: u64,
+        size: usize,
+    ) -> FileAndPathHelperResult<()> {
+        Self::read_bytes_into_with(buffer, offset, size, &self.0)
+    }
+}
+
+#[cfg(feature = "send_futures")]
+impl<F> FileByteSource for CallbackFileByteSource<F>
+where
+    F: Fn(Range<u64>) -> FileAndPathHelperResult<Vec<u8>> + Send + Sync,
+{
+    fn read_bytes_into(
+        &self,
+        buffer: &mut Vec<u8>,
+        offset: u64,
+        size: usize,
+    ) -> FileAndPathHelperResult<()> {
+        Self::read_bytes_into_with(buffer, offset, size, &self.0)
+    }
+}
+
 pub struct FileContentsWithChunkedCaching<S: FileByteSource> {
     source: S,
     file_len: u64,
@@ -179,3 +240,46 @@ impl<S: FileByteSource> FileContents for FileContentsWithChunkedCaching<S> {
         self.source.read_bytes_into(buffer, offset, size)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::FileContentsWrapper;
+    use object::macho::MachHeader64;
+    use object::read::macho::MachHeader;
+    use object::LittleEndian;
+    use std::sync::Arc;
+
+    #[test]
+    fn callback_source_only_fetches_needed_chunks() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../fixtures/macos-local/libmozglue.dylib");
+        let data = Arc::new(std::fs::read(path).unwrap());
+        let fetched_bytes = Arc::new(AtomicUsize::new(0));
+
+        let source = {
+            let data = data.clone();
+            let fetched_bytes = fetched_bytes.clone();
+            CallbackFileByteSource(
+                move |range: Range<u64>| -> FileAndPathHelperResult<Vec<u8>> {
+                    let range = range.start as usize..range.end as usize;
+                    fetched_bytes.fetch_add(range.len(), std::sync::atomic::Ordering::SeqCst);
+                    Ok(data[range].to_vec())
+                },
+            )
+        };
+        let file_contents = FileContentsWrapper::new(FileContentsWithChunkedCaching::new(
+            data.len() as u64,
+            source,
+        ));
+
+        let header = MachHeader64::<LittleEndian>::parse(&file_contents, 0).unwrap();
+        let uuid = header.uuid(LittleEndian, &file_contents, 0).unwrap();
+        assert!(uuid.is_some());
+
+        let fetched_bytes = fetched_bytes.load(std::sync::atomic::Ordering::SeqCst);
+        assert!(fetched_bytes > 0);
+        assert!(fetched_bytes <= CHUNK_SIZE as usize);
+        assert!(fetched_bytes < data.len());
+    }
+}
@@ -0,0 +1,168 @@
+use std::io::Read;
+use std::ops::Range;
+
+use crate::{FileAndPathHelperResult, FileContents};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// A [`FileContents`] adapter for symbol files which may be compressed with gzip or
+/// zstd, as served by some symbol servers.
+///
+/// If the wrapped contents start with a gzip or zstd header, the whole file is
+/// decompressed into memory when the adapter is created, and all reads see the
+/// decompressed bytes. Otherwise, reads are forwarded to the wrapped contents
+/// without copying. This means that code which parses the file, for example to
+/// find the UUID of a Mach-O file or to open a PDB file, works on compressed files
+/// without any changes.
+///
+/// Cabinet-compressed files, such as `.pd_` files from Microsoft symbol servers,
+/// are not handled.
+pub enum DecompressingFileContents<F: FileContents> {
+    Uncompressed(F),
+    Decompressed(Vec<u8>),
+}
+
+impl<F: FileContents> DecompressingFileContents<F> {
+    /// The default for the maximum decompressed size, 4 GiB.
+    pub const DEFAULT_MAX_DECOMPRESSED_SIZE: u64 = 4 << 30;
+
+    /// Checks the magic bytes of `contents` and decompresses it if needed.
+    ///
+    /// Fails if the decompressed size exceeds [`Self::DEFAULT_MAX_DECOMPRESSED_SIZE`].
+    pub fn new(contents: F) -> FileAndPathHelperResult<Self> {
+        Self::with_max_decompressed_size(contents, Self::DEFAULT_MAX_DECOMPRESSED_SIZE)
+    }
+
+    /// Like [`DecompressingFileContents::new`], but fails if the decompressed size
+    /// exceeds `max_size` bytes. Decompression stops as soon as the limit is
+    /// exceeded, so a small compressed file can't make us allocate an unbounded
+    /// amount of memory.
+    pub fn with_max_decompressed_size(contents: F, max_size: u64) -> FileAndPathHelperResult<Self> {
+        let header_len = contents.len().min(ZSTD_MAGIC.len() as u64);
+        let header = contents.read_bytes_at(0, header_len)?;
+        let mut decompressed = Vec::new();
+        if header.starts_with(&GZIP_MAGIC) {
+            let compressed = contents.read_bytes_at(0, contents.len())?;
+            flate2::read::MultiGzDecoder::new(compressed)
+                .take(max_size.saturating_add(1))
+                .read_to_end(&mut decompressed)?;
+        } else if header.starts_with(&ZSTD_MAGIC) {
+            let mut compressed = contents.read_bytes_at(0, contents.len())?;
+            // A zstd file can consist of multiple frames, and the streaming decoder
+            // stops at the end of each frame.
+            while !compressed.is_empty() && decompressed.len() as u64 <= max_size {
+                let remaining = max_size + 1 - decompressed.len() as u64;
+                let decoder = ruzstd::StreamingDecoder::new(&mut compressed)
+                    .map_err(|e| format!("zstd: {e}"))?;
+                decoder.take(remaining).read_to_end(&mut decompressed)?;
+            }
+        } else {
+            return Ok(Self::Uncompressed(contents));
+        }
+        if decompressed.len() as u64 > max_size {
+            return Err(
+                format!("The decompressed size exceeds the limit of {max_size} bytes").into(),
+            );
+        }
+        Ok(Self::Decompressed(decompressed))
+    }
+
+    /// Whether the wrapped contents were compressed.
+    pub fn was_compressed(&self) -> bool {
+        matches!(self, Self::Decompressed(_))
+    }
+}
+
+impl<F: FileContents> FileContents for DecompressingFileContents<F> {
+    fn len(&self) -> u64 {
+        match self {
+            Self::Uncompressed(contents) => contents.len(),
+            Self::Decompressed(bytes) => FileContents::len(bytes),
+        }
+    }
+
+    fn read_bytes_at(&self, offset: u64, size: u64) -> FileAndPathHelperResult<&[u8]> {
+        match self {
+            Self::Uncompressed(contents) => contents.read_bytes_at(offset, size),
+            Self::Decompressed(bytes) => bytes.read_bytes_at(offset, size),
+        }
+    }
+
+    fn read_bytes_at_until(
+        &self,
+        range: Range<u64>,
+        delimiter: u8,
+    ) -> FileAndPathHelperResult<&[u8]> {
+        match self {
+            Self::Uncompressed(contents) => contents.read_bytes_at_until(range, delimiter),
+            Self::Decompressed(bytes) => bytes.read_bytes_at_until(range, delimiter),
+        }
+    }
+
+    fn read_bytes_into(
+        &self,
+        buffer: &mut Vec<u8>,
+        offset: u64,
+        size: usize,
+    ) -> FileAndPathHelperResult<()> {
+        match self {
+            Self::Uncompressed(contents) => contents.read_bytes_into(buffer, offset, size),
+            Self::Decompressed(bytes) => bytes.read_bytes_into(buffer, offset, size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn uncompressed_contents_are_passed_through() {
+        let contents = DecompressingFileContents::new(b"hello".to_vec()).unwrap();
+        assert!(!contents.was_compressed());
+        assert_eq!(contents.read_bytes_at(1, 3).unwrap(), b"ell");
+        let empty = DecompressingFileContents::new(Vec::new()).unwrap();
+        assert!(!empty.was_compressed());
+        assert!(FileContents::is_empty(&empty));
+    }
+
+    #[test]
+    fn gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"hello world").unwrap();
+        let compressed = encoder.finish().unwrap();
+        let contents = DecompressingFileContents::new(compressed).unwrap();
+        assert!(contents.was_compressed());
+        assert_eq!(FileContents::len(&contents), 11);
+        assert_eq!(contents.read_bytes_at(6, 5).unwrap(), b"world");
+    }
+
+    #[test]
+    fn max_decompressed_size() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(&[0; 100_000]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let contents =
+            DecompressingFileContents::with_max_decompressed_size(compressed.clone(), 100_000)
+                .unwrap();
+        assert_eq!(FileContents::len(&contents), 100_000);
+        assert!(DecompressingFileContents::with_max_decompressed_size(compressed, 99_999).is_err());
+    }
+
+    #[test]
+    fn zstd() {
+        // A single frame with a single uncompressed ("raw") block.
+        let mut compressed = ZSTD_MAGIC.to_vec();
+        // Frame header: single segment, with a one-byte content size.
+        compressed.extend_from_slice(&[0x20, 5]);
+        // Block header: last block, raw, 5 bytes.
+        let block_header: u32 = 1 | (5 << 3);
+        compressed.extend_from_slice(&block_header.to_le_bytes()[..3]);
+        compressed.extend_from_slice(b"hello");
+        let contents = DecompressingFileContents::new(compressed).unwrap();
+        assert!(contents.was_compressed());
+        assert_eq!(contents.read_bytes_at(0, 5).unwrap(), b"hello");
+    }
+}
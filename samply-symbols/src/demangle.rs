@@ -1,17 +1,22 @@
 use super::demangle_ocaml;
+use crate::FrameDebugInfo;
 use msvc_demangler::DemangleFlags;
 
+/// Demangles a symbol name, trying the MSVC, Rust, Itanium C++ and OCaml mangling
+/// schemes. Names which aren't mangled, or which fail to demangle, are returned
+/// unchanged, except that a single leading underscore is removed (mach-O and 32 bit
+/// Windows prefix C symbol names with an underscore).
+///
+/// Objective-C method names such as `-[NSString stringWithFormat:]` are kept as
+/// they are, and the invocation functions of blocks are turned into
+/// `invocation function for block in <parent function>`.
 pub fn demangle_any(name: &str) -> String {
+    if let Some(demangled) = demangle_objc(name) {
+        return demangled;
+    }
+
     if name.starts_with('?') {
-        let flags = DemangleFlags::NO_ACCESS_SPECIFIERS
-            | DemangleFlags::NO_FUNCTION_RETURNS
-            | DemangleFlags::NO_MEMBER_TYPE
-            | DemangleFlags::NO_MS_KEYWORDS
-            | DemangleFlags::NO_THISTYPE
-            | DemangleFlags::NO_CLASS_TYPE
-            | DemangleFlags::SPACE_AFTER_COMMA
-            | DemangleFlags::HUG_TYPE;
-        return msvc_demangler::demangle(name, flags).unwrap_or_else(|_| name.to_string());
+        return demangle_msvc(name).unwrap_or_else(|| name.to_string());
     }
 
     if let Ok(demangled_symbol) = rustc_demangle::try_demangle(name) {
@@ -37,3 +42,322 @@ pub fn demangle_any(name: &str) -> String {
 
     name.to_owned()
 }
+
+/// Handles Objective-C method names and the names of block invocation functions,
+/// such as `___28-[Foo bar:]_block_invoke`, `___ZN3Foo3barEv_block_invoke_2` or
+/// `___main_block_invoke`. Returns `None` for all other names.
+fn demangle_objc(name: &str) -> Option<String> {
+    if is_objc_method_name(name) {
+        return Some(name.to_owned());
+    }
+
+    // Block invocation functions are named after their parent function, with two
+    // leading underscores (plus the mach-O underscore) and a `_block_invoke` suffix.
+    let parent = strip_block_invoke_suffix(name.strip_prefix("___")?)?;
+    let parent = if let Some(mangled) = parent.strip_prefix('Z') {
+        // A C++ parent function. Its own leading underscore is among the three.
+        demangle_any(&format!("_Z{mangled}"))
+    } else {
+        // Objective-C parent methods are prefixed with the length of their name.
+        let without_length = parent.trim_start_matches(|c: char| c.is_ascii_digit());
+        if is_objc_method_name(without_length) {
+            without_length.to_owned()
+        } else {
+            parent.to_owned()
+        }
+    };
+    Some(format!("invocation function for block in {parent}"))
+}
+
+fn is_objc_method_name(name: &str) -> bool {
+    (name.starts_with("-[") || name.starts_with("+[")) && name.ends_with(']') && name.contains(' ')
+}
+
+/// Strips `_block_invoke` and an optional `_<number>` after it.
+fn strip_block_invoke_suffix(name: &str) -> Option<&str> {
+    const SUFFIX: &str = "_block_invoke";
+    let pos = name.rfind(SUFFIX)?;
+    let rest = &name[pos + SUFFIX.len()..];
+    let is_numbered = rest.strip_prefix('_').map_or(false, |n| {
+        !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())
+    });
+    if pos > 0 && (rest.is_empty() || is_numbered) {
+        Some(&name[..pos])
+    } else {
+        None
+    }
+}
+
+/// Demangles an MSVC-decorated name, such as `?foo@Bar@@QEAAXH@Z`, into a short
+/// form without return types, calling conventions and access specifiers.
+///
+/// Returns `None` if the name is not MSVC-decorated or could not be parsed.
+pub fn demangle_msvc(name: &str) -> Option<String> {
+    if !name.starts_with('?') {
+        return None;
+    }
+    let flags = DemangleFlags::NO_ACCESS_SPECIFIERS
+        | DemangleFlags::NO_FUNCTION_RETURNS
+        | DemangleFlags::NO_MEMBER_TYPE
+        | DemangleFlags::NO_MS_KEYWORDS
+        | DemangleFlags::NO_THISTYPE
+        | DemangleFlags::NO_CLASS_TYPE
+        | DemangleFlags::SPACE_AFTER_COMMA
+        | DemangleFlags::HUG_TYPE;
+    msvc_demangler::demangle(name, flags).ok()
+}
+
+/// Removes the parameter list, and any `const` / `volatile` / reference qualifiers
+/// after it, from a demangled function name, for example
+/// `Namespace::Class::method(int, const Foo&) const` becomes `Namespace::Class::method`.
+///
+/// This works on the output of any demangler, and on the function names which
+/// pdb-addr2line formats from PDB type information. Names without a parameter list
+/// are returned unchanged.
+pub fn strip_function_arguments(name: &str) -> &str {
+    const QUALIFIERS: &[&str] = &["const", "volatile", "&", "&&", "noexcept"];
+    let close_paren = match name.rfind(')') {
+        Some(pos) => pos,
+        None => return name,
+    };
+    let suffix = &name[close_paren + 1..];
+    if !suffix
+        .split_whitespace()
+        .all(|token| QUALIFIERS.contains(&token))
+    {
+        return name;
+    }
+
+    // Find the matching opening parenthesis.
+    let mut nesting = 0;
+    for (pos, b) in name[..close_paren].bytes().enumerate().rev() {
+        match b {
+            b')' => nesting += 1,
+            b'(' if nesting == 0 => {
+                let prefix = &name[..pos];
+                if prefix.is_empty() {
+                    return name;
+                }
+                return prefix;
+            }
+            b'(' => nesting -= 1,
+            _ => {}
+        }
+    }
+    name
+}
+
+/// Collapses template argument lists which are nested deeper than `visible_depth`
+/// into `<...>`, for example with `visible_depth` 1,
+/// `std::vector<std::basic_string<char, std::char_traits<char>>>::push_back` becomes
+/// `std::vector<std::basic_string<...>>::push_back`. With `visible_depth` 0, all
+/// template argument lists are collapsed.
+///
+/// Like [`strip_function_arguments`], this can be applied to the names of both
+/// outer and inlined functions. Operators such as `operator<<` are left alone.
+pub fn elide_template_args(name: &str, visible_depth: usize) -> String {
+    const OPERATOR: &str = "operator";
+    const OPERATOR_SYMBOLS: &[&str] = &[
+        "<<=", ">>=", "<=>", "->*", "<<", ">>", "<=", ">=", "->", "<", ">",
+    ];
+    let mut result = String::with_capacity(name.len());
+    let mut depth = 0;
+    let mut pos = 0;
+    while let Some(c) = name[pos..].chars().next() {
+        let rest = &name[pos..];
+        let follows_identifier = name[..pos]
+            .chars()
+            .next_back()
+            .map_or(false, |c| c.is_alphanumeric() || c == '_');
+        if rest.starts_with(OPERATOR) && !follows_identifier {
+            let after_operator = &rest[OPERATOR.len()..];
+            let symbol_len = OPERATOR_SYMBOLS
+                .iter()
+                .find(|symbol| after_operator.starts_with(**symbol))
+                .map_or(0, |symbol| symbol.len());
+            let len = OPERATOR.len() + symbol_len;
+            if depth <= visible_depth {
+                result.push_str(&rest[..len]);
+            }
+            pos += len;
+            continue;
+        }
+        match c {
+            '<' => {
+                depth += 1;
+                if depth == visible_depth + 1 {
+                    result.push_str("<...>");
+                } else if depth <= visible_depth {
+                    result.push('<');
+                }
+            }
+            '>' if depth > 0 => {
+                if depth <= visible_depth {
+                    result.push('>');
+                }
+                depth -= 1;
+            }
+            c if depth <= visible_depth => result.push(c),
+            _ => {}
+        }
+        pos += c.len_utf8();
+    }
+    result
+}
+
+/// Options for shortening the function names which a symbol map returns, see
+/// [`SymbolMap::set_function_name_style`](crate::SymbolMap::set_function_name_style).
+/// The default keeps the full names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FunctionNameStyle {
+    /// Remove the parameter lists, see [`strip_function_arguments`].
+    pub strip_arguments: bool,
+    /// Collapse the template argument lists which are nested deeper than this,
+    /// see [`elide_template_args`]. `None` keeps all template arguments.
+    pub template_args_visible_depth: Option<usize>,
+}
+
+impl FunctionNameStyle {
+    /// Applies this style to a function name.
+    pub fn apply(&self, mut name: String) -> String {
+        if self.strip_arguments {
+            let len = strip_function_arguments(&name).len();
+            name.truncate(len);
+        }
+        if let Some(visible_depth) = self.template_args_visible_depth {
+            name = elide_template_args(&name, visible_depth);
+        }
+        name
+    }
+
+    pub(crate) fn apply_to_frames(&self, frames: &mut [FrameDebugInfo]) {
+        if self.is_default() {
+            return;
+        }
+        for frame in frames {
+            frame.function = frame.function.take().map(|name| self.apply(name));
+        }
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn demangle_rust() {
+        assert_eq!(
+            demangle_any("_ZN4core3fmt9Formatter3pad17h0123456789abcdefE"),
+            "core::fmt::Formatter::pad"
+        );
+    }
+
+    #[test]
+    fn demangle_cpp() {
+        assert_eq!(demangle_any("_ZN3foo3barEv"), "foo::bar()");
+    }
+
+    #[test]
+    fn demangle_msvc_names() {
+        assert_eq!(demangle_msvc("?f@@YAXH@Z").as_deref(), Some("f(int)"));
+        assert_eq!(
+            demangle_msvc("?foo@Bar@@QEAAXH@Z").as_deref(),
+            Some("Bar::foo(int)")
+        );
+        assert_eq!(demangle_any("?foo@Bar@@QEAAXH@Z"), "Bar::foo(int)");
+        assert_eq!(demangle_msvc("NtQueryVirtualMemory"), None);
+        assert_eq!(demangle_msvc("?"), None);
+        assert_eq!(demangle_any("?"), "?");
+    }
+
+    #[test]
+    fn demangle_plain_names() {
+        assert_eq!(demangle_any("_main"), "main");
+        assert_eq!(demangle_any("main"), "main");
+        assert_eq!(demangle_any("foo::bar()"), "foo::bar()");
+        assert_eq!(
+            demangle_any("core::fmt::Formatter::pad"),
+            "core::fmt::Formatter::pad"
+        );
+    }
+
+    #[test]
+    fn demangle_objc_names() {
+        assert_eq!(
+            demangle_any("-[NSString stringWithFormat:]"),
+            "-[NSString stringWithFormat:]"
+        );
+        assert_eq!(
+            demangle_any("+[NSObject(MyCategory) load]"),
+            "+[NSObject(MyCategory) load]"
+        );
+        assert_eq!(
+            demangle_any("___28-[AppDelegate didLaunch:]_block_invoke"),
+            "invocation function for block in -[AppDelegate didLaunch:]"
+        );
+        assert_eq!(
+            demangle_any("___ZN3foo3barEv_block_invoke_2"),
+            "invocation function for block in foo::bar()"
+        );
+        assert_eq!(
+            demangle_any("___main_block_invoke"),
+            "invocation function for block in main"
+        );
+        // Not a block invocation function.
+        assert_eq!(demangle_any("___block_invoke_x"), "__block_invoke_x");
+        assert_eq!(demangle_any("-[not a method"), "-[not a method");
+    }
+
+    #[test]
+    fn strip_arguments() {
+        assert_eq!(
+            strip_function_arguments("Namespace::Class::method(int, const Foo&)"),
+            "Namespace::Class::method"
+        );
+        assert_eq!(
+            strip_function_arguments("Foo::operator()(int) const &"),
+            "Foo::operator()"
+        );
+        assert_eq!(strip_function_arguments("f(int (*)(char))"), "f");
+        assert_eq!(strip_function_arguments("foo::bar()"), "foo::bar");
+        assert_eq!(
+            strip_function_arguments("core::fmt::Formatter::pad"),
+            "core::fmt::Formatter::pad"
+        );
+        assert_eq!(
+            strip_function_arguments("`foo(int)'::`2'::bar"),
+            "`foo(int)'::`2'::bar"
+        );
+        assert_eq!(strip_function_arguments("(anonymous)"), "(anonymous)");
+    }
+
+    #[test]
+    fn elide_templates() {
+        let name = "std::vector<std::basic_string<char, std::char_traits<char>>>::push_back(int)";
+        assert_eq!(
+            elide_template_args(name, 0),
+            "std::vector<...>::push_back(int)"
+        );
+        assert_eq!(
+            elide_template_args(name, 1),
+            "std::vector<std::basic_string<...>>::push_back(int)"
+        );
+        assert_eq!(elide_template_args(name, 5), name);
+        assert_eq!(
+            elide_template_args("Foo::operator<<(std::ostream&, Bar<int>)", 0),
+            "Foo::operator<<(std::ostream&, Bar<...>)"
+        );
+        assert_eq!(
+            elide_template_args("Foo<int>::operator->()", 0),
+            "Foo<...>::operator->()"
+        );
+        assert_eq!(
+            elide_template_args("cooperator<int>::run", 0),
+            "cooperator<...>::run"
+        );
+    }
+}
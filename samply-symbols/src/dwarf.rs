@@ -1,31 +1,82 @@
+use std::io::Read;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 use crate::path_mapper::PathMapper;
-use crate::shared::FrameDebugInfo;
+use crate::shared::{DwoRef, FrameDebugInfo};
 use crate::{demangle, Error, SourceFilePath};
 use addr2line::fallible_iterator;
 use addr2line::gimli;
-use elsa::sync::FrozenVec;
+use addr2line::LookupContinuation;
+use elsa::sync::{FrozenMap, FrozenVec};
 use fallible_iterator::FallibleIterator;
 use gimli::{EndianSlice, Reader, RunTimeEndian, SectionId};
 use object::read::ReadRef;
-use object::{CompressedFileRange, CompressionFormat};
+use object::{CompressedFileRange, CompressionFormat, File, SectionFlags};
 
+/// Returns the frames for `address`, innermost first.
+///
+/// If the address is in inlined code, there is one frame for each
+/// `DW_TAG_inlined_subroutine` which covers it, followed by the frame of the
+/// containing function. The innermost frame has the file and line of the address
+/// itself; every other frame has the call site of the next-inner frame, i.e. the
+/// `DW_AT_call_file` / `DW_AT_call_line` of the inlined subroutine.
 pub fn get_frames<R: Reader>(
     address: u64,
     context: Option<&addr2line::Context<R>>,
     path_mapper: &mut PathMapper<()>,
 ) -> Option<Vec<FrameDebugInfo>> {
-    let frame_iter = context?.find_frames(address).ok()?;
-    let frames: Vec<_> = frame_iter
+    get_frames_with_split_dwarf(address, context, |_| Ok(None), path_mapper)
+        .ok()
+        .flatten()
+}
+
+/// Like `get_frames`, but calls `load_split_dwarf` whenever the address is covered
+/// by a skeleton unit whose DWARF lives in a `.dwo` file or in a `.dwp` package.
+/// If `load_split_dwarf` returns `Ok(None)`, the frames only contain the information
+/// from the skeleton unit. If it returns `Err(dwo_ref)` because the `.dwo` file
+/// hasn't been loaded yet, the lookup is abandoned and `Err(dwo_ref)` is returned.
+/// Abandoning the lookup leaves the skeleton unit unresolved in the context, so
+/// the next lookup calls `load_split_dwarf` for it again.
+pub fn get_frames_with_split_dwarf<R: Reader>(
+    address: u64,
+    context: Option<&addr2line::Context<R>>,
+    load_split_dwarf: impl Fn(
+        &addr2line::SplitDwarfLoad<R>,
+    ) -> Result<Option<Arc<gimli::Dwarf<R>>>, DwoRef>,
+    path_mapper: &mut PathMapper<()>,
+) -> Result<Option<Vec<FrameDebugInfo>>, DwoRef> {
+    let context = match context {
+        Some(context) => context,
+        None => return Ok(None),
+    };
+    let mut lookup_result = context.find_frames(address);
+    let frame_iter = loop {
+        match lookup_result {
+            addr2line::LookupResult::Output(Ok(frame_iter)) => break frame_iter,
+            addr2line::LookupResult::Output(Err(_)) => return Ok(None),
+            addr2line::LookupResult::Load { load, continuation } => {
+                lookup_result = continuation.resume(load_split_dwarf(&load)?);
+            }
+        }
+    };
+    let mut frames: Vec<_> = match frame_iter
         .map(|f| Ok(convert_stack_frame(f, &mut *path_mapper)))
         .collect()
-        .ok()?;
+    {
+        Ok(frames) => frames,
+        Err(_) => return Ok(None),
+    };
+    if let Some((_outer, inlines)) = frames.split_last_mut() {
+        for frame in inlines {
+            frame.is_inlined = true;
+        }
+    }
 
     if frames.is_empty() {
-        None
+        Ok(None)
     } else {
-        Some(frames)
+        Ok(Some(frames))
     }
 }
 
@@ -52,6 +103,8 @@ pub fn convert_stack_frame<R: gimli::Reader>(
         function,
         file_path,
         line_number: frame.location.and_then(|l| l.line),
+        compiland: None,
+        is_inlined: false,
     }
 }
 
@@ -65,18 +118,38 @@ pub enum SingleSectionData<'data, T: ReadRef<'data>> {
     Owned(Vec<u8>),
 }
 
+/// Returns the data for the requested DWARF section, decompressing it if needed.
+///
+/// Returns `Ok(None)` if the section doesn't exist. Both zlib and zstd compressed
+/// sections are supported, in either the SHF_COMPRESSED form or the old-style
+/// `.zdebug_*` form. Returns an error if the section claims to be compressed but
+/// the compressed data cannot be decompressed to the advertised size.
 pub fn try_get_section_data<'data, 'file, O, T>(
     data: T,
     file: &'file O,
     section_id: SectionId,
-) -> Option<SingleSectionData<'data, T>>
+) -> Result<Option<SingleSectionData<'data, T>>, Error>
+where
+    'data: 'file,
+    O: object::Object<'data, 'file>,
+    T: ReadRef<'data>,
+{
+    try_get_section_data_by_name(data, file, section_id.name())
+}
+
+/// Like `try_get_section_data`, but takes a section name, for example the `.dwo`
+/// name of a section in a split DWARF file.
+fn try_get_section_data_by_name<'data, 'file, O, T>(
+    data: T,
+    file: &'file O,
+    section_name: &'static str,
+) -> Result<Option<SingleSectionData<'data, T>>, Error>
 where
     'data: 'file,
     O: object::Object<'data, 'file>,
     T: ReadRef<'data>,
 {
     use object::ObjectSection;
-    let section_name = section_id.name();
     let (section, used_manual_zdebug_path) =
         if let Some(section) = file.section_by_name(section_name) {
             (section, false)
@@ -87,17 +160,39 @@ where
             let mut name = Vec::with_capacity(section_name.len() + 1);
             name.extend_from_slice(b".zdebug_");
             name.extend_from_slice(&section_name.as_bytes()[7..]);
-            let section = file.section_by_name_bytes(&name)?;
-            (section, true)
+            match file.section_by_name_bytes(&name) {
+                Some(section) => (section, true),
+                None => return Ok(None),
+            }
         };
 
+    // object doesn't know about zstd compressed ELF sections yet and returns an
+    // error for them, so look for those before asking object for the file range.
+    if let Some((offset, compressed_size, uncompressed_size)) =
+        elf_zstd_compressed_range(data, file, &section)
+    {
+        let decompressed = read_and_decompress_section_data(
+            data,
+            offset,
+            compressed_size,
+            SectionCompression::Zstd,
+            uncompressed_size,
+        )
+        .map_err(|reason| Error::SectionDecompressionError(section_name, reason))?;
+        return Ok(Some(SingleSectionData::Owned(decompressed)));
+    }
+
     // Handle sections which are not compressed.
-    let mut file_range = section.compressed_file_range().ok()?;
+    let mut file_range = section
+        .compressed_file_range()
+        .map_err(|e| Error::SectionDecompressionError(section_name, e.to_string()))?;
     if file_range.format == CompressionFormat::None
         && used_manual_zdebug_path
         && file_range.uncompressed_size > 12
     {
-        let first_twelve = data.read_bytes_at(file_range.offset, 12).ok()?;
+        let first_twelve = data
+            .read_bytes_at(file_range.offset, 12)
+            .map_err(|_| Error::SectionDecompressionError(section_name, "truncated".into()))?;
         if first_twelve.starts_with(b"ZLIB\0\0\0\0") {
             // Object's built-in compressed section handling didn't detect this as a
             // compressed section. This happens on old Go binaries which use compressed
@@ -105,7 +200,7 @@ where
             // object's mach-O parser doesn't handle them.
             // But we want to handle them.
             // Go fixed this in https://github.com/golang/go/issues/50796 .
-            let b = first_twelve.get(8..12)?;
+            let b = &first_twelve[8..12];
             let uncompressed_size = u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
             file_range = CompressedFileRange {
                 format: CompressionFormat::Zlib,
@@ -116,32 +211,149 @@ where
         }
     }
 
-    match file_range.format {
-        CompressionFormat::None => Some(SingleSectionData::View {
-            data,
-            offset: file_range.offset,
-            size: file_range.uncompressed_size,
-            _phantom: PhantomData,
-        }),
-        CompressionFormat::Zlib => {
-            let compressed_bytes = data
-                .read_bytes_at(file_range.offset, file_range.compressed_size)
-                .ok()?;
+    let compression = match file_range.format {
+        CompressionFormat::None => {
+            return Ok(Some(SingleSectionData::View {
+                data,
+                offset: file_range.offset,
+                size: file_range.uncompressed_size,
+                _phantom: PhantomData,
+            }));
+        }
+        CompressionFormat::Zlib => SectionCompression::Zlib,
+        format => {
+            return Err(Error::SectionDecompressionError(
+                section_name,
+                format!("unsupported compression format {format:?}"),
+            ))
+        }
+    };
+    let decompressed = read_and_decompress_section_data(
+        data,
+        file_range.offset,
+        file_range.compressed_size,
+        compression,
+        file_range.uncompressed_size,
+    )
+    .map_err(|reason| Error::SectionDecompressionError(section_name, reason))?;
+    Ok(Some(SingleSectionData::Owned(decompressed)))
+}
+
+/// The compression formats of DWARF sections which we can decompress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SectionCompression {
+    Zlib,
+    Zstd,
+}
+
+/// `ch_type` of an ELF compression header for zstd compressed data.
+const ELFCOMPRESS_ZSTD: u32 = 2;
+
+/// Returns the offset, compressed size and uncompressed size of the data in an
+/// `SHF_COMPRESSED` ELF section which uses zstd, or `None` for all other sections.
+fn elf_zstd_compressed_range<'data, 'file, O, S, T>(
+    data: T,
+    file: &'file O,
+    section: &S,
+) -> Option<(u64, u64, u64)>
+where
+    'data: 'file,
+    O: object::Object<'data, 'file>,
+    S: object::ObjectSection<'data>,
+    T: ReadRef<'data>,
+{
+    use object::elf::{CompressionHeader32, CompressionHeader64, SHF_COMPRESSED};
+    use object::Endianness;
 
-            let mut decompressed = Vec::with_capacity(file_range.uncompressed_size as usize);
-            let mut decompress = flate2::Decompress::new(true);
-            decompress
-                .decompress_vec(
-                    compressed_bytes,
-                    &mut decompressed,
-                    flate2::FlushDecompress::Finish,
-                )
-                .ok()?;
+    match section.flags() {
+        SectionFlags::Elf { sh_flags } if sh_flags & u64::from(SHF_COMPRESSED) != 0 => {}
+        _ => return None,
+    }
+    let (offset, size) = section.file_range()?;
+    let endian = if file.is_little_endian() {
+        Endianness::Little
+    } else {
+        Endianness::Big
+    };
+    let (ch_type, uncompressed_size, header_size) = if file.is_64() {
+        let header: &CompressionHeader64<Endianness> = data.read_at(offset).ok()?;
+        let header_size = std::mem::size_of::<CompressionHeader64<Endianness>>();
+        (
+            header.ch_type.get(endian),
+            header.ch_size.get(endian),
+            header_size as u64,
+        )
+    } else {
+        let header: &CompressionHeader32<Endianness> = data.read_at(offset).ok()?;
+        let header_size = std::mem::size_of::<CompressionHeader32<Endianness>>();
+        (
+            header.ch_type.get(endian),
+            u64::from(header.ch_size.get(endian)),
+            header_size as u64,
+        )
+    };
+    if ch_type != ELFCOMPRESS_ZSTD {
+        return None;
+    }
+    Some((
+        offset + header_size,
+        size.checked_sub(header_size)?,
+        uncompressed_size,
+    ))
+}
 
-            return Some(SingleSectionData::Owned(decompressed));
+fn read_and_decompress_section_data<'data, T: ReadRef<'data>>(
+    data: T,
+    offset: u64,
+    compressed_size: u64,
+    compression: SectionCompression,
+    uncompressed_size: u64,
+) -> Result<Vec<u8>, String> {
+    let compressed_bytes = data
+        .read_bytes_at(offset, compressed_size)
+        .map_err(|_| "compressed data extends past the end of the file".to_string())?;
+    decompress_section_data(compressed_bytes, compression, uncompressed_size)
+}
+
+fn decompress_section_data(
+    compressed_bytes: &[u8],
+    compression: SectionCompression,
+    uncompressed_size: u64,
+) -> Result<Vec<u8>, String> {
+    let uncompressed_size = usize::try_from(uncompressed_size)
+        .map_err(|_| "uncompressed size is too large".to_string())?;
+    // Don't trust the uncompressed size from the header for the initial allocation.
+    // A bogus header could otherwise make us allocate huge amounts of memory.
+    let capacity = uncompressed_size.min(compressed_bytes.len().saturating_mul(32));
+    let mut decompressed = Vec::with_capacity(capacity);
+    match compression {
+        SectionCompression::Zlib => {
+            flate2::read::ZlibDecoder::new(compressed_bytes)
+                .take(uncompressed_size as u64)
+                .read_to_end(&mut decompressed)
+                .map_err(|e| format!("zlib: {e}"))?;
+        }
+        SectionCompression::Zstd => {
+            let mut input = compressed_bytes;
+            while !input.is_empty() && decompressed.len() < uncompressed_size {
+                let mut decoder =
+                    ruzstd::StreamingDecoder::new(&mut input).map_err(|e| format!("zstd: {e}"))?;
+                let remaining = (uncompressed_size - decompressed.len()) as u64;
+                (&mut decoder)
+                    .take(remaining)
+                    .read_to_end(&mut decompressed)
+                    .map_err(|e| format!("zstd: {e}"))?;
+            }
         }
-        _ => None,
     }
+    if decompressed.len() != uncompressed_size {
+        return Err(format!(
+            "expected {} bytes of uncompressed data, got {}",
+            uncompressed_size,
+            decompressed.len()
+        ));
+    }
+    Ok(decompressed)
 }
 
 /// Holds on to section data so that we can create an addr2line::Context for that
@@ -151,12 +363,17 @@ where
 /// See addr2line::Context::new for details.
 pub struct Addr2lineContextData {
     uncompressed_section_data: FrozenVec<Vec<u8>>,
+    /// The contents of the `.dwo` files which have been added with
+    /// `SplitDwarfFiles::add_dwo_file`, by path. `None` if the file could not be
+    /// loaded.
+    dwo_files: FrozenMap<String, Box<Option<Vec<u8>>>>,
 }
 
 impl Addr2lineContextData {
     pub fn new() -> Self {
         Self {
             uncompressed_section_data: FrozenVec::new(),
+            dwo_files: FrozenMap::new(),
         }
     }
 
@@ -164,9 +381,9 @@ impl Addr2lineContextData {
         &'ctxdata self,
         data: R,
         obj: &'file O,
-        section_id: SectionId,
+        section_name: Option<&'static str>,
         endian: RunTimeEndian,
-    ) -> EndianSlice<'ctxdata, RunTimeEndian>
+    ) -> Result<EndianSlice<'ctxdata, RunTimeEndian>, Error>
     where
         'data: 'file,
         'data: 'ctxdata,
@@ -174,7 +391,11 @@ impl Addr2lineContextData {
         O: object::Object<'data, 'file>,
         R: ReadRef<'data>,
     {
-        let slice: &[u8] = match try_get_section_data(data, obj, section_id) {
+        let section_name = match section_name {
+            Some(section_name) => section_name,
+            None => return Ok(EndianSlice::new(&[], endian)),
+        };
+        let slice: &[u8] = match try_get_section_data_by_name(data, obj, section_name)? {
             Some(SingleSectionData::Owned(section_data)) => {
                 self.uncompressed_section_data.push_get(section_data)
             }
@@ -183,7 +404,7 @@ impl Addr2lineContextData {
             }) => data.read_bytes_at(offset, size).unwrap_or(&[]),
             None => &[],
         };
-        EndianSlice::new(slice, endian)
+        Ok(EndianSlice::new(slice, endian))
     }
 
     pub fn make_context<'data, 'ctxdata, 'file, O, R>(
@@ -200,20 +421,331 @@ impl Addr2lineContextData {
         O: object::Object<'data, 'file>,
         R: ReadRef<'data>,
     {
-        let e = if obj.is_little_endian() {
-            gimli::RunTimeEndian::Little
-        } else {
-            gimli::RunTimeEndian::Big
-        };
-        let mut dwarf = gimli::Dwarf::load(|s| Ok(self.sect(data, obj, s, e)))
-            .map_err(Error::Addr2lineContextCreationError)?;
+        let e = endian_of(obj);
+        let mut dwarf = gimli::Dwarf::load(|s| self.sect(data, obj, Some(s.name()), e))?;
         if let (Some(sup_obj), Some(sup_data)) = (sup_obj, sup_data) {
-            dwarf
-                .load_sup(|s| Ok(self.sect(sup_data, sup_obj, s, e)))
-                .map_err(Error::Addr2lineContextCreationError)?;
+            dwarf.load_sup(|s| self.sect(sup_data, sup_obj, Some(s.name()), e))?;
         }
         let context =
             addr2line::Context::from_dwarf(dwarf).map_err(Error::Addr2lineContextCreationError)?;
         Ok(context)
     }
+
+    /// Prepares the split DWARF of a binary so that `SplitDwarfFiles::load` can hand
+    /// it to addr2line. `dwp_data` is the binary's DWARF package file, if there is
+    /// one. Without a package, the `.dwo` files are added one by one as they are
+    /// needed.
+    pub fn make_split_dwarf_files<'data, 'ctxdata, R>(
+        &'ctxdata self,
+        dwp_data: Option<R>,
+    ) -> SplitDwarfFiles<'ctxdata>
+    where
+        'data: 'ctxdata,
+        R: ReadRef<'data>,
+    {
+        let package = dwp_data.and_then(|dwp_data| self.load_dwarf_package(dwp_data).ok());
+        SplitDwarfFiles {
+            package,
+            context_data: self,
+        }
+    }
+
+    fn load_dwarf_package<'data, 'ctxdata, R>(
+        &'ctxdata self,
+        dwp_data: R,
+    ) -> Result<gimli::DwarfPackage<EndianSlice<'ctxdata, RunTimeEndian>>, Error>
+    where
+        'data: 'ctxdata,
+        R: ReadRef<'data>,
+    {
+        let dwp_obj =
+            File::parse(dwp_data).map_err(|e| Error::SplitDwarfParseError(e.to_string()))?;
+        let e = endian_of(&dwp_obj);
+        let empty = EndianSlice::new(&[], e);
+        gimli::DwarfPackage::load(
+            |s| {
+                let section = self.sect(dwp_data, &dwp_obj, s.dwo_name(), e);
+                let section = section.unwrap_or(empty);
+                // GNU dwp writes a .debug_tu_index without any slots if there
+                // are no type units, which gimli rejects. Treat it as absent.
+                if is_unit_index_without_units(section) {
+                    return Ok::<_, gimli::Error>(empty);
+                }
+                Ok(section)
+            },
+            empty,
+        )
+        .map_err(|e| Error::SplitDwarfParseError(e.to_string()))
+    }
+
+    /// Creates the `gimli::Dwarf` for the split unit in the `.dwo` file `data`.
+    /// Returns `None` if the file can't be parsed or if it belongs to a different
+    /// build, i.e. if its DWO ID doesn't match the one in the skeleton unit.
+    fn make_dwo_dwarf<'ctxdata>(
+        &'ctxdata self,
+        data: &'ctxdata [u8],
+        load: &addr2line::SplitDwarfLoad<EndianSlice<'ctxdata, RunTimeEndian>>,
+    ) -> Option<gimli::Dwarf<EndianSlice<'ctxdata, RunTimeEndian>>> {
+        let dwo_obj = File::parse(data).ok()?;
+        let e = endian_of(&dwo_obj);
+        let mut dwarf = gimli::Dwarf::load(|s| self.sect(data, &dwo_obj, s.dwo_name(), e)).ok()?;
+        dwarf.make_dwo(&load.parent);
+        let header = dwarf.units().next().ok()??;
+        let unit = dwarf.unit(header).ok()?;
+        if unit.dwo_id != Some(load.dwo_id) {
+            return None;
+        }
+        Some(dwarf)
+    }
+}
+
+fn endian_of<'data, 'file, O>(obj: &'file O) -> RunTimeEndian
+where
+    'data: 'file,
+    O: object::Object<'data, 'file>,
+{
+    if obj.is_little_endian() {
+        RunTimeEndian::Little
+    } else {
+        RunTimeEndian::Big
+    }
+}
+
+/// Returns whether `section` is a `.debug_cu_index` or `.debug_tu_index` section
+/// whose header says that it contains no units.
+fn is_unit_index_without_units(section: EndianSlice<RunTimeEndian>) -> bool {
+    // The header is the version, the section count, the unit count and the slot
+    // count, each as a u32 in DWARF 4; DWARF 5 splits the version into a u16 and
+    // u16 padding.
+    let mut header = section;
+    match header.skip(8).and_then(|()| header.read_u32()) {
+        Ok(unit_count) => unit_count == 0,
+        Err(_) => false,
+    }
+}
+
+/// The split DWARF of a binary, ready to be handed to addr2line when it encounters
+/// a skeleton unit.
+pub struct SplitDwarfFiles<'a> {
+    package: Option<gimli::DwarfPackage<EndianSlice<'a, RunTimeEndian>>>,
+    context_data: &'a Addr2lineContextData,
+}
+
+impl<'a> SplitDwarfFiles<'a> {
+    /// Returns the split unit requested by `load`. If the binary has a package, the
+    /// unit is taken from the package. Otherwise it's taken from the `.dwo` file
+    /// that the skeleton unit refers to; if that file hasn't been added with
+    /// `add_dwo_file` yet, this returns a `DwoRef` for it.
+    pub fn load(
+        &self,
+        load: &addr2line::SplitDwarfLoad<EndianSlice<'a, RunTimeEndian>>,
+    ) -> Result<Option<Arc<gimli::Dwarf<EndianSlice<'a, RunTimeEndian>>>>, DwoRef> {
+        if let Some(package) = &self.package {
+            let dwarf = package.find_cu(load.dwo_id, &load.parent).ok().flatten();
+            return Ok(dwarf.map(Arc::new));
+        }
+        let path = load
+            .path
+            .and_then(|path| dwo_path(load.comp_dir.map(|d| d.slice()), path.slice()));
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let data = match self.context_data.dwo_files.get(&path) {
+            Some(Some(data)) => data,
+            Some(None) => return Ok(None),
+            None => {
+                return Err(DwoRef {
+                    path,
+                    dwo_id: load.dwo_id.0,
+                })
+            }
+        };
+        Ok(self.context_data.make_dwo_dwarf(data, load).map(Arc::new))
+    }
+
+    /// Adds the contents of the `.dwo` file at `dwo_path`, or records that it could
+    /// not be loaded if `data` is `None`. Only the first call for each path has an
+    /// effect.
+    pub fn add_dwo_file(&self, dwo_path: &str, data: Option<Vec<u8>>) {
+        if self.package.is_none() {
+            self.context_data
+                .dwo_files
+                .insert(dwo_path.to_owned(), Box::new(data));
+        }
+    }
+}
+
+/// Checks that the DWARF package file `dwp_data` contains the split units for all
+/// of `dwo_refs`, i.e. that it was created from the `.dwo` files of the same build.
+pub fn verify_dwp<'data, R: ReadRef<'data>>(dwp_data: R, dwo_refs: &[DwoRef]) -> Result<(), Error> {
+    let context_data = Addr2lineContextData::new();
+    let package = context_data.load_dwarf_package(dwp_data)?;
+    let mut missing_dwo_ids: Vec<u64> = dwo_refs
+        .iter()
+        .map(|dwo_ref| dwo_ref.dwo_id)
+        .filter(|dwo_id| package.cu_index.find(*dwo_id).is_none())
+        .collect();
+    if missing_dwo_ids.is_empty() {
+        return Ok(());
+    }
+    missing_dwo_ids.sort_unstable();
+    missing_dwo_ids.dedup();
+    Err(Error::DwoIdsNotInDwp(missing_dwo_ids))
+}
+
+/// Checks that the `.dwo` file `data` contains the split unit for `dwo_ref`, i.e.
+/// that its DWO ID matches the one in the skeleton unit.
+pub fn verify_dwo_file<'data, R: ReadRef<'data>>(data: R, dwo_ref: &DwoRef) -> Result<(), Error> {
+    let context_data = Addr2lineContextData::new();
+    let dwo_obj = File::parse(data).map_err(|e| Error::SplitDwarfParseError(e.to_string()))?;
+    let e = endian_of(&dwo_obj);
+    let mut dwarf = gimli::Dwarf::load(|s| context_data.sect(data, &dwo_obj, s.dwo_name(), e))?;
+    dwarf.file_type = gimli::DwarfFileType::Dwo;
+    let parse_error = |e: gimli::Error| Error::SplitDwarfParseError(e.to_string());
+    let dwo_id = match dwarf.units().next().map_err(parse_error)? {
+        Some(header) => dwarf.unit(header).map_err(parse_error)?.dwo_id,
+        None => None,
+    };
+    match dwo_id {
+        Some(dwo_id) if dwo_id.0 == dwo_ref.dwo_id => Ok(()),
+        dwo_id => Err(Error::UnmatchedDwoId(
+            dwo_ref.dwo_id,
+            dwo_id.map(|dwo_id| dwo_id.0),
+        )),
+    }
+}
+
+/// Returns the `.dwo` files referenced by the skeleton units in `obj`. Relative
+/// `DW_AT_dwo_name` paths are resolved against `DW_AT_comp_dir`.
+pub fn get_dwo_refs<'data, 'file, O, R>(data: R, obj: &'file O) -> Vec<DwoRef>
+where
+    'data: 'file,
+    O: object::Object<'data, 'file>,
+    R: ReadRef<'data>,
+{
+    // Skeleton units always come with a .debug_addr section. Don't bother iterating
+    // over all units in binaries without one.
+    if obj.section_by_name(".debug_addr").is_none() {
+        return Vec::new();
+    }
+
+    let e = endian_of(obj);
+    let owned_section_data: FrozenVec<Vec<u8>> = FrozenVec::new();
+    let dwarf = gimli::Dwarf::load(|s| {
+        let slice: &[u8] = match try_get_section_data(data, obj, s)? {
+            Some(SingleSectionData::Owned(section_data)) => {
+                owned_section_data.push_get(section_data)
+            }
+            Some(SingleSectionData::View {
+                data, offset, size, ..
+            }) => data.read_bytes_at(offset, size).unwrap_or(&[]),
+            None => &[],
+        };
+        Ok::<_, Error>(EndianSlice::new(slice, e))
+    });
+    let dwarf = match dwarf {
+        Ok(dwarf) => dwarf,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut dwo_refs = Vec::new();
+    let mut units = dwarf.units();
+    while let Ok(Some(header)) = units.next() {
+        let unit = match dwarf.unit(header) {
+            Ok(unit) => unit,
+            Err(_) => continue,
+        };
+        let dwo_id = match unit.dwo_id {
+            Some(dwo_id) => dwo_id,
+            None => continue,
+        };
+        let dwo_name = match unit.dwo_name() {
+            Ok(Some(dwo_name)) => dwo_name,
+            _ => continue,
+        };
+        let dwo_name = match dwarf.attr_string(&unit, dwo_name) {
+            Ok(dwo_name) => dwo_name,
+            Err(_) => continue,
+        };
+        if let Some(path) = dwo_path(unit.comp_dir.map(|d| d.slice()), dwo_name.slice()) {
+            dwo_refs.push(DwoRef {
+                path,
+                dwo_id: dwo_id.0,
+            });
+        }
+    }
+    dwo_refs.sort_unstable();
+    dwo_refs.dedup();
+    dwo_refs
+}
+
+fn dwo_path(comp_dir: Option<&[u8]>, dwo_name: &[u8]) -> Option<String> {
+    let dwo_name = std::str::from_utf8(dwo_name).ok()?;
+    match comp_dir {
+        Some(comp_dir) if !dwo_name.starts_with('/') => {
+            let comp_dir = std::str::from_utf8(comp_dir).ok()?;
+            Some(format!("{}/{}", comp_dir.trim_end_matches('/'), dwo_name))
+        }
+        _ => Some(dwo_name.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decompress_zlib_section() {
+        use std::io::Write;
+        let uncompressed = b"some .debug_line data, some .debug_line data".repeat(10);
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&uncompressed).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let size = uncompressed.len() as u64;
+        assert_eq!(
+            decompress_section_data(&compressed, SectionCompression::Zlib, size).unwrap(),
+            uncompressed
+        );
+
+        // A header which claims a larger uncompressed size than what's there is an error.
+        assert!(decompress_section_data(&compressed, SectionCompression::Zlib, size + 1).is_err());
+    }
+
+    #[test]
+    fn decompress_zstd_section() {
+        // A zstd frame with a single raw block: magic number, a frame header with the
+        // single segment flag and a one-byte content size, then the block header
+        // (last block, raw, size 20) and the block contents.
+        let uncompressed = b".debug_info contents";
+        let mut compressed = vec![0x28, 0xb5, 0x2f, 0xfd, 0x20, 20];
+        compressed.extend_from_slice(&(1u32 | (20 << 3)).to_le_bytes()[..3]);
+        compressed.extend_from_slice(uncompressed);
+        assert_eq!(
+            decompress_section_data(&compressed, SectionCompression::Zstd, 20).unwrap(),
+            uncompressed
+        );
+        assert!(decompress_section_data(&compressed, SectionCompression::Zstd, 21).is_err());
+    }
+
+    #[test]
+    fn dwo_paths() {
+        assert_eq!(
+            dwo_path(Some(b"/build/out/"), b"obj/foo.dwo").as_deref(),
+            Some("/build/out/obj/foo.dwo")
+        );
+        assert_eq!(
+            dwo_path(Some(b"/build/out"), b"/abs/foo.dwo").as_deref(),
+            Some("/abs/foo.dwo")
+        );
+        assert_eq!(dwo_path(None, b"foo.dwo").as_deref(), Some("foo.dwo"));
+    }
+
+    #[test]
+    fn decompress_bogus_section() {
+        let garbage = b"this is not compressed data";
+        assert!(decompress_section_data(garbage, SectionCompression::Zlib, 1000).is_err());
+        assert!(decompress_section_data(garbage, SectionCompression::Zstd, 1000).is_err());
+    }
 }
@@ -1,12 +1,12 @@
 use crate::error::Error;
-use crate::shared::{FileContents, FileContentsWrapper};
+use crate::shared::{relative_address_base, FileContents, FileContentsWrapper};
 use crate::symbol_map::{
     GenericSymbolMap, SymbolMap, SymbolMapDataMidTrait, SymbolMapDataOuterTrait,
 };
 use crate::symbol_map_object::{FunctionAddressesComputer, ObjectSymbolMapDataMid};
-use crate::{debug_id_for_object, ElfBuildId, FileAndPathHelper, FileLocation};
+use crate::{debug_id_for_object, CodeId, ElfBuildId, FileAndPathHelper, FileLocation};
 use debugid::DebugId;
-use gimli::{CieOrFde, EhFrame, UnwindSection};
+use gimli::{CieOrFde, DebugFrame, EhFrame, UnwindOffset, UnwindSection};
 use object::{File, FileKind, Object, ObjectSection, ReadRef};
 use std::io::Cursor;
 
@@ -30,10 +30,13 @@ where
         return Ok(symbol_map);
     }
 
+    let dwp_file_data = try_to_load_dwp_file(&file_location, &elf_file, helper).await;
+
     if let Some(supplementary_file) =
         try_to_load_supplementary_file(&file_location, &elf_file, helper).await
     {
-        let owner = ElfSymbolMapData::new(file_contents, Some(supplementary_file), file_kind, None);
+        let owner = ElfSymbolMapData::new(file_contents, Some(supplementary_file), file_kind, None)
+            .with_dwp_file_data(dwp_file_data);
         let symbol_map = GenericSymbolMap::new(owner)?;
         return Ok(SymbolMap::new(file_location, Box::new(symbol_map)));
     }
@@ -45,7 +48,8 @@ where
         return Ok(symbol_map);
     }
 
-    let owner = ElfSymbolMapData::new(file_contents, None, file_kind, None);
+    let owner = ElfSymbolMapData::new(file_contents, None, file_kind, None)
+        .with_dwp_file_data(dwp_file_data);
     let symbol_map = GenericSymbolMap::new(owner)?;
     Ok(SymbolMap::new(file_location, Box::new(symbol_map)))
 }
@@ -63,6 +67,11 @@ where
 {
     let (name, crc) = elf_file.gnu_debuglink().ok().flatten()?;
     let debug_id = debug_id_for_object(elf_file)?;
+    let build_id = elf_file
+        .build_id()
+        .ok()
+        .flatten()
+        .map(ElfBuildId::from_bytes);
     let name = std::str::from_utf8(name).ok()?;
     let candidate_paths = helper
         .get_candidate_paths_for_gnu_debug_link_dest(original_file_location, name)
@@ -73,6 +82,7 @@ where
             original_file_location,
             &candidate_path,
             debug_id,
+            build_id.as_ref(),
             crc,
             file_kind,
             helper,
@@ -90,6 +100,7 @@ async fn get_symbol_map_for_debug_link_candidate<'h, H, FL>(
     original_file_location: &FL,
     path: &FL,
     debug_id: DebugId,
+    expected_build_id: Option<&ElfBuildId>,
     expected_crc: u32,
     file_kind: FileKind,
     helper: &'h H,
@@ -109,7 +120,27 @@ where
         return Err(Error::DebugLinkCrcMismatch(actual_crc, expected_crc));
     }
 
-    let owner = ElfSymbolMapData::new(file_contents, None, file_kind, Some(debug_id));
+    // If both files have a build ID, make sure that they match. The CRC only tells
+    // us that the debug file is intact, not that it was produced by the same build.
+    if let Some(expected_build_id) = expected_build_id {
+        let debug_file =
+            File::parse(&file_contents).map_err(|e| Error::ObjectParseError(file_kind, e))?;
+        if let Some(actual_build_id) = debug_file.build_id().ok().flatten() {
+            if actual_build_id != expected_build_id.0.as_slice() {
+                return Err(Error::UnmatchedCodeId(
+                    CodeId::ElfBuildId(expected_build_id.clone()),
+                    Some(CodeId::ElfBuildId(ElfBuildId::from_bytes(actual_build_id))),
+                ));
+            }
+        }
+    }
+
+    let dwp_file_data = match File::parse(&file_contents) {
+        Ok(debug_file) => try_to_load_dwp_file(original_file_location, &debug_file, helper).await,
+        Err(_) => None,
+    };
+    let owner = ElfSymbolMapData::new(file_contents, None, file_kind, Some(debug_id))
+        .with_dwp_file_data(dwp_file_data);
     let symbol_map = GenericSymbolMap::new(owner)?;
     Ok(SymbolMap::new(
         original_file_location.clone(),
@@ -254,6 +285,28 @@ where
     None
 }
 
+/// If the binary may have been compiled with `-gsplit-dwarf`, loads its DWARF
+/// package file (`.dwp`), if there is one. Without a package, the `.dwo` files
+/// which the skeleton units refer to are loaded on demand, see
+/// `FramesLookupResult::NeedDwo`.
+async fn try_to_load_dwp_file<'h, 'data, H, F, R>(
+    original_file_location: &H::FL,
+    elf_file: &File<'data, R>,
+    helper: &'h H,
+) -> Option<FileContentsWrapper<F>>
+where
+    H: FileAndPathHelper<'h, F = F>,
+    R: ReadRef<'data>,
+    F: FileContents + 'static,
+{
+    // Skeleton units always come with a .debug_addr section. Checking for the
+    // section is cheaper than iterating over all units to find skeleton units.
+    elf_file.section_by_name(".debug_addr")?;
+    let dwp_location = original_file_location.location_for_dwp()?;
+    let file_contents = helper.load_file(dwp_location).await.ok()?;
+    Some(FileContentsWrapper::new(file_contents))
+}
+
 fn try_get_symbol_map_from_mini_debug_info<'data, R: ReadRef<'data>, FL: FileLocation>(
     elf_file: &File<'data, R>,
     file_kind: FileKind,
@@ -279,6 +332,7 @@ where
 {
     file_data: FileContentsWrapper<T>,
     supplementary_file_data: Option<FileContentsWrapper<T>>,
+    dwp_file_data: Option<FileContentsWrapper<T>>,
     file_kind: FileKind,
     override_debug_id: Option<DebugId>,
 }
@@ -293,10 +347,16 @@ impl<T: FileContents> ElfSymbolMapData<T> {
         Self {
             file_data,
             supplementary_file_data,
+            dwp_file_data: None,
             file_kind,
             override_debug_id,
         }
     }
+
+    pub fn with_dwp_file_data(mut self, dwp_file_data: Option<FileContentsWrapper<T>>) -> Self {
+        self.dwp_file_data = dwp_file_data;
+        self
+    }
 }
 
 impl<T: FileContents + 'static> SymbolMapDataOuterTrait for ElfSymbolMapData<T> {
@@ -324,7 +384,8 @@ impl<T: FileContents + 'static> SymbolMapDataOuterTrait for ElfSymbolMapData<T>
             self.supplementary_file_data.as_ref(),
             None,
             debug_id,
-        );
+        )
+        .with_dwp_file_data(self.dwp_file_data.as_ref());
 
         Ok(Box::new(object))
     }
@@ -344,13 +405,11 @@ impl<'data> FunctionAddressesComputer<'data> for ElfFunctionAddressesComputer {
         // Get an approximation of the list of function start addresses by
         // iterating over the exception handling info. Every FDE roughly
         // maps to one function.
-        // This currently only covers the ELF format. For mach-O, this information is
-        // not in .eh_frame, it is in __unwind_info (plus some auxiliary data
-        // in __eh_frame, but that's only needed for the actual unwinding, not
-        // for the function start addresses).
-        // We also don't handle .debug_frame yet, which is sometimes found
-        // instead of .eh_frame.
-        // And we don't have anything for the PE format yet, either.
+        // This information is usually in .eh_frame. Some binaries, for example
+        // binaries compiled with -fno-asynchronous-unwind-tables, only have
+        // .debug_frame, which has the same format, so we use that as a fallback.
+        // For mach-O and PE, the equivalent information comes from __unwind_info
+        // and .pdata, see MachOFunctionAddressesComputer and PeFunctionAddressesComputer.
 
         let eh_frame = object_file.section_by_name(".eh_frame");
         let eh_frame_hdr = object_file.section_by_name(".eh_frame_hdr");
@@ -381,44 +440,83 @@ impl<'data> FunctionAddressesComputer<'data> for ElfFunctionAddressesComputer {
             .address_size()
             .unwrap_or(object::AddressSize::U64) as u8;
 
-        let eh_frame = match eh_frame {
-            Some(eh_frame) => eh_frame,
-            None => return (None, None),
-        };
+        // The FDE addresses are SVMAs. Convert them to relative addresses.
+        let base_address = relative_address_base(object_file);
+
+        // Linkers often emit an empty .eh_frame section even if no object file
+        // had one, so an empty .eh_frame doesn't prevent the fallback.
+        if let Some(eh_frame_data) = eh_frame
+            .and_then(|s| s.uncompressed_data().ok())
+            .filter(|data| !data.is_empty())
+        {
+            let mut eh_frame = EhFrame::new(&eh_frame_data, endian);
+            eh_frame.set_address_size(address_size);
+            let (start_addresses, end_addresses) =
+                function_addresses_from_fdes(eh_frame, &bases, base_address);
+            return (Some(start_addresses), Some(end_addresses));
+        }
 
-        let eh_frame_data = match eh_frame.uncompressed_data() {
-            Ok(eh_frame_data) => eh_frame_data,
-            Err(_) => return (None, None),
-        };
+        // On 32-bit ARM, the symbol table has the addresses of Thumb functions with
+        // the lowest bit set, but .debug_frame has them without it. Mixing the two
+        // would cut off the last byte of every Thumb function, so we don't use
+        // .debug_frame on ARM.
+        if let Some(debug_frame_data) = object_file
+            .section_by_name(".debug_frame")
+            .filter(|_| object_file.architecture() != object::Architecture::Arm)
+            .and_then(|s| s.uncompressed_data().ok())
+        {
+            let mut debug_frame = DebugFrame::new(&debug_frame_data, endian);
+            debug_frame.set_address_size(address_size);
+            let (start_addresses, end_addresses) =
+                function_addresses_from_fdes(debug_frame, &bases, base_address);
+            return (Some(start_addresses), Some(end_addresses));
+        }
 
-        let mut eh_frame = EhFrame::new(&eh_frame_data, endian);
-        eh_frame.set_address_size(address_size);
-        let mut cur_cie = None;
-        let mut entries_iter = eh_frame.entries(&bases);
-        let mut start_addresses = Vec::new();
-        let mut end_addresses = Vec::new();
-        while let Ok(Some(entry)) = entries_iter.next() {
-            match entry {
-                CieOrFde::Cie(cie) => cur_cie = Some(cie),
-                CieOrFde::Fde(partial_fde) => {
-                    if let Ok(fde) = partial_fde.parse(|eh_frame, bases, cie_offset| {
-                        if let Some(cie) = &cur_cie {
-                            if cie.offset() == cie_offset.0 {
-                                return Ok(cie.clone());
-                            }
+        (None, None)
+    }
+}
+
+/// Returns the start and end addresses of the FDEs in `section`, relative to
+/// `base_address`. FDEs outside the 32-bit relative address range are skipped.
+fn function_addresses_from_fdes<R: gimli::Reader, S: UnwindSection<R>>(
+    section: S,
+    bases: &gimli::BaseAddresses,
+    base_address: u64,
+) -> (Vec<u32>, Vec<u32>) {
+    let mut cur_cie = None;
+    let mut entries_iter = section.entries(bases);
+    let mut start_addresses = Vec::new();
+    let mut end_addresses = Vec::new();
+    while let Ok(Some(entry)) = entries_iter.next() {
+        match entry {
+            CieOrFde::Cie(cie) => cur_cie = Some(cie),
+            CieOrFde::Fde(partial_fde) => {
+                if let Ok(fde) = partial_fde.parse(|section, bases, cie_offset| {
+                    if let Some(cie) = &cur_cie {
+                        if cie.offset() == UnwindOffset::into(cie_offset) {
+                            return Ok(cie.clone());
                         }
-                        let cie = eh_frame.cie_from_offset(bases, cie_offset);
-                        if let Ok(cie) = &cie {
-                            cur_cie = Some(cie.clone());
+                    }
+                    let cie = section.cie_from_offset(bases, cie_offset);
+                    if let Ok(cie) = &cie {
+                        cur_cie = Some(cie.clone());
+                    }
+                    cie
+                }) {
+                    let start = fde.initial_address().checked_sub(base_address);
+                    let end = fde
+                        .initial_address()
+                        .checked_add(fde.len())
+                        .and_then(|end| end.checked_sub(base_address));
+                    if let (Some(start), Some(end)) = (start, end) {
+                        if let (Ok(start), Ok(end)) = (u32::try_from(start), u32::try_from(end)) {
+                            start_addresses.push(start);
+                            end_addresses.push(end);
                         }
-                        cie
-                    }) {
-                        start_addresses.push(fde.initial_address() as u32);
-                        end_addresses.push((fde.initial_address() + fde.len()) as u32);
                     }
                 }
             }
         }
-        (Some(start_addresses), Some(end_addresses))
     }
+    (start_addresses, end_addresses)
 }
@@ -41,6 +41,14 @@ pub enum Error {
     #[error("External file has an unexpected FileKind: {0:?}")]
     UnexpectedExternalFileFileKind(FileKind),
 
+    #[error("The external file {0} does not define the symbol {1}, so it is not the file which the binary was linked with")]
+    ExternalFileDoesNotDefineSymbol(String, String),
+
+    #[error(
+        "The external file was not loaded because the budget for external files was exceeded: {0}"
+    )]
+    ExternalFileBudgetExceeded(String),
+
     #[error("Not enough information was supplied to identify the requested symbol map. The debug ID is required.")]
     NotEnoughInformationToIdentifySymbolMap,
 
@@ -58,6 +66,9 @@ pub enum Error {
     #[error("The FileLocation for the debug file does not support loading source files.")]
     FileLocationRefusedSourceFileLocation,
 
+    #[error("The FileLocation for the debug file does not support loading .dwo files.")]
+    FileLocationRefusedDwoLocation,
+
     #[error(
         "No disambiguator supplied for universal binary, available images: {}", format_multiarch_members(.0)
     )]
@@ -75,6 +86,9 @@ pub enum Error {
     #[error("CRC mismatch on file found via GNU debug link, got {0}, expected {1}")]
     DebugLinkCrcMismatch(u32, u32),
 
+    #[error("The PDB file was linked with /DEBUG:FASTLINK and does not contain full type information; use a PDB linked with /DEBUG:FULL")]
+    FastLinkPdbUnsupported,
+
     #[error("PDB error: {1} ({0})")]
     PdbError(&'static str, PdbError),
 
@@ -96,6 +110,15 @@ pub enum Error {
     #[error("MachOHeader parsing error: {0}")]
     MachOHeaderParseError(#[source] object::read::Error),
 
+    #[error("The file is truncated: its header and load commands need {expected} bytes, but the file only has {actual} bytes")]
+    TruncatedFile { expected: u64, actual: u64 },
+
+    #[error("Expected a mach-O file, but the file kind is {0:?}")]
+    UnexpectedFileKind(FileKind),
+
+    #[error("The {0:?} file does not contain a UUID, build ID or debug ID")]
+    NoSymbolFileId(FileKind),
+
     #[error("get_candidate_paths_for_debug_file helper callback for {0:?} returned error: {1}")]
     HelperErrorDuringGetCandidatePathsForDebugFile(
         Box<LibraryInfo>,
@@ -108,6 +131,14 @@ pub enum Error {
     #[error("get_dyld_shared_cache_paths helper callback returned error: {0}")]
     HelperErrorDuringGetDyldSharedCachePaths(#[source] Box<dyn std::error::Error + Send + Sync>),
 
+    #[error(
+        "get_candidate_paths_for_external_object_file helper callback for {0} returned error: {1}"
+    )]
+    HelperErrorDuringGetCandidatePathsForExternalObjectFile(
+        String,
+        #[source] Box<dyn std::error::Error + Send + Sync>,
+    ),
+
     #[error("open_file helper callback for file {0} returned error: {1}")]
     HelperErrorDuringOpenFile(String, #[source] Box<dyn std::error::Error + Send + Sync>),
 
@@ -167,6 +198,18 @@ pub enum Error {
 
     #[error("Could not create addr2line Context: {0}")]
     Addr2lineContextCreationError(#[source] gimli::Error),
+
+    #[error("Could not decompress the {0} section: {1}")]
+    SectionDecompressionError(&'static str, String),
+
+    #[error("Could not parse the split DWARF file: {0}")]
+    SplitDwarfParseError(String),
+
+    #[error("Unmatched DWO ID: Expected {0:#x}, but received {}", .1.map_or("<none>".into(), |id| format!("{id:#x}")))]
+    UnmatchedDwoId(u64, Option<u64>),
+
+    #[error("The .dwp file does not contain the split units with the DWO IDs {}", format_dwo_ids(.0))]
+    DwoIdsNotInDwp(Vec<u64>),
 }
 
 fn format_errors(errors: &[Error]) -> String {
@@ -177,6 +220,14 @@ fn format_errors(errors: &[Error]) -> String {
         .join(", ")
 }
 
+fn format_dwo_ids(dwo_ids: &[u64]) -> String {
+    dwo_ids
+        .iter()
+        .map(|id| format!("{id:#x}"))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
 fn format_multiarch_members(members: &[FatArchiveMember]) -> String {
     members
         .iter()
@@ -244,9 +295,13 @@ impl Error {
             }
             Error::FileLocationRefusedPdbLocation => "FileLocationRefusedPdbLocation",
             Error::FileLocationRefusedSourceFileLocation => "FileLocationRefusedSourceFileLocation",
+            Error::FileLocationRefusedDwoLocation => "FileLocationRefusedDwoLocation",
             Error::UnexpectedExternalFileFileKind(_) => "UnexpectedExternalFileFileKind",
+            Error::ExternalFileDoesNotDefineSymbol(_, _) => "ExternalFileDoesNotDefineSymbol",
+            Error::ExternalFileBudgetExceeded(_) => "ExternalFileBudgetExceeded",
             Error::NoMatchMultiArch(_) => "NoMatchMultiArch",
             Error::NoLuckMacOsSystemLibrary(_) => "NoLuckMacOsSystemLibrary",
+            Error::FastLinkPdbUnsupported => "FastLinkPdbUnsupported",
             Error::PdbError(_, _) => "PdbError",
             Error::PdbAddr2lineErrorWithContext(_, _) => "PdbAddr2lineErrorWithContext",
             Error::InvalidInputError(_) => "InvalidInputError",
@@ -254,6 +309,9 @@ impl Error {
             Error::NoMatchingDyldCacheImagePath(_) => "NoMatchingDyldCacheImagePath",
             Error::ObjectParseError(_, _) => "ObjectParseError",
             Error::MachOHeaderParseError(_) => "MachOHeaderParseError",
+            Error::TruncatedFile { .. } => "TruncatedFile",
+            Error::UnexpectedFileKind(_) => "UnexpectedFileKind",
+            Error::NoSymbolFileId(_) => "NoSymbolFileId",
             Error::HelperErrorDuringGetCandidatePathsForDebugFile(_, _) => {
                 "HelperErrorDuringGetCandidatePathsForDebugFile"
             }
@@ -263,6 +321,9 @@ impl Error {
             Error::HelperErrorDuringGetDyldSharedCachePaths(_) => {
                 "HelperErrorDuringGetDyldSharedCachePaths"
             }
+            Error::HelperErrorDuringGetCandidatePathsForExternalObjectFile(_, _) => {
+                "HelperErrorDuringGetCandidatePathsForExternalObjectFile"
+            }
             Error::HelperErrorDuringOpenFile(_, _) => "HelperErrorDuringOpenFile",
             Error::HelperErrorDuringFileReading(_, _) => "HelperErrorDuringFileReading",
             Error::JitDumpFileReading(_) => "JitDumpFileReading",
@@ -279,6 +340,10 @@ impl Error {
             Error::SrcSrvParseError(_) => "SrcSrvParseError",
             Error::SrcSrvEvalError(_) => "SrcSrvEvalError",
             Error::Addr2lineContextCreationError(_) => "Addr2lineContextCreationError",
+            Error::SectionDecompressionError(_, _) => "SectionDecompressionError",
+            Error::SplitDwarfParseError(_) => "SplitDwarfParseError",
+            Error::UnmatchedDwoId(_, _) => "UnmatchedDwoId",
+            Error::DwoIdsNotInDwp(_) => "DwoIdsNotInDwp",
         }
     }
 }
@@ -7,7 +7,11 @@ use crate::{
     dwarf::{get_frames, Addr2lineContextData},
     macho,
     path_mapper::PathMapper,
-    shared::{ExternalFileAddressInFileRef, ExternalFileRef, FileContentsWrapper, RangeReadRef},
+    shared::{
+        ExternalFileAddressInFileRef, ExternalFileIdentity, ExternalFileRef, FileContentsWrapper,
+        RangeReadRef,
+    },
+    symbol_map::truncate_inline_frames,
     Error, FileAndPathHelper, FileContents, FileLocation, FrameDebugInfo, MultiArchDisambiguator,
 };
 
@@ -19,20 +23,52 @@ pub async fn load_external_file<'h, H>(
 where
     H: FileAndPathHelper<'h>,
 {
-    let file = helper
-        .load_file(
-            original_file_location
-                .location_for_external_object_file(&external_file_ref.file_name)
-                .ok_or(Error::FileLocationRefusedExternalObjectLocation)?,
-        )
-        .await
-        .map_err(|e| Error::HelperErrorDuringOpenFile(external_file_ref.file_name.clone(), e))?;
-    let symbol_map = ExternalFileSymbolMapImpl::new(
-        &external_file_ref.file_name,
-        file,
-        external_file_ref.arch.as_deref(),
-    )?;
-    Ok(ExternalFileSymbolMap(Box::new(symbol_map)))
+    // Object paths in the object map often contain `..` components, e.g.
+    // `/build/obj/js/src/../../../js/src/jsapi.o`. Collapse them so that the path
+    // can be matched against path remapping rules in the helper.
+    let normalized_file_name = normalize_path_lexically(&external_file_ref.file_name);
+    let mut candidate_locations: Vec<H::FL> = original_file_location
+        .location_for_external_object_file(&normalized_file_name)
+        .into_iter()
+        .collect();
+    let fallback_locations = helper
+        .get_candidate_paths_for_external_object_file(original_file_location, &normalized_file_name)
+        .map_err(|e| {
+            Error::HelperErrorDuringGetCandidatePathsForExternalObjectFile(
+                external_file_ref.file_name.clone(),
+                e,
+            )
+        })?;
+    candidate_locations.extend(fallback_locations);
+
+    // Try the candidates in order, and use the first one which is the file that the
+    // binary was linked with.
+    let mut last_err = Error::FileLocationRefusedExternalObjectLocation;
+    for location in candidate_locations {
+        let location_string = location.to_string();
+        let result = match helper.load_file(location).await {
+            Ok(file) => ExternalFileSymbolMapImpl::new(
+                &external_file_ref.file_name,
+                file,
+                external_file_ref.arch.as_deref(),
+            )
+            .and_then(|symbol_map| match &external_file_ref.identity {
+                Some(identity) if !symbol_map.0.backing_cart().defines_symbol(identity) => {
+                    Err(Error::ExternalFileDoesNotDefineSymbol(
+                        location_string,
+                        String::from_utf8_lossy(&identity.symbol_name).into_owned(),
+                    ))
+                }
+                _ => Ok(symbol_map),
+            }),
+            Err(e) => Err(Error::HelperErrorDuringOpenFile(location_string, e)),
+        };
+        match result {
+            Ok(symbol_map) => return Ok(ExternalFileSymbolMap(Box::new(symbol_map))),
+            Err(e) => last_err = e,
+        }
+    }
+    Err(last_err)
 }
 
 struct ExternalFileMemberContext<'a> {
@@ -121,6 +157,9 @@ struct ExternalFileSymbolMapImpl<F: FileContents + 'static>(
 trait ExternalFileSymbolMapTrait {
     fn name(&self) -> &str;
     fn is_same_file(&self, external_file_ref: &ExternalFileRef) -> bool;
+    fn is_archive(&self) -> bool;
+    fn file_size(&self) -> u64;
+    fn thin_archive_member_path(&self, name_in_archive: &str) -> Option<&str>;
     fn lookup(
         &self,
         external_file_address: &ExternalFileAddressInFileRef,
@@ -151,6 +190,23 @@ impl<F: FileContents + 'static> ExternalFileSymbolMapTrait for ExternalFileSymbo
         self.name() == external_file_ref.file_name
     }
 
+    fn is_archive(&self) -> bool {
+        let external_file = self.0.backing_cart();
+        external_file.is_archive || !external_file.thin_archive_member_paths.is_empty()
+    }
+
+    fn file_size(&self) -> u64 {
+        self.0.backing_cart().file_contents.len()
+    }
+
+    fn thin_archive_member_path(&self, name_in_archive: &str) -> Option<&str> {
+        self.0
+            .backing_cart()
+            .thin_archive_member_paths
+            .get(name_in_archive.as_bytes())
+            .map(String::as_str)
+    }
+
     fn lookup(
         &self,
         external_file_address: &ExternalFileAddressInFileRef,
@@ -183,12 +239,39 @@ impl ExternalFileSymbolMap {
         self.0.is_same_file(external_file_ref)
     }
 
+    /// Whether this external file is an archive (`.a` file) with object file
+    /// members, as opposed to a single object file.
+    pub fn is_archive(&self) -> bool {
+        self.0.is_archive()
+    }
+
+    /// The size of the external file, in bytes. For archives, this is the size of
+    /// the whole archive.
+    pub fn file_size(&self) -> u64 {
+        self.0.file_size()
+    }
+
+    /// If this external file is a GNU thin archive, returns the path of the file for
+    /// the member `name_in_archive`. Thin archives only store references to their
+    /// members, so the member file needs to be loaded separately, as a regular
+    /// external file. `SymbolManager::lookup_external` takes care of this.
+    pub fn thin_archive_member_path(&self, name_in_archive: &str) -> Option<&str> {
+        self.0.thin_archive_member_path(name_in_archive)
+    }
+
     /// Look up the debug info for the given [`ExternalFileAddressInFileRef`].
     pub fn lookup(
         &self,
         external_file_address: &ExternalFileAddressInFileRef,
     ) -> Option<Vec<FrameDebugInfo>> {
-        self.0.lookup(external_file_address)
+        let mut frames = self.0.lookup(external_file_address)?;
+        if let Some(max_inline_depth) = external_file_address.max_inline_depth {
+            truncate_inline_frames(&mut frames, max_inline_depth);
+        }
+        external_file_address
+            .function_name_style
+            .apply_to_frames(&mut frames);
+        Some(frames)
     }
 }
 
@@ -247,27 +330,38 @@ struct ArchiveMemberObject<'a, R: ReadRef<'a>> {
 struct ExternalFileData<F: FileContents> {
     name: String,
     file_contents: FileContentsWrapper<F>,
-    /// name in bytes -> (start, size) in file_contents
-    archive_members_by_name: HashMap<Vec<u8>, (u64, u64)>,
+    /// Whether the file is a regular archive. Its members are found by name when
+    /// they're requested, see `find_archive_member`.
+    is_archive: bool,
+    /// For thin archives: member name in bytes -> path of the member file. Members
+    /// can be looked up both by their full name and by their file name.
+    thin_archive_member_paths: HashMap<Vec<u8>, String>,
     fat_archive_range: Option<(u64, u64)>,
     addr2line_context_data: Addr2lineContextData,
 }
 
 impl<F: FileContents> ExternalFileData<F> {
     pub fn new(file_name: &str, file: F, arch: Option<&str>) -> Result<Self, Error> {
-        let mut archive_members_by_name: HashMap<Vec<u8>, (u64, u64)> = HashMap::new();
         let file_contents = FileContentsWrapper::new(file);
+        let mut is_archive = false;
         let mut fat_archive_range = None;
+        if let Some(thin_archive_member_paths) =
+            thin_archive_member_paths(file_name, &file_contents)
+        {
+            return Ok(Self {
+                name: file_name.to_owned(),
+                file_contents,
+                is_archive,
+                thin_archive_member_paths,
+                fat_archive_range,
+                addr2line_context_data: Addr2lineContextData::new(),
+            });
+        }
         let file_kind = FileKind::parse(&file_contents)
             .map_err(|_| Error::CouldNotDetermineExternalFileFileKind)?;
         match file_kind {
             FileKind::Archive => {
-                if let Ok(archive) = ArchiveFile::parse(&file_contents) {
-                    for member in archive.members().flatten() {
-                        archive_members_by_name
-                            .insert(member.name().to_owned(), member.file_range());
-                    }
-                }
+                is_archive = true;
             }
             FileKind::MachO32 | FileKind::MachO64 => {
                 // Good
@@ -275,7 +369,7 @@ impl<F: FileContents> ExternalFileData<F> {
             FileKind::MachOFat32 | FileKind::MachOFat64 => {
                 let disambiguator = arch.map(|arch| MultiArchDisambiguator::Arch(arch.to_string()));
                 let member =
-                    macho::get_fat_archive_member(&file_contents, file_kind, disambiguator)?;
+                    macho::get_fat_archive_member(&file_contents, file_kind, disambiguator, None)?;
                 fat_archive_range = Some(member.offset_and_size);
             }
             _ => {
@@ -285,7 +379,8 @@ impl<F: FileContents> ExternalFileData<F> {
         Ok(Self {
             name: file_name.to_owned(),
             file_contents,
-            archive_members_by_name,
+            is_archive,
+            thin_archive_member_paths: HashMap::new(),
             fat_archive_range,
             addr2line_context_data: Addr2lineContextData::new(),
         })
@@ -299,10 +394,9 @@ impl<F: FileContents> ExternalFileData<F> {
         let data = match (name_in_archive, self.fat_archive_range) {
             (Some(name_in_archive), _) => {
                 let (start, size) = self
-                    .archive_members_by_name
-                    .get(name_in_archive.as_bytes())
+                    .find_archive_member(name_in_archive.as_bytes())
                     .ok_or_else(|| Error::FileNotInArchive(name_in_archive.to_owned()))?;
-                RangeReadRef::new(data, *start, *size)
+                RangeReadRef::new(data, start, size)
             }
             (None, Some((offset, size))) => RangeReadRef::new(data, offset, size),
             (None, None) => RangeReadRef::new(data, 0, data.len()),
@@ -311,6 +405,41 @@ impl<F: FileContents> ExternalFileData<F> {
         Ok(ArchiveMemberObject { data, object_file })
     }
 
+    /// Returns the (start, size) of the archive member with the name `name`.
+    ///
+    /// Only the member headers up to the first member with this name are read, so
+    /// no index of all members is built. This is called once per member, because
+    /// `ExternalFileContext` keeps the context of each member which was looked up.
+    fn find_archive_member(&self, name: &[u8]) -> Option<(u64, u64)> {
+        if !self.is_archive {
+            return None;
+        }
+        let archive = ArchiveFile::parse(&self.file_contents).ok()?;
+        let member = archive
+            .members()
+            .flatten()
+            .find(|member| member.name() == name)?;
+        Some(member.file_range())
+    }
+
+    /// Checks whether this file defines the symbol in `identity`. For thin archives,
+    /// whose members are separate files that are checked when they're loaded, this
+    /// only checks that the archive has the member.
+    fn defines_symbol(&self, identity: &ExternalFileIdentity) -> bool {
+        use object::{Object, ObjectSymbol};
+        if !self.thin_archive_member_paths.is_empty() {
+            return identity.name_in_archive.as_ref().map_or(false, |name| {
+                self.thin_archive_member_paths.contains_key(name.as_bytes())
+            });
+        }
+        match self.get_archive_member(identity.name_in_archive.as_deref()) {
+            Ok(ArchiveMemberObject { object_file, .. }) => object_file
+                .symbols()
+                .any(|symbol| symbol.name_bytes() == Ok(&identity.symbol_name[..])),
+            Err(_) => false,
+        }
+    }
+
     pub fn make_file_context(&self) -> ExternalFileContext<'_, F> {
         let path_mapper = PathMapper::new();
         ExternalFileContext {
@@ -320,3 +449,225 @@ impl<F: FileContents> ExternalFileData<F> {
         }
     }
 }
+
+/// Removes `.` components from the path and collapses `..` components with the
+/// preceding component, without accessing the file system. Leading `..` components
+/// of a relative path are kept. Symlinks are not resolved.
+///
+/// Windows paths, i.e. paths which contain a backslash or start with a drive
+/// letter, accept both separators and are joined with backslashes. Their drive
+/// letter or UNC prefix is kept as is.
+fn normalize_path_lexically(path: &str) -> String {
+    let has_drive_letter =
+        path.len() >= 2 && path.as_bytes()[0].is_ascii_alphabetic() && path.as_bytes()[1] == b':';
+    let is_windows = has_drive_letter || path.contains('\\');
+    let separator = if is_windows { "\\" } else { "/" };
+    let (prefix, rest) = if has_drive_letter {
+        path.split_at(2)
+    } else if path.starts_with("\\\\") {
+        ("\\", &path[1..])
+    } else {
+        ("", path)
+    };
+    let is_separator = |c: char| c == '/' || (is_windows && c == '\\');
+    let is_absolute = rest.starts_with(is_separator);
+    let mut components: Vec<&str> = Vec::new();
+    for component in rest.split(is_separator) {
+        match component {
+            "" | "." => {}
+            ".." => match components.last() {
+                Some(&last) if last != ".." => {
+                    components.pop();
+                }
+                _ if is_absolute => {}
+                _ => components.push(".."),
+            },
+            _ => components.push(component),
+        }
+    }
+    let joined = components.join(separator);
+    if is_absolute {
+        format!("{prefix}{separator}{joined}")
+    } else {
+        format!("{prefix}{joined}")
+    }
+}
+
+const THIN_ARCHIVE_MAGIC: &[u8] = b"!<thin>\n";
+
+/// If `file_contents` is a GNU thin archive, returns the paths of its member files,
+/// resolved relative to the archive's directory.
+fn thin_archive_member_paths<F: FileContents>(
+    archive_path: &str,
+    file_contents: &FileContentsWrapper<F>,
+) -> Option<HashMap<Vec<u8>, String>> {
+    if file_contents
+        .read_bytes_at(0, THIN_ARCHIVE_MAGIC.len() as u64)
+        .ok()?
+        != THIN_ARCHIVE_MAGIC
+    {
+        return None;
+    }
+    // Thin archives don't contain the member data, so they're small enough to read
+    // in one go.
+    let data = file_contents.read_entire_data().ok()?;
+    let archive_dir = match archive_path.rfind(['/', '\\']) {
+        Some(pos) => &archive_path[..=pos],
+        None => "",
+    };
+    let mut member_paths = HashMap::new();
+    for member_name in parse_thin_archive_member_names(data)? {
+        let member_name_str = std::str::from_utf8(member_name).ok()?;
+        let is_absolute = member_name_str.starts_with('/')
+            || member_name_str.starts_with('\\')
+            || member_name_str.get(1..3) == Some(":\\");
+        let path = if is_absolute {
+            member_name_str.to_owned()
+        } else {
+            format!("{archive_dir}{member_name_str}")
+        };
+        if let Some(pos) = member_name.iter().rposition(|b| *b == b'/' || *b == b'\\') {
+            member_paths
+                .entry(member_name[pos + 1..].to_vec())
+                .or_insert_with(|| path.clone());
+        }
+        member_paths.insert(member_name.to_vec(), path);
+    }
+    Some(member_paths)
+}
+
+/// Parses the member headers of a GNU thin archive and returns the member names.
+/// Unlike in regular archives, the member headers are not followed by the member
+/// data, with the exception of the symbol table and the long name table.
+fn parse_thin_archive_member_names(data: &[u8]) -> Option<Vec<&[u8]>> {
+    const HEADER_SIZE: usize = 60;
+    let mut offset = THIN_ARCHIVE_MAGIC.len();
+    let mut long_names: &[u8] = &[];
+    let mut member_names = Vec::new();
+    while let Some(header) = data.get(offset..offset + HEADER_SIZE) {
+        if &header[58..60] != b"`\n" {
+            return None;
+        }
+        let name = &header[0..16];
+        let name_len = name
+            .iter()
+            .rposition(|b| *b != b' ')
+            .map_or(0, |pos| pos + 1);
+        let name = &name[..name_len];
+        let size: usize = std::str::from_utf8(&header[48..58])
+            .ok()?
+            .trim()
+            .parse()
+            .ok()?;
+        offset += HEADER_SIZE;
+        match name {
+            b"/" | b"/SYM64/" => {
+                offset += size + (size & 1);
+            }
+            b"//" => {
+                long_names = data.get(offset..offset + size)?;
+                offset += size + (size & 1);
+            }
+            _ => {
+                let member_name = match name.strip_prefix(b"/") {
+                    Some(long_name_offset) => {
+                        let long_name_offset: usize =
+                            std::str::from_utf8(long_name_offset).ok()?.parse().ok()?;
+                        let long_name = long_names.get(long_name_offset..)?;
+                        let end = memchr::memchr(b'\n', long_name)?;
+                        &long_name[..end]
+                    }
+                    None => name,
+                };
+                let member_name = member_name.strip_suffix(b"/").unwrap_or(member_name);
+                member_names.push(member_name);
+            }
+        }
+    }
+    Some(member_names)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!(
+            normalize_path_lexically("/build/obj/js/src/../../../js/src/./jsapi.o"),
+            "/build/js/src/jsapi.o"
+        );
+        assert_eq!(normalize_path_lexically("/../a//b.o"), "/a/b.o");
+        assert_eq!(normalize_path_lexically("../x/../y/z.o"), "../y/z.o");
+        assert_eq!(normalize_path_lexically("a/../../b.o"), "../b.o");
+        assert_eq!(normalize_path_lexically("/a/b.o"), "/a/b.o");
+        assert_eq!(
+            normalize_path_lexically(r"C:\build\obj\..\src\.\foo.obj"),
+            r"C:\build\src\foo.obj"
+        );
+        assert_eq!(
+            normalize_path_lexically("C:/build/obj/../foo.obj"),
+            r"C:\build\foo.obj"
+        );
+        assert_eq!(normalize_path_lexically(r"C:\..\foo.obj"), r"C:\foo.obj");
+        assert_eq!(
+            normalize_path_lexically(r"\\server\share\obj\..\foo.obj"),
+            r"\\server\share\foo.obj"
+        );
+        assert_eq!(
+            normalize_path_lexically(r"..\obj\..\foo.obj"),
+            r"..\foo.obj"
+        );
+    }
+
+    fn ar_header(name: &str, size: usize) -> Vec<u8> {
+        format!("{name:<16}{:<12}{:<6}{:<6}{:<8}{size:<10}`\n", 0, 0, 0, 644).into_bytes()
+    }
+
+    #[test]
+    fn archive_member_by_name() {
+        let mut data = b"!<arch>\n".to_vec();
+        data.extend(ar_header("foo.o/", 5));
+        data.extend(b"abcde\n");
+        data.extend(ar_header("bar.o/", 4));
+        data.extend(b"wxyz");
+
+        let external_file = ExternalFileData::new("libfoo.a", data, None).unwrap();
+        assert!(external_file.is_archive);
+        assert_eq!(external_file.find_archive_member(b"foo.o"), Some((68, 5)));
+        assert_eq!(external_file.find_archive_member(b"bar.o"), Some((134, 4)));
+        assert_eq!(external_file.find_archive_member(b"baz.o"), None);
+        assert!(matches!(
+            external_file.get_archive_member(Some("baz.o")),
+            Err(Error::FileNotInArchive(name)) if name == "baz.o"
+        ));
+    }
+
+    #[test]
+    fn thin_archive_members() {
+        let long_names = "obj/foo.o/\n/abs/bar.o/\n";
+        let mut data = THIN_ARCHIVE_MAGIC.to_vec();
+        data.extend(ar_header("//", long_names.len()));
+        data.extend(long_names.as_bytes());
+        // The long name table has an odd size, so it's followed by a padding byte.
+        data.push(b'\n');
+        data.extend(ar_header("/0", 1234));
+        data.extend(ar_header("/11", 5678));
+        data.extend(ar_header("baz.o/", 42));
+
+        assert_eq!(
+            parse_thin_archive_member_names(&data).unwrap(),
+            vec![&b"obj/foo.o"[..], &b"/abs/bar.o"[..], &b"baz.o"[..]]
+        );
+
+        let file_contents = FileContentsWrapper::new(data);
+        let paths = thin_archive_member_paths("/build/lib/libthin.a", &file_contents).unwrap();
+        assert_eq!(paths[&b"obj/foo.o"[..]], "/build/lib/obj/foo.o");
+        assert_eq!(paths[&b"foo.o"[..]], "/build/lib/obj/foo.o");
+        assert_eq!(paths[&b"bar.o"[..]], "/abs/bar.o");
+        assert_eq!(paths[&b"baz.o"[..]], "/build/lib/baz.o");
+
+        let regular_archive = FileContentsWrapper::new(b"!<arch>\n".to_vec());
+        assert!(thin_archive_member_paths("libregular.a", &regular_archive).is_none());
+    }
+}
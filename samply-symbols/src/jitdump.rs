@@ -8,7 +8,7 @@ use yoke::Yoke;
 
 use std::{
     borrow::Cow,
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, BTreeMap, HashMap},
     sync::Mutex,
 };
 
@@ -71,7 +71,7 @@ impl JitDumpIndex {
                 JitDumpRecordType::JIT_CODE_LOAD => {
                     // Read the full record.
                     let Some(raw_record) = reader.next_record()? else { break };
-                    let JitDumpRecord::CodeLoad(record) = raw_record.parse()? else { panic!() };
+                    let JitDumpRecord::CodeLoad(record) = raw_record.parse()? else { continue };
                     let code_debug_info_record_offset_and_len =
                         offset_and_len_of_pending_debug_record.take();
                     let relative_address = cumulative_address;
@@ -84,6 +84,7 @@ impl JitDumpIndex {
                         name_len: record.function_name.len() as u32,
                         code_debug_info_record_offset_and_len,
                         code_bytes_len: record.code_bytes.len() as u64,
+                        code_addr: record.code_addr,
                     });
                     relative_addresses.push(relative_address);
                 }
@@ -153,8 +154,82 @@ pub struct JitDumpIndexEntry {
     pub code_load_record_offset: u64,
     pub code_bytes_offset: u64,
     pub name_len: u32,
+    /// The offset and length of the `JIT_CODE_DEBUG_INFO` record which preceded this
+    /// entry's `JIT_CODE_LOAD` record, if any. The debug info record is only used if its
+    /// `code_addr` matches this entry's `code_addr`.
     pub code_debug_info_record_offset_and_len: Option<(u64, u32)>,
     pub code_bytes_len: u64,
+    /// The address at which the code bytes were loaded in the JIT process.
+    pub code_addr: u64,
+}
+
+/// A function from a `JIT_CODE_LOAD` record in a jitdump file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JitSymbol {
+    /// The address at which the code was loaded in the JIT process.
+    pub code_addr: u64,
+    /// The size of the code, in bytes.
+    pub size: u64,
+    /// The function name, as written by the JIT.
+    pub name: String,
+}
+
+/// Reads the `JIT_CODE_LOAD` records of a jitdump file, in file order.
+///
+/// The returned symbols use the addresses in the JIT process, so they can be
+/// matched against the runtime's code address ranges. If the JIT reused an address
+/// range, later symbols for the same range supersede earlier ones: symbols which
+/// overlap a symbol from a later record are not returned, so the returned symbols
+/// never overlap.
+pub fn parse_jitdump<T: FileContents>(data: T) -> Result<Vec<JitSymbol>, Error> {
+    let data = FileContentsWrapper::new(data);
+    let cursor = FileContentsCursor::new(&data);
+    let reader = JitDumpReader::new(cursor)?;
+    let index = JitDumpIndex::from_reader(reader).map_err(Error::JitDumpFileReading)?;
+    let mut cache = JitDumpSymbolMapCache::new(&data, &index);
+    let symbols = index
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(entry_index, entry)| {
+            let name = cache
+                .get_function_name(entry_index)
+                .map(|name| String::from_utf8_lossy(name).into_owned())
+                .unwrap_or_else(|| format!("jit_{:x}", entry.code_addr));
+            JitSymbol {
+                code_addr: entry.code_addr,
+                size: entry.code_bytes_len,
+                name,
+            }
+        })
+        .collect();
+    Ok(remove_superseded_symbols(symbols))
+}
+
+/// Removes the symbols whose address range overlaps the range of a later symbol.
+fn remove_superseded_symbols(symbols: Vec<JitSymbol>) -> Vec<JitSymbol> {
+    // The ranges of the symbols which are kept, start -> end. Going from the last
+    // symbol to the first, a symbol is kept if its range doesn't overlap any of the
+    // kept ranges. Since the kept ranges don't overlap each other, only the kept
+    // range with the highest start below our end needs to be checked.
+    let mut kept_ranges = BTreeMap::new();
+    let mut kept_symbols: Vec<JitSymbol> = symbols
+        .into_iter()
+        .rev()
+        .filter(|symbol| {
+            let start = symbol.code_addr;
+            let end = start.saturating_add(symbol.size.max(1));
+            if let Some((_, kept_end)) = kept_ranges.range(..end).next_back() {
+                if *kept_end > start {
+                    return false;
+                }
+            }
+            kept_ranges.insert(start, end);
+            true
+        })
+        .collect();
+    kept_symbols.reverse();
+    kept_symbols
 }
 
 pub fn get_symbol_map_for_jitdump<F, FL>(
@@ -189,10 +264,27 @@ impl<T: FileContents> SymbolMapTrait for JitDumpSymbolMap<T> {
         self.0.get().0.iter_symbols()
     }
 
+    fn iter_symbols_with_size(
+        &self,
+    ) -> Box<dyn Iterator<Item = (u32, Option<u32>, Cow<'_, str>)> + '_> {
+        self.0.get().0.iter_symbols_with_size()
+    }
+
+    fn lookup_symbol(&self, address: u32) -> Option<SymbolInfo> {
+        self.0.get().0.lookup_symbol(address)
+    }
+
     fn lookup_relative_address(&self, address: u32) -> Option<AddressInfo> {
         self.0.get().0.lookup_relative_address(address)
     }
 
+    fn boundaries_in_relative_address_range(&self, start: u32, end: u32) -> Vec<u32> {
+        self.0
+            .get()
+            .0
+            .boundaries_in_relative_address_range(start, end)
+    }
+
     fn lookup_svma(&self, svma: u64) -> Option<AddressInfo> {
         self.0.get().0.lookup_svma(svma)
     }
@@ -232,7 +324,9 @@ struct JitDumpSymbolMapInner<'a, T: FileContents> {
 #[derive(Debug)]
 struct JitDumpSymbolMapCache<'a, T: FileContents> {
     names: HashMap<usize, &'a [u8]>,
-    debug_infos: HashMap<usize, JitCodeDebugInfoRecord<'a>>,
+    /// `None` if the entry has no usable debug info record, so that we don't re-read
+    /// and re-parse the record on every lookup.
+    debug_infos: HashMap<usize, Option<JitCodeDebugInfoRecord<'a>>>,
     data: &'a FileContentsWrapper<T>,
     index: &'a JitDumpIndex,
 }
@@ -264,21 +358,31 @@ impl<'a, T: FileContents> JitDumpSymbolMapCache<'a, T> {
     }
 
     pub fn get_debug_info(&mut self, entry_index: usize) -> Option<&JitCodeDebugInfoRecord<'a>> {
-        match self.debug_infos.entry(entry_index) {
-            Entry::Occupied(entry) => Some(entry.into_mut()),
+        let debug_info = match self.debug_infos.entry(entry_index) {
+            Entry::Occupied(entry) => entry.into_mut(),
             Entry::Vacant(entry) => {
                 let desc = &self.index.entries[entry_index];
-                let (record_start, record_len) = desc.code_debug_info_record_offset_and_len?;
-                let record_data = self
-                    .data
-                    .read_bytes_at(record_start, record_len.into())
-                    .ok()?;
-                let mut record_data = RawData::Single(record_data);
-                record_data.skip(JitDumpRecordHeader::SIZE).ok()?;
-                let record = JitCodeDebugInfoRecord::parse(self.index.endian, record_data).ok()?;
-                Some(entry.insert(record))
+                entry.insert(Self::read_debug_info(self.data, self.index.endian, desc))
             }
+        };
+        debug_info.as_ref()
+    }
+
+    fn read_debug_info(
+        data: &'a FileContentsWrapper<T>,
+        endian: Endianness,
+        desc: &JitDumpIndexEntry,
+    ) -> Option<JitCodeDebugInfoRecord<'a>> {
+        let (record_start, record_len) = desc.code_debug_info_record_offset_and_len?;
+        let record_data = data.read_bytes_at(record_start, record_len.into()).ok()?;
+        let mut record_data = RawData::Single(record_data);
+        record_data.skip(JitDumpRecordHeader::SIZE).ok()?;
+        let record = JitCodeDebugInfoRecord::parse(endian, record_data).ok()?;
+        if record.code_addr != desc.code_addr {
+            // This debug info record belongs to a different code load.
+            return None;
         }
+        Some(record)
     }
 }
 
@@ -304,6 +408,8 @@ impl<'a, T: FileContents> JitDumpSymbolMapInner<'a, T> {
                             function: Some(name.clone()),
                             file_path: Some(SourceFilePath::new(file_path, None)),
                             line_number: Some(entry.line),
+                            compiland: None,
+                            is_inlined: false,
                         };
                         FramesLookupResult::Available(vec![frame])
                     }
@@ -319,6 +425,7 @@ impl<'a, T: FileContents> JitDumpSymbolMapInner<'a, T> {
                 name,
             },
             frames,
+            section_name: None,
         })
     }
 }
@@ -342,6 +449,19 @@ impl<'a, T: FileContents> SymbolMapTrait for JitDumpSymbolMapInner<'a, T> {
         Box::new(iter)
     }
 
+    fn iter_symbols_with_size(
+        &self,
+    ) -> Box<dyn Iterator<Item = (u32, Option<u32>, Cow<'_, str>)> + '_> {
+        let iter = (0..self.symbol_count()).filter_map(move |i| {
+            let address = self.index.relative_addresses[i];
+            let size = self.index.entries[i].code_bytes_len as u32;
+            let mut cache = self.cache.lock().unwrap();
+            let name = cache.get_function_name(i)?;
+            Some((address, Some(size), String::from_utf8_lossy(name)))
+        });
+        Box::new(iter)
+    }
+
     fn lookup_relative_address(&self, address: u32) -> Option<AddressInfo> {
         let (index, symbol_address, offset_from_symbol) =
             self.index.lookup_relative_address(address)?;
@@ -358,3 +478,119 @@ impl<'a, T: FileContents> SymbolMapTrait for JitDumpSymbolMapInner<'a, T> {
         self.lookup_by_entry_index(index, symbol_address, offset_from_symbol)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn code_load_record(code_addr: u64, name: &str, code_size: u64) -> Vec<u8> {
+        let name_bytes_len = name.len() as u64 + 1;
+        let total_size = 16 + 40 + name_bytes_len + code_size;
+        let mut record = Vec::new();
+        record.extend_from_slice(&0u32.to_le_bytes()); // JIT_CODE_LOAD
+        record.extend_from_slice(&(total_size as u32).to_le_bytes());
+        record.extend_from_slice(&0u64.to_le_bytes()); // timestamp
+        record.extend_from_slice(&1234u32.to_le_bytes()); // pid
+        record.extend_from_slice(&1234u32.to_le_bytes()); // tid
+        record.extend_from_slice(&code_addr.to_le_bytes()); // vma
+        record.extend_from_slice(&code_addr.to_le_bytes()); // code_addr
+        record.extend_from_slice(&code_size.to_le_bytes());
+        record.extend_from_slice(&0u64.to_le_bytes()); // code_index
+        record.extend_from_slice(name.as_bytes());
+        record.push(0);
+        record.extend(std::iter::repeat(0xcc).take(code_size as usize));
+        record
+    }
+
+    fn debug_info_record(code_addr: u64, file: &str, line: u32) -> Vec<u8> {
+        let total_size = 16 + 16 + 16 + file.len() as u64 + 1;
+        let mut record = Vec::new();
+        record.extend_from_slice(&2u32.to_le_bytes()); // JIT_CODE_DEBUG_INFO
+        record.extend_from_slice(&(total_size as u32).to_le_bytes());
+        record.extend_from_slice(&0u64.to_le_bytes()); // timestamp
+        record.extend_from_slice(&code_addr.to_le_bytes());
+        record.extend_from_slice(&1u64.to_le_bytes()); // nr_entry
+        record.extend_from_slice(&code_addr.to_le_bytes()); // addr
+        record.extend_from_slice(&line.to_le_bytes());
+        record.extend_from_slice(&0u32.to_le_bytes()); // discrim
+        record.extend_from_slice(file.as_bytes());
+        record.push(0);
+        record
+    }
+
+    fn jitdump_header() -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"DTiJ"); // little-endian magic
+        data.extend_from_slice(&1u32.to_le_bytes()); // version
+        data.extend_from_slice(&40u32.to_le_bytes()); // header size
+        data.extend_from_slice(&62u32.to_le_bytes()); // EM_X86_64
+        data.extend_from_slice(&0u32.to_le_bytes()); // padding
+        data.extend_from_slice(&1234u32.to_le_bytes()); // pid
+        data.extend_from_slice(&0u64.to_le_bytes()); // timestamp
+        data.extend_from_slice(&0u64.to_le_bytes()); // flags
+        data
+    }
+
+    #[test]
+    fn parse_code_load_records() {
+        let mut data = jitdump_header();
+        data.extend(code_load_record(0x7f00_0000_1000, "JS:foo", 0x20));
+        data.extend(code_load_record(0x7f00_0000_1020, "JS:bar", 0x10));
+
+        let symbols = parse_jitdump(data).unwrap();
+        assert_eq!(
+            symbols,
+            vec![
+                JitSymbol {
+                    code_addr: 0x7f00_0000_1000,
+                    size: 0x20,
+                    name: "JS:foo".to_string(),
+                },
+                JitSymbol {
+                    code_addr: 0x7f00_0000_1020,
+                    size: 0x10,
+                    name: "JS:bar".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reused_code_addresses() {
+        let mut data = jitdump_header();
+        data.extend(code_load_record(0x7f00_0000_1000, "JS:foo", 0x20));
+        data.extend(code_load_record(0x7f00_0000_1020, "JS:bar", 0x10));
+        // Reuses the second half of foo's range.
+        data.extend(code_load_record(0x7f00_0000_1010, "JS:baz", 0x10));
+        // Reuses the same address as bar.
+        data.extend(code_load_record(0x7f00_0000_1020, "JS:qux", 0x8));
+
+        let symbols = parse_jitdump(data).unwrap();
+        let names: Vec<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["JS:baz", "JS:qux"]);
+    }
+
+    #[test]
+    fn mismatched_debug_info_is_cached() {
+        let mut data = jitdump_header();
+        data.extend(debug_info_record(0x7f00_0000_1000, "foo.js", 10));
+        data.extend(code_load_record(0x7f00_0000_1000, "JS:foo", 0x20));
+        data.extend(debug_info_record(0x7f00_0000_5000, "other.js", 20));
+        data.extend(code_load_record(0x7f00_0000_1020, "JS:bar", 0x10));
+        let data = FileContentsWrapper::new(data);
+        let reader = JitDumpReader::new(FileContentsCursor::new(&data)).unwrap();
+        let index = JitDumpIndex::from_reader(reader).unwrap();
+        let mut cache = JitDumpSymbolMapCache::new(&data, &index);
+
+        let debug_info = cache.get_debug_info(0).unwrap();
+        assert_eq!(debug_info.code_addr, 0x7f00_0000_1000);
+        assert_eq!(debug_info.entries.len(), 1);
+
+        // The second debug info record is for a different code address. The negative
+        // result is remembered, so the record is only read once.
+        assert!(cache.get_debug_info(1).is_none());
+        assert!(matches!(cache.debug_infos.get(&1), Some(None)));
+        assert!(cache.get_debug_info(1).is_none());
+        assert_eq!(cache.debug_infos.len(), 2);
+    }
+}
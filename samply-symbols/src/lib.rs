@@ -119,7 +119,7 @@
 //!                         }
 //!                     }
 //!                 }
-//!                 FramesLookupResult::Unavailable => {}
+//!                 FramesLookupResult::NeedDwo(_) | FramesLookupResult::Unavailable => {}
 //!             }
 //!         }
 //!         None => {
@@ -217,6 +217,16 @@
 //!     fn location_for_breakpad_symindex(&self) -> Option<Self> {
 //!         Some(Self(self.0.with_extension("symindex")))
 //!     }
+//!
+//!     fn location_for_dwp(&self) -> Option<Self> {
+//!         let mut path = self.0.clone().into_os_string();
+//!         path.push(".dwp");
+//!         Some(Self(path.into()))
+//!     }
+//!
+//!     fn location_for_dwo(&self, dwo_path: &str) -> Option<Self> {
+//!         Some(Self(dwo_path.into()))
+//!     }
 //! }
 //! ```
 
@@ -233,11 +243,14 @@ use object::read::FileKind;
 use shared::FileContentsCursor;
 
 mod binary_image;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 mod breakpad;
 mod cache;
 mod chunked_read_buffer_manager;
 mod compact_symbol_table;
 mod debugid_util;
+mod decompressing_file_contents;
 mod demangle;
 mod demangle_ocaml;
 mod dwarf;
@@ -247,36 +260,54 @@ mod external_file;
 mod jitdump;
 mod macho;
 mod mapped_path;
+#[cfg(feature = "mmap")]
+mod mmap_file_contents;
 mod path_mapper;
 mod shared;
+mod symbol_file_id;
 mod symbol_map;
 mod symbol_map_object;
+mod verify;
 mod windows;
 
 pub use crate::binary_image::{BinaryImage, CodeByteReadingError};
 pub use crate::breakpad::{
-    BreakpadIndex, BreakpadIndexParser, BreakpadParseError, BreakpadSymindexParseError,
+    BreakpadIndex, BreakpadIndexParser, BreakpadParseError, BreakpadSymSink,
+    BreakpadSymindexParseError,
 };
-pub use crate::cache::{FileByteSource, FileContentsWithChunkedCaching};
+pub use crate::cache::{CallbackFileByteSource, FileByteSource, FileContentsWithChunkedCaching};
 pub use crate::compact_symbol_table::CompactSymbolTable;
 pub use crate::debugid_util::{debug_id_for_object, DebugIdExt};
+pub use crate::decompressing_file_contents::DecompressingFileContents;
+pub use crate::demangle::{
+    demangle_any, demangle_msvc, elide_template_args, strip_function_arguments, FunctionNameStyle,
+};
 pub use crate::error::Error;
 pub use crate::external_file::{load_external_file, ExternalFileSymbolMap};
-pub use crate::jitdump::debug_id_and_code_id_for_jitdump;
-pub use crate::macho::FatArchiveMember;
+pub use crate::jitdump::{debug_id_and_code_id_for_jitdump, parse_jitdump, JitSymbol};
+pub use crate::macho::{list_fat_archive_members, FatArchiveMember, UuidCache};
 pub use crate::mapped_path::MappedPath;
+#[cfg(feature = "mmap")]
+pub use crate::mmap_file_contents::MmapFileContents;
 pub use crate::shared::{
-    relative_address_base, AddressInfo, CandidatePathInfo, CodeId, ElfBuildId,
-    ExternalFileAddressInFileRef, ExternalFileAddressRef, ExternalFileRef, FileAndPathHelper,
-    FileAndPathHelperError, FileAndPathHelperResult, FileContents, FileContentsWrapper,
-    FileLocation, FrameDebugInfo, FramesLookupResult, LibraryInfo, MultiArchDisambiguator,
-    OptionallySendFuture, PeCodeId, SourceFilePath, SymbolInfo,
+    macho_text_segment_address, relative_address_base, AddressInfo, CandidatePathInfo, CodeId,
+    DwoRef, ElfBuildId, ExternalFileAddressInFileRef, ExternalFileAddressRef, ExternalFileIdentity,
+    ExternalFileRef, FileAndPathHelper, FileAndPathHelperError, FileAndPathHelperResult,
+    FileContents, FileContentsWrapper, FileLocation, FrameDebugInfo, FramesLookupResult,
+    LibraryInfo, MultiArchDisambiguator, OptionallySendFuture, PeCodeId, SourceFilePath,
+    SymbolInfo, SymbolMapWarning,
+};
+pub use crate::symbol_file_id::{symbol_file_identity, SymbolFileId};
+pub use crate::symbol_map::{FunctionLineInfo, SymbolMap};
+pub use crate::verify::{ReferencedDebugFile, VerificationReport};
+pub use crate::windows::{
+    debug_id_for_pdb, source_files_for_pdb, PdbSourceFile, PdbSourceFileChecksum,
 };
-pub use crate::symbol_map::SymbolMap;
 
 pub struct SymbolManager<'h, H: FileAndPathHelper<'h>> {
     helper: &'h H,
     cached_external_file: Mutex<Option<ExternalFileSymbolMap>>,
+    uuid_cache: Option<&'h UuidCache>,
 }
 
 impl<'h, H, F, FL> SymbolManager<'h, H>
@@ -290,9 +321,20 @@ where
         Self {
             helper,
             cached_external_file: Mutex::new(None),
+            uuid_cache: None,
         }
     }
 
+    /// Use `uuid_cache` to remember the UUIDs of the members of universal binaries.
+    ///
+    /// This avoids re-parsing the mach-O headers of every member when the same
+    /// universal binary is loaded repeatedly. The cache is keyed by the file location's
+    /// string representation, so it can be shared between multiple `SymbolManager`s.
+    pub fn with_uuid_cache(mut self, uuid_cache: &'h UuidCache) -> Self {
+        self.uuid_cache = Some(uuid_cache);
+        self
+    }
+
     /// Exposes the helper.
     pub fn helper(&self) -> &'h H {
         self.helper
@@ -373,6 +415,72 @@ where
             .unwrap_or_else(|| Error::NoCandidatePathForDebugFile(Box::new(library_info.clone()))))
     }
 
+    /// Check that the debug file for the library can be found, and find out which of
+    /// the files it refers to for additional debug info can be loaded, without
+    /// looking up any addresses.
+    ///
+    /// This finds the debug file in the same way as `load_symbol_map`, so it fails if
+    /// no file with the right debug ID is found. The referenced files are the
+    /// external object files and archives of mach-O binaries without a dSYM, and the
+    /// `.dwp` or `.dwo` files of ELF binaries compiled with `-gsplit-dwarf`. They are
+    /// checked for whether they can be loaded and whether they belong to the same
+    /// build: external files need to define the symbols which the binary expects from
+    /// them, and split DWARF files need to have the DWO IDs of the skeleton units. No
+    /// line information is read from any of the files.
+    pub async fn verify_symbols(
+        &self,
+        library_info: &LibraryInfo,
+    ) -> Result<VerificationReport<FL>, Error> {
+        let (debug_file_location, debug_id, external_file_refs, dwo_refs, dwp_result) = {
+            let symbol_map = self.load_symbol_map(library_info).await?;
+            if let Some(err) = symbol_map.lookup_error() {
+                return Err(err);
+            }
+            let dwo_refs = symbol_map.dwo_refs();
+            let dwp_result = if dwo_refs.is_empty() {
+                None
+            } else {
+                symbol_map.verify_dwp(&dwo_refs)
+            };
+            (
+                symbol_map.debug_file_location().clone(),
+                symbol_map.debug_id(),
+                symbol_map.external_file_refs(),
+                dwo_refs,
+                dwp_result,
+            )
+        };
+
+        let mut referenced_files = Vec::new();
+        for file_ref in external_file_refs {
+            let result = self
+                .load_external_file(&debug_file_location, &file_ref)
+                .await
+                .map(|_| ());
+            referenced_files.push((ReferencedDebugFile::ExternalFile(file_ref), result));
+        }
+        // A .dwp file takes precedence over the .dwo files. If there is one, only the
+        // .dwp file is checked, and a .dwp file which doesn't match is reported
+        // rather than falling back to the .dwo files.
+        if let Some(dwp_result) = dwp_result {
+            referenced_files.push((ReferencedDebugFile::Dwp, dwp_result));
+        } else {
+            for dwo_ref in dwo_refs {
+                let result = self
+                    .load_dwo_file(&debug_file_location, &dwo_ref)
+                    .await
+                    .and_then(|data| dwarf::verify_dwo_file(&data[..], &dwo_ref));
+                referenced_files.push((ReferencedDebugFile::Dwo(dwo_ref.path), result));
+            }
+        }
+
+        Ok(VerificationReport {
+            debug_file_location,
+            debug_id,
+            referenced_files,
+        })
+    }
+
     /// Load and return an external file which may contain additional debug info.
     ///
     /// This is used on macOS: When linking multiple `.o` files together into a library or
@@ -399,31 +507,106 @@ where
     ///
     /// This keeps the most recent external file cached, so that repeated lookups
     /// for the same external file are fast.
+    ///
+    /// If the external file is a GNU thin archive, the archive member is loaded
+    /// from its own path, like a regular object file, and replaces the archive in
+    /// the cache.
     pub async fn lookup_external(
         &self,
         debug_file_location: &H::FL,
         address: &ExternalFileAddressRef,
     ) -> Option<Vec<FrameDebugInfo>> {
+        let address_in_file = &address.address_in_file;
+        let (thin_archive_member_path, lookup_result) = self
+            .with_external_file(debug_file_location, &address.file_ref, |external_file| {
+                let name_in_archive = address_in_file.name_in_archive.as_deref();
+                match name_in_archive.and_then(|name| external_file.thin_archive_member_path(name))
+                {
+                    Some(member_path) => (Some(member_path.to_owned()), None),
+                    None => (None, external_file.lookup(address_in_file)),
+                }
+            })
+            .await?;
+        let member_path = match thin_archive_member_path {
+            Some(member_path) => member_path,
+            None => return lookup_result,
+        };
+
+        let member_file_ref = ExternalFileRef {
+            file_name: member_path,
+            arch: address.file_ref.arch.clone(),
+            identity: Some(ExternalFileIdentity {
+                name_in_archive: None,
+                symbol_name: address_in_file.symbol_name.clone(),
+            }),
+        };
+        let address_in_member = ExternalFileAddressInFileRef {
+            name_in_archive: None,
+            ..address_in_file.clone()
+        };
+        self.with_external_file(debug_file_location, &member_file_ref, |member_file| {
+            member_file.lookup(&address_in_member)
+        })
+        .await?
+    }
+
+    /// Calls `f` with the external file for `external_file_ref`, which is taken from
+    /// the cache if possible and otherwise loaded and then stored in the cache.
+    async fn with_external_file<R>(
+        &self,
+        debug_file_location: &H::FL,
+        external_file_ref: &ExternalFileRef,
+        f: impl FnOnce(&ExternalFileSymbolMap) -> R,
+    ) -> Option<R> {
         {
             let cached_external_file = self.cached_external_file.lock().ok()?;
             match &*cached_external_file {
-                Some(external_file) if external_file.is_same_file(&address.file_ref) => {
-                    return external_file.lookup(&address.address_in_file);
+                Some(external_file) if external_file.is_same_file(external_file_ref) => {
+                    return Some(f(external_file));
                 }
                 _ => {}
             }
         }
 
         let external_file = self
-            .load_external_file(debug_file_location, &address.file_ref)
+            .load_external_file(debug_file_location, external_file_ref)
             .await
             .ok()?;
-        let lookup_result = external_file.lookup(&address.address_in_file);
+        let result = f(&external_file);
 
         if let Ok(mut guard) = self.cached_external_file.lock() {
             *guard = Some(external_file);
         }
-        lookup_result
+        Some(result)
+    }
+
+    /// Load the contents of the split DWARF `.dwo` file which a lookup asked for with
+    /// `FramesLookupResult::NeedDwo`, to be handed to `SymbolMap::add_dwo_file`.
+    ///
+    /// `debug_file_location` is the location of the symbol map which returned the
+    /// `DwoRef`, see `SymbolMap::debug_file_location`. If loading fails, pass `None`
+    /// to `SymbolMap::add_dwo_file` so that the symbol map stops asking for the file.
+    ///
+    /// This doesn't need the symbol map itself, so that callers can drop it while
+    /// they wait for the file, for example if the symbol map is not `Send`.
+    pub async fn load_dwo_file(
+        &self,
+        debug_file_location: &H::FL,
+        dwo_ref: &DwoRef,
+    ) -> Result<Vec<u8>, Error> {
+        let dwo_location = debug_file_location
+            .location_for_dwo(&dwo_ref.path)
+            .ok_or(Error::FileLocationRefusedDwoLocation)?;
+        let file_contents = self
+            .helper
+            .load_file(dwo_location.clone())
+            .await
+            .map_err(|e| Error::HelperErrorDuringOpenFile(dwo_location.to_string(), e))?;
+        let file_contents = FileContentsWrapper::new(file_contents);
+        let data = file_contents
+            .read_bytes_at(0, file_contents.len())
+            .map_err(|e| Error::HelperErrorDuringFileReading(dwo_location.to_string(), e))?;
+        Ok(data.to_vec())
     }
 
     async fn load_binary_from_dyld_cache(
@@ -449,7 +632,11 @@ where
             .map_err(|e| Error::HelperErrorDuringGetCandidatePathsForBinary(e))?;
 
         let disambiguator = match (&info.debug_id, &info.arch) {
-            (Some(debug_id), _) => Some(MultiArchDisambiguator::DebugId(*debug_id)),
+            (Some(debug_id), Some(arch)) => Some(MultiArchDisambiguator::DebugIdAndArch(
+                *debug_id,
+                arch.clone(),
+            )),
+            (Some(debug_id), None) => Some(MultiArchDisambiguator::DebugId(*debug_id)),
             (None, Some(arch)) => Some(MultiArchDisambiguator::Arch(arch.clone())),
             (None, None) => None,
         };
@@ -511,6 +698,7 @@ where
     ) -> Result<BinaryImage<F>, Error> {
         let arch = match &multi_arch_disambiguator {
             Some(MultiArchDisambiguator::Arch(arch)) => Some(arch.as_str()),
+            Some(MultiArchDisambiguator::DebugIdAndArch(_, arch)) => Some(arch.as_str()),
             _ => None,
         };
         let dyld_shared_cache_paths = self
@@ -524,7 +712,13 @@ where
                 .load_binary_from_dyld_cache(dyld_cache_path, dylib_path.to_owned())
                 .await;
             match (&multi_arch_disambiguator, binary_res) {
-                (Some(MultiArchDisambiguator::DebugId(expected_debug_id)), Ok(binary)) => {
+                (
+                    Some(
+                        MultiArchDisambiguator::DebugId(expected_debug_id)
+                        | MultiArchDisambiguator::DebugIdAndArch(expected_debug_id, _),
+                    ),
+                    Ok(binary),
+                ) => {
                     if binary.debug_id().as_ref() == Some(expected_debug_id) {
                         return Ok(binary);
                     }
@@ -547,6 +741,7 @@ where
     ) -> Result<SymbolMap<FL>, Error> {
         let arch = match &multi_arch_disambiguator {
             Some(MultiArchDisambiguator::Arch(arch)) => Some(arch.as_str()),
+            Some(MultiArchDisambiguator::DebugIdAndArch(_, arch)) => Some(arch.as_str()),
             _ => None,
         };
         let dyld_shared_cache_paths = self
@@ -563,7 +758,13 @@ where
             )
             .await;
             match (&multi_arch_disambiguator, symbol_map_res) {
-                (Some(MultiArchDisambiguator::DebugId(expected_debug_id)), Ok(symbol_map)) => {
+                (
+                    Some(
+                        MultiArchDisambiguator::DebugId(expected_debug_id)
+                        | MultiArchDisambiguator::DebugIdAndArch(expected_debug_id, _),
+                    ),
+                    Ok(symbol_map),
+                ) => {
                     if &symbol_map.debug_id() == expected_debug_id {
                         return Ok(symbol_map);
                     }
@@ -604,10 +805,12 @@ where
                     .await
                 }
                 FileKind::MachOFat32 | FileKind::MachOFat64 => {
+                    let file_identity = file_location.to_string();
                     let member = macho::get_fat_archive_member(
                         &file_contents,
                         file_kind,
                         multi_arch_disambiguator,
+                        self.uuid_cache.map(|cache| (cache, file_identity.as_str())),
                     )?;
                     macho::get_symbol_map_for_fat_archive_member(
                         file_location,
@@ -700,10 +903,12 @@ where
             | FileKind::Pe32
             | FileKind::Pe64 => BinaryImageInner::Normal(file_contents, file_kind),
             FileKind::MachOFat32 | FileKind::MachOFat64 => {
+                let file_identity = file_location.to_string();
                 let member = macho::get_fat_archive_member(
                     &file_contents,
                     file_kind,
                     multi_arch_disambiguator,
+                    self.uuid_cache.map(|cache| (cache, file_identity.as_str())),
                 )?;
                 let (offset, size) = member.offset_and_size;
                 let arch = member.arch;
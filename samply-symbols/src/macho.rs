@@ -12,7 +12,9 @@ use object::macho::{self, FatHeader, LinkeditDataCommand, MachHeader32, MachHead
 use object::read::macho::{FatArch, LoadCommandIterator, MachHeader};
 use object::read::{File, Object, ObjectSection};
 use object::{Endianness, FileKind, ReadRef};
+use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::sync::Mutex;
 use uuid::Uuid;
 
 /// Converts a cpu type/subtype pair into the architecture name.
@@ -50,12 +52,16 @@ fn macho_arch_name_for_cpu_type(cputype: u32, cpusubtype: u32) -> Option<&'stati
 /// `disambiguator`, if found.
 ///
 /// If `disambiguator` is `None`, this will always return [`Error::NoDisambiguatorForFatArchive`].
+///
+/// If `uuid_cache` is supplied, it is consulted for the UUIDs of the members, with the
+/// string being an identifier for the file, for example its path.
 pub fn get_fat_archive_member(
     file_contents: &FileContentsWrapper<impl FileContents>,
     archive_kind: FileKind,
     disambiguator: Option<MultiArchDisambiguator>,
+    uuid_cache: Option<(&UuidCache, &str)>,
 ) -> Result<FatArchiveMember, Error> {
-    let mut members = get_fat_archive_members(file_contents, archive_kind)?;
+    let mut members = get_fat_archive_members(file_contents, archive_kind, uuid_cache)?;
 
     if members.is_empty() {
         return Err(Error::EmptyFatArchive);
@@ -84,9 +90,96 @@ pub fn get_fat_archive_member(
     }
 }
 
+/// Caches the UUIDs of the members of universal binaries, so that repeated lookups
+/// in the same file don't need to parse the mach-O header of each member again.
+///
+/// Entries are keyed by `(file identity, member offset, member size)`. The file
+/// identity is a string chosen by the caller, usually the file path. Once the cache
+/// holds `capacity` entries, inserting a new entry evicts the least recently used one.
+#[derive(Debug)]
+pub struct UuidCache {
+    inner: Mutex<UuidCacheInner>,
+}
+
+#[derive(Debug)]
+struct UuidCacheInner {
+    capacity: usize,
+    /// The value and the time of the last use of each entry.
+    entries: HashMap<(String, u64, u64), (Option<Uuid>, u64)>,
+    clock: u64,
+}
+
+impl Default for UuidCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UuidCache {
+    /// The number of entries kept by [`UuidCache::new`].
+    pub const DEFAULT_CAPACITY: usize = 1024;
+
+    pub fn new() -> Self {
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+
+    /// Creates a cache which keeps at most `capacity` entries.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(UuidCacheInner {
+                capacity,
+                entries: HashMap::new(),
+                clock: 0,
+            }),
+        }
+    }
+
+    fn get(&self, file_identity: &str, start: u64, size: u64) -> Option<Option<Uuid>> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        let (uuid, last_use) = inner
+            .entries
+            .get_mut(&(file_identity.to_owned(), start, size))?;
+        *last_use = clock;
+        Some(*uuid)
+    }
+
+    fn insert(&self, file_identity: &str, start: u64, size: u64, uuid: Option<Uuid>) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.capacity == 0 {
+            return;
+        }
+        let key = (file_identity.to_owned(), start, size);
+        if inner.entries.len() >= inner.capacity && !inner.entries.contains_key(&key) {
+            let least_recently_used = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_use))| *last_use)
+                .map(|(key, _)| key.clone());
+            if let Some(least_recently_used) = least_recently_used {
+                inner.entries.remove(&least_recently_used);
+            }
+        }
+        inner.clock += 1;
+        let clock = inner.clock;
+        inner.entries.insert(key, (uuid, clock));
+    }
+
+    /// The number of entries in the cache.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 pub fn get_fat_archive_members_impl<FC: FileContents, FA: FatArch>(
     file_contents: &FileContentsWrapper<FC>,
     arches: &[FA],
+    uuid_cache: Option<(&UuidCache, &str)>,
 ) -> Result<Vec<FatArchiveMember>, Error> {
     let mut members = Vec::new();
 
@@ -94,9 +187,19 @@ pub fn get_fat_archive_members_impl<FC: FileContents, FA: FatArch>(
         let (cputype, cpusubtype) = (fat_arch.cputype(), fat_arch.cpusubtype());
         let arch = macho_arch_name_for_cpu_type(cputype, cpusubtype).map(ToString::to_string);
         let (start, size) = fat_arch.file_range();
-        let file =
-            File::parse(file_contents.range(start, size)).map_err(Error::MachOHeaderParseError)?;
-        let uuid = file.mach_uuid().ok().flatten().map(Uuid::from_bytes);
+        let cached_uuid =
+            uuid_cache.and_then(|(cache, file_identity)| cache.get(file_identity, start, size));
+        let uuid = match cached_uuid {
+            Some(uuid) => uuid,
+            None => {
+                let file = parse_macho_file(file_contents.range(start, size))?;
+                let uuid = file.mach_uuid().ok().flatten().map(Uuid::from_bytes);
+                if let Some((cache, file_identity)) = uuid_cache {
+                    cache.insert(file_identity, start, size, uuid);
+                }
+                uuid
+            }
+        };
         members.push(FatArchiveMember {
             offset_and_size: (start, size),
             cputype,
@@ -109,6 +212,89 @@ pub fn get_fat_archive_members_impl<FC: FileContents, FA: FatArch>(
     Ok(members)
 }
 
+/// Parses a single mach-O image which starts at offset 0 of `data`.
+///
+/// Fails with [`Error::TruncatedFile`] if `data` is too short to hold the mach
+/// header and the load commands, so that an incomplete download can be told apart
+/// from a file which isn't a mach-O file. Fails with [`Error::UnexpectedFileKind`]
+/// if `data` is a different kind of object file, e.g. an ELF file.
+fn parse_macho_file<'data, R: ReadRef<'data>>(data: R) -> Result<File<'data, R>, Error> {
+    if let Some((expected, actual)) = truncated_macho_size(data) {
+        return Err(Error::TruncatedFile { expected, actual });
+    }
+    match FileKind::parse(data).map_err(Error::MachOHeaderParseError)? {
+        FileKind::MachO32 | FileKind::MachO64 => {}
+        file_kind => return Err(Error::UnexpectedFileKind(file_kind)),
+    }
+    File::parse(data).map_err(Error::MachOHeaderParseError)
+}
+
+/// Returns `Some((expected, actual))` if `data` starts with a mach header but is
+/// shorter than the header plus the load commands.
+fn truncated_macho_size<'data, R: ReadRef<'data>>(data: R) -> Option<(u64, u64)> {
+    let actual = data.len().ok()?;
+    let magic: [u8; 4] = data.read_bytes_at(0, 4).ok()?.try_into().ok()?;
+    let (header_size, big_endian) = match magic {
+        [0xfe, 0xed, 0xfa, 0xce] => (28, true),
+        [0xce, 0xfa, 0xed, 0xfe] => (28, false),
+        [0xfe, 0xed, 0xfa, 0xcf] => (32, true),
+        [0xcf, 0xfa, 0xed, 0xfe] => (32, false),
+        _ => return None,
+    };
+    if actual < header_size {
+        return Some((header_size, actual));
+    }
+    // sizeofcmds is at the same offset in 32-bit and 64-bit mach headers.
+    let sizeofcmds: [u8; 4] = data.read_bytes_at(20, 4).ok()?.try_into().ok()?;
+    let sizeofcmds = if big_endian {
+        u32::from_be_bytes(sizeofcmds)
+    } else {
+        u32::from_le_bytes(sizeofcmds)
+    };
+    let expected = header_size + u64::from(sizeofcmds);
+    if actual < expected {
+        Some((expected, actual))
+    } else {
+        None
+    }
+}
+
+/// Lists the architectures in a mach-O file.
+///
+/// For a universal binary ("fat archive"), this returns one entry per member. For a
+/// regular mach-O file, this returns a single entry which covers the entire file.
+/// This can be used to pick a member by CPU type if the debug ID isn't known yet.
+pub fn list_fat_archive_members(
+    file_contents: &FileContentsWrapper<impl FileContents>,
+) -> Result<Vec<FatArchiveMember>, Error> {
+    let file_kind = FileKind::parse(file_contents)
+        .map_err(|_| Error::InvalidInputError("Could not determine the file kind"))?;
+    match file_kind {
+        FileKind::MachOFat32 | FileKind::MachOFat64 => {
+            get_fat_archive_members(file_contents, file_kind, None)
+        }
+        FileKind::MachO32 | FileKind::MachO64 => {
+            let macho_data = MachOData::new(file_contents, 0, file_kind == FileKind::MachO64);
+            let (cputype, cpusubtype) = macho_data
+                .get_cpu_type()
+                .ok_or(Error::InvalidInputError("Could not parse the mach header"))?;
+            let arch = macho_arch_name_for_cpu_type(cputype, cpusubtype).map(ToString::to_string);
+            let file = parse_macho_file(file_contents)?;
+            let uuid = file.mach_uuid().ok().flatten().map(Uuid::from_bytes);
+            Ok(vec![FatArchiveMember {
+                offset_and_size: (0, file_contents.len()),
+                cputype,
+                cpusubtype,
+                arch,
+                uuid,
+            }])
+        }
+        file_kind => Err(Error::UnexpectedFileKind(file_kind)),
+    }
+}
+
+/// A member of a universal binary ("fat archive"), or the entire file for a
+/// regular mach-O file.
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct FatArchiveMember {
     pub offset_and_size: (u64, u64),
@@ -167,6 +353,15 @@ impl FatArchiveMember {
                     None
                 }
             }
+            MultiArchDisambiguator::DebugIdAndArch(expected_debug_id, expected_arch) => {
+                if self.uuid.map(DebugId::from_uuid) == Some(*expected_debug_id)
+                    && self.arch.as_deref() == Some(expected_arch)
+                {
+                    Some(0)
+                } else {
+                    None
+                }
+            }
         }
     }
 }
@@ -174,15 +369,16 @@ impl FatArchiveMember {
 pub fn get_fat_archive_members(
     file_contents: &FileContentsWrapper<impl FileContents>,
     archive_kind: FileKind,
+    uuid_cache: Option<(&UuidCache, &str)>,
 ) -> Result<Vec<FatArchiveMember>, Error> {
     if archive_kind == FileKind::MachOFat64 {
         let arches = FatHeader::parse_arch64(file_contents)
             .map_err(|e| Error::ObjectParseError(archive_kind, e))?;
-        get_fat_archive_members_impl(file_contents, arches)
+        get_fat_archive_members_impl(file_contents, arches, uuid_cache)
     } else {
         let arches = FatHeader::parse_arch32(file_contents)
             .map_err(|e| Error::ObjectParseError(archive_kind, e))?;
-        get_fat_archive_members_impl(file_contents, arches)
+        get_fat_archive_members_impl(file_contents, arches, uuid_cache)
     }
 }
 
@@ -389,7 +585,7 @@ impl<T: FileContents> MachSymbolMapData<T> {
 
 impl<T: FileContents + 'static> SymbolMapDataOuterTrait for MachSymbolMapData<T> {
     fn make_symbol_map_data_mid(&self) -> Result<Box<dyn SymbolMapDataMidTrait + '_>, Error> {
-        let macho_file = File::parse(&self.file_data).map_err(Error::MachOHeaderParseError)?;
+        let macho_file = parse_macho_file(&self.file_data)?;
         let macho_data = MachOData::new(&self.file_data, 0, macho_file.is_64());
         let arch = macho_data.get_arch();
         let function_addresses_computer = MachOFunctionAddressesComputer { macho_data };
@@ -442,7 +638,7 @@ impl<T: FileContents> MachOFatArchiveMemberData<T> {
 impl<T: FileContents + 'static> SymbolMapDataOuterTrait for MachOFatArchiveMemberData<T> {
     fn make_symbol_map_data_mid(&self) -> Result<Box<dyn SymbolMapDataMidTrait + '_>, Error> {
         let range_data = self.data();
-        let macho_file = File::parse(range_data).map_err(Error::MachOHeaderParseError)?;
+        let macho_file = parse_macho_file(range_data)?;
         let macho_data = MachOData::new(range_data, 0, macho_file.is_64());
         let arch = macho_data.get_arch();
         let function_addresses_computer = MachOFunctionAddressesComputer { macho_data };
@@ -568,17 +764,23 @@ impl<'data, R: ReadRef<'data>> MachOData<'data, R> {
     }
 
     pub fn get_arch(&self) -> Option<&'static str> {
+        let (cputype, cpusubtype) = self.get_cpu_type()?;
+        macho_arch_name_for_cpu_type(cputype, cpusubtype)
+    }
+
+    /// Returns the (cputype, cpusubtype) pair from the mach header.
+    pub fn get_cpu_type(&self) -> Option<(u32, u32)> {
         if self.is_64 {
-            self.get_arch_impl::<MachHeader64<Endianness>>()
+            self.get_cpu_type_impl::<MachHeader64<Endianness>>()
         } else {
-            self.get_arch_impl::<MachHeader32<Endianness>>()
+            self.get_cpu_type_impl::<MachHeader32<Endianness>>()
         }
     }
 
-    fn get_arch_impl<M: MachHeader>(&self) -> Option<&'static str> {
+    fn get_cpu_type_impl<M: MachHeader>(&self) -> Option<(u32, u32)> {
         let header = M::parse(self.data, self.header_offset).ok()?;
         let endian = header.endian().ok()?;
-        macho_arch_name_for_cpu_type(header.cputype(endian), header.cpusubtype(endian))
+        Some((header.cputype(endian), header.cpusubtype(endian)))
     }
 
     fn load_command_iter<M: MachHeader>(
@@ -633,3 +835,157 @@ fn read_uleb128(mut bytes: &[u8]) -> Option<(u64, &[u8])> {
     }
     None
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::FileAndPathHelperResult;
+    use std::ops::Range;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Counts the reads which touch the contents of the fat archive members,
+    /// i.e. everything after the fat header.
+    struct MemberReadCountingFileContents {
+        data: Vec<u8>,
+        first_member_offset: u64,
+        member_reads: Arc<AtomicUsize>,
+    }
+
+    impl MemberReadCountingFileContents {
+        fn record_read(&self, offset: u64) {
+            if offset >= self.first_member_offset {
+                self.member_reads.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    impl FileContents for MemberReadCountingFileContents {
+        fn len(&self) -> u64 {
+            self.data.len() as u64
+        }
+
+        fn read_bytes_at(&self, offset: u64, size: u64) -> FileAndPathHelperResult<&[u8]> {
+            self.record_read(offset);
+            self.data.read_bytes_at(offset, size)
+        }
+
+        fn read_bytes_at_until(
+            &self,
+            range: Range<u64>,
+            delimiter: u8,
+        ) -> FileAndPathHelperResult<&[u8]> {
+            self.record_read(range.start);
+            self.data.read_bytes_at_until(range, delimiter)
+        }
+
+        fn read_bytes_into(
+            &self,
+            buffer: &mut Vec<u8>,
+            offset: u64,
+            size: usize,
+        ) -> FileAndPathHelperResult<()> {
+            self.record_read(offset);
+            self.data.read_bytes_into(buffer, offset, size)
+        }
+    }
+
+    #[test]
+    fn uuid_cache_avoids_reparsing_member_headers() {
+        let path =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../fixtures/macos-ci/firefox");
+        let data = std::fs::read(path).unwrap();
+        let members = list_fat_archive_members(&FileContentsWrapper::new(data.clone())).unwrap();
+        let first_member_offset = members.iter().map(|m| m.offset_and_size.0).min().unwrap();
+        let member_read_count = Arc::new(AtomicUsize::new(0));
+        let file_contents = FileContentsWrapper::new(MemberReadCountingFileContents {
+            data,
+            first_member_offset,
+            member_reads: member_read_count.clone(),
+        });
+        let member_reads = || member_read_count.load(Ordering::SeqCst);
+
+        let cache = UuidCache::new();
+        let cache_key = Some((&cache, "macos-ci/firefox"));
+        let first =
+            get_fat_archive_members(&file_contents, FileKind::MachOFat32, cache_key).unwrap();
+        assert_eq!(first, members);
+        let reads_after_first_lookup = member_reads();
+        assert!(reads_after_first_lookup > 0);
+
+        let second =
+            get_fat_archive_members(&file_contents, FileKind::MachOFat32, cache_key).unwrap();
+        assert_eq!(second, members);
+        assert_eq!(member_reads(), reads_after_first_lookup);
+    }
+
+    #[test]
+    fn uuid_cache_evicts_least_recently_used_entry() {
+        let cache = UuidCache::with_capacity(2);
+        let uuid = |byte| Some(Uuid::from_bytes([byte; 16]));
+        cache.insert("a", 0, 10, uuid(1));
+        cache.insert("b", 0, 10, uuid(2));
+        assert_eq!(cache.get("a", 0, 10), Some(uuid(1)));
+        cache.insert("c", 0, 10, None);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("a", 0, 10), Some(uuid(1)));
+        assert_eq!(cache.get("b", 0, 10), None);
+        assert_eq!(cache.get("c", 0, 10), Some(None));
+    }
+
+    #[test]
+    fn truncated_macho_file() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../fixtures/macos-local/libmozglue.dylib");
+        let mut data = std::fs::read(path).unwrap();
+        data.truncate(100);
+        match list_fat_archive_members(&FileContentsWrapper::new(data)) {
+            Err(Error::TruncatedFile { expected, actual }) => {
+                assert!(expected > 100);
+                assert_eq!(actual, 100);
+            }
+            other => panic!("expected TruncatedFile, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn text_segment_without_name() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../fixtures/macos-local/firefox");
+        let mut data = std::fs::read(path).unwrap();
+        let base = |data: &[u8]| {
+            let file = object::File::parse(data).unwrap();
+            (
+                crate::relative_address_base(&file),
+                crate::macho_text_segment_address(&file),
+            )
+        };
+        assert_eq!(base(&data), (0x100000000, Some(0x100000000)));
+
+        // Rename the __TEXT segment. The segment_command_64 has the segment name
+        // at offset 8 and initprot at offset 60.
+        let segname = b"__TEXT\0\0\0\0\0\0\0\0\0\0";
+        let segname_offset = data
+            .windows(segname.len())
+            .position(|window| window == segname)
+            .unwrap();
+        data[segname_offset..][..6].copy_from_slice(b"__CODE");
+        assert_eq!(base(&data), (0x100000000, Some(0x100000000)));
+
+        // Make it non-executable, so that no text segment can be found.
+        let initprot_offset = segname_offset - 8 + 60;
+        data[initprot_offset..][..4].copy_from_slice(&object::macho::VM_PROT_READ.to_le_bytes());
+        assert_eq!(base(&data), (0, None));
+    }
+
+    #[test]
+    fn non_macho_file() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../fixtures/other/example-linux");
+        let data = std::fs::read(path).unwrap();
+        match list_fat_archive_members(&FileContentsWrapper::new(data)) {
+            Err(Error::UnexpectedFileKind(FileKind::Elf64)) => {}
+            other => panic!("expected UnexpectedFileKind, got {other:?}"),
+        }
+    }
+}
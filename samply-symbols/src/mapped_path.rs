@@ -95,6 +95,31 @@ impl MappedPath {
         }
     }
 
+    /// Returns a URL from which the raw contents of this file can be downloaded, if
+    /// the hosting service has such a URL.
+    ///
+    /// This is the inverse of [`MappedPath::from_url`]. It returns `None` for git
+    /// repositories which aren't hosted on GitHub, and for cargo packages.
+    pub fn to_url(&self) -> Option<String> {
+        match self {
+            MappedPath::Git { repo, path, rev } => {
+                let repo = repo.strip_prefix("github.com/")?;
+                Some(format!(
+                    "https://raw.githubusercontent.com/{repo}/{rev}/{path}"
+                ))
+            }
+            MappedPath::Hg { repo, path, rev } => {
+                Some(format!("https://{repo}/raw-file/{rev}/{path}"))
+            }
+            MappedPath::S3 {
+                bucket,
+                digest,
+                path,
+            } => Some(format!("https://{bucket}.s3.amazonaws.com/{digest}/{path}")),
+            MappedPath::Cargo { .. } => None,
+        }
+    }
+
     /// Create a short, display-friendly form of this path.
     pub fn display_path(&self) -> String {
         match self {
@@ -402,6 +427,23 @@ mod test {
         assert_eq!(&roundtripped, s);
     }
 
+    fn test_url_roundtrip(url: &str) {
+        let mapped_path = MappedPath::from_url(url).unwrap();
+        assert_eq!(mapped_path.to_url().as_deref(), Some(url));
+    }
+
+    #[test]
+    fn url_roundtrips() {
+        test_url_roundtrip("https://raw.githubusercontent.com/baldurk/renderdoc/v1.15/renderdoc/data/glsl/gl_texsample.h");
+        test_url_roundtrip("https://hg.mozilla.org/mozilla-central/raw-file/1706d4d54ec68fae1280305b70a02cb24c16ff68/mozglue/baseprofiler/core/ProfilerBacktrace.cpp");
+        test_url_roundtrip("https://gecko-generated-sources.s3.amazonaws.com/7a1db5dfd0061d0e0bcca227effb419a20439aef4f6c4e9cd391a9f136c6283e89043d62e63e7edbd63ad81c339c401092bcfeff80f74f9cae8217e072f0c6f3/x86_64-pc-windows-msvc/release/build/swgl-59e3a0e09f56f4ea/out/brush_solid_DEBUG_OVERDRAW.h");
+        let googlesource = MappedPath::from_special_path_str(
+            "git:chromium.googlesource.com/chromium/src:content/gpu/gpu_main.cc:4dac2548d4812df2aa4a90ac1fc8912363f4d59c",
+        )
+        .unwrap();
+        assert_eq!(googlesource.to_url(), None);
+    }
+
     #[test]
     fn roundtrips() {
         test_roundtrip("hg:hg.mozilla.org/mozilla-central:widget/cocoa/nsAppShell.mm:997f00815e6bc28806b75448c8829f0259d2cb28");
@@ -0,0 +1,72 @@
+use std::fs::File;
+use std::ops::Deref;
+use std::path::Path;
+
+/// A [`FileContents`](crate::FileContents) implementation for local files, backed by
+/// a memory map.
+///
+/// Empty files are not mapped, because mapping a zero-length file fails on some
+/// platforms; they are exposed as an empty slice instead.
+pub struct MmapFileContents(Option<memmap2::Mmap>);
+
+impl MmapFileContents {
+    /// Opens and maps the file at `path`.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        Self::from_file(&file)
+    }
+
+    /// Maps `file`. The file can be closed afterwards, the mapping stays valid.
+    pub fn from_file(file: &File) -> std::io::Result<Self> {
+        if file.metadata()?.len() == 0 {
+            return Ok(Self(None));
+        }
+        // Safety: The mapping is only sound as long as nobody modifies the file while
+        // it's mapped. Like all other users of memory-mapped symbol files, we assume
+        // that symbol files don't change underneath us.
+        let mmap = unsafe { memmap2::MmapOptions::new().map(file)? };
+        Ok(Self(Some(mmap)))
+    }
+}
+
+// `FileContents` is implemented for all `Deref<Target = [u8]>` types.
+impl Deref for MmapFileContents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Some(mmap) => mmap,
+            None => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::FileContents;
+
+    #[test]
+    fn map_fixture_file() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../fixtures/other/example-linux");
+        let contents = MmapFileContents::open(&path).unwrap();
+        let expected = std::fs::read(&path).unwrap();
+        assert_eq!(FileContents::len(&contents), expected.len() as u64);
+        assert_eq!(contents.read_bytes_at(0, 4).unwrap(), &expected[..4]);
+    }
+
+    #[test]
+    fn map_empty_file() {
+        let path = std::env::temp_dir().join(format!(
+            "samply-symbols-empty-mmap-test-{}",
+            std::process::id()
+        ));
+        File::create(&path).unwrap();
+        let contents = MmapFileContents::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        let contents = contents.unwrap();
+        assert!(FileContents::is_empty(&contents));
+        assert!(contents.read_bytes_at(0, 1).is_err());
+        assert_eq!(contents.read_bytes_at(0, 0).unwrap(), &[] as &[u8]);
+    }
+}
@@ -3,7 +3,7 @@ use object::read::ReadRef;
 use object::FileFlags;
 use uuid::Uuid;
 
-use crate::MappedPath;
+use crate::{FunctionNameStyle, MappedPath};
 
 use std::fmt::{Debug, Display};
 use std::future::Future;
@@ -83,6 +83,17 @@ pub enum MultiArchDisambiguator {
 
     /// Disambiguate by `DebugId`.
     DebugId(DebugId),
+
+    /// Disambiguate by `DebugId`, and pin the CPU architecture.
+    ///
+    /// Fat archives can contain multiple members with the same UUID, for example
+    /// an `arm64` slice and an `arm64e` slice built from the same sources. In that
+    /// case, a `DebugId` alone is ambiguous, and only the member whose architecture
+    /// matches the string is accepted.
+    ///
+    /// The string is a name for what mach-O calls the "CPU type" and "CPU subtype",
+    /// as returned by `macho_arch_name_for_cpu_type`.
+    DebugIdAndArch(DebugId, String),
 }
 
 /// An enum carrying an identifier for a binary. This is stores the same information
@@ -289,6 +300,12 @@ pub trait FileAndPathHelper<'h> {
     /// `load_file` for each until it succeeds and finds a file whose contents
     /// match the breakpad ID. Any remaining paths are discarded.
     ///
+    /// If the same file can be obtained from multiple sources, for example from
+    /// several symbol servers, return one candidate per source, in the order in
+    /// which they should be tried. Then a source which is down, or which has a file
+    /// with a different ID, is skipped and the next source is tried; `load_file`
+    /// doesn't need to implement any failover itself.
+    ///
     /// # Arguments
     ///
     ///  - `debug_name`: On Windows, this is the filename of the associated PDB
@@ -336,6 +353,21 @@ pub trait FileAndPathHelper<'h> {
         Ok(Vec::new())
     }
 
+    /// Called when an external object file (from the "object map" of a mach-O debug
+    /// file) needs to be loaded. Returns additional locations at which the file can
+    /// be found, for example in a symbol cache, if it's no longer at its recorded
+    /// path. They are tried in order after the location returned by
+    /// `FileLocation::location_for_external_object_file`, until one of them can be
+    /// loaded and defines the symbol in `ExternalFileRef::identity`. `object_file` is
+    /// the recorded path, with `..` components collapsed.
+    fn get_candidate_paths_for_external_object_file(
+        &self,
+        _original_file_location: &Self::FL,
+        _object_file: &str,
+    ) -> FileAndPathHelperResult<Vec<Self::FL>> {
+        Ok(Vec::new())
+    }
+
     /// This method is the entry point for file access during symbolication.
     /// The implementer needs to return an object which implements the `FileContents` trait.
     /// This method is asynchronous, but once it returns, the file data needs to be
@@ -417,7 +449,11 @@ pub trait FileContents: Send + Sync {
 
 /// The debug information (function name, file path, line number) for a single frame
 /// at the looked-up address.
+///
+/// New fields may be added to this struct, so use [`FrameDebugInfo::new`] to create
+/// one outside of this crate.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct FrameDebugInfo {
     /// The function name for this frame, if known.
     pub function: Option<String>,
@@ -425,6 +461,32 @@ pub struct FrameDebugInfo {
     pub file_path: Option<SourceFilePath>,
     /// The line number for this frame, if known.
     pub line_number: Option<u32>,
+    /// The name of the compiland (object file) which contains this frame's code, if
+    /// known. This is only filled in for PDB symbols, from the module whose section
+    /// contribution covers the looked-up address.
+    pub compiland: Option<String>,
+    /// Whether this frame is an inline call site. For each looked-up address, the
+    /// frames are ordered from inside to outside, so this is true for all frames
+    /// except the last one, which is the frame of the function that contains the
+    /// address in the binary.
+    pub is_inlined: bool,
+}
+
+impl FrameDebugInfo {
+    /// Creates a frame which is not an inline call site and has no compiland.
+    pub fn new(
+        function: Option<String>,
+        file_path: Option<SourceFilePath>,
+        line_number: Option<u32>,
+    ) -> Self {
+        Self {
+            function,
+            file_path,
+            line_number,
+            compiland: None,
+            is_inlined: false,
+        }
+    }
 }
 
 /// A trait which abstracts away the token that's passed to the [`FileAndPathHelper::load_file`]
@@ -466,6 +528,25 @@ pub trait FileLocation: Clone + Display {
     /// Called on the location of a Breakpad sym file, to get a location for its
     /// corresponding symindex file.
     fn location_for_breakpad_symindex(&self) -> Option<Self>;
+
+    /// Called on the location of an ELF binary or debug file which was compiled with
+    /// `-gsplit-dwarf`, to get a location for its DWARF package file. This file is
+    /// usually next to the binary and has a `.dwp` suffix, e.g. `libxul.so.dwp`.
+    ///
+    /// The default implementation returns `None`, i.e. DWARF package files are not
+    /// loaded.
+    fn location_for_dwp(&self) -> Option<Self> {
+        None
+    }
+
+    /// Called on the location of an ELF binary or debug file in order to create a
+    /// location for a split DWARF `.dwo` file, based on the path found in one of
+    /// its skeleton units. This is usually an absolute path.
+    ///
+    /// The default implementation returns `None`, i.e. `.dwo` files are not loaded.
+    fn location_for_dwo(&self, _dwo_path: &str) -> Option<Self> {
+        None
+    }
 }
 
 /// The path of a source file, as found in the debug info.
@@ -581,6 +662,8 @@ impl SourceFilePath {
 ///
 ///  - For Windows binaries, the base address is the "image base address".
 ///  - For mach-O binaries, the base address is the vmaddr of the __TEXT segment.
+///    If there is no segment with that name, the first executable segment is
+///    used instead, see [`macho_text_segment_address`].
 ///  - For ELF binaries, the base address is the vmaddr of the *first* segment,
 ///    i.e. the vmaddr of the first "LOAD" ELF command.
 ///
@@ -609,13 +692,10 @@ pub fn relative_address_base<'data: 'file, 'file>(
     object_file: &'file impl object::Object<'data, 'file>,
 ) -> u64 {
     use object::read::ObjectSegment;
-    if let Some(text_segment) = object_file
-        .segments()
-        .find(|s| s.name() == Ok(Some("__TEXT")))
-    {
+    if let Some(text_segment_address) = macho_text_segment_address(object_file) {
         // This is a mach-O image. "Relative addresses" are relative to the
         // vmaddr of the __TEXT segment.
-        return text_segment.address();
+        return text_segment_address;
     }
 
     if let FileFlags::Elf { .. } = object_file.flags() {
@@ -627,9 +707,51 @@ pub fn relative_address_base<'data: 'file, 'file>(
     }
 
     // For PE binaries, relative_address_base() returns the image base address.
+    // For mach-O images without a text segment, this is zero.
     object_file.relative_address_base()
 }
 
+/// Returns the vmaddr of the text segment of a mach-O image, i.e. the address
+/// which relative addresses are relative to.
+///
+/// This is the segment named `__TEXT`. Some binaries, for example kexts or the
+/// output of unusual linkers, don't have a segment with that name; for those, the
+/// first segment whose initial protection includes `VM_PROT_EXECUTE` is used.
+/// The name is checked first because some images have a read-only `__TEXT`
+/// segment, which contains the mach header, followed by a separate executable
+/// segment such as `__TEXT_EXEC`; the profiler computes addresses relative to the
+/// former.
+///
+/// Returns `None` for non-mach-O files, and for mach-O files which have neither
+/// kind of segment.
+pub fn macho_text_segment_address<'data: 'file, 'file>(
+    object_file: &'file impl object::Object<'data, 'file>,
+) -> Option<u64> {
+    use object::read::ObjectSegment;
+    use object::SegmentFlags;
+    if let Some(text_segment) = object_file
+        .segments()
+        .find(|s| s.name() == Ok(Some("__TEXT")))
+    {
+        return Some(text_segment.address());
+    }
+    let executable_segment = object_file.segments().find(|s| match s.flags() {
+        SegmentFlags::MachO { initprot, .. } => initprot & object::macho::VM_PROT_EXECUTE != 0,
+        _ => false,
+    })?;
+    Some(executable_segment.address())
+}
+
+/// A problem with a symbol file which doesn't prevent lookups, but which may
+/// cause the lookup results to be wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolMapWarning {
+    /// The mach-O file has neither a `__TEXT` segment nor an executable segment,
+    /// so relative addresses were assumed to be relative to address zero. If that
+    /// is wrong, every address is translated incorrectly.
+    NoMachOTextSegment,
+}
+
 /// The symbol for a function.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SymbolInfo {
@@ -648,6 +770,11 @@ pub struct AddressInfo {
     pub symbol: SymbolInfo,
     /// Information about the frames at the looked up address, from the debug info.
     pub frames: FramesLookupResult,
+    /// The name of the section which contains the looked up address, for example
+    /// `__text`, `__stubs`, `.text` or `.plt`. This is only known for symbol maps
+    /// which are created from binaries or from ELF / mach-O debug files, not for
+    /// PDB, Breakpad or JITDUMP files.
+    pub section_name: Option<String>,
 }
 
 /// Contains address debug info (inlined functions, file names, line numbers) if
@@ -676,10 +803,31 @@ pub enum FramesLookupResult {
     /// info must be obtained from those original files.
     External(ExternalFileAddressRef),
 
+    /// Debug info for this address is in a split DWARF `.dwo` file which hasn't
+    /// been loaded yet. Load it with `SymbolManager::load_dwo_file`, hand it to
+    /// the symbol map with `SymbolMap::add_dwo_file`, and then look up the address
+    /// again.
+    ///
+    /// This case is only hit for ELF binaries which were compiled with
+    /// `-gsplit-dwarf` and which don't have a DWARF package file (`.dwp`). Each
+    /// `.dwo` file is only loaded once the first address in it is looked up.
+    NeedDwo(DwoRef),
+
     /// No debug info is available.
     Unavailable,
 }
 
+/// Information to find a split DWARF `.dwo` file, to be passed to
+/// `SymbolManager::load_dwo_file`.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DwoRef {
+    /// The path to the `.dwo` file, as specified in the skeleton unit. Relative
+    /// `DW_AT_dwo_name` paths have been resolved against `DW_AT_comp_dir`.
+    pub path: String,
+    /// The DWO ID of the split unit, which the `.dwo` file needs to match.
+    pub dwo_id: u64,
+}
+
 /// Information to find an external file and an address within that file, to be
 /// passed to `SymbolManager::lookup_external`.
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -698,6 +846,21 @@ pub struct ExternalFileRef {
     /// A mach-O arch string ("x86_64", "arm64" etc.) which is needed in the rare case
     /// that the external file is a fat binary.
     pub arch: Option<String>,
+    /// A symbol which the linked binary expects the external file to define. A file
+    /// which is found for this reference is only used if it defines the symbol, so
+    /// that a stale or unrelated file with the same name is not used for debug info.
+    /// `None` if there is nothing to check against.
+    pub identity: Option<ExternalFileIdentity>,
+}
+
+/// A symbol in an external file, see [`ExternalFileRef::identity`].
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ExternalFileIdentity {
+    /// The archive member which defines the symbol, if the external file is an
+    /// archive.
+    pub name_in_archive: Option<String>,
+    /// The name of the symbol, as bytes.
+    pub symbol_name: Vec<u8>,
 }
 
 /// Information to find an address within an external file, for debug info lookup.
@@ -712,6 +875,14 @@ pub struct ExternalFileAddressInFileRef {
     pub symbol_name: Vec<u8>,
     /// The address to look up, as a relative offset from the function symbol address.
     pub offset_from_symbol: u32,
+    /// The inline depth limit of the symbol map which returned this reference, see
+    /// `SymbolMap::set_max_inline_depth`. It is applied to the frames from the
+    /// external file.
+    pub max_inline_depth: Option<usize>,
+    /// The function name style of the symbol map which returned this reference, see
+    /// `SymbolMap::set_function_name_style`. It is applied to the frames from the
+    /// external file.
+    pub function_name_style: FunctionNameStyle,
 }
 
 /// Implementation for slices.
@@ -832,6 +1003,12 @@ impl std::fmt::Display for FileReadStats {
 
 /// A wrapper for a FileContents object. The wrapper provides some convenience methods
 /// and, most importantly, implements `ReadRef` for `&FileContentsWrapper`.
+///
+/// The wrapper doesn't cache reads itself: the slices it hands out come straight from
+/// the wrapped `FileContents`, which already has to keep them alive. If reading from
+/// the underlying source is expensive, wrap the source in
+/// [`FileContentsWithChunkedCaching`](crate::FileContentsWithChunkedCaching), which
+/// keeps the chunks it has read in memory. Memory-mapped sources don't need this.
 pub struct FileContentsWrapper<T: FileContents> {
     file_contents: T,
     len: u64,
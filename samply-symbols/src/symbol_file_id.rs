@@ -0,0 +1,79 @@
+use debugid::DebugId;
+use object::{FileKind, Object};
+use uuid::Uuid;
+
+use crate::shared::{ElfBuildId, FileContents, FileContentsWrapper};
+use crate::{windows, Error};
+
+/// The identifier which is embedded in a symbol file, see [`symbol_file_identity`].
+///
+/// Two files with the same identifier were produced by the same build, so this can
+/// be used as a cache key for the file or for data derived from it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SymbolFileId {
+    /// The `LC_UUID` of a mach-O file. dSYM files have the same UUID as their binary.
+    MachOUuid(Uuid),
+    /// The debug ID (GUID + age) of a PDB file, or the debug ID which a PE binary
+    /// stores for its PDB file.
+    PeOrPdbDebugId(DebugId),
+    /// The GNU build ID of an ELF file.
+    ElfBuildId(ElfBuildId),
+}
+
+/// Returns the identifier which is embedded in the file: the UUID of a mach-O file,
+/// the debug ID of a PE or PDB file, or the build ID of an ELF file.
+///
+/// Unlike the debug ID of a [`SymbolMap`](crate::SymbolMap), this never falls back
+/// to a hash of the file's code; files without an identifier fail with
+/// [`Error::NoSymbolFileId`]. Universal mach-O binaries contain one UUID per
+/// architecture, use [`list_fat_archive_members`](crate::list_fat_archive_members)
+/// for them.
+pub fn symbol_file_identity<F: FileContents>(
+    file_contents: &FileContentsWrapper<F>,
+) -> Result<SymbolFileId, Error> {
+    let file_kind = match FileKind::parse(file_contents) {
+        Ok(file_kind) => file_kind,
+        Err(_) if windows::is_pdb_file(file_contents) => {
+            return Ok(SymbolFileId::PeOrPdbDebugId(windows::debug_id_for_pdb(
+                file_contents,
+            )?));
+        }
+        Err(_) => {
+            return Err(Error::InvalidInputError(
+                "The file is not an ELF, mach-O, PE or PDB file",
+            ))
+        }
+    };
+    let file = match file_kind {
+        FileKind::Elf32
+        | FileKind::Elf64
+        | FileKind::MachO32
+        | FileKind::MachO64
+        | FileKind::Pe32
+        | FileKind::Pe64 => {
+            object::File::parse(file_contents).map_err(|e| Error::ObjectParseError(file_kind, e))?
+        }
+        FileKind::MachOFat32 | FileKind::MachOFat64 => {
+            return Err(Error::InvalidInputError(
+                "Universal mach-O binaries have one UUID per architecture",
+            ))
+        }
+        _ => {
+            return Err(Error::InvalidInputError(
+                "Input was Archive, Coff or Wasm format, which are unsupported for now",
+            ))
+        }
+    };
+    let id = if let Ok(Some(build_id)) = file.build_id() {
+        SymbolFileId::ElfBuildId(ElfBuildId::from_bytes(build_id))
+    } else if let Ok(Some(uuid)) = file.mach_uuid() {
+        SymbolFileId::MachOUuid(Uuid::from_bytes(uuid))
+    } else if let Ok(Some(pdb_info)) = file.pdb_info() {
+        let debug_id = DebugId::from_guid_age(&pdb_info.guid(), pdb_info.age())
+            .map_err(|_| Error::NoSymbolFileId(file_kind))?;
+        SymbolFileId::PeOrPdbDebugId(debug_id)
+    } else {
+        return Err(Error::NoSymbolFileId(file_kind));
+    };
+    Ok(id)
+}
@@ -1,13 +1,87 @@
 use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
 
 use debugid::DebugId;
 use yoke::{Yoke, Yokeable};
 
-use crate::{shared::AddressInfo, Error, FileLocation};
+use crate::{
+    shared::{
+        AddressInfo, DwoRef, ExternalFileRef, FrameDebugInfo, FramesLookupResult, SymbolInfo,
+        SymbolMapWarning,
+    },
+    Error, FileLocation, FunctionNameStyle, SourceFilePath,
+};
+
+/// The address range and line table of a single function, see
+/// [`SymbolMap::enumerate_functions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionLineInfo {
+    pub name: String,
+    /// The relative address of the function start.
+    pub start_address: u32,
+    /// The relative address of the function end (exclusive).
+    pub end_address: u32,
+    /// The line table, ordered by address. Each entry has the relative address at
+    /// which a new line record starts, and the file and line of the innermost frame
+    /// at that address. Empty if there is no line information for this function.
+    pub lines: Vec<(u32, Option<SourceFilePath>, Option<u32>)>,
+}
 
 pub struct SymbolMap<FL: FileLocation> {
     debug_file_location: FL,
     pub(crate) inner: Box<dyn SymbolMapTrait>,
+    max_inline_depth: Option<usize>,
+    function_name_style: FunctionNameStyle,
+    address_cache: Option<Mutex<AddressCache>>,
+}
+
+/// The cache for [`SymbolMap::set_address_cache_capacity`]. It stores the results
+/// before `max_inline_depth` and the function name style are applied. When the cache is full, the least recently
+/// used entry is evicted.
+struct AddressCache {
+    capacity: usize,
+    /// The cached result and the time of the last use of each address.
+    entries: HashMap<u32, (Option<AddressInfo>, u64)>,
+    /// The addresses in `entries`, keyed by the time of their last use.
+    addresses_by_last_use: BTreeMap<u64, u32>,
+    clock: u64,
+}
+
+impl AddressCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            addresses_by_last_use: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, address: u32) -> Option<Option<AddressInfo>> {
+        let (info, last_use) = self.entries.get_mut(&address)?;
+        self.clock += 1;
+        self.addresses_by_last_use.remove(last_use);
+        self.addresses_by_last_use.insert(self.clock, address);
+        *last_use = self.clock;
+        Some(info.clone())
+    }
+
+    fn insert(&mut self, address: u32, info: Option<AddressInfo>) {
+        if self.capacity == 0 || self.entries.contains_key(&address) {
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            let least_recently_used = self.addresses_by_last_use.keys().next().copied();
+            if let Some(last_use) = least_recently_used {
+                let address = self.addresses_by_last_use.remove(&last_use).unwrap();
+                self.entries.remove(&address);
+            }
+        }
+        self.clock += 1;
+        self.addresses_by_last_use.insert(self.clock, address);
+        self.entries.insert(address, (info, self.clock));
+    }
 }
 
 impl<FL: FileLocation> SymbolMap<FL> {
@@ -15,9 +89,75 @@ impl<FL: FileLocation> SymbolMap<FL> {
         Self {
             debug_file_location,
             inner,
+            max_inline_depth: None,
+            function_name_style: FunctionNameStyle::default(),
+            address_cache: None,
         }
     }
 
+    /// Limit the number of inlined frames which are returned for each address.
+    ///
+    /// Heavily-inlined C++ code can produce very deep inline stacks. If a limit
+    /// is set, only the innermost `max_inline_depth` inlined frames are kept. The
+    /// outermost frame, for the function that contains the address, is always
+    /// retained, so `Some(0)` returns only that frame. `None` means no limit.
+    ///
+    /// The limit also applies to the frames from external object files, which are
+    /// looked up with `SymbolManager::lookup_external` for the
+    /// [`FramesLookupResult::External`] results of this symbol map.
+    pub fn set_max_inline_depth(&mut self, max_inline_depth: Option<usize>) {
+        self.max_inline_depth = max_inline_depth;
+    }
+
+    /// Shorten the function names which lookups return, for example by removing
+    /// the parameter lists from C++ function signatures. The style is applied to
+    /// the symbol name and to the names of the outer and the inlined frames.
+    ///
+    /// Like the inline depth limit, the style also applies to the frames from
+    /// external object files. The default style keeps the full names.
+    pub fn set_function_name_style(&mut self, function_name_style: FunctionNameStyle) {
+        self.function_name_style = function_name_style;
+    }
+
+    /// Cache the results of `lookup_relative_address` (and `lookup_avma`) for up to
+    /// `capacity` addresses.
+    ///
+    /// Profiles usually contain the same hot addresses many times. With a cache,
+    /// repeated lookups of an address return a copy of the earlier result instead
+    /// of walking the debug info again. When the cache is full, the address which
+    /// was looked up least recently is evicted. `None` disables the cache and drops its contents;
+    /// this is the default.
+    pub fn set_address_cache_capacity(&mut self, capacity: Option<usize>) {
+        self.address_cache = capacity.map(|capacity| Mutex::new(AddressCache::new(capacity)));
+    }
+
+    fn apply_lookup_options(&self, mut info: AddressInfo) -> AddressInfo {
+        if !self.function_name_style.is_default() {
+            info.symbol.name = self.function_name_style.apply(info.symbol.name);
+            match &mut info.frames {
+                FramesLookupResult::Available(frames) => {
+                    self.function_name_style.apply_to_frames(frames);
+                }
+                FramesLookupResult::External(ext_address) => {
+                    ext_address.address_in_file.function_name_style = self.function_name_style;
+                }
+                FramesLookupResult::NeedDwo(_) | FramesLookupResult::Unavailable => {}
+            }
+        }
+        if let Some(max_inline_depth) = self.max_inline_depth {
+            match &mut info.frames {
+                FramesLookupResult::Available(frames) => {
+                    truncate_inline_frames(frames, max_inline_depth);
+                }
+                FramesLookupResult::External(ext_address) => {
+                    ext_address.address_in_file.max_inline_depth = Some(max_inline_depth);
+                }
+                FramesLookupResult::NeedDwo(_) | FramesLookupResult::Unavailable => {}
+            }
+        }
+        info
+    }
+
     pub fn debug_file_location(&self) -> &FL {
         &self.debug_file_location
     }
@@ -34,16 +174,268 @@ impl<FL: FileLocation> SymbolMap<FL> {
         self.inner.iter_symbols()
     }
 
+    /// Like `iter_symbols`, but also yields the size of each symbol, if known.
+    ///
+    /// If the symbol file doesn't record a size for a symbol, the size is the distance
+    /// to the next symbol.
+    pub fn iter_symbols_with_size(
+        &self,
+    ) -> Box<dyn Iterator<Item = (u32, Option<u32>, Cow<'_, str>)> + '_> {
+        self.inner.iter_symbols_with_size()
+    }
+
+    /// Look up the symbol covering `address`, without consulting any debug info.
+    ///
+    /// This is much cheaper than `lookup_relative_address` if you only need the
+    /// function name: no DWARF or PDB line information is parsed, and no external
+    /// object files are referenced. The offset of `address` into the function is
+    /// `address - symbol.address`.
+    pub fn lookup_symbol(&self, address: u32) -> Option<SymbolInfo> {
+        let mut symbol = self.inner.lookup_symbol(address)?;
+        symbol.name = self.function_name_style.apply(symbol.name);
+        Some(symbol)
+    }
+
+    /// Look up the name of the symbol covering `address` as it appears in the symbol
+    /// table, before demangling.
+    ///
+    /// This is useful for correlating results with other tools which work with
+    /// mangled names. For PDB files, this is the decorated name of the function's
+    /// public symbol, so it is `None` for functions with internal linkage. It is
+    /// always `None` for Breakpad symbol files, whose function names are already
+    /// formatted.
+    pub fn lookup_raw_symbol_name(&self, address: u32) -> Option<String> {
+        self.inner.lookup_raw_symbol_name(address)
+    }
+
     pub fn lookup_relative_address(&self, address: u32) -> Option<AddressInfo> {
-        self.inner.lookup_relative_address(address)
+        let lookup = |inner: &dyn SymbolMapTrait| inner.lookup_relative_address(address);
+        let info = match &self.address_cache {
+            Some(cache) => {
+                let cached_info = cache.lock().unwrap().get(address);
+                match cached_info {
+                    Some(info) => info,
+                    None => {
+                        let info = self.lookup_in_inner(lookup);
+                        // The result changes once the .dwo file has been added.
+                        let frames = info.as_ref().map(|info| &info.frames);
+                        if !matches!(frames, Some(FramesLookupResult::NeedDwo(_))) {
+                            cache.lock().unwrap().insert(address, info.clone());
+                        }
+                        info
+                    }
+                }
+            }
+            None => self.lookup_in_inner(lookup),
+        }?;
+        Some(self.apply_lookup_options(info))
+    }
+
+    /// Calls `lookup` on the inner symbol map. If the result asks for a `.dwo` file
+    /// which the debug file location refuses to create a location for, the file is
+    /// marked as unavailable and the lookup is repeated, so that callers only get
+    /// `FramesLookupResult::NeedDwo` for files which they can load.
+    fn lookup_in_inner(
+        &self,
+        lookup: impl Fn(&dyn SymbolMapTrait) -> Option<AddressInfo>,
+    ) -> Option<AddressInfo> {
+        let info = lookup(&*self.inner)?;
+        if let FramesLookupResult::NeedDwo(dwo_ref) = &info.frames {
+            if self
+                .debug_file_location
+                .location_for_dwo(&dwo_ref.path)
+                .is_none()
+            {
+                self.inner.add_dwo_file(&dwo_ref.path, None);
+                return lookup(&*self.inner);
+            }
+        }
+        Some(info)
+    }
+
+    /// Adds the contents of a `.dwo` file which a lookup asked for with
+    /// [`FramesLookupResult::NeedDwo`]. The contents can be obtained with
+    /// [`SymbolManager::load_dwo_file`](crate::SymbolManager::load_dwo_file).
+    ///
+    /// Pass `None` if the file could not be loaded. Lookups in the units of that
+    /// file then return the information from the skeleton unit, instead of asking
+    /// for the file again.
+    pub fn add_dwo_file(&self, dwo_ref: &DwoRef, contents: Option<Vec<u8>>) {
+        self.inner.add_dwo_file(&dwo_ref.path, contents);
+    }
+
+    /// Returns the split DWARF `.dwo` files which the skeleton units of this symbol
+    /// map refer to. This is only non-empty for ELF binaries compiled with
+    /// `-gsplit-dwarf`.
+    pub(crate) fn dwo_refs(&self) -> Vec<DwoRef> {
+        self.inner.dwo_refs()
+    }
+
+    /// If a DWARF package file (`.dwp`) was loaded for this symbol map, checks that
+    /// it contains the split units for all of `dwo_refs`. Returns `None` if there
+    /// is no package, in which case lookups use the `.dwo` files.
+    pub(crate) fn verify_dwp(&self, dwo_refs: &[DwoRef]) -> Option<Result<(), Error>> {
+        self.inner.verify_dwp(dwo_refs)
+    }
+
+    /// Returns the external files, i.e. object files and archives, which this symbol
+    /// map refers to for debug info. This is only non-empty for mach-O binaries whose
+    /// debug info was not linked into a dSYM, see [`FramesLookupResult::External`].
+    pub fn external_file_refs(&self) -> Vec<ExternalFileRef> {
+        self.inner.external_file_refs()
+    }
+
+    /// Returns the problems which were found when creating this symbol map and
+    /// which may make lookup results wrong, for example if the base address for
+    /// relative addresses had to be guessed.
+    pub fn warnings(&self) -> Vec<SymbolMapWarning> {
+        self.inner.warnings()
+    }
+
+    /// Returns the error which makes all lookups in this symbol map fail, if there
+    /// is one. In that case, lookups return `None` and the symbol map has no
+    /// symbols.
+    ///
+    /// Symbol maps usually read what lookups need when they are created, and the
+    /// creation fails if that's not possible. But PDB symbol maps only read the type
+    /// information and the global symbols for the first lookup, so that creating
+    /// them doesn't read these large streams. For them, this method reads the
+    /// streams if that hasn't happened yet, and returns the error if reading them
+    /// failed.
+    pub fn lookup_error(&self) -> Option<Error> {
+        self.inner.lookup_error()
+    }
+
+    /// Look up all the addresses in the range `start..end` at which the symbol or
+    /// the debug info changes, for example for annotating disassembly.
+    ///
+    /// The returned entries are ordered by address. The first entry is for `start`
+    /// (if `start` is covered by a symbol). Further entries are returned for every
+    /// symbol start inside the range, and, if the symbol map has line information,
+    /// for every line record inside the range. Consecutive entries with identical
+    /// results are merged into the first one.
+    pub fn lookup_relative_address_range(&self, start: u32, end: u32) -> Vec<(u32, AddressInfo)> {
+        let results = self.inner.lookup_relative_address_range(start, end);
+        if self.max_inline_depth.is_none() && self.function_name_style.is_default() {
+            return results;
+        }
+        let mut results: Vec<_> = results
+            .into_iter()
+            .map(|(address, info)| (address, self.apply_lookup_options(info)))
+            .collect();
+        // Results which only differed in the removed frames or in the removed parts
+        // of the function names are now identical.
+        results.dedup_by(|next, previous| next.1 == previous.1);
+        results
+    }
+
+    /// Enumerate all functions of this library, with their address ranges and line tables.
+    ///
+    /// Functions whose end address is unknown, i.e. the last function if the symbol
+    /// file doesn't record symbol sizes, are skipped. Line information which is only
+    /// available in external object files ([`FramesLookupResult::External`]) or in
+    /// `.dwo` files which haven't been added yet ([`FramesLookupResult::NeedDwo`]) is
+    /// not included.
+    pub fn enumerate_functions(&self) -> Vec<FunctionLineInfo> {
+        let mut symbols: Vec<(u32, Option<u32>, String)> = self
+            .iter_symbols_with_size()
+            .map(|(address, size, name)| (address, size, name.into_owned()))
+            .collect();
+        symbols.sort_by_key(|(address, ..)| *address);
+        symbols.dedup_by_key(|(address, ..)| *address);
+
+        let ranges: Vec<(u32, u32, &str)> = symbols
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (start_address, size, name))| {
+                let end_address = match size {
+                    Some(size) => start_address.saturating_add(*size),
+                    None => symbols.get(i + 1)?.0,
+                };
+                Some((*start_address, end_address, name.as_str()))
+            })
+            .collect();
+        let (Some(first), Some(range_end)) = (
+            ranges.first(),
+            ranges.iter().map(|(_, end_address, _)| *end_address).max(),
+        ) else {
+            return Vec::new();
+        };
+
+        // Collect the boundaries of the whole library at once and walk them in
+        // order, instead of asking for the boundaries of each function separately.
+        let mut boundaries = self
+            .inner
+            .boundaries_in_relative_address_range(first.0, range_end);
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        let mut functions = Vec::with_capacity(ranges.len());
+        let mut boundary_index = 0;
+        for (start_address, end_address, name) in ranges {
+            boundary_index += boundaries[boundary_index..].partition_point(|a| *a <= start_address);
+            let remaining_boundaries = &boundaries[boundary_index..];
+            let function_boundaries =
+                &remaining_boundaries[..remaining_boundaries.partition_point(|a| *a < end_address)];
+            let mut results: Vec<(u32, AddressInfo)> = std::iter::once(start_address)
+                .chain(function_boundaries.iter().copied())
+                .filter_map(|address| {
+                    let info = self.inner.lookup_relative_address(address)?;
+                    Some((address, self.apply_lookup_options(info)))
+                })
+                .collect();
+            results.dedup_by(|next, previous| next.1 == previous.1);
+            let lines = results
+                .into_iter()
+                .filter(|(_, info)| info.symbol.address == start_address)
+                .filter_map(|(address, info)| match info.frames {
+                    FramesLookupResult::Available(mut frames) if !frames.is_empty() => {
+                        let innermost = frames.swap_remove(0);
+                        Some((address, innermost.file_path, innermost.line_number))
+                    }
+                    _ => None,
+                })
+                .collect();
+            functions.push(FunctionLineInfo {
+                name: name.to_owned(),
+                start_address,
+                end_address,
+                lines,
+            });
+        }
+        functions
+    }
+
+    /// Look up an address in the address space of the process into which this
+    /// library was loaded.
+    ///
+    /// `image_base` is the address at which the start of the image (the mach-O
+    /// header, the PE header, or the first ELF segment) was loaded in the process.
+    /// For position-independent binaries which are loaded at a random base, this
+    /// is different from the address the image was linked at, so `avma` can't be
+    /// used with `lookup_svma` directly.
+    pub fn lookup_avma(&self, avma: u64, image_base: u64) -> Option<AddressInfo> {
+        let relative_address = u32::try_from(avma.checked_sub(image_base)?).ok()?;
+        self.lookup_relative_address(relative_address)
     }
 
     pub fn lookup_svma(&self, svma: u64) -> Option<AddressInfo> {
-        self.inner.lookup_svma(svma)
+        let info = self.lookup_in_inner(|inner| inner.lookup_svma(svma))?;
+        Some(self.apply_lookup_options(info))
     }
 
     pub fn lookup_offset(&self, offset: u64) -> Option<AddressInfo> {
-        self.inner.lookup_offset(offset)
+        let info = self.lookup_in_inner(|inner| inner.lookup_offset(offset))?;
+        Some(self.apply_lookup_options(info))
+    }
+}
+
+/// Keeps the innermost `max_inline_depth` inlined frames and the outermost frame.
+pub(crate) fn truncate_inline_frames(frames: &mut Vec<FrameDebugInfo>, max_inline_depth: usize) {
+    // frames is ordered innermost first; the last frame is the outermost.
+    let inline_frame_count = frames.len().saturating_sub(1);
+    if inline_frame_count > max_inline_depth {
+        frames.drain(max_inline_depth..inline_frame_count);
     }
 }
 
@@ -54,7 +446,89 @@ pub trait SymbolMapTrait {
 
     fn iter_symbols(&self) -> Box<dyn Iterator<Item = (u32, Cow<'_, str>)> + '_>;
 
+    fn iter_symbols_with_size(
+        &self,
+    ) -> Box<dyn Iterator<Item = (u32, Option<u32>, Cow<'_, str>)> + '_> {
+        Box::new(
+            self.iter_symbols()
+                .map(|(address, name)| (address, None, name)),
+        )
+    }
+
+    /// Returns only the symbol for `address`. Implementations should override this
+    /// if they can skip the debug info lookup which `lookup_relative_address` does.
+    fn lookup_symbol(&self, address: u32) -> Option<SymbolInfo> {
+        self.lookup_relative_address(address)
+            .map(|info| info.symbol)
+    }
+
+    /// Returns the undemangled name of the symbol for `address`, if the symbol
+    /// map has one.
+    fn lookup_raw_symbol_name(&self, _address: u32) -> Option<String> {
+        None
+    }
+
     fn lookup_relative_address(&self, address: u32) -> Option<AddressInfo>;
+
+    /// Returns the external files which lookups can refer to with
+    /// `FramesLookupResult::External`.
+    fn external_file_refs(&self) -> Vec<ExternalFileRef> {
+        Vec::new()
+    }
+
+    fn warnings(&self) -> Vec<SymbolMapWarning> {
+        Vec::new()
+    }
+
+    /// Returns the error which makes all lookups fail, if some of the debug info is
+    /// only read when it is first needed, and reading it fails. See
+    /// `SymbolMap::lookup_error`.
+    fn lookup_error(&self) -> Option<Error> {
+        None
+    }
+
+    /// Adds the contents of the `.dwo` file at `dwo_path`, see
+    /// `SymbolMap::add_dwo_file`. Only symbol maps for ELF files use `.dwo` files.
+    fn add_dwo_file(&self, _dwo_path: &str, _data: Option<Vec<u8>>) {}
+
+    /// Returns the `.dwo` files which the skeleton units refer to, see
+    /// `SymbolMap::dwo_refs`.
+    fn dwo_refs(&self) -> Vec<DwoRef> {
+        Vec::new()
+    }
+
+    /// See `SymbolMap::verify_dwp`.
+    fn verify_dwp(&self, _dwo_refs: &[DwoRef]) -> Option<Result<(), Error>> {
+        None
+    }
+
+    /// Returns the addresses in `start..end` at which a new lookup result begins.
+    /// The default implementation only knows about symbol starts.
+    fn boundaries_in_relative_address_range(&self, start: u32, end: u32) -> Vec<u32> {
+        self.iter_symbols()
+            .map(|(address, _)| address)
+            .filter(|address| start < *address && *address < end)
+            .collect()
+    }
+
+    fn lookup_relative_address_range(&self, start: u32, end: u32) -> Vec<(u32, AddressInfo)> {
+        if start >= end {
+            return Vec::new();
+        }
+        let mut addresses = self.boundaries_in_relative_address_range(start, end);
+        addresses.push(start);
+        addresses.sort_unstable();
+        addresses.dedup();
+        let mut results: Vec<(u32, AddressInfo)> = addresses
+            .into_iter()
+            .filter_map(|address| Some((address, self.lookup_relative_address(address)?)))
+            .collect();
+        // Compilers often split the code for one source line into several consecutive
+        // line records. Only keep the first of a run of identical results.
+        results.dedup_by(|next, previous| next.1 == previous.1);
+        results
+    }
+
     fn lookup_svma(&self, svma: u64) -> Option<AddressInfo>;
     fn lookup_offset(&self, offset: u64) -> Option<AddressInfo>;
 }
@@ -65,6 +539,17 @@ pub trait SymbolMapDataOuterTrait {
 
 pub trait SymbolMapDataMidTrait {
     fn make_symbol_map_inner(&self) -> Result<SymbolMapInnerWrapper<'_>, Error>;
+
+    /// See `SymbolMapTrait::dwo_refs`. This is on the mid level because it needs
+    /// the file data, which the inner symbol map doesn't keep.
+    fn dwo_refs(&self) -> Vec<DwoRef> {
+        Vec::new()
+    }
+
+    /// See `SymbolMapTrait::verify_dwp`.
+    fn verify_dwp(&self, _dwo_refs: &[DwoRef]) -> Option<Result<(), Error>> {
+        None
+    }
 }
 
 #[derive(Yokeable)]
@@ -117,10 +602,55 @@ impl<SMDO: SymbolMapDataOuterTrait> SymbolMapTrait for GenericSymbolMap<SMDO> {
         self.0.get().0.iter_symbols()
     }
 
+    fn iter_symbols_with_size(
+        &self,
+    ) -> Box<dyn Iterator<Item = (u32, Option<u32>, Cow<'_, str>)> + '_> {
+        self.0.get().0.iter_symbols_with_size()
+    }
+
+    fn lookup_symbol(&self, address: u32) -> Option<SymbolInfo> {
+        self.0.get().0.lookup_symbol(address)
+    }
+
+    fn lookup_raw_symbol_name(&self, address: u32) -> Option<String> {
+        self.0.get().0.lookup_raw_symbol_name(address)
+    }
+
     fn lookup_relative_address(&self, address: u32) -> Option<AddressInfo> {
         self.0.get().0.lookup_relative_address(address)
     }
 
+    fn external_file_refs(&self) -> Vec<ExternalFileRef> {
+        self.0.get().0.external_file_refs()
+    }
+
+    fn warnings(&self) -> Vec<SymbolMapWarning> {
+        self.0.get().0.warnings()
+    }
+
+    fn lookup_error(&self) -> Option<Error> {
+        self.0.get().0.lookup_error()
+    }
+
+    fn add_dwo_file(&self, dwo_path: &str, data: Option<Vec<u8>>) {
+        self.0.get().0.add_dwo_file(dwo_path, data)
+    }
+
+    fn dwo_refs(&self) -> Vec<DwoRef> {
+        self.0.backing_cart().0.get().0.dwo_refs()
+    }
+
+    fn verify_dwp(&self, dwo_refs: &[DwoRef]) -> Option<Result<(), Error>> {
+        self.0.backing_cart().0.get().0.verify_dwp(dwo_refs)
+    }
+
+    fn boundaries_in_relative_address_range(&self, start: u32, end: u32) -> Vec<u32> {
+        self.0
+            .get()
+            .0
+            .boundaries_in_relative_address_range(start, end)
+    }
+
     fn lookup_svma(&self, svma: u64) -> Option<AddressInfo> {
         self.0.get().0.lookup_svma(svma)
     }
@@ -129,3 +659,45 @@ impl<SMDO: SymbolMapDataOuterTrait> SymbolMapTrait for GenericSymbolMap<SMDO> {
         self.0.get().0.lookup_offset(offset)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn address_info(address: u32) -> AddressInfo {
+        AddressInfo {
+            symbol: SymbolInfo {
+                address,
+                size: None,
+                name: format!("fun_{address:x}"),
+            },
+            frames: FramesLookupResult::Unavailable,
+            section_name: None,
+        }
+    }
+
+    #[test]
+    fn address_cache_evicts_least_recently_used_entry() {
+        let mut cache = AddressCache::new(2);
+        cache.insert(0x10, Some(address_info(0x10)));
+        cache.insert(0x20, None);
+        assert_eq!(cache.get(0x20), Some(None));
+        assert_eq!(cache.get(0x10), Some(Some(address_info(0x10))));
+        assert_eq!(cache.get(0x30), None);
+
+        // 0x10 was used more recently than 0x20, even though it was inserted first.
+        cache.insert(0x30, Some(address_info(0x30)));
+        assert_eq!(cache.get(0x20), None);
+        assert_eq!(cache.get(0x10), Some(Some(address_info(0x10))));
+        assert_eq!(cache.get(0x30), Some(Some(address_info(0x30))));
+
+        cache.insert(0x40, None);
+        assert_eq!(cache.get(0x10), None);
+        assert_eq!(cache.get(0x30), Some(Some(address_info(0x30))));
+        assert_eq!(cache.get(0x40), Some(None));
+
+        let mut cache = AddressCache::new(0);
+        cache.insert(0x10, Some(address_info(0x10)));
+        assert_eq!(cache.get(0x10), None);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{borrow::Cow, slice, sync::Mutex};
+use std::{borrow::Cow, collections::HashMap, slice, sync::Mutex};
 
 use debugid::DebugId;
 use object::{
@@ -9,11 +9,15 @@ use object::{
 use crate::ExternalFileAddressRef;
 use crate::{
     demangle,
-    dwarf::{get_frames, Addr2lineContextData},
+    dwarf::{
+        get_dwo_refs, get_frames_with_split_dwarf, verify_dwp, Addr2lineContextData,
+        SplitDwarfFiles,
+    },
     path_mapper::PathMapper,
     shared::{
-        relative_address_base, AddressInfo, ExternalFileAddressInFileRef, ExternalFileRef,
-        SymbolInfo,
+        macho_text_segment_address, relative_address_base, AddressInfo, DwoRef,
+        ExternalFileAddressInFileRef, ExternalFileIdentity, ExternalFileRef, SymbolInfo,
+        SymbolMapWarning,
     },
     symbol_map::{SymbolMapDataMidTrait, SymbolMapInnerWrapper, SymbolMapTrait},
     Error, FramesLookupResult,
@@ -35,6 +39,7 @@ pub struct ObjectSymbolMapDataMid<'data, R: ReadRef<'data>, FAC: FunctionAddress
     function_addresses_computer: FAC,
     file_data: R,
     supplementary_file_data: Option<R>,
+    dwp_file_data: Option<R>,
     addr2line_context_data: Addr2lineContextData,
     arch: Option<&'static str>,
     debug_id: DebugId,
@@ -58,11 +63,19 @@ impl<'data, R: ReadRef<'data>, FAC: FunctionAddressesComputer<'data>>
             function_addresses_computer,
             file_data,
             supplementary_file_data,
+            dwp_file_data: None,
             addr2line_context_data: Addr2lineContextData::new(),
             arch,
             debug_id,
         }
     }
+
+    /// Sets the DWARF package file (`.dwp`) which contains the DWARF for the
+    /// skeleton units in this object.
+    pub fn with_dwp_file_data(mut self, dwp_file_data: Option<R>) -> Self {
+        self.dwp_file_data = dwp_file_data;
+        self
+    }
 }
 
 impl<'data, R: ReadRef<'data>, FAC: FunctionAddressesComputer<'data>> SymbolMapDataMidTrait
@@ -78,6 +91,7 @@ impl<'data, R: ReadRef<'data>, FAC: FunctionAddressesComputer<'data>> SymbolMapD
             self.supplementary_object.as_ref(),
             self.file_data,
             self.supplementary_file_data,
+            self.dwp_file_data,
             self.debug_id,
             function_starts.as_deref(),
             function_ends.as_deref(),
@@ -87,6 +101,15 @@ impl<'data, R: ReadRef<'data>, FAC: FunctionAddressesComputer<'data>> SymbolMapD
         let symbol_map = SymbolMapInnerWrapper(Box::new(symbol_map));
         Ok(symbol_map)
     }
+
+    fn dwo_refs(&self) -> Vec<DwoRef> {
+        get_dwo_refs(self.file_data, &self.object)
+    }
+
+    fn verify_dwp(&self, dwo_refs: &[DwoRef]) -> Option<Result<(), Error>> {
+        let dwp_file_data = self.dwp_file_data?;
+        Some(verify_dwp(dwp_file_data, dwo_refs))
+    }
 }
 
 enum FullSymbolListEntry<'a, Symbol: object::ObjectSymbol<'a>> {
@@ -168,6 +191,25 @@ impl std::fmt::Debug for SvmaFileRange {
     }
 }
 
+/// Splits an entry of the object map into the path of the external file and, if the
+/// external file is an archive, the name of the archive member.
+fn split_external_file_name(external_file_name: &str) -> (&str, Option<&str>) {
+    match external_file_name.find('(') {
+        Some(index) => {
+            // This is an "archive" reference of the form
+            // "/Users/mstange/code/obj-m-opt/toolkit/library/build/../../../js/src/build/libjs_static.a(Unified_cpp_js_src13.o)"
+            let (path, paren_rest) = external_file_name.split_at(index);
+            let name_in_archive = paren_rest.trim_start_matches('(').trim_end_matches(')');
+            (path, Some(name_in_archive))
+        }
+        None => {
+            // This is a reference to a regular object file. Example:
+            // "/Users/mstange/code/obj-m-opt/toolkit/library/build/../../components/sessionstore/Unified_cpp_sessionstore0.o"
+            (external_file_name, None)
+        }
+    }
+}
+
 pub struct ObjectSymbolMapInner<'data, 'file, Symbol: object::ObjectSymbol<'data>>
 where
     'data: 'file,
@@ -177,9 +219,17 @@ where
     arch: Option<&'static str>,
     path_mapper: Mutex<PathMapper<()>>,
     object_map: ObjectMap<'data>,
+    /// For each external file in the object map, the symbol at the lowest address
+    /// which the object map attributes to it.
+    external_file_identities: HashMap<&'data str, ExternalFileIdentity>,
     context: Option<addr2line::Context<gimli::EndianSlice<'file, gimli::RunTimeEndian>>>,
+    split_dwarf: SplitDwarfFiles<'file>,
     svma_file_ranges: Vec<SvmaFileRange>,
+    /// (start address, end address, name) of each section with an address, as
+    /// relative addresses.
+    sections: Vec<(u32, u32, String)>,
     image_base_address: u64,
+    warnings: Vec<SymbolMapWarning>,
 }
 
 #[test]
@@ -202,6 +252,7 @@ where
         sup_object_file: Option<&'file O>,
         data: R,
         sup_data: Option<R>,
+        dwp_data: Option<R>,
         debug_id: DebugId,
         function_start_addresses: Option<&[u32]>,
         function_end_addresses: Option<&[u32]>,
@@ -216,6 +267,12 @@ where
         let mut entries: Vec<_> = Vec::new();
 
         let base_address = relative_address_base(object_file);
+        let mut warnings = Vec::new();
+        if let object::FileFlags::MachO { .. } = object_file.flags() {
+            if macho_text_segment_address(object_file).is_none() {
+                warnings.push(SymbolMapWarning::NoMachOTextSegment);
+            }
+        }
 
         // Compute the executable sections upfront. This will be used to filter out uninteresting symbols.
         let executable_sections: Vec<SectionIndex> = object_file
@@ -282,12 +339,12 @@ where
 
         // 3. Exports (only used by exe / dll objects)
         if let Ok(exports) = object_file.exports() {
-            for export in exports {
-                entries.push((
-                    (export.address() - base_address) as u32,
+            entries.extend(exports.into_iter().filter_map(|export| {
+                Some((
+                    u32::try_from(export.address().checked_sub(base_address)?).ok()?,
                     FullSymbolListEntry::Export(export),
-                ));
-            }
+                ))
+            }));
         }
 
         // 4. Placeholder symbols based on function start addresses
@@ -367,6 +424,7 @@ where
         let context = addr2line_context_data
             .make_context(data, object_file, sup_data, sup_object_file)
             .ok();
+        let split_dwarf = addr2line_context_data.make_split_dwarf_files(dwp_data);
 
         let path_mapper = Mutex::new(PathMapper::new());
 
@@ -383,18 +441,68 @@ where
                 .collect();
         }
 
+        let sections = object_file
+            .sections()
+            .filter(|section| section.address() != 0 && section.size() != 0)
+            .filter_map(|section| {
+                let start = section.address().checked_sub(base_address)?;
+                let end = start.checked_add(section.size())?;
+                let name = section.name().ok()?;
+                Some((
+                    u32::try_from(start).ok()?,
+                    u32::try_from(end).ok()?,
+                    name.to_owned(),
+                ))
+            })
+            .collect();
+
+        let object_map = object_file.object_map();
+        let mut external_file_identities = HashMap::new();
+        for entry in object_map.symbols() {
+            let Ok(external_file_name) = std::str::from_utf8(entry.object(&object_map)) else {
+                continue;
+            };
+            let (file_name, name_in_archive) = split_external_file_name(external_file_name);
+            external_file_identities
+                .entry(file_name)
+                .or_insert_with(|| ExternalFileIdentity {
+                    name_in_archive: name_in_archive.map(ToOwned::to_owned),
+                    symbol_name: entry.name().to_owned(),
+                });
+        }
+
         Self {
             entries,
             debug_id,
             path_mapper,
-            object_map: object_file.object_map(),
+            object_map,
+            external_file_identities,
             context,
+            split_dwarf,
             arch,
             image_base_address: base_address,
             svma_file_ranges,
+            sections,
+            warnings,
         }
     }
 
+    fn external_file_ref(&self, file_name: &str) -> ExternalFileRef {
+        ExternalFileRef {
+            file_name: file_name.to_owned(),
+            arch: self.arch.map(ToOwned::to_owned),
+            identity: self.external_file_identities.get(file_name).cloned(),
+        }
+    }
+
+    fn section_name(&self, address: u32) -> Option<String> {
+        let (_, _, name) = self
+            .sections
+            .iter()
+            .find(|(start, end, _)| *start <= address && address < *end)?;
+        Some(name.clone())
+    }
+
     fn file_offset_to_svma(&self, offset: u64) -> Option<u64> {
         for svma_file_range in &self.svma_file_ranges {
             if svma_file_range.file_offset <= offset
@@ -407,6 +515,26 @@ where
         }
         None
     }
+
+    /// Finds the entry covering `address` and returns its start address, size and
+    /// (mangled) name.
+    fn find_symbol_entry(&self, address: u32) -> Option<(u32, u32, Cow<'data, str>)> {
+        let index = match self
+            .entries
+            .binary_search_by_key(&address, |&(addr, _)| addr)
+        {
+            Err(0) => return None,
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        let (start_addr, entry) = &self.entries[index];
+        let (end_addr, _) = self.entries.get(index + 1)?;
+        // If the found entry is an EndAddress entry, this means that `address` falls
+        // in the dead space between known functions, and we consider it to be not found.
+        // In that case, entry.name returns Err().
+        let name = entry.name(*start_addr).ok()?;
+        Some((*start_addr, end_addr - *start_addr, name))
+    }
 }
 
 impl<'data, 'file, Symbol: object::ObjectSymbol<'data>> SymbolMapTrait
@@ -436,57 +564,69 @@ where
         })
     }
 
-    fn lookup_relative_address(&self, address: u32) -> Option<AddressInfo> {
-        let index = match self
+    fn iter_symbols_with_size(
+        &self,
+    ) -> Box<dyn Iterator<Item = (u32, Option<u32>, Cow<'_, str>)> + '_> {
+        // The entries list contains end addresses for symbols with a known size,
+        // so the distance to the next entry is the symbol size if the object file
+        // recorded one, and the distance to the next symbol otherwise.
+        let iter = self
             .entries
-            .binary_search_by_key(&address, |&(addr, _)| addr)
-        {
-            Err(0) => return None,
-            Ok(i) => i,
-            Err(i) => i - 1,
-        };
-        let (start_addr, entry) = &self.entries[index];
-        let next_entry = self.entries.get(index + 1);
-        // If the found entry is an EndAddress entry, this means that `address` falls
-        // in the dead space between known functions, and we consider it to be not found.
-        // In that case, entry.name returns Err().
-        if let (Ok(name), Some((end_addr, _))) = (entry.name(*start_addr), next_entry) {
-            let function_size = end_addr - *start_addr;
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, (address, entry))| {
+                let name = entry.name(*address).ok()?;
+                let size = self.entries[i + 1..]
+                    .iter()
+                    .find(|(next_address, _)| next_address > address)
+                    .map(|(next_address, _)| next_address - address);
+                Some((*address, size, name))
+            });
+        Box::new(iter)
+    }
+
+    fn lookup_symbol(&self, address: u32) -> Option<SymbolInfo> {
+        let (start_addr, function_size, name) = self.find_symbol_entry(address)?;
+        Some(SymbolInfo {
+            address: start_addr,
+            size: Some(function_size),
+            name: demangle::demangle_any(&name),
+        })
+    }
+
+    fn lookup_raw_symbol_name(&self, address: u32) -> Option<String> {
+        let (_start_addr, _function_size, name) = self.find_symbol_entry(address)?;
+        Some(name.into_owned())
+    }
 
+    fn lookup_relative_address(&self, address: u32) -> Option<AddressInfo> {
+        if let Some((start_addr, function_size, name)) = self.find_symbol_entry(address) {
             let mut path_mapper = self.path_mapper.lock().unwrap();
 
             let svma = self.image_base_address + u64::from(address);
-            let frames = match get_frames(svma, self.context.as_ref(), &mut path_mapper) {
-                Some(frames) => FramesLookupResult::Available(frames),
-                None => {
+            let frames = match get_frames_with_split_dwarf(
+                svma,
+                self.context.as_ref(),
+                |load| self.split_dwarf.load(load),
+                &mut path_mapper,
+            ) {
+                Ok(Some(frames)) => FramesLookupResult::Available(frames),
+                Err(dwo_ref) => FramesLookupResult::NeedDwo(dwo_ref),
+                Ok(None) => {
                     if let Some(entry) = self.object_map.get(svma) {
                         let external_file_name = entry.object(&self.object_map);
                         let external_file_name = std::str::from_utf8(external_file_name).unwrap();
                         let offset_from_symbol = (svma - entry.address()) as u32;
-                        let (file_name, name_in_archive) = match external_file_name.find('(') {
-                            Some(index) => {
-                                // This is an "archive" reference of the form
-                                // "/Users/mstange/code/obj-m-opt/toolkit/library/build/../../../js/src/build/libjs_static.a(Unified_cpp_js_src13.o)"
-                                let (path, paren_rest) = external_file_name.split_at(index);
-                                let name_in_archive =
-                                    paren_rest.trim_start_matches('(').trim_end_matches(')');
-                                (path, Some(name_in_archive))
-                            }
-                            None => {
-                                // This is a reference to a regular object file. Example:
-                                // "/Users/mstange/code/obj-m-opt/toolkit/library/build/../../components/sessionstore/Unified_cpp_sessionstore0.o"
-                                (external_file_name, None)
-                            }
-                        };
+                        let (file_name, name_in_archive) =
+                            split_external_file_name(external_file_name);
                         FramesLookupResult::External(ExternalFileAddressRef {
-                            file_ref: ExternalFileRef {
-                                file_name: file_name.to_owned(),
-                                arch: self.arch.map(ToOwned::to_owned),
-                            },
+                            file_ref: self.external_file_ref(file_name),
                             address_in_file: ExternalFileAddressInFileRef {
                                 name_in_archive: name_in_archive.map(ToOwned::to_owned),
                                 symbol_name: entry.name().to_owned(),
                                 offset_from_symbol,
+                                max_inline_depth: None,
+                                function_name_style: Default::default(),
                             },
                         })
                     } else {
@@ -498,17 +638,66 @@ where
             let name = demangle::demangle_any(&name);
             Some(AddressInfo {
                 symbol: SymbolInfo {
-                    address: *start_addr,
+                    address: start_addr,
                     size: Some(function_size),
                     name,
                 },
                 frames,
+                section_name: self.section_name(address),
             })
         } else {
             None
         }
     }
 
+    fn external_file_refs(&self) -> Vec<ExternalFileRef> {
+        let mut file_refs: Vec<ExternalFileRef> = self
+            .object_map
+            .objects()
+            .iter()
+            .filter_map(|external_file_name| {
+                let external_file_name = std::str::from_utf8(external_file_name).ok()?;
+                let (file_name, _name_in_archive) = split_external_file_name(external_file_name);
+                Some(self.external_file_ref(file_name))
+            })
+            .collect();
+        // All members of an archive are in the same external file.
+        file_refs.sort_unstable();
+        file_refs.dedup();
+        file_refs
+    }
+
+    fn warnings(&self) -> Vec<SymbolMapWarning> {
+        self.warnings.clone()
+    }
+
+    fn add_dwo_file(&self, dwo_path: &str, data: Option<Vec<u8>>) {
+        self.split_dwarf.add_dwo_file(dwo_path, data);
+    }
+
+    fn boundaries_in_relative_address_range(&self, start: u32, end: u32) -> Vec<u32> {
+        let mut boundaries: Vec<u32> = self
+            .entries
+            .iter()
+            .map(|(address, _)| *address)
+            .filter(|address| start < *address && *address < end)
+            .collect();
+        if let Some(context) = self.context.as_ref() {
+            let svma_start = self.image_base_address + u64::from(start);
+            let svma_end = self.image_base_address + u64::from(end);
+            if let Ok(locations) = context.find_location_range(svma_start, svma_end) {
+                boundaries.extend(
+                    locations
+                        .filter_map(|(svma, _len, _location)| {
+                            u32::try_from(svma.checked_sub(self.image_base_address)?).ok()
+                        })
+                        .filter(|address| start < *address && *address < end),
+                );
+            }
+        }
+        boundaries
+    }
+
     fn lookup_svma(&self, svma: u64) -> Option<AddressInfo> {
         let relative_address = svma.checked_sub(self.image_base_address)?.try_into().ok()?;
         // 4200608 2103456 2097152
@@ -0,0 +1,42 @@
+use debugid::DebugId;
+
+use crate::{Error, ExternalFileRef};
+
+/// A file which a debug file refers to for additional debug info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReferencedDebugFile {
+    /// An object file or archive, referenced by the object map (OSO stabs) of a mach-O
+    /// binary which was linked without creating a dSYM.
+    ExternalFile(ExternalFileRef),
+    /// The DWARF package file (`.dwp`) of an ELF binary which was compiled with
+    /// `-gsplit-dwarf`.
+    Dwp,
+    /// A `.dwo` file which is referenced by a skeleton unit of an ELF binary. These
+    /// are only checked if no `.dwp` file was found.
+    Dwo(String),
+}
+
+/// The result of [`SymbolManager::verify_symbols`](crate::SymbolManager::verify_symbols).
+#[derive(Debug)]
+pub struct VerificationReport<FL> {
+    /// The location of the debug file which was found for the library.
+    pub debug_file_location: FL,
+    /// The debug ID of that file. This always matches the requested debug ID.
+    pub debug_id: DebugId,
+    /// The files which the debug file refers to, each with the result of loading it.
+    pub referenced_files: Vec<(ReferencedDebugFile, Result<(), Error>)>,
+}
+
+impl<FL> VerificationReport<FL> {
+    /// Returns the referenced files which could not be loaded.
+    pub fn missing_files(&self) -> impl Iterator<Item = (&ReferencedDebugFile, &Error)> {
+        self.referenced_files
+            .iter()
+            .filter_map(|(file, result)| Some((file, result.as_ref().err()?)))
+    }
+
+    /// Whether all referenced files could be loaded.
+    pub fn is_complete(&self) -> bool {
+        self.missing_files().next().is_none()
+    }
+}
@@ -12,12 +12,13 @@ use crate::symbol_map::{
 use crate::symbol_map_object::{FunctionAddressesComputer, ObjectSymbolMapDataMid};
 use crate::{demangle, FileLocation, MappedPath, SourceFilePath};
 use debugid::DebugId;
+use elsa::FrozenVec;
 use nom::bytes::complete::{tag, take_until1};
 use nom::combinator::eof;
 use nom::sequence::terminated;
 use object::{File, FileKind};
 use pdb::PDB;
-use pdb_addr2line::pdb;
+use pdb_addr2line::{pdb, ModuleProvider};
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::ops::Deref;
@@ -143,14 +144,32 @@ pub fn is_pdb_file<F: FileContents>(file: &FileContentsWrapper<F>) -> bool {
 }
 
 struct PdbObject<'data, FC: FileContents + 'static> {
-    context_data: pdb_addr2line::ContextPdbData<'data, 'data, &'data FileContentsWrapper<FC>>,
+    /// The PDB file, for reading streams which `context_data` doesn't give access to.
+    file_contents: &'data FileContentsWrapper<FC>,
+    /// The PDB which was opened to create the symbol map. It's handed over to
+    /// `context_data` the first time that's needed.
+    pdb: Mutex<Option<PDB<'data, &'data FileContentsWrapper<FC>>>>,
+    /// Created on first use, see `context_data()`. At most one element.
+    context_data: FrozenVec<Box<PdbContextData<'data, FC>>>,
+    /// The DBI stream, for the module list and the section contributions. The module
+    /// streams themselves are read through `context_data`, which caches them, so
+    /// that the pdb-addr2line context and our own lookups share them.
+    debug_info: pdb::DebugInformation<'data>,
+    address_map: pdb::AddressMap<'data>,
     debug_id: DebugId,
     srcsrv_stream: Option<Box<dyn Deref<Target = [u8]> + 'data>>,
 }
 
 impl<'data, FC: FileContents + 'static> SymbolMapDataMidTrait for PdbObject<'data, FC> {
     fn make_symbol_map_inner(&self) -> Result<SymbolMapInnerWrapper<'_>, Error> {
-        let context = self.make_context()?;
+        use pdb::FallibleIterator;
+
+        let modules = self
+            .debug_info
+            .modules()
+            .context("modules")?
+            .collect()
+            .context("modules.collect()")?;
 
         let path_mapper = match &self.srcsrv_stream {
             Some(srcsrv_stream) => Some(SrcSrvPathMapper::new(srcsrv::SrcSrvStream::parse(
@@ -161,19 +180,47 @@ impl<'data, FC: FileContents + 'static> SymbolMapDataMidTrait for PdbObject<'dat
         let path_mapper = PathMapper::new_with_maybe_extra_mapper(path_mapper);
 
         let symbol_map = PdbSymbolMapInner {
-            context,
-            debug_id: self.debug_id,
+            object: self,
+            context: FrozenVec::new(),
+            modules,
             path_mapper: Mutex::new(path_mapper),
+            module_ranges: Mutex::new(None),
+            thunks: Mutex::new(HashMap::new()),
+            line_addresses: Mutex::new(HashMap::new()),
+            public_function_names: Mutex::new(None),
         };
         Ok(SymbolMapInnerWrapper(Box::new(symbol_map)))
     }
 }
 
+type PdbContextData<'data, FC> =
+    pdb_addr2line::ContextPdbData<'data, 'data, &'data FileContentsWrapper<FC>>;
+
 impl<'data, FC: FileContents + 'static> PdbObject<'data, FC> {
+    /// Returns the pdb-addr2line context data, creating it on the first successful
+    /// call. Creating it reads the TPI, IPI, global symbols and names streams, which
+    /// aren't needed for creating the symbol map.
+    fn context_data(&self) -> Result<&PdbContextData<'data, FC>, Error> {
+        if let Some(context_data) = self.context_data.get(0) {
+            return Ok(context_data);
+        }
+        let pdb = self.pdb.lock().unwrap().take();
+        let pdb = match pdb {
+            Some(pdb) => pdb,
+            None => PDB::open(self.file_contents)?,
+        };
+        let context_data = pdb_addr2line::ContextPdbData::try_from_pdb(pdb)
+            .context("ContextConstructionData::try_from_pdb")?;
+        Ok(self.context_data.push_get(Box::new(context_data)))
+    }
+
     fn make_context<'object>(
         &'object self,
     ) -> Result<Box<dyn PdbAddr2lineContextTrait + 'object>, Error> {
-        let context = self.context_data.make_context().context("make_context()")?;
+        let context = self
+            .context_data()?
+            .make_context()
+            .context("make_context()")?;
         Ok(Box::new(context))
     }
 }
@@ -183,6 +230,10 @@ trait PdbAddr2lineContextTrait {
         &self,
         probe: u32,
     ) -> Result<Option<pdb_addr2line::FunctionFrames>, pdb_addr2line::Error>;
+    fn find_function(
+        &self,
+        probe: u32,
+    ) -> Result<Option<pdb_addr2line::Function>, pdb_addr2line::Error>;
     fn function_count(&self) -> usize;
     fn functions(&self) -> Box<dyn Iterator<Item = pdb_addr2line::Function> + '_>;
 }
@@ -195,6 +246,13 @@ impl<'a, 's> PdbAddr2lineContextTrait for pdb_addr2line::Context<'a, 's> {
         self.find_frames(probe)
     }
 
+    fn find_function(
+        &self,
+        probe: u32,
+    ) -> Result<Option<pdb_addr2line::Function>, pdb_addr2line::Error> {
+        self.find_function(probe)
+    }
+
     fn function_count(&self) -> usize {
         self.function_count()
     }
@@ -204,42 +262,464 @@ impl<'a, 's> PdbAddr2lineContextTrait for pdb_addr2line::Context<'a, 's> {
     }
 }
 
-struct PdbSymbolMapInner<'object> {
-    context: Box<dyn PdbAddr2lineContextTrait + 'object>,
-    debug_id: DebugId,
+/// Returns the start and end RVA of a range of `len` bytes at `start_rva`, or `None`
+/// if the end doesn't fit into a u32. PDB files can come from arbitrary sources, so
+/// a corrupt length must not cause an overflow.
+fn rva_range(start_rva: pdb::Rva, len: u32) -> Option<(u32, u32)> {
+    Some((start_rva.0, start_rva.0.checked_add(len)?))
+}
+
+/// A thunk symbol from a module's symbol stream, for example an incremental linking
+/// jump stub.
+struct PdbThunk {
+    start_rva: u32,
+    end_rva: u32,
+    name: String,
+}
+
+/// Reads the thunk symbols of one module, sorted by start address.
+///
+/// pdb-addr2line only knows about procedures and public symbols, so addresses in
+/// thunks would otherwise not be found.
+fn read_module_thunks(
+    module_info: &pdb::ModuleInfo,
+    address_map: &pdb::AddressMap,
+) -> Result<Vec<PdbThunk>, pdb::Error> {
+    use pdb::FallibleIterator;
+
+    let mut thunks = Vec::new();
+    let mut symbols = module_info.symbols()?;
+    while let Some(symbol) = symbols.next()? {
+        if let Ok(pdb::SymbolData::Thunk(thunk)) = symbol.parse() {
+            let range = thunk
+                .offset
+                .to_rva(address_map)
+                .and_then(|start_rva| rva_range(start_rva, u32::from(thunk.len)));
+            if let Some((start_rva, end_rva)) = range {
+                thunks.push(PdbThunk {
+                    start_rva,
+                    end_rva,
+                    name: thunk.name.to_string().into_owned(),
+                });
+            }
+        }
+    }
+    thunks.sort_by_key(|thunk| thunk.start_rva);
+    Ok(thunks)
+}
+
+/// Reads the names of the public symbols for functions, by RVA. Unlike the names
+/// of procedure symbols, these are the decorated names which the linker used.
+fn read_public_function_names(
+    global_symbols: &pdb::SymbolTable,
+    address_map: &pdb::AddressMap,
+) -> Result<HashMap<u32, String>, pdb::Error> {
+    use pdb::FallibleIterator;
+
+    let mut names = HashMap::new();
+    let mut symbols = global_symbols.iter();
+    while let Some(symbol) = symbols.next()? {
+        if let Ok(pdb::SymbolData::Public(public)) = symbol.parse() {
+            if !public.function {
+                continue;
+            }
+            if let Some(rva) = public.offset.to_rva(address_map) {
+                names.insert(rva.0, public.name.to_string().into_owned());
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Reads the start addresses of the line records of a module's procedures, sorted
+/// and deduplicated.
+///
+/// The lines are read per procedure, like pdb-addr2line does. The iterator over all
+/// of a module's lines carries the last line of one lines subsection over into the
+/// next subsection.
+fn read_module_line_addresses(
+    module_info: &pdb::ModuleInfo,
+    address_map: &pdb::AddressMap,
+) -> Result<Vec<u32>, pdb::Error> {
+    use pdb::FallibleIterator;
+
+    let line_program = module_info.line_program()?;
+    let mut addresses = Vec::new();
+    let mut symbols = module_info.symbols()?;
+    while let Some(symbol) = symbols.next()? {
+        let procedure_offset = match symbol.parse() {
+            Ok(pdb::SymbolData::Procedure(procedure)) => procedure.offset,
+            _ => continue,
+        };
+        let mut lines = line_program.lines_for_symbol(procedure_offset);
+        while let Some(line) = lines.next()? {
+            if let Some(rva) = line.offset.to_rva(address_map) {
+                addresses.push(rva.0);
+            }
+        }
+    }
+    addresses.sort_unstable();
+    addresses.dedup();
+    Ok(addresses)
+}
+
+/// The address ranges which each module (compiland) contributes to the image, from
+/// the section contributions substream of the DBI stream.
+#[derive(Default)]
+struct PdbModuleRanges {
+    /// (start_rva, end_rva, module index), sorted by start_rva.
+    ranges: Vec<(u32, u32, usize)>,
+    /// The modules without any section contributions, for example the "Import:"
+    /// modules with the import thunks. The linker attributes the code of the import
+    /// thunks to the "* Linker *" module instead.
+    modules_without_ranges: Vec<usize>,
+}
+
+impl PdbModuleRanges {
+    /// Returns the index of the module whose code covers `address`.
+    fn lookup(&self, address: u32) -> Option<usize> {
+        let index = match self.ranges.binary_search_by_key(&address, |range| range.0) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let (_, end_rva, module_index) = self.ranges[index];
+        if address >= end_rva {
+            return None;
+        }
+        Some(module_index)
+    }
+
+    /// Returns the indexes of the modules whose code overlaps `start..end`.
+    fn modules_in_range(&self, start: u32, end: u32) -> Vec<usize> {
+        let first = match self.ranges.binary_search_by_key(&start, |range| range.0) {
+            Ok(i) => i,
+            Err(0) => 0,
+            Err(i) => i - 1,
+        };
+        let mut module_indexes: Vec<usize> = self.ranges[first..]
+            .iter()
+            .take_while(|(range_start, _, _)| *range_start < end)
+            .filter(|(_, range_end, _)| *range_end > start)
+            .map(|(_, _, module_index)| *module_index)
+            .collect();
+        module_indexes.sort_unstable();
+        module_indexes.dedup();
+        module_indexes
+    }
+}
+
+fn read_pdb_module_ranges(
+    debug_info: &pdb::DebugInformation,
+    address_map: &pdb::AddressMap,
+    module_count: usize,
+) -> Result<PdbModuleRanges, pdb::Error> {
+    use pdb::FallibleIterator;
+
+    let mut ranges = Vec::new();
+    let mut module_has_ranges = vec![false; module_count];
+    let mut contributions = debug_info.section_contributions()?;
+    while let Some(contribution) = contributions.next()? {
+        if let Some(has_ranges) = module_has_ranges.get_mut(contribution.module) {
+            *has_ranges = true;
+        }
+        let range = contribution
+            .offset
+            .to_rva(address_map)
+            .and_then(|start_rva| rva_range(start_rva, contribution.size));
+        if let Some((start_rva, end_rva)) = range {
+            ranges.push((start_rva, end_rva, contribution.module));
+        }
+    }
+    ranges.sort_unstable_by_key(|range| range.0);
+    let modules_without_ranges = (0..module_count)
+        .filter(|module_index| !module_has_ranges[*module_index])
+        .collect();
+    Ok(PdbModuleRanges {
+        ranges,
+        modules_without_ranges,
+    })
+}
+
+struct PdbSymbolMapInner<'object, 'data, FC: FileContents + 'static> {
+    object: &'object PdbObject<'data, FC>,
+    /// The pdb-addr2line context, or the error from creating it. Created on first
+    /// use, see `context()`. At most one element.
+    context: FrozenVec<Box<Result<Box<dyn PdbAddr2lineContextTrait + 'object>, Error>>>,
+    /// The module list from the DBI stream. This doesn't read the module streams.
+    modules: Vec<pdb::Module<'object>>,
     path_mapper: Mutex<PathMapper<SrcSrvPathMapper<'object>>>,
+    /// Read lazily, the first time we need to know which module covers an address.
+    module_ranges: Mutex<Option<PdbModuleRanges>>,
+    /// The thunks of each module, by module index. A module's thunks are read the
+    /// first time an address in that module isn't found by `context`.
+    thunks: Mutex<HashMap<usize, Vec<PdbThunk>>>,
+    /// The line record start addresses of each module whose lines were needed, see
+    /// `read_module_line_addresses`.
+    line_addresses: Mutex<HashMap<usize, Vec<u32>>>,
+    /// The decorated names of the public function symbols, by RVA. Read the first
+    /// time a raw name is requested, see `read_public_function_names`.
+    public_function_names: Mutex<Option<HashMap<u32, String>>>,
+}
+
+impl<'object, 'data, FC: FileContents + 'static> PdbSymbolMapInner<'object, 'data, FC> {
+    fn context(&self) -> Result<&(dyn PdbAddr2lineContextTrait + 'object), &Error> {
+        let context = match self.context.get(0) {
+            Some(context) => context,
+            None => self.context.push_get(Box::new(self.object.make_context())),
+        };
+        context.as_deref()
+    }
+
+    fn with_module_ranges<R>(&self, f: impl FnOnce(&PdbModuleRanges) -> R) -> R {
+        let mut module_ranges = self.module_ranges.lock().unwrap();
+        let module_ranges = module_ranges.get_or_insert_with(|| {
+            read_pdb_module_ranges(
+                &self.object.debug_info,
+                &self.object.address_map,
+                self.modules.len(),
+            )
+            .unwrap_or_default()
+        });
+        f(module_ranges)
+    }
+
+    fn module_info(&self, module_index: usize) -> Option<&'object pdb::ModuleInfo<'data>> {
+        let module = self.modules.get(module_index)?;
+        self.object
+            .context_data()
+            .ok()?
+            .get_module_info(module_index, module)
+            .ok()?
+    }
+
+    /// Looks for a thunk which covers `address`. Only the symbol streams of the
+    /// module which covers the address and of the modules without section
+    /// contributions are read, and each of them only once.
+    fn lookup_thunk(&self, address: u32) -> Option<AddressInfo> {
+        let candidate_modules: Vec<usize> = self.with_module_ranges(|module_ranges| {
+            module_ranges
+                .lookup(address)
+                .into_iter()
+                .chain(module_ranges.modules_without_ranges.iter().copied())
+                .collect()
+        });
+        let mut thunks_by_module = self.thunks.lock().unwrap();
+        for module_index in candidate_modules {
+            let thunks = thunks_by_module.entry(module_index).or_insert_with(|| {
+                self.module_info(module_index)
+                    .and_then(|module_info| {
+                        read_module_thunks(module_info, &self.object.address_map).ok()
+                    })
+                    .unwrap_or_default()
+            });
+            let index = match thunks.binary_search_by_key(&address, |thunk| thunk.start_rva) {
+                Ok(i) => i,
+                Err(0) => continue,
+                Err(i) => i - 1,
+            };
+            let thunk = &thunks[index];
+            if address >= thunk.end_rva {
+                continue;
+            }
+            return Some(AddressInfo {
+                symbol: SymbolInfo {
+                    address: thunk.start_rva,
+                    size: Some(thunk.end_rva - thunk.start_rva),
+                    name: thunk.name.clone(),
+                },
+                frames: FramesLookupResult::Unavailable,
+                section_name: None,
+            });
+        }
+        None
+    }
+
+    /// Returns the decorated name of the public symbol at `start_rva`, if there is
+    /// one. Functions with internal linkage don't have a public symbol.
+    fn public_function_name(&self, start_rva: u32) -> Option<String> {
+        let mut public_function_names = self.public_function_names.lock().unwrap();
+        public_function_names
+            .get_or_insert_with(|| {
+                // pdb-addr2line reads the global symbols, but doesn't give access to
+                // them, so the stream is read from a separately opened PDB.
+                PDB::open(self.object.file_contents)
+                    .and_then(|mut pdb| pdb.global_symbols())
+                    .and_then(|global_symbols| {
+                        read_public_function_names(&global_symbols, &self.object.address_map)
+                    })
+                    .unwrap_or_default()
+            })
+            .get(&start_rva)
+            .cloned()
+    }
+
+    fn lookup_compiland(&self, address: u32) -> Option<String> {
+        let module_index =
+            self.with_module_ranges(|module_ranges| module_ranges.lookup(address))?;
+        let module = self.modules.get(module_index)?;
+        Some(module.module_name().into_owned())
+    }
 }
 
-impl<'object> SymbolMapTrait for PdbSymbolMapInner<'object> {
+impl<'object, 'data, FC: FileContents + 'static> SymbolMapTrait
+    for PdbSymbolMapInner<'object, 'data, FC>
+{
     fn debug_id(&self) -> DebugId {
-        self.debug_id
+        self.object.debug_id
+    }
+
+    /// The error from creating the pdb-addr2line context. The context is only
+    /// created when it's first needed, so a corrupt TPI, IPI or global symbols
+    /// stream doesn't make the creation of the symbol map fail.
+    fn lookup_error(&self) -> Option<Error> {
+        self.context().err()?;
+        // The error itself is kept for the lookups, so create it again.
+        self.object.make_context().err()
     }
 
     fn symbol_count(&self) -> usize {
-        self.context.function_count()
+        self.context().map_or(0, |context| context.function_count())
     }
 
     fn iter_symbols(&self) -> Box<dyn Iterator<Item = (u32, Cow<'_, str>)> + '_> {
-        let iter = self.context.functions().map(|f| {
-            let start_rva = f.start_rva;
-            (
-                start_rva,
-                Cow::Owned(f.name.unwrap_or_else(|| format!("fun_{start_rva:x}"))),
-            )
-        });
+        let iter = self
+            .context()
+            .ok()
+            .into_iter()
+            .flat_map(|context| context.functions())
+            .map(|f| {
+                let start_rva = f.start_rva;
+                (
+                    start_rva,
+                    Cow::Owned(f.name.unwrap_or_else(|| format!("fun_{start_rva:x}"))),
+                )
+            });
         Box::new(iter)
     }
 
+    fn iter_symbols_with_size(
+        &self,
+    ) -> Box<dyn Iterator<Item = (u32, Option<u32>, Cow<'_, str>)> + '_> {
+        let iter = self
+            .context()
+            .ok()
+            .into_iter()
+            .flat_map(|context| context.functions())
+            .map(|f| {
+                let start_rva = f.start_rva;
+                let size = f.end_rva.and_then(|end_rva| end_rva.checked_sub(start_rva));
+                (
+                    start_rva,
+                    size,
+                    Cow::Owned(f.name.unwrap_or_else(|| format!("fun_{start_rva:x}"))),
+                )
+            });
+        Box::new(iter)
+    }
+
+    /// Returns the function starts and the starts of the line records in the range.
+    /// The line records come from the line programs of the modules which contribute
+    /// code to the range, so only the streams of these modules are read.
+    fn boundaries_in_relative_address_range(&self, start: u32, end: u32) -> Vec<u32> {
+        let context = match self.context() {
+            Ok(context) => context,
+            Err(_) => return Vec::new(),
+        };
+        let mut boundaries = Vec::new();
+
+        // Walk from function to function. This also finds the functions which only
+        // have a public symbol.
+        let mut address = start;
+        while let Ok(Some(function)) = context.find_function(address) {
+            match function.end_rva {
+                Some(function_end) if function_end > address && function_end < end => {
+                    boundaries.push(function_end);
+                    address = function_end;
+                }
+                _ => break,
+            }
+        }
+
+        let module_indexes =
+            self.with_module_ranges(|module_ranges| module_ranges.modules_in_range(start, end));
+        let mut line_addresses_by_module = self.line_addresses.lock().unwrap();
+        for module_index in module_indexes {
+            let line_addresses =
+                line_addresses_by_module
+                    .entry(module_index)
+                    .or_insert_with(|| {
+                        self.module_info(module_index)
+                            .and_then(|module_info| {
+                                read_module_line_addresses(module_info, &self.object.address_map)
+                                    .ok()
+                            })
+                            .unwrap_or_default()
+                    });
+            let first = line_addresses.partition_point(|address| *address <= start);
+            let last = line_addresses.partition_point(|address| *address < end);
+            boundaries.extend_from_slice(&line_addresses[first..last]);
+        }
+
+        boundaries.sort_unstable();
+        boundaries.dedup();
+        boundaries
+    }
+
+    fn lookup_symbol(&self, address: u32) -> Option<SymbolInfo> {
+        // find_function only looks at procedure symbols and doesn't read any line
+        // information. Everything else, for example addresses which are only covered
+        // by a public symbol, goes through the full lookup.
+        match self.context().ok()?.find_function(address).ok()? {
+            Some(function) => {
+                let start_rva = function.start_rva;
+                let name = match function.name {
+                    Some(name) => demangle::demangle_any(&name),
+                    None => format!("fun_{start_rva:x}"),
+                };
+                Some(SymbolInfo {
+                    address: start_rva,
+                    size: function
+                        .end_rva
+                        .and_then(|end_rva| end_rva.checked_sub(start_rva)),
+                    name,
+                })
+            }
+            None => self
+                .lookup_relative_address(address)
+                .map(|address_info| address_info.symbol),
+        }
+    }
+
+    /// Returns the decorated name of the public symbol for the function which
+    /// contains `address`, i.e. the name before pdb-addr2line's `TypeFormatter` turns
+    /// it into a function signature.
+    fn lookup_raw_symbol_name(&self, address: u32) -> Option<String> {
+        let function = self.context().ok()?.find_function(address).ok()??;
+        self.public_function_name(function.start_rva)
+    }
+
     fn lookup_relative_address(&self, address: u32) -> Option<AddressInfo> {
-        let function_frames = self.context.find_frames(address).ok()??;
+        let function_frames = match self.context().ok()?.find_frames(address).ok()? {
+            Some(function_frames) => function_frames,
+            None => return self.lookup_thunk(address),
+        };
         let symbol_address = function_frames.start_rva;
-        let symbol_name = match &function_frames.frames.last().unwrap().function {
+        // For addresses which aren't covered by a procedure symbol, for example in
+        // thunks and assembly stubs, pdb-addr2line falls back to the closest preceding
+        // public symbol and returns a single frame without file and line. If not even
+        // that is available, use the same placeholder name as iter_symbols.
+        let symbol_name = match function_frames
+            .frames
+            .last()
+            .and_then(|frame| frame.function.as_deref())
+        {
             Some(name) => demangle::demangle_any(name),
-            None => "unknown".to_string(),
+            None => format!("fun_{symbol_address:x}"),
         };
         let function_size = function_frames
             .end_rva
-            .map(|end_rva| end_rva - function_frames.start_rva);
+            .and_then(|end_rva| end_rva.checked_sub(function_frames.start_rva));
 
         let symbol = SymbolInfo {
             address: symbol_address,
@@ -252,13 +732,23 @@ impl<'object> SymbolMapTrait for PdbSymbolMapInner<'object> {
                 let mapped_path = path_mapper.map_path(&path);
                 SourceFilePath::new(path.into_owned(), mapped_path)
             };
+            let compiland = self.lookup_compiland(address);
+            let frame_count = function_frames.frames.len();
             let frames: Vec<_> = function_frames
                 .frames
                 .into_iter()
-                .map(|frame| FrameDebugInfo {
-                    function: frame.function,
+                .enumerate()
+                .map(|(i, frame)| FrameDebugInfo {
+                    // Frames from public symbols can have MSVC-decorated names.
+                    function: frame
+                        .function
+                        .map(|name| demangle::demangle_msvc(&name).unwrap_or(name)),
                     file_path: frame.file.map(&mut map_path),
                     line_number: frame.line,
+                    compiland: compiland.clone(),
+                    // pdb-addr2line returns the inline frames first, and the frame
+                    // for the procedure last.
+                    is_inlined: i + 1 < frame_count,
                 })
                 .collect();
             FramesLookupResult::Available(frames)
@@ -266,7 +756,11 @@ impl<'object> SymbolMapTrait for PdbSymbolMapInner<'object> {
             FramesLookupResult::Unavailable
         };
 
-        Some(AddressInfo { symbol, frames })
+        Some(AddressInfo {
+            symbol,
+            frames,
+            section_name: None,
+        })
     }
 
     fn lookup_svma(&self, _svma: u64) -> Option<AddressInfo> {
@@ -288,33 +782,197 @@ where
     Box::new(stream)
 }
 
+/// The symbol data for a PDB file.
+///
+/// PDB files are MSF files, which are made of pages, and the streams in the file
+/// are read page by page through the [`pdb::Source`] implementation for
+/// [`FileContentsWrapper`]. Only the streams which are needed are read, so a
+/// [`FileContents`] implementation which fetches byte ranges on demand, such as
+/// [`FileContentsWithChunkedCaching`](crate::FileContentsWithChunkedCaching), only
+/// needs to fetch the pages of these streams:
+///
+///  - When the symbol map is created: the stream directory, the PDB information
+///    stream, the DBI stream, the section headers and OMAP streams, and the `srcsrv`
+///    stream. The module list and the section contributions are in the DBI stream.
+///  - For the first lookup, or the first call which lists the symbols: everything
+///    pdb-addr2line needs to create its context, i.e. the TPI and IPI streams, which
+///    are needed to format function signatures and inlinee names, the global
+///    symbols stream with the public symbols, and the names stream.
+///  - For each looked up address (`find_frames`): the stream of the module which
+///    contains the address, for its line program and inlinees. pdb-addr2line keeps
+///    the parsed module info, so this only happens for the first lookup in each
+///    module. The compilands (see [`FrameDebugInfo::compiland`]) come from the
+///    section contributions in the DBI stream, which is kept from the creation of
+///    the symbol map.
+///  - Only for addresses which aren't covered by a procedure or public symbol: the
+///    symbol streams of the module which covers the address and of the modules
+///    without section contributions, to find thunks. Module streams are cached and
+///    shared with pdb-addr2line, so each of them is read at most once.
+///
+/// The debug ID can be obtained without creating a symbol map, with
+/// [`debug_id_for_pdb`], which only reads the PDB information stream and the DBI
+/// stream.
 struct PdbSymbolData<T: FileContents + 'static>(FileContentsWrapper<T>);
 
 impl<T: FileContents + 'static> SymbolMapDataOuterTrait for PdbSymbolData<T> {
     fn make_symbol_map_data_mid(&self) -> Result<Box<dyn SymbolMapDataMidTrait + '_>, Error> {
         let mut pdb = PDB::open(&self.0)?;
         let info = pdb.pdb_information().context("pdb_information")?;
-        let dbi = pdb.debug_information()?;
-        let age = dbi.age().unwrap_or(info.age);
-        let debug_id = DebugId::from_parts(info.guid, age);
+        let debug_info = pdb.debug_information()?;
+        let debug_id = pdb_debug_id(&info, &debug_info);
+        if is_fastlink_pdb(&mut pdb)? {
+            return Err(Error::FastLinkPdbUnsupported);
+        }
 
         let srcsrv_stream = match pdb.named_stream(b"srcsrv") {
             Ok(stream) => Some(box_stream(stream)),
             Err(pdb::Error::StreamNameNotFound | pdb::Error::StreamNotFound(_)) => None,
             Err(e) => return Err(Error::PdbError("pdb.named_stream(srcsrv)", e)),
         };
-
-        let context_data = pdb_addr2line::ContextPdbData::try_from_pdb(pdb)
-            .context("ContextConstructionData::try_from_pdb")?;
+        let address_map = pdb.address_map().context("address_map")?;
 
         Ok(Box::new(PdbObject {
-            context_data,
+            file_contents: &self.0,
+            pdb: Mutex::new(Some(pdb)),
+            context_data: FrozenVec::new(),
+            debug_info,
+            address_map,
             debug_id,
             srcsrv_stream,
         }))
     }
 }
 
+/// Returns the debug ID of a PDB file, from the GUID in its PDB information stream
+/// and the age in its DBI stream. This matches the debug ID of the corresponding
+/// PE binary; use [`DebugId::breakpad`] to format it as a breakpad ID.
+pub fn debug_id_for_pdb<F: FileContents>(
+    pdb_file: &FileContentsWrapper<F>,
+) -> Result<DebugId, Error> {
+    let mut pdb = PDB::open(pdb_file)?;
+    read_pdb_debug_id(&mut pdb)
+}
+
+fn read_pdb_debug_id<'s, S: pdb::Source<'s> + 's>(pdb: &mut PDB<'s, S>) -> Result<DebugId, Error> {
+    let info = pdb.pdb_information().context("pdb_information")?;
+    let debug_info = pdb.debug_information()?;
+    Ok(pdb_debug_id(&info, &debug_info))
+}
+
+fn pdb_debug_id(info: &pdb::PDBInformation, debug_info: &pdb::DebugInformation) -> DebugId {
+    let age = debug_info.age().unwrap_or(info.age);
+    DebugId::from_parts(info.guid, age)
+}
+
+/// A source file which is referenced by the line information in a PDB file, see
+/// [`source_files_for_pdb`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PdbSourceFile {
+    /// The path of the file, as recorded by the compiler.
+    pub path: String,
+    /// The checksum of the file contents, if the compiler recorded one.
+    pub checksum: Option<PdbSourceFileChecksum>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PdbSourceFileChecksum {
+    Md5(Vec<u8>),
+    Sha1(Vec<u8>),
+    Sha256(Vec<u8>),
+}
+
+/// Returns all source files which are referenced by the line programs of the
+/// modules in the PDB file, sorted by path and without duplicates.
+pub fn source_files_for_pdb<F: FileContents>(
+    pdb_file: &FileContentsWrapper<F>,
+) -> Result<Vec<PdbSourceFile>, Error> {
+    use pdb::FallibleIterator;
+
+    let mut pdb = PDB::open(pdb_file)?;
+    let string_table = pdb.string_table().context("string_table")?;
+    let dbi = pdb.debug_information()?;
+    let mut modules = dbi.modules().context("modules")?;
+    let mut source_files = Vec::new();
+    while let Some(module) = modules.next().context("modules.next()")? {
+        let module_info = match pdb.module_info(&module).context("module_info")? {
+            Some(module_info) => module_info,
+            None => continue,
+        };
+        let line_program = module_info.line_program().context("line_program")?;
+        let mut files = line_program.files();
+        while let Some(file) = files.next().context("files.next()")? {
+            let path = file
+                .name
+                .to_string_lossy(&string_table)
+                .context("file name")?;
+            let checksum = match file.checksum {
+                pdb::FileChecksum::None => None,
+                pdb::FileChecksum::Md5(bytes) => Some(PdbSourceFileChecksum::Md5(bytes.to_vec())),
+                pdb::FileChecksum::Sha1(bytes) => Some(PdbSourceFileChecksum::Sha1(bytes.to_vec())),
+                pdb::FileChecksum::Sha256(bytes) => {
+                    Some(PdbSourceFileChecksum::Sha256(bytes.to_vec()))
+                }
+            };
+            source_files.push(PdbSourceFile {
+                path: path.into_owned(),
+                checksum,
+            });
+        }
+    }
+    source_files.sort_unstable();
+    source_files.dedup();
+    Ok(source_files)
+}
+
+/// The feature code ("MINI", for minimal debug info) which the linker adds to the
+/// PDB information stream when linking with `/DEBUG:FASTLINK`.
+const PDB_FEATURE_MINIMAL_DEBUG_INFO: u32 = 0x494e494d;
+
+/// Returns whether the PDB was produced with `/DEBUG:FASTLINK`. Such PDBs keep the
+/// type information in the object files, so function signatures can't be formatted
+/// correctly from the PDB alone.
+fn is_fastlink_pdb<'s, S: pdb::Source<'s> + 's>(pdb: &mut PDB<'s, S>) -> Result<bool, Error> {
+    let stream = match pdb
+        .raw_stream(pdb::StreamIndex(1))
+        .context("raw_stream(PDB information)")?
+    {
+        Some(stream) => stream,
+        None => return Ok(false),
+    };
+    let feature_codes = pdb_information_feature_codes(stream.as_slice()).unwrap_or_default();
+    Ok(feature_codes.contains(&PDB_FEATURE_MINIMAL_DEBUG_INFO))
+}
+
+/// Reads the feature codes at the end of the PDB information stream, which follow
+/// the stream header and the named stream map.
+fn pdb_information_feature_codes(data: &[u8]) -> Option<Vec<u32>> {
+    fn read_u32(data: &[u8], offset: &mut usize) -> Option<u32> {
+        let bytes = data.get(*offset..offset.checked_add(4)?)?;
+        *offset += 4;
+        Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    // Skip the version, signature and age, and the GUID.
+    let mut offset = 12 + 16;
+    // The named stream map consists of a string buffer and a hash table. The hash
+    // table has a size, a capacity, a "present" and a "deleted" bit vector, and
+    // a (key, value) pair for each present entry.
+    let string_buffer_size = read_u32(data, &mut offset)?;
+    offset = offset.checked_add(string_buffer_size as usize)?;
+    let entry_count = read_u32(data, &mut offset)?;
+    let _capacity = read_u32(data, &mut offset)?;
+    for _bit_vector in 0..2 {
+        let word_count = read_u32(data, &mut offset)?;
+        offset = offset.checked_add(word_count as usize * 4)?;
+    }
+    offset = offset.checked_add(entry_count as usize * 8)?;
+    let mut feature_codes = Vec::new();
+    while let Some(feature_code) = read_u32(data, &mut offset) {
+        feature_codes.push(feature_code);
+    }
+    Some(feature_codes)
+}
+
 pub fn get_symbol_map_for_pdb<F, FL>(
     file_contents: FileContentsWrapper<F>,
     debug_file_location: FL,
@@ -516,6 +1174,308 @@ fn parse_gitiles_url(input: &str) -> Result<MappedPath, nom::Err<nom::error::Err
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::FileAndPathHelperResult;
+    use std::ops::Range;
+    use std::sync::Arc;
+
+    /// Records the byte ranges which are read from the file.
+    struct ReadRecordingFileContents {
+        data: Vec<u8>,
+        reads: Arc<Mutex<Vec<Range<u64>>>>,
+    }
+
+    impl ReadRecordingFileContents {
+        fn record_read(&self, offset: u64, size: u64) {
+            self.reads.lock().unwrap().push(offset..offset + size);
+        }
+    }
+
+    impl FileContents for ReadRecordingFileContents {
+        fn len(&self) -> u64 {
+            self.data.len() as u64
+        }
+
+        fn read_bytes_at(&self, offset: u64, size: u64) -> FileAndPathHelperResult<&[u8]> {
+            self.record_read(offset, size);
+            self.data.read_bytes_at(offset, size)
+        }
+
+        fn read_bytes_at_until(
+            &self,
+            range: Range<u64>,
+            delimiter: u8,
+        ) -> FileAndPathHelperResult<&[u8]> {
+            self.record_read(range.start, range.end - range.start);
+            self.data.read_bytes_at_until(range, delimiter)
+        }
+
+        fn read_bytes_into(
+            &self,
+            buffer: &mut Vec<u8>,
+            offset: u64,
+            size: usize,
+        ) -> FileAndPathHelperResult<()> {
+            self.record_read(offset, size as u64);
+            self.data.read_bytes_into(buffer, offset, size)
+        }
+    }
+
+    fn read_u16(data: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// The streams of an MSF file, as the file ranges of their pages.
+    struct MsfStreams {
+        page_size: u64,
+        pages: Vec<Vec<u32>>,
+    }
+
+    impl MsfStreams {
+        /// Parses the superblock and the stream directory.
+        fn parse(data: &[u8]) -> Self {
+            let page_size = read_u32(data, 32) as usize;
+            let directory_size = read_u32(data, 44) as usize;
+            let directory_page_list = read_u32(data, 52) as usize * page_size;
+            let directory: Vec<u8> = (0..(directory_size + page_size - 1) / page_size)
+                .flat_map(|i| {
+                    let page = read_u32(data, directory_page_list + i * 4) as usize;
+                    &data[page * page_size..(page + 1) * page_size]
+                })
+                .copied()
+                .collect();
+            let stream_count = read_u32(&directory, 0) as usize;
+            let mut page_offset = 4 + stream_count * 4;
+            let pages = (0..stream_count)
+                .map(|stream| {
+                    let size = match read_u32(&directory, 4 + stream * 4) {
+                        u32::MAX => 0,
+                        size => size as usize,
+                    };
+                    let page_count = (size + page_size - 1) / page_size;
+                    let pages = (0..page_count)
+                        .map(|i| read_u32(&directory, page_offset + i * 4))
+                        .collect();
+                    page_offset += page_count * 4;
+                    pages
+                })
+                .collect();
+            Self {
+                page_size: page_size as u64,
+                pages,
+            }
+        }
+
+        fn stream_data(&self, data: &[u8], stream: usize) -> Vec<u8> {
+            self.pages[stream]
+                .iter()
+                .flat_map(|page| {
+                    let start = *page as usize * self.page_size as usize;
+                    &data[start..start + self.page_size as usize]
+                })
+                .copied()
+                .collect()
+        }
+
+        /// Returns the indexes of the streams with at least one page in `reads`.
+        fn touched_streams(&self, reads: &[Range<u64>]) -> Vec<usize> {
+            (0..self.pages.len())
+                .filter(|stream| {
+                    self.pages[*stream].iter().any(|page| {
+                        let start = u64::from(*page) * self.page_size;
+                        let end = start + self.page_size;
+                        reads
+                            .iter()
+                            .any(|range| range.start < end && range.end > start)
+                    })
+                })
+                .collect()
+        }
+    }
+
+    /// Returns the stream indexes of the modules in the module list of the DBI
+    /// stream.
+    fn module_streams(dbi_stream: &[u8]) -> Vec<usize> {
+        let module_list_size = read_u32(dbi_stream, 24) as usize;
+        let module_list = &dbi_stream[64..64 + module_list_size];
+        let mut module_streams = Vec::new();
+        let mut offset = 0;
+        while offset < module_list.len() {
+            let stream = read_u16(module_list, offset + 34);
+            if stream != u16::MAX {
+                module_streams.push(stream as usize);
+            }
+            // The fixed-size fields are followed by the module name and the object
+            // file name, and the record is padded to a multiple of 4 bytes.
+            offset += 64;
+            for _name in 0..2 {
+                offset += module_list[offset..].iter().position(|b| *b == 0).unwrap() + 1;
+            }
+            offset = (offset + 3) & !3;
+        }
+        module_streams
+    }
+
+    #[test]
+    fn pdb_lookup_reads_only_some_streams() {
+        const TPI_STREAM: usize = 2;
+        const DBI_STREAM: usize = 3;
+        const IPI_STREAM: usize = 4;
+
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../fixtures/win64-ci/mozglue.pdb");
+        let data = std::fs::read(path).unwrap();
+        let streams = MsfStreams::parse(&data);
+        let dbi_stream = streams.stream_data(&data, DBI_STREAM);
+        let global_streams = [
+            read_u16(&dbi_stream, 12) as usize,
+            read_u16(&dbi_stream, 16) as usize,
+            read_u16(&dbi_stream, 20) as usize,
+        ];
+        let module_streams = module_streams(&dbi_stream);
+        assert!(module_streams.len() > 50);
+
+        // Pick an address from a symbol map which doesn't record the reads.
+        let address = GenericSymbolMap::new(PdbSymbolData(FileContentsWrapper::new(data.clone())))
+            .unwrap()
+            .lookup_relative_address(0x1000)
+            .unwrap()
+            .symbol
+            .address;
+
+        let reads = Arc::new(Mutex::new(Vec::new()));
+        let symbol_map = GenericSymbolMap::new(PdbSymbolData(FileContentsWrapper::new(
+            ReadRecordingFileContents {
+                data,
+                reads: reads.clone(),
+            },
+        )))
+        .unwrap();
+
+        // Creating the symbol map reads neither the type information, nor the global
+        // symbols, nor any module stream.
+        let touched_streams = streams.touched_streams(&reads.lock().unwrap());
+        assert!(touched_streams.contains(&DBI_STREAM));
+        for stream in [TPI_STREAM, IPI_STREAM]
+            .iter()
+            .chain(&global_streams)
+            .chain(&module_streams)
+        {
+            assert!(
+                !touched_streams.contains(stream),
+                "stream {stream} was read when creating the symbol map"
+            );
+        }
+
+        // Looking up one address reads the stream of one module.
+        assert!(symbol_map.lookup_relative_address(address).is_some());
+        let touched_streams = streams.touched_streams(&reads.lock().unwrap());
+        let touched_module_streams = module_streams
+            .iter()
+            .filter(|stream| touched_streams.contains(stream))
+            .count();
+        assert_eq!(touched_module_streams, 1);
+
+        // Looking up the same address again doesn't read anything.
+        let read_count = reads.lock().unwrap().len();
+        assert!(symbol_map.lookup_relative_address(address).is_some());
+        assert_eq!(reads.lock().unwrap().len(), read_count);
+    }
+
+    #[test]
+    fn pdb_corrupt_type_information() {
+        const TPI_STREAM: usize = 2;
+
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../fixtures/win64-ci/mozglue.pdb");
+        let mut data = std::fs::read(path).unwrap();
+        let streams = MsfStreams::parse(&data);
+        let page_size = streams.page_size as usize;
+        let first_page = streams.pages[TPI_STREAM][0] as usize * page_size;
+        data[first_page..first_page + page_size].fill(0xff);
+
+        // The type information is only read for the first lookup, so creating the
+        // symbol map succeeds, but the error is kept.
+        let symbol_map =
+            GenericSymbolMap::new(PdbSymbolData(FileContentsWrapper::new(data))).unwrap();
+        assert!(symbol_map.lookup_relative_address(0x1000).is_none());
+        assert_eq!(symbol_map.symbol_count(), 0);
+        assert!(matches!(
+            symbol_map.lookup_error(),
+            Some(Error::PdbAddr2lineErrorWithContext(
+                "ContextConstructionData::try_from_pdb",
+                _
+            ))
+        ));
+    }
+
+    #[test]
+    fn thunks_in_module() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../fixtures/win64-ci/mozglue.pdb");
+        let data = FileContentsWrapper::new(std::fs::read(path).unwrap());
+        let mut pdb = PDB::open(&data).unwrap();
+        let address_map = pdb.address_map().unwrap();
+        let debug_info = pdb.debug_information().unwrap();
+        let modules: Vec<_> = {
+            use pdb::FallibleIterator;
+            debug_info.modules().unwrap().collect().unwrap()
+        };
+        let module_ranges =
+            read_pdb_module_ranges(&debug_info, &address_map, modules.len()).unwrap();
+
+        // The code of the import thunk for SystemFunction036 is attributed to the linker,
+        // but the thunk symbol is in the "Import:ADVAPI32.dll" module, which doesn't
+        // have any section contributions.
+        let linker_module = &modules[module_ranges.lookup(0x789b2).unwrap()];
+        assert_eq!(linker_module.module_name(), "* Linker *");
+        let module = module_ranges
+            .modules_without_ranges
+            .iter()
+            .map(|module_index| &modules[*module_index])
+            .find(|module| module.module_name() == "Import:ADVAPI32.dll")
+            .unwrap();
+        let module_info = pdb.module_info(module).unwrap().unwrap();
+        let thunks = read_module_thunks(&module_info, &address_map).unwrap();
+        let thunk = thunks
+            .iter()
+            .find(|thunk| thunk.start_rva <= 0x789b2 && 0x789b2 < thunk.end_rva)
+            .unwrap();
+        assert_eq!(thunk.name, "SystemFunction036");
+        assert_eq!((thunk.start_rva, thunk.end_rva), (0x789b0, 0x789b6));
+    }
+
+    #[test]
+    fn rva_range_overflow() {
+        assert_eq!(rva_range(pdb::Rva(0x1000), 0x20), Some((0x1000, 0x1020)));
+        assert_eq!(rva_range(pdb::Rva(0xffff_fff0), 0x10), None);
+        assert_eq!(rva_range(pdb::Rva(0x1000), u32::MAX), None);
+    }
+
+    #[test]
+    fn fastlink_feature_code() {
+        let mut data = Vec::new();
+        // Version, signature, age, GUID.
+        data.extend_from_slice(&20000404u32.to_le_bytes());
+        data.extend_from_slice(&[0; 4 + 4 + 16]);
+        // Named stream map with one entry: "/names" at string offset 0, stream 5.
+        data.extend_from_slice(&7u32.to_le_bytes());
+        data.extend_from_slice(b"/names\0");
+        for value in [1u32, 2, 1, 0b1, 0, 0, 5] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&20140508u32.to_le_bytes());
+        assert_eq!(pdb_information_feature_codes(&data), Some(vec![20140508]));
+        data.extend_from_slice(&PDB_FEATURE_MINIMAL_DEBUG_INFO.to_le_bytes());
+        assert_eq!(
+            pdb_information_feature_codes(&data),
+            Some(vec![20140508, PDB_FEATURE_MINIMAL_DEBUG_INFO])
+        );
+        assert_eq!(pdb_information_feature_codes(&data[..30]), None);
+    }
 
     #[test]
     fn test_parse_gitiles_url() {
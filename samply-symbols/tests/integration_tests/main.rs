@@ -1,8 +1,8 @@
 use samply_symbols::debugid::DebugId;
 use samply_symbols::{
-    self, CandidatePathInfo, CompactSymbolTable, Error, FileAndPathHelper, FileAndPathHelperResult,
-    FileLocation, LibraryInfo, MultiArchDisambiguator, OptionallySendFuture, SymbolManager,
-    SymbolMap,
+    self, CandidatePathInfo, CompactSymbolTable, ElfBuildId, Error, FileAndPathHelper,
+    FileAndPathHelperResult, FileLocation, LibraryInfo, MultiArchDisambiguator,
+    OptionallySendFuture, SymbolFileId, SymbolManager, SymbolMap,
 };
 use std::fs::File;
 use std::io::{BufWriter, Read, Write};
@@ -105,7 +105,8 @@ impl FileLocation for FileLocationType {
     }
 
     fn location_for_external_object_file(&self, object_file: &str) -> Option<Self> {
-        Some(Self(object_file.into()))
+        // Relative object paths are relative to the linked binary.
+        Some(Self(self.0.parent()?.join(object_file)))
     }
 
     fn location_for_pdb_from_binary(&self, pdb_path_in_binary: &str) -> Option<Self> {
@@ -119,6 +120,18 @@ impl FileLocation for FileLocationType {
     fn location_for_breakpad_symindex(&self) -> Option<Self> {
         Some(Self(self.0.with_extension("symindex")))
     }
+
+    fn location_for_dwp(&self) -> Option<Self> {
+        let mut path = self.0.clone().into_os_string();
+        path.push(".dwp");
+        Some(Self(path.into()))
+    }
+
+    fn location_for_dwo(&self, dwo_path: &str) -> Option<Self> {
+        // Relative .dwo paths are relative to the compilation directory, which is
+        // the fixture directory for the fixtures built with `-fdebug-prefix-map`.
+        Some(Self(self.0.parent()?.join(dwo_path)))
+    }
 }
 
 fn mmap_to_file_contents(m: memmap2::Mmap) -> FileContentsType {
@@ -221,6 +234,17 @@ impl<'h> FileAndPathHelper<'h> for Helper {
         Ok(paths)
     }
 
+    fn get_candidate_paths_for_external_object_file(
+        &self,
+        _original_file_location: &FileLocationType,
+        object_file: &str,
+    ) -> FileAndPathHelperResult<Vec<FileLocationType>> {
+        // Also look for relocated object files in the symbol directory.
+        Ok(vec![FileLocationType(
+            self.symbol_directory.join(object_file),
+        )])
+    }
+
     fn get_dyld_shared_cache_paths(
         &self,
         _arch: Option<&str>,
@@ -255,6 +279,31 @@ fn successful_pdb() {
         );
 }
 
+#[test]
+fn pdb_debug_id() {
+    let pdb_path = fixtures_dir().join("win64-ci").join("firefox.pdb");
+    let file_contents = samply_symbols::FileContentsWrapper::new(std::fs::read(&pdb_path).unwrap());
+    let debug_id = samply_symbols::debug_id_for_pdb(&file_contents).unwrap();
+    assert_eq!(
+        debug_id.breakpad().to_string(),
+        "AA152DEB2D9B76084C4C44205044422E1"
+    );
+    assert_eq!(
+        DebugId::from_breakpad(&debug_id.breakpad().to_string()).unwrap(),
+        debug_id
+    );
+
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let symbol_map = futures::executor::block_on(
+        symbol_manager.load_symbol_map_from_location(FileLocationType(pdb_path), None),
+    )
+    .unwrap();
+    assert_eq!(symbol_map.debug_id(), debug_id);
+}
+
 #[test]
 fn successful_pdb2() {
     let result = futures::executor::block_on(crate::get_table(
@@ -301,6 +350,48 @@ fn successful_dll() {
     );
 }
 
+#[test]
+fn dll_raw_symbol_name() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-local"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("win64-local").join("mozglue.dll")),
+        None,
+    ))
+    .unwrap();
+    assert_eq!(
+        symbol_map.lookup_raw_symbol_name(0x34670).as_deref(),
+        Some("?profiler_get_profile@baseprofiler@mozilla@@YA?AV?$UniquePtr@$$BY0A@DV?$DefaultDelete@$$BY0A@D@mozilla@@@2@N_N0@Z")
+    );
+    assert_ne!(
+        symbol_map.lookup_symbol(0x34670).unwrap().name,
+        symbol_map.lookup_raw_symbol_name(0x34670).unwrap()
+    );
+}
+
+#[test]
+fn pdb_raw_symbol_name() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-local"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("win64-local").join("mozglue.pdb")),
+        None,
+    ))
+    .unwrap();
+    assert_eq!(
+        symbol_map.lookup_raw_symbol_name(0x34670).as_deref(),
+        Some("?profiler_get_profile@baseprofiler@mozilla@@YA?AV?$UniquePtr@$$BY0A@DV?$DefaultDelete@$$BY0A@D@mozilla@@@2@N_N0@Z")
+    );
+    assert_eq!(
+        symbol_map.lookup_symbol(0x34670).unwrap().name,
+        "mozilla::baseprofiler::profiler_get_profile(double, bool, bool)"
+    );
+}
+
 #[test]
 fn successful_pdb_unspecified_id() {
     let result = futures::executor::block_on(crate::get_table(
@@ -413,6 +504,43 @@ fn fat_arch_2() {
     );
 }
 
+#[test]
+fn list_fat_arch_members() {
+    let file = File::open(fixtures_dir().join("macos-ci").join("firefox")).unwrap();
+    let mmap = unsafe { memmap2::MmapOptions::new().map(&file).unwrap() };
+    let file_contents = samply_symbols::FileContentsWrapper::new(mmap);
+    let members = samply_symbols::list_fat_archive_members(&file_contents).unwrap();
+    assert_eq!(members.len(), 2);
+    let member_ids: Vec<DebugId> = members
+        .iter()
+        .filter_map(|m| m.uuid)
+        .map(DebugId::from_uuid)
+        .collect();
+    assert!(
+        member_ids.contains(&DebugId::from_breakpad("B993FABD8143361AB199F7DE9DF7E4360").unwrap())
+    );
+    assert!(
+        member_ids.contains(&DebugId::from_breakpad("8E7B0ED0B04F3FCCA05E139E5250BA720").unwrap())
+    );
+    assert!(members.iter().all(|m| m.arch.is_some()));
+}
+
+#[test]
+fn fat_arch_member_debug_id_and_arch() {
+    let file = File::open(fixtures_dir().join("macos-ci").join("firefox")).unwrap();
+    let mmap = unsafe { memmap2::MmapOptions::new().map(&file).unwrap() };
+    let file_contents = samply_symbols::FileContentsWrapper::new(mmap);
+    let members = samply_symbols::list_fat_archive_members(&file_contents).unwrap();
+    for member in &members {
+        let debug_id = DebugId::from_uuid(member.uuid.unwrap());
+        let arch = member.arch.clone().unwrap();
+        let matching = MultiArchDisambiguator::DebugIdAndArch(debug_id, arch);
+        assert_eq!(member.match_score_for_disambiguator(&matching), Some(0));
+        let wrong_arch = MultiArchDisambiguator::DebugIdAndArch(debug_id, "ppc".to_string());
+        assert_eq!(member.match_score_for_disambiguator(&wrong_arch), None);
+    }
+}
+
 #[test]
 fn linux_nonzero_base_address() {
     let helper = Helper {
@@ -514,6 +642,301 @@ fn example_linux() {
     );
 }
 
+#[test]
+fn example_linux_lookup_symbol() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("other").join("example-linux")),
+        None,
+    ))
+    .unwrap();
+    for address in [0x1156, 0x1158, 0x1160, 0x1165] {
+        assert_eq!(
+            symbol_map.lookup_symbol(address),
+            symbol_map
+                .lookup_relative_address(address)
+                .map(|info| info.symbol),
+            "Mismatch at 0x{address:x}"
+        );
+    }
+    let symbol = symbol_map.lookup_symbol(0x1160).unwrap();
+    assert_eq!(symbol.name, "f");
+    assert_eq!(symbol.address, 0x1160);
+}
+
+#[test]
+fn example_linux_lookup_avma() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("other").join("example-linux")),
+        None,
+    ))
+    .unwrap();
+    let image_base = 0x5555_5555_4000;
+    assert_eq!(
+        symbol_map.lookup_avma(image_base + 0x1160, image_base),
+        symbol_map.lookup_relative_address(0x1160)
+    );
+    assert_eq!(
+        symbol_map
+            .lookup_avma(image_base + 0x1160, image_base)
+            .unwrap()
+            .symbol
+            .name,
+        "f"
+    );
+    // Addresses below the image base aren't in this library.
+    assert_eq!(symbol_map.lookup_avma(image_base - 0x10, image_base), None);
+}
+
+#[test]
+fn example_linux_address_cache() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let mut symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("other").join("example-linux")),
+        None,
+    ))
+    .unwrap();
+    let addresses = [0x1156, 0x1158, 0x1160, 0x1165, 0x1160, 0x1156, 0x1160];
+    let uncached: Vec<_> = addresses
+        .iter()
+        .map(|address| symbol_map.lookup_relative_address(*address))
+        .collect();
+    assert!(uncached[2].is_some());
+    assert!(uncached[1].is_none());
+
+    symbol_map.set_address_cache_capacity(Some(2));
+    for _ in 0..2 {
+        let cached: Vec<_> = addresses
+            .iter()
+            .map(|address| symbol_map.lookup_relative_address(*address))
+            .collect();
+        assert_eq!(cached, uncached);
+    }
+}
+
+#[test]
+fn verify_symbols() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let debug_id = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("other").join("example-linux")),
+        None,
+    ))
+    .unwrap()
+    .debug_id();
+    let library_info = LibraryInfo {
+        debug_name: Some("example-linux".to_string()),
+        debug_id: Some(debug_id),
+        ..Default::default()
+    };
+    let report = futures::executor::block_on(symbol_manager.verify_symbols(&library_info)).unwrap();
+    assert_eq!(report.debug_id, debug_id);
+    assert_eq!(
+        report.debug_file_location.0,
+        fixtures_dir().join("other").join("example-linux")
+    );
+    assert!(report.referenced_files.is_empty());
+    assert!(report.is_complete());
+
+    let library_info = LibraryInfo {
+        debug_id: Some(DebugId::nil()),
+        ..library_info
+    };
+    assert!(matches!(
+        futures::executor::block_on(symbol_manager.verify_symbols(&library_info)),
+        Err(Error::UnmatchedDebugId(..))
+    ));
+}
+
+#[test]
+fn verify_symbols_missing_external_files() {
+    // This library was linked without a dSYM, and its object map refers to object
+    // files on the machine where it was built.
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("macos-local"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let debug_id = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("macos-local").join("libmozglue.dylib")),
+        None,
+    ))
+    .unwrap()
+    .debug_id();
+    let library_info = LibraryInfo {
+        debug_name: Some("libmozglue.dylib".to_string()),
+        debug_id: Some(debug_id),
+        ..Default::default()
+    };
+    let report = futures::executor::block_on(symbol_manager.verify_symbols(&library_info)).unwrap();
+    assert!(!report.referenced_files.is_empty());
+    assert!(report
+        .referenced_files
+        .iter()
+        .all(|(file, _)| matches!(file, samply_symbols::ReferencedDebugFile::ExternalFile(_))));
+    assert_eq!(
+        report.missing_files().count(),
+        report.referenced_files.len()
+    );
+}
+
+#[test]
+fn symbol_file_identity() {
+    let identity = |path: PathBuf| {
+        let file_contents = samply_symbols::FileContentsWrapper::new(std::fs::read(path).unwrap());
+        samply_symbols::symbol_file_identity(&file_contents).unwrap()
+    };
+
+    assert_eq!(
+        identity(fixtures_dir().join("other").join("example-linux")),
+        SymbolFileId::ElfBuildId(ElfBuildId::from_bytes(&[
+            0x6c, 0x97, 0x4e, 0xbe, 0x52, 0x32, 0xee, 0x46, 0x9d, 0x6b, 0x78, 0x47, 0xa6, 0x70,
+            0xb2, 0xa9, 0x56, 0xf8, 0xae, 0xde
+        ]))
+    );
+
+    let pdb_debug_id = DebugId::from_breakpad("AA152DEB2D9B76084C4C44205044422E1").unwrap();
+    assert_eq!(
+        identity(fixtures_dir().join("win64-ci").join("firefox.pdb")),
+        SymbolFileId::PeOrPdbDebugId(pdb_debug_id)
+    );
+    assert_eq!(
+        identity(fixtures_dir().join("win64-ci").join("firefox.exe")),
+        SymbolFileId::PeOrPdbDebugId(pdb_debug_id)
+    );
+
+    let dylib_path = fixtures_dir().join("macos-local").join("libmozglue.dylib");
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("macos-local"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let debug_id = futures::executor::block_on(
+        symbol_manager.load_symbol_map_from_location(FileLocationType(dylib_path.clone()), None),
+    )
+    .unwrap()
+    .debug_id();
+    assert_eq!(
+        identity(dylib_path),
+        SymbolFileId::MachOUuid(debug_id.uuid())
+    );
+
+    // Universal binaries have one UUID per architecture.
+    let file_contents = samply_symbols::FileContentsWrapper::new(
+        std::fs::read(fixtures_dir().join("macos-ci").join("firefox")).unwrap(),
+    );
+    assert!(matches!(
+        samply_symbols::symbol_file_identity(&file_contents),
+        Err(Error::InvalidInputError(_))
+    ));
+}
+
+#[test]
+fn example_linux_section_name() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("other").join("example-linux")),
+        None,
+    ))
+    .unwrap();
+    let address_info = symbol_map.lookup_relative_address(0x1160).unwrap();
+    assert_eq!(address_info.section_name.as_deref(), Some(".text"));
+}
+
+#[test]
+fn example_linux_enumerate_functions() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("other").join("example-linux")),
+        None,
+    ))
+    .unwrap();
+    let functions = symbol_map.enumerate_functions();
+    assert!(functions
+        .windows(2)
+        .all(|w| w[0].start_address < w[1].start_address));
+    let f = functions.iter().find(|f| f.name == "f").unwrap();
+    assert_eq!(f.start_address, 0x1160);
+    assert!(f.end_address > f.start_address);
+    assert!(!f.lines.is_empty());
+    assert_eq!(f.lines[0].0, 0x1160);
+    assert!(f
+        .lines
+        .iter()
+        .all(|(address, _, _)| (f.start_address..f.end_address).contains(address)));
+    assert!(f.lines.iter().all(|(_, file, line)| {
+        file.as_ref()
+            .map_or(false, |file| file.raw_path().ends_with(".c"))
+            && line.is_some()
+    }));
+
+    // Walking the boundaries of the whole library gives the same lines as looking
+    // up each function's range on its own.
+    for function in &functions {
+        let lines: Vec<_> = symbol_map
+            .lookup_relative_address_range(function.start_address, function.end_address)
+            .into_iter()
+            .filter(|(_, info)| info.symbol.address == function.start_address)
+            .filter_map(|(address, info)| match info.frames {
+                samply_symbols::FramesLookupResult::Available(frames) => {
+                    let innermost = frames.into_iter().next()?;
+                    Some((address, innermost.file_path, innermost.line_number))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(function.lines, lines, "{}", function.name);
+    }
+}
+
+#[test]
+fn example_linux_address_range() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("other").join("example-linux")),
+        None,
+    ))
+    .unwrap();
+    let results = symbol_map.lookup_relative_address_range(0x1156, 0x1170);
+    assert_eq!(results.first().unwrap().0, 0x1156);
+    assert_eq!(results.first().unwrap().1.symbol.name, "main");
+    assert!(results.windows(2).all(|w| w[0].0 < w[1].0));
+    assert!(
+        results.windows(2).all(|w| w[0].1 != w[1].1),
+        "Consecutive identical results should be merged"
+    );
+    assert!(results
+        .iter()
+        .all(|(address, _)| (0x1156..0x1170).contains(address)));
+    let f = results
+        .iter()
+        .find(|(_, info)| info.symbol.name == "f")
+        .unwrap();
+    assert_eq!(f.0, 0x1160);
+    assert!(symbol_map
+        .lookup_relative_address_range(0x1160, 0x1160)
+        .is_empty());
+}
+
 #[test]
 fn example_linux_fallback() {
     let helper = Helper {
@@ -533,6 +956,665 @@ fn example_linux_fallback() {
     assert_eq!(symbol_map.lookup_relative_address(0x6), None);
 }
 
+#[test]
+fn example_linux_stripped_function_starts() {
+    // This is example-linux after `objcopy --strip-all`. It has no function symbols
+    // left, so the function boundaries come from .eh_frame.
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("other").join("example-linux-stripped")),
+        None,
+    ))
+    .unwrap();
+    assert_eq!(
+        symbol_map.debug_id(),
+        DebugId::from_breakpad("BE4E976C325246EE9D6B7847A670B2A90").unwrap()
+    );
+    let symbol = symbol_map.lookup_relative_address(0x1170).unwrap().symbol;
+    assert_eq!(symbol.name, "fun_1160");
+    assert_eq!(symbol.address, 0x1160);
+    assert_eq!(symbol.size, Some(0x45));
+    assert_eq!(
+        symbol_map.lookup_relative_address(0x1158),
+        None,
+        "Gap between the FDEs for main and f"
+    );
+}
+
+#[test]
+fn nopie_stripped_function_starts() {
+    // A stripped non-PIE executable, whose image base is 0x400000. The FDE
+    // addresses in .eh_frame are converted to relative addresses.
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other").join("stripped-fde"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let path = fixtures_dir()
+        .join("other")
+        .join("stripped-fde")
+        .join("nopie-stripped");
+    let symbol_map = futures::executor::block_on(
+        symbol_manager.load_symbol_map_from_location(FileLocationType(path), None),
+    )
+    .unwrap();
+    assert_eq!(
+        symbol_map.debug_id(),
+        DebugId::from_breakpad("CE3889CDEC917310316F44B33D9851140").unwrap()
+    );
+    // sum_of_squares is at 0x40110c..0x401140.
+    let symbol = symbol_map.lookup_relative_address(0x1120).unwrap().symbol;
+    assert_eq!(symbol.name, "fun_110c");
+    assert_eq!(symbol.address, 0x110c);
+    assert_eq!(symbol.size, Some(0x34));
+}
+
+#[test]
+fn debug_frame_only_function_starts() {
+    // A stripped shared library which was compiled without unwind tables. Its
+    // .eh_frame section is empty, so the function boundaries come from
+    // .debug_frame.
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other").join("stripped-fde"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let path = fixtures_dir()
+        .join("other")
+        .join("stripped-fde")
+        .join("debug-frame-only-stripped.so");
+    let symbol_map = futures::executor::block_on(
+        symbol_manager.load_symbol_map_from_location(FileLocationType(path), None),
+    )
+    .unwrap();
+    // square is at 0x1000..0x1006, sum_of_squares at 0x1006..0x103a.
+    let symbol = symbol_map.lookup_relative_address(0x1004).unwrap().symbol;
+    assert_eq!(symbol.name, "fun_1000");
+    assert_eq!(symbol.size, Some(0x6));
+    let symbol = symbol_map.lookup_relative_address(0x1020).unwrap().symbol;
+    assert_eq!(symbol.name, "fun_1006");
+    assert_eq!(symbol.size, Some(0x34));
+}
+
+#[test]
+fn split_dwarf_dwo_files() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other").join("split-dwarf"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let path = fixtures_dir()
+        .join("other")
+        .join("split-dwarf")
+        .join("split-dwarf");
+    let symbol_map = futures::executor::block_on(
+        symbol_manager.load_symbol_map_from_location(FileLocationType(path), None),
+    )
+    .unwrap();
+
+    // 0x1129 is in main, in the inlined call to square. main's debug info is in main.dwo,
+    // which needs to be loaded first.
+    let main_dwo = match symbol_map.lookup_relative_address(0x1129).unwrap().frames {
+        samply_symbols::FramesLookupResult::NeedDwo(dwo_ref) => dwo_ref,
+        frames => panic!("Expected NeedDwo, got {frames:?}"),
+    };
+    assert_eq!(
+        main_dwo,
+        samply_symbols::DwoRef {
+            path: "./main.dwo".to_string(),
+            dwo_id: 0x4735c9465027f4e4,
+        }
+    );
+    let contents = futures::executor::block_on(
+        symbol_manager.load_dwo_file(symbol_map.debug_file_location(), &main_dwo),
+    )
+    .unwrap();
+    symbol_map.add_dwo_file(&main_dwo, Some(contents));
+
+    let frames = match symbol_map.lookup_relative_address(0x1129).unwrap().frames {
+        samply_symbols::FramesLookupResult::Available(frames) => frames,
+        frames => panic!("Expected Available, got {frames:?}"),
+    };
+    let frames: Vec<_> = frames
+        .iter()
+        .map(|frame| {
+            (
+                frame.function.as_deref().unwrap(),
+                frame.file_path.as_ref().unwrap().raw_path(),
+                frame.line_number.unwrap(),
+                frame.is_inlined,
+            )
+        })
+        .collect();
+    assert_eq!(
+        frames,
+        vec![
+            ("square", "./main.c", 6, true),
+            ("main", "./main.c", 10, false),
+        ]
+    );
+
+    // compute_sum's debug info is in sum.dwo, which hasn't been loaded.
+    let sum_dwo = match symbol_map.lookup_relative_address(0x1165).unwrap().frames {
+        samply_symbols::FramesLookupResult::NeedDwo(dwo_ref) => dwo_ref,
+        frames => panic!("Expected NeedDwo, got {frames:?}"),
+    };
+    assert_eq!(sum_dwo.path, "./sum.dwo");
+
+    // If the .dwo file can't be loaded, the lookup falls back to the skeleton unit.
+    symbol_map.add_dwo_file(&sum_dwo, None);
+    assert!(!matches!(
+        symbol_map.lookup_relative_address(0x1165).unwrap().frames,
+        samply_symbols::FramesLookupResult::NeedDwo(_)
+    ));
+}
+
+#[test]
+fn split_dwarf_dwp_file() {
+    // split-dwarf-packaged is the same binary as split-dwarf, with the .dwo files
+    // packaged into split-dwarf-packaged.dwp.
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("other").join("split-dwarf"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let path = fixtures_dir()
+        .join("other")
+        .join("split-dwarf")
+        .join("split-dwarf-packaged");
+    let symbol_map = futures::executor::block_on(
+        symbol_manager.load_symbol_map_from_location(FileLocationType(path), None),
+    )
+    .unwrap();
+    let frames = match symbol_map.lookup_relative_address(0x1165).unwrap().frames {
+        samply_symbols::FramesLookupResult::Available(frames) => frames,
+        frames => panic!("Expected Available, got {frames:?}"),
+    };
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].function.as_deref(), Some("compute_sum"));
+    assert_eq!(frames[0].line_number, Some(8));
+}
+
+#[test]
+fn verify_symbols_split_dwarf() {
+    let fixture_dir = fixtures_dir().join("other").join("split-dwarf");
+    let verify = |symbol_directory: &Path, debug_name: &str| {
+        let helper = Helper {
+            symbol_directory: symbol_directory.to_owned(),
+        };
+        let symbol_manager = SymbolManager::with_helper(&helper);
+        let debug_id = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+            FileLocationType(symbol_directory.join(debug_name)),
+            None,
+        ))
+        .unwrap()
+        .debug_id();
+        let library_info = LibraryInfo {
+            debug_name: Some(debug_name.to_string()),
+            debug_id: Some(debug_id),
+            ..Default::default()
+        };
+        let report =
+            futures::executor::block_on(symbol_manager.verify_symbols(&library_info)).unwrap();
+        report.referenced_files
+    };
+
+    let referenced_files = verify(&fixture_dir, "split-dwarf");
+    assert_eq!(referenced_files.len(), 2);
+    assert_eq!(
+        referenced_files[0].0,
+        samply_symbols::ReferencedDebugFile::Dwo("./main.dwo".to_string())
+    );
+    assert_eq!(
+        referenced_files[1].0,
+        samply_symbols::ReferencedDebugFile::Dwo("./sum.dwo".to_string())
+    );
+    assert!(referenced_files.iter().all(|(_, result)| result.is_ok()));
+
+    let referenced_files = verify(&fixture_dir, "split-dwarf-packaged");
+    assert_eq!(referenced_files.len(), 1);
+    assert_eq!(
+        referenced_files[0].0,
+        samply_symbols::ReferencedDebugFile::Dwp
+    );
+    assert!(referenced_files[0].1.is_ok());
+
+    // Swap the two .dwo files, so that each has the wrong DWO ID.
+    let dir = std::env::temp_dir().join(format!(
+        "samply-symbols-verify-split-dwarf-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy(fixture_dir.join("split-dwarf"), dir.join("split-dwarf")).unwrap();
+    std::fs::copy(fixture_dir.join("sum.dwo"), dir.join("main.dwo")).unwrap();
+    std::fs::copy(fixture_dir.join("main.dwo"), dir.join("sum.dwo")).unwrap();
+    let referenced_files = verify(&dir, "split-dwarf");
+    assert_eq!(referenced_files.len(), 2);
+    match &referenced_files[0].1 {
+        Err(Error::UnmatchedDwoId(expected, Some(found))) => {
+            assert_eq!(*expected, 0x4735c9465027f4e4);
+            assert_ne!(found, expected);
+        }
+        result => panic!("Expected UnmatchedDwoId, got {result:?}"),
+    }
+    assert!(matches!(
+        referenced_files[1].1,
+        Err(Error::UnmatchedDwoId(..))
+    ));
+
+    // A .dwp file takes precedence over the .dwo files, even if it's broken.
+    std::fs::write(dir.join("split-dwarf.dwp"), b"not a dwp file").unwrap();
+    let referenced_files = verify(&dir, "split-dwarf");
+    assert_eq!(referenced_files.len(), 1);
+    assert_eq!(
+        referenced_files[0].0,
+        samply_symbols::ReferencedDebugFile::Dwp
+    );
+    assert!(matches!(
+        referenced_files[0].1,
+        Err(Error::SplitDwarfParseError(_))
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn pdb_public_symbol_fallback() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("win64-ci").join("mozglue.pdb")),
+        None,
+    ))
+    .unwrap();
+    // 0x789c0 is an import thunk, which only has a public symbol and no procedure.
+    let address_info = symbol_map.lookup_relative_address(0x789c0).unwrap();
+    assert_eq!(address_info.symbol.address, 0x789c0);
+    assert_eq!(address_info.symbol.name, "NtQueryVirtualMemory");
+    assert_eq!(
+        address_info.frames,
+        samply_symbols::FramesLookupResult::Unavailable
+    );
+}
+
+#[test]
+fn pdb_max_inline_depth() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let mut symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("win64-ci").join("mozglue.pdb")),
+        None,
+    ))
+    .unwrap();
+    fn frames_at(
+        symbol_map: &samply_symbols::SymbolMap<FileLocationType>,
+        address: u32,
+    ) -> Option<Vec<samply_symbols::FrameDebugInfo>> {
+        match symbol_map.lookup_relative_address(address)?.frames {
+            samply_symbols::FramesLookupResult::Available(frames) => Some(frames),
+            _ => None,
+        }
+    }
+    // Find an address with at least two levels of inlining.
+    let symbol_addresses: Vec<u32> = symbol_map
+        .iter_symbols()
+        .map(|(address, _)| address)
+        .collect();
+    let (address, all_frames) = symbol_addresses
+        .iter()
+        .flat_map(|address| (0..0x100).step_by(4).map(move |offset| address + offset))
+        .find_map(|address| {
+            let frames = frames_at(&symbol_map, address)?;
+            (frames.len() >= 3).then(|| (address, frames))
+        })
+        .expect("mozglue.pdb should have deeply inlined code");
+
+    symbol_map.set_max_inline_depth(Some(1));
+    let frames = frames_at(&symbol_map, address).unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0], all_frames[0]);
+    assert_eq!(frames[1], *all_frames.last().unwrap());
+
+    symbol_map.set_max_inline_depth(Some(0));
+    let frames = frames_at(&symbol_map, address).unwrap();
+    assert_eq!(frames, vec![all_frames.last().unwrap().clone()]);
+
+    symbol_map.set_max_inline_depth(None);
+    assert_eq!(frames_at(&symbol_map, address).unwrap(), all_frames);
+}
+
+#[test]
+fn pdb_function_name_style() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let mut symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("win64-ci").join("mozglue.pdb")),
+        None,
+    ))
+    .unwrap();
+    // Find an address with an inlined frame, in a function whose name has a parameter
+    // list.
+    let symbol_addresses: Vec<u32> = symbol_map
+        .iter_symbols()
+        .map(|(address, _)| address)
+        .collect();
+    let (address, full_info) = symbol_addresses
+        .iter()
+        .flat_map(|address| (0..0x100).step_by(4).map(move |offset| address + offset))
+        .find_map(|address| {
+            let info = symbol_map.lookup_relative_address(address)?;
+            match &info.frames {
+                samply_symbols::FramesLookupResult::Available(frames)
+                    if frames.len() >= 2 && info.symbol.name.ends_with(')') =>
+                {
+                    Some((address, info))
+                }
+                _ => None,
+            }
+        })
+        .expect("mozglue.pdb should have inlined code");
+
+    symbol_map.set_function_name_style(samply_symbols::FunctionNameStyle {
+        strip_arguments: true,
+        template_args_visible_depth: None,
+    });
+    let info = symbol_map.lookup_relative_address(address).unwrap();
+    assert_eq!(
+        info.symbol.name,
+        samply_symbols::strip_function_arguments(&full_info.symbol.name)
+    );
+    assert!(!info.symbol.name.contains('('));
+    let (frames, full_frames) = match (info.frames, full_info.frames) {
+        (
+            samply_symbols::FramesLookupResult::Available(frames),
+            samply_symbols::FramesLookupResult::Available(full_frames),
+        ) => (frames, full_frames),
+        _ => panic!("frames should be available"),
+    };
+    assert_eq!(frames.len(), full_frames.len());
+    for (frame, full_frame) in frames.iter().zip(&full_frames) {
+        assert_eq!(
+            frame.function.as_deref(),
+            full_frame
+                .function
+                .as_deref()
+                .map(samply_symbols::strip_function_arguments)
+        );
+        assert_eq!(frame.line_number, full_frame.line_number);
+    }
+    assert_eq!(
+        symbol_map.lookup_symbol(address).unwrap().name,
+        info.symbol.name
+    );
+}
+
+#[test]
+fn pdb_function_name_style_elide_templates() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let mut symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("win64-ci").join("mozglue.pdb")),
+        None,
+    ))
+    .unwrap();
+    fn function_names(info: samply_symbols::AddressInfo) -> Vec<String> {
+        let mut names = vec![info.symbol.name];
+        if let samply_symbols::FramesLookupResult::Available(frames) = info.frames {
+            names.extend(frames.into_iter().filter_map(|frame| frame.function));
+        }
+        names
+    }
+    // Find an address with an inlined frame and with nested template arguments in
+    // one of the function names.
+    let symbol_addresses: Vec<u32> = symbol_map
+        .iter_symbols()
+        .map(|(address, _)| address)
+        .collect();
+    let (address, full_names) = symbol_addresses
+        .iter()
+        .flat_map(|address| (0..0x100).step_by(4).map(move |offset| address + offset))
+        .find_map(|address| {
+            let names = function_names(symbol_map.lookup_relative_address(address)?);
+            let has_nested_templates = names
+                .iter()
+                .any(|name| samply_symbols::elide_template_args(name, 1) != *name);
+            (names.len() >= 3 && has_nested_templates).then(|| (address, names))
+        })
+        .expect("mozglue.pdb should have inlined template code");
+
+    symbol_map.set_function_name_style(samply_symbols::FunctionNameStyle {
+        strip_arguments: false,
+        template_args_visible_depth: Some(1),
+    });
+    let names = function_names(symbol_map.lookup_relative_address(address).unwrap());
+    let expected_names: Vec<String> = full_names
+        .iter()
+        .map(|name| samply_symbols::elide_template_args(name, 1))
+        .collect();
+    assert_eq!(names, expected_names);
+    assert_ne!(names, full_names);
+}
+
+#[test]
+fn macho_dwarf_inline_frames() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("macos-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let dsym_path = fixtures_dir()
+        .join("macos-ci")
+        .join("libmozglue.dylib.dSYM")
+        .join("Contents")
+        .join("Resources")
+        .join("DWARF")
+        .join("libmozglue.dylib");
+    let mut symbol_map = futures::executor::block_on(
+        symbol_manager.load_symbol_map_from_location(FileLocationType(dsym_path), None),
+    )
+    .unwrap();
+    fn frames_at(
+        symbol_map: &samply_symbols::SymbolMap<FileLocationType>,
+        address: u32,
+    ) -> Option<Vec<samply_symbols::FrameDebugInfo>> {
+        match symbol_map.lookup_relative_address(address)?.frames {
+            samply_symbols::FramesLookupResult::Available(frames) => Some(frames),
+            _ => None,
+        }
+    }
+    // 0x1544 is in the static initializer of SSE.cpp, where __get_cpuid_max has
+    // been inlined into has_cpuid_bits, which has been inlined into the variable
+    // initializer. One frame per inline level, innermost first, and the callers
+    // have the call site of the inlined function. The outer function is an
+    // artificial one without a call line.
+    let address = 0x1544;
+    let frames = frames_at(&symbol_map, address).unwrap();
+    let frame_summary: Vec<_> = frames
+        .iter()
+        .map(|frame| {
+            (
+                frame.function.as_deref().unwrap(),
+                frame.file_path.as_ref().unwrap().raw_path(),
+                frame.line_number,
+                frame.is_inlined,
+            )
+        })
+        .collect();
+    let sse_cpp = "/builds/worker/checkouts/gecko/mozglue/build/SSE.cpp";
+    assert_eq!(
+        frame_summary,
+        vec![
+            (
+                "__get_cpuid_max(unsigned int, unsigned int*)",
+                "/builds/worker/fetches/clang/lib/clang/9.0.1/include/cpuid.h",
+                Some(271),
+                true
+            ),
+            (
+                "(anonymous namespace)::has_cpuid_bits(unsigned int, (anonymous namespace)::CPUIDRegister, unsigned int)",
+                sse_cpp,
+                Some(33),
+                true
+            ),
+            ("_cxx_global_var_init", sse_cpp, Some(139), true),
+            ("GLOBAL__sub_I_SSE.cpp", sse_cpp, None, false),
+        ]
+    );
+    let outer = frames.last().unwrap();
+
+    symbol_map.set_max_inline_depth(Some(0));
+    assert_eq!(
+        frames_at(&symbol_map, address).unwrap(),
+        vec![outer.clone()]
+    );
+}
+
+#[test]
+fn pdb_source_files() {
+    let pdb_path = fixtures_dir().join("win64-ci").join("mozglue.pdb");
+    let file_contents = samply_symbols::FileContentsWrapper::new(std::fs::read(pdb_path).unwrap());
+    let source_files = samply_symbols::source_files_for_pdb(&file_contents).unwrap();
+    assert!(source_files.iter().any(|file| file.path.ends_with(".cpp")));
+    assert!(source_files.windows(2).all(|pair| pair[0] < pair[1]));
+    for file in &source_files {
+        match &file.checksum {
+            Some(samply_symbols::PdbSourceFileChecksum::Md5(bytes)) => assert_eq!(bytes.len(), 16),
+            Some(samply_symbols::PdbSourceFileChecksum::Sha1(bytes)) => assert_eq!(bytes.len(), 20),
+            Some(samply_symbols::PdbSourceFileChecksum::Sha256(bytes)) => {
+                assert_eq!(bytes.len(), 32)
+            }
+            None => {}
+        }
+    }
+}
+
+#[test]
+fn pdb_compiland() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("win64-ci").join("mozglue.pdb")),
+        None,
+    ))
+    .unwrap();
+    let compiland_at = |address: u32| match symbol_map.lookup_relative_address(address)?.frames {
+        samply_symbols::FramesLookupResult::Available(frames) => {
+            let compiland = frames.last()?.compiland.clone();
+            assert!(frames.iter().all(|frame| frame.compiland == compiland));
+            compiland
+        }
+        _ => None,
+    };
+
+    // mozglue was built with LTO, so all of its own code comes from the LTO object.
+    // arena_t::SplitRun
+    assert_eq!(
+        compiland_at(0x1160).as_deref(),
+        Some("/builds/worker/workspace/obj-build/mozglue/build/lto.tmp")
+    );
+    // __security_init_cookie, from the CRT
+    assert_eq!(
+        compiland_at(0x78530).as_deref(),
+        Some("f:\\binaries\\Intermediate\\vctools\\msvcrt.nativeproj_110336922\\objr\\amd64\\gs_support.obj")
+    );
+}
+
+#[test]
+fn pdb_address_range() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("win64-ci").join("mozglue.pdb")),
+        None,
+    ))
+    .unwrap();
+    let split_run = symbol_map.lookup_relative_address(0x1160).unwrap().symbol;
+    assert_eq!(
+        split_run.name,
+        "arena_t::SplitRun(arena_run_t*, unsigned long long, bool, bool)"
+    );
+    let start = split_run.address;
+    let end = start + split_run.size.unwrap();
+    let results = symbol_map.lookup_relative_address_range(start, end + 0x10);
+    assert_eq!(results.first().unwrap().0, start);
+    assert!(results.windows(2).all(|w| w[0].0 < w[1].0));
+    assert!(
+        results.windows(2).all(|w| w[0].1 != w[1].1),
+        "Consecutive identical results should be merged"
+    );
+
+    // The function has line records for many different lines.
+    let split_run_results: Vec<_> = results
+        .iter()
+        .filter(|(address, _)| *address < end)
+        .collect();
+    assert!(split_run_results.len() > 10);
+    assert!(split_run_results
+        .iter()
+        .all(|(_, info)| info.symbol.address == start));
+
+    // The next function, which starts after some padding, starts a new result.
+    let next = results
+        .iter()
+        .find(|(_, info)| info.symbol.address != start)
+        .unwrap();
+    assert!(next.0 >= end);
+    assert_eq!(next.0, next.1.symbol.address);
+}
+
+#[test]
+fn pdb_breakpad_sym_export() {
+    let helper = Helper {
+        symbol_directory: fixtures_dir().join("win64-ci"),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let symbol_map = futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+        FileLocationType(fixtures_dir().join("win64-ci").join("mozglue.pdb")),
+        None,
+    ))
+    .unwrap();
+    let mut sink = samply_symbols::BreakpadSymSink::new(
+        "windows",
+        "x86_64",
+        symbol_map.debug_id(),
+        "mozglue.pdb",
+    );
+    sink.add_symbol_map(&symbol_map);
+    let sym = sink.to_sym_string();
+
+    // The LINE records of arena_t::SplitRun come from the PDB's line table.
+    let split_run_lines: Vec<(u32, u32)> = sym
+        .lines()
+        .skip_while(|line| !line.starts_with("FUNC 1160 "))
+        .skip(1)
+        .take_while(|line| !line.starts_with("FUNC ") && !line.starts_with("PUBLIC "))
+        .filter(|line| !line.starts_with("INLINE "))
+        .map(|line| {
+            let mut fields = line.split(' ');
+            let address = u32::from_str_radix(fields.next().unwrap(), 16).unwrap();
+            let line_number = fields.nth(1).unwrap().parse().unwrap();
+            (address, line_number)
+        })
+        .collect();
+    assert!(split_run_lines.len() > 10);
+    for (address, line_number) in split_run_lines {
+        let frames = match symbol_map.lookup_relative_address(address).unwrap().frames {
+            samply_symbols::FramesLookupResult::Available(frames) => frames,
+            _ => panic!("No frames at 0x{address:x}"),
+        };
+        assert_eq!(frames[0].line_number, Some(line_number));
+    }
+}
+
 #[test]
 fn compare_snapshot() {
     let table = futures::executor::block_on(crate::get_table(
@@ -569,3 +1651,111 @@ fn compare_snapshot() {
 
     assert_eq!(output, expected);
 }
+
+#[test]
+fn macho_external_max_inline_depth() {
+    // libexternal.dylib was linked without a dSYM, so its debug info is in the
+    // object files next to it.
+    let dir = fixtures_dir().join("other").join("macho-external");
+    let helper = Helper {
+        symbol_directory: dir.clone(),
+    };
+    let symbol_manager = SymbolManager::with_helper(&helper);
+    let mut symbol_map = futures::executor::block_on(
+        symbol_manager
+            .load_symbol_map_from_location(FileLocationType(dir.join("libexternal.dylib")), None),
+    )
+    .unwrap();
+    let frames_at = |symbol_map: &samply_symbols::SymbolMap<FileLocationType>, address| {
+        let ext_address = match symbol_map.lookup_relative_address(address).unwrap().frames {
+            samply_symbols::FramesLookupResult::External(ext_address) => ext_address,
+            frames => panic!("Expected external frames, got {frames:?}"),
+        };
+        let frames = futures::executor::block_on(
+            symbol_manager.lookup_external(symbol_map.debug_file_location(), &ext_address),
+        )
+        .unwrap();
+        frames
+            .into_iter()
+            .map(|frame| (frame.function.unwrap(), frame.line_number.unwrap()))
+            .collect::<Vec<_>>()
+    };
+
+    // square() and wrapping_mul() are inlined into sum_of_squares(), which is
+    // inlined into compute().
+    assert_eq!(
+        frames_at(&symbol_map, 0x244),
+        vec![
+            ("<u32>::wrapping_mul".to_string(), 2533),
+            ("square::square".to_string(), 5),
+            ("square::sum_of_squares".to_string(), 10),
+            ("compute".to_string(), 15),
+        ]
+    );
+
+    symbol_map.set_max_inline_depth(Some(1));
+    assert_eq!(
+        frames_at(&symbol_map, 0x244),
+        vec![
+            ("<u32>::wrapping_mul".to_string(), 2533),
+            ("compute".to_string(), 15),
+        ]
+    );
+
+    symbol_map.set_max_inline_depth(Some(0));
+    assert_eq!(
+        frames_at(&symbol_map, 0x244),
+        vec![("compute".to_string(), 15)]
+    );
+}
+
+#[test]
+fn macho_external_object_file_identity() {
+    // Next to this copy of libexternal.dylib, square.o is a different object file,
+    // so the real square.o has to be found in the symbol directory.
+    let fixture_dir = fixtures_dir().join("other").join("macho-external");
+    let dir = std::env::temp_dir().join(format!(
+        "samply-symbols-external-identity-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy(
+        fixture_dir.join("libexternal.dylib"),
+        dir.join("libexternal.dylib"),
+    )
+    .unwrap();
+    std::fs::copy(fixture_dir.join("sum.o"), dir.join("square.o")).unwrap();
+
+    let lookup_compute = |symbol_directory: &Path| {
+        let helper = Helper {
+            symbol_directory: symbol_directory.to_owned(),
+        };
+        let symbol_manager = SymbolManager::with_helper(&helper);
+        let symbol_map =
+            futures::executor::block_on(symbol_manager.load_symbol_map_from_location(
+                FileLocationType(dir.join("libexternal.dylib")),
+                None,
+            ))
+            .unwrap();
+        let ext_address = match symbol_map.lookup_relative_address(0x244).unwrap().frames {
+            samply_symbols::FramesLookupResult::External(ext_address) => ext_address,
+            frames => panic!("Expected external frames, got {frames:?}"),
+        };
+        let external_file = futures::executor::block_on(
+            symbol_manager
+                .load_external_file(symbol_map.debug_file_location(), &ext_address.file_ref),
+        )?;
+        let frames = external_file.lookup(&ext_address.address_in_file).unwrap();
+        Ok::<_, Error>(frames.last().unwrap().function.clone().unwrap())
+    };
+
+    assert_eq!(lookup_compute(&fixture_dir).unwrap(), "compute");
+    match lookup_compute(&dir) {
+        Err(Error::ExternalFileDoesNotDefineSymbol(file, symbol)) => {
+            assert!(file.ends_with("square.o"));
+            assert_eq!(symbol, "_compute");
+        }
+        result => panic!("Expected ExternalFileDoesNotDefineSymbol, got {result:?}"),
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -212,7 +212,7 @@ where
 /// TODO: We should not do this adjustment if the length of 16 was written down in
 /// the perf.data file. However, at the moment linux-perf-data doesn't tell us
 /// whether the build ID length is "real" or guessed.
-fn fixup_perf_jit_build_ids(build_ids: &mut HashMap<DsoKey, DsoInfo>) {
+pub fn fixup_perf_jit_build_ids(build_ids: &mut HashMap<DsoKey, DsoInfo>) {
     for (key, info) in build_ids {
         let name = key.name();
         if name.starts_with("jitted-") && name.ends_with(".so") && info.build_id.len() == 16 {
@@ -0,0 +1,388 @@
+use debugid::DebugId;
+use linux_perf_data::linux_perf_event_reader;
+use linux_perf_data::{DsoKey, Endianness, PerfFileReader, PerfFileRecord};
+use linux_perf_event_reader::{EventRecord, Mmap2FileId};
+use memmap2::Mmap;
+use object::Object;
+use serde_json::json;
+use wholesym::samply_symbols::{self, DebugIdExt};
+use wholesym::{LibraryInfo, SymbolManager, SymbolManagerConfig};
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use super::perf::{fixup_perf_jit_build_ids, Error};
+use crate::linux_shared::compute_vma_bias;
+use crate::shared::utils::open_file_with_fallback;
+
+/// The sampled addresses of one module in a perf.data file, together with the
+/// information that's needed to find the module's symbols.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolicationQuery {
+    /// The path of the module, as recorded by perf.
+    pub path: String,
+    pub debug_name: String,
+    /// Derived from the build ID. Nil if perf didn't record a build ID for the module.
+    pub debug_id: DebugId,
+    /// The sorted, deduplicated sample addresses, relative to the module's base address.
+    pub addresses: Vec<u32>,
+}
+
+impl SymbolicationQuery {
+    /// Returns the request JSON for the `/symbolicate/v5` API.
+    pub fn request_json(&self) -> String {
+        let frames: Vec<_> = self
+            .addresses
+            .iter()
+            .map(|address| json!([0, address]))
+            .collect();
+        json!({
+            "memoryMap": [[self.debug_name, self.debug_id.breakpad().to_string()]],
+            "stacks": [frames],
+        })
+        .to_string()
+    }
+
+    /// Runs the query against `symbol_manager` and returns the response JSON.
+    pub async fn run(&self, symbol_manager: &mut SymbolManager) -> String {
+        symbol_manager.add_known_library(LibraryInfo {
+            debug_name: Some(self.debug_name.clone()),
+            debug_id: Some(self.debug_id),
+            debug_path: Some(self.path.clone()),
+            name: Some(self.debug_name.clone()),
+            code_id: None,
+            path: Some(self.path.clone()),
+            arch: None,
+        });
+        symbol_manager
+            .query_json_api("/symbolicate/v5", &self.request_json())
+            .await
+    }
+}
+
+struct Mapping {
+    start_avma: u64,
+    end_avma: u64,
+    /// The address in process memory at which the module's relative addresses start.
+    base_avma: u64,
+    module_index: usize,
+}
+
+struct Module {
+    path: String,
+    build_id: Option<Vec<u8>>,
+    /// The module's file, if we could open it and its build ID matches.
+    file: Option<Mmap>,
+    addresses: BTreeSet<u32>,
+}
+
+struct QueryBuilder {
+    endian: Endianness,
+    modules: Vec<Module>,
+    module_indexes: HashMap<(String, Option<Vec<u8>>), usize>,
+    mappings_per_pid: HashMap<i32, Vec<Mapping>>,
+}
+
+impl QueryBuilder {
+    fn new(endian: Endianness) -> Self {
+        Self {
+            endian,
+            modules: Vec::new(),
+            module_indexes: HashMap::new(),
+            mappings_per_pid: HashMap::new(),
+        }
+    }
+
+    fn add_mapping(
+        &mut self,
+        pid: i32,
+        path: String,
+        build_id: Option<Vec<u8>>,
+        start_avma: u64,
+        len: u64,
+        file_offset: u64,
+    ) {
+        // Skip malformed mappings which extend past the end of the address space.
+        let end_avma = match start_avma.checked_add(len) {
+            Some(end_avma) => end_avma,
+            None => return,
+        };
+        let modules = &mut self.modules;
+        let module_index = *self
+            .module_indexes
+            .entry((path, build_id))
+            .or_insert_with_key(|(path, build_id)| {
+                modules.push(Module {
+                    path: path.clone(),
+                    build_id: build_id.clone(),
+                    file: open_module_file(path, build_id.as_deref()),
+                    addresses: BTreeSet::new(),
+                });
+                modules.len() - 1
+            });
+        let base_avma = self.modules[module_index]
+            .file
+            .as_ref()
+            .and_then(|mmap| base_avma_from_file(mmap, file_offset, start_avma, len))
+            // Without the file, assume that relative addresses and file offsets
+            // are the same. This is the case for most binaries linked with GNU ld.
+            .unwrap_or_else(|| start_avma.wrapping_sub(file_offset));
+        self.mappings_per_pid.entry(pid).or_default().push(Mapping {
+            start_avma,
+            end_avma,
+            base_avma,
+            module_index,
+        });
+    }
+
+    fn add_sample(&mut self, pid: i32, ip: u64) {
+        let mappings = match self.mappings_per_pid.get(&pid) {
+            Some(mappings) => mappings,
+            None => return,
+        };
+        // Later mappings replace earlier ones at the same address.
+        let mapping = match mappings
+            .iter()
+            .rev()
+            .find(|m| m.start_avma <= ip && ip < m.end_avma)
+        {
+            Some(mapping) => mapping,
+            None => return,
+        };
+        let relative_address = ip.wrapping_sub(mapping.base_avma);
+        if let Ok(relative_address) = u32::try_from(relative_address) {
+            self.modules[mapping.module_index]
+                .addresses
+                .insert(relative_address);
+        }
+    }
+
+    fn finish(self) -> Vec<SymbolicationQuery> {
+        let little_endian = self.endian == Endianness::LittleEndian;
+        self.modules
+            .into_iter()
+            .filter(|module| !module.addresses.is_empty())
+            .map(|module| {
+                let Module {
+                    path,
+                    build_id,
+                    addresses,
+                    ..
+                } = module;
+                let debug_name = Path::new(&path)
+                    .file_name()
+                    .map_or_else(|| path.clone(), |name| name.to_string_lossy().into());
+                let debug_id = build_id
+                    .map(|id| DebugId::from_identifier(&id, little_endian))
+                    .unwrap_or_default();
+                SymbolicationQuery {
+                    path,
+                    debug_name,
+                    debug_id,
+                    addresses: addresses.into_iter().collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Opens the file at `path`, and checks that it has the expected build ID.
+fn open_module_file(path: &str, build_id: Option<&[u8]>) -> Option<Mmap> {
+    if path.starts_with('[') {
+        return None;
+    }
+    let (file, _) = open_file_with_fallback(Path::new(path), None).ok()?;
+    let mmap = unsafe { memmap2::MmapOptions::new().map(&file) }.ok()?;
+    let object_file = object::File::parse(&mmap[..]).ok()?;
+    if let Some(build_id) = build_id {
+        if object_file.build_id().ok().flatten() != Some(build_id) {
+            return None;
+        }
+    }
+    drop(object_file);
+    Some(mmap)
+}
+
+/// Computes the module's base address in process memory from its segments, the
+/// same way the perf.data converter does.
+fn base_avma_from_file(
+    data: &[u8],
+    mapping_start_file_offset: u64,
+    mapping_start_avma: u64,
+    mapping_size: u64,
+) -> Option<u64> {
+    let file = object::File::parse(data).ok()?;
+    let bias = compute_vma_bias(
+        &file,
+        mapping_start_file_offset,
+        mapping_start_avma,
+        mapping_size,
+    )?;
+    Some(samply_symbols::relative_address_base(&file).wrapping_add(bias))
+}
+
+/// Reads the mmap records and the samples from a perf.data file, and returns one
+/// query per user-space module which has samples in it. Only the instruction
+/// pointer of each sample is used, not its call chain.
+pub fn symbolication_queries<C: Read + Seek>(cursor: C) -> Result<Vec<SymbolicationQuery>, Error> {
+    let PerfFileReader {
+        mut perf_file,
+        mut record_iter,
+    } = PerfFileReader::parse_file(cursor)?;
+    let mut build_ids = perf_file.build_ids().ok().unwrap_or_default();
+    fixup_perf_jit_build_ids(&mut build_ids);
+
+    let mut builder = QueryBuilder::new(perf_file.endian());
+    while let Ok(Some(record)) = record_iter.next_record(&mut perf_file) {
+        let record = match record {
+            PerfFileRecord::EventRecord { record, .. } => match record.parse() {
+                Ok(r) => r,
+                Err(_) => continue,
+            },
+            PerfFileRecord::UserRecord(_) => continue,
+        };
+        match record {
+            EventRecord::Sample(e) => {
+                if let (Some(pid), Some(ip)) = (e.pid, e.ip) {
+                    builder.add_sample(pid, ip);
+                }
+            }
+            EventRecord::Mmap(e) => {
+                if !e.is_executable || e.pid == -1 {
+                    continue;
+                }
+                let path = e.path.as_slice();
+                let build_id = DsoKey::detect(&path, e.cpu_mode)
+                    .and_then(|dso_key| build_ids.get(&dso_key))
+                    .map(|dso_info| dso_info.build_id.to_owned());
+                let path = String::from_utf8_lossy(&path).into_owned();
+                builder.add_mapping(e.pid, path, build_id, e.address, e.length, e.page_offset);
+            }
+            EventRecord::Mmap2(e) => {
+                const PROT_EXEC: u32 = 0b100;
+                if e.protection & PROT_EXEC == 0 || e.pid == -1 {
+                    continue;
+                }
+                let path = e.path.as_slice();
+                let build_id = match &e.file_id {
+                    Mmap2FileId::BuildId(build_id) => Some(build_id.to_owned()),
+                    Mmap2FileId::InodeAndVersion(_) => DsoKey::detect(&path, e.cpu_mode)
+                        .and_then(|dso_key| build_ids.get(&dso_key))
+                        .map(|dso_info| dso_info.build_id.to_owned()),
+                };
+                let path = String::from_utf8_lossy(&path).into_owned();
+                builder.add_mapping(e.pid, path, build_id, e.address, e.length, e.page_offset);
+            }
+            _ => {}
+        }
+    }
+    Ok(builder.finish())
+}
+
+/// Runs `queries` and prints each response JSON on its own line.
+#[tokio::main]
+pub async fn run_and_print_queries(queries: &[SymbolicationQuery]) {
+    let config =
+        SymbolManagerConfig::new().use_debuginfod(std::env::var("SAMPLY_USE_DEBUGINFOD").is_ok());
+    let mut symbol_manager = SymbolManager::with_config(config);
+    for query in queries {
+        println!("{}", query.run(&mut symbol_manager).await);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn samples_are_grouped_by_module() {
+        let mut builder = QueryBuilder::new(Endianness::LittleEndian);
+        builder.add_mapping(
+            1,
+            "/usr/lib/libc.so.6".to_string(),
+            Some(vec![0x12, 0x34, 0x56, 0x78]),
+            0x7f0000001000,
+            0x2000,
+            0x1000,
+        );
+        builder.add_mapping(1, "/home/me/app".to_string(), None, 0x5000, 0x1000, 0);
+        builder.add_sample(1, 0x7f0000001010);
+        builder.add_sample(1, 0x7f0000001010);
+        builder.add_sample(1, 0x7f0000002000);
+        builder.add_sample(1, 0x5100);
+        // Not in any mapping, or in a process without mappings.
+        builder.add_sample(1, 0x9000);
+        builder.add_sample(2, 0x5100);
+
+        let queries = builder.finish();
+        assert_eq!(queries.len(), 2);
+        assert_eq!(queries[0].debug_name, "libc.so.6");
+        assert_eq!(queries[0].addresses, vec![0x1010, 0x2000]);
+        assert_eq!(
+            queries[0].debug_id,
+            DebugId::from_identifier(&[0x12, 0x34, 0x56, 0x78], true)
+        );
+        assert_eq!(queries[1].debug_name, "app");
+        assert_eq!(queries[1].addresses, vec![0x100]);
+        assert!(queries[1].debug_id.is_nil());
+    }
+
+    #[test]
+    fn overflowing_mappings_are_skipped() {
+        let mut builder = QueryBuilder::new(Endianness::LittleEndian);
+        builder.add_mapping(1, "/home/me/app".to_string(), None, 0x5000, 0x1000, 0);
+        builder.add_mapping(
+            1,
+            "/home/me/broken".to_string(),
+            None,
+            0xffff_ffff_ffff_f000,
+            0x2000,
+            0,
+        );
+        builder.add_sample(1, 0x5100);
+        builder.add_sample(1, 0xffff_ffff_ffff_f100);
+
+        let queries = builder.finish();
+        assert_eq!(queries.len(), 1);
+        assert_eq!(queries[0].debug_name, "app");
+    }
+
+    #[test]
+    fn relative_addresses_come_from_the_segments() {
+        // This binary was linked with lld: its executable segment is at the file
+        // offset 0x8a0 but at the relative address 0x18a0.
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../fixtures/linux64-ci/firefox")
+            .to_string_lossy()
+            .into_owned();
+        let mut builder = QueryBuilder::new(Endianness::LittleEndian);
+        builder.add_mapping(1, path, None, 0x5555_5555_58a0, 0x2f0, 0x8a0);
+        builder.add_sample(1, 0x5555_5555_58b0);
+
+        let queries = builder.finish();
+        assert_eq!(queries.len(), 1);
+        assert_eq!(queries[0].addresses, vec![0x18b0]);
+    }
+
+    #[test]
+    fn debug_id_uses_the_perf_file_endianness() {
+        let build_id = vec![0x12, 0x34, 0x56, 0x78];
+        let mut builder = QueryBuilder::new(Endianness::BigEndian);
+        builder.add_mapping(
+            1,
+            "/home/me/app".to_string(),
+            Some(build_id.clone()),
+            0x5000,
+            0x1000,
+            0,
+        );
+        builder.add_sample(1, 0x5100);
+
+        let queries = builder.finish();
+        assert_eq!(
+            queries[0].debug_id,
+            DebugId::from_identifier(&build_id, false)
+        );
+    }
+}
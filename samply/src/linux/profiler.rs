@@ -9,7 +9,6 @@ use std::ffi::OsString;
 use std::fs::File;
 use std::io::BufWriter;
 use std::path::Path;
-use std::process::ExitStatus;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
@@ -21,7 +20,8 @@ use super::proc_maps;
 use super::process::SuspendedLaunchedProcess;
 use crate::linux_shared::{ConvertRegs, Converter, EventInterpretation, MmapRangeOrVec};
 use crate::server::{start_server_main, ServerProps};
-use crate::ConversionArgs;
+use crate::shared::exit_status::ChildExitStatus;
+use crate::{ConversionArgs, LaunchArgs};
 
 #[cfg(target_arch = "x86_64")]
 pub type ConvertRegsNative = crate::linux_shared::ConvertRegsX86_64;
@@ -38,8 +38,16 @@ pub fn start_recording(
     interval: Duration,
     server_props: Option<ServerProps>,
     conversion_args: &ConversionArgs,
+    launch_args: &LaunchArgs,
     iteration_count: u32,
-) -> Result<ExitStatus, ()> {
+) -> Result<ChildExitStatus, ()> {
+    // The perf events follow new threads and new child processes in the same way,
+    // so profiling only the launched process would also lose its other threads.
+    if launch_args.main_process_only {
+        eprintln!("Error: --main-process-only is not supported on Linux. On Linux, samply always profiles the launched process together with the processes it launches.");
+        std::process::exit(1);
+    }
+
     // Ignore SIGINT while the subcommand is running. The signal still reaches the process
     // under observation while we continue to record it. (ctrl+c will send the SIGINT signal
     // to all processes in the foreground process group).
@@ -127,7 +135,7 @@ pub fn start_recording(
 
     // Wait for the child process to quit.
     // This is where the main thread spends all its time during profiling.
-    let mut exit_status = process.wait().unwrap();
+    let mut exit_status = ChildExitStatus::from(process.wait().unwrap());
 
     for i in 2..=iteration_count {
         if !exit_status.success() {
@@ -164,7 +172,7 @@ pub fn start_recording(
             }
         };
 
-        exit_status = process.wait().expect("couldn't wait for child");
+        exit_status = ChildExitStatus::from(process.wait().expect("couldn't wait for child"));
     }
 
     profile_another_pid_request_sender
@@ -17,3 +17,4 @@ pub use convert_regs::{ConvertRegs, ConvertRegsAarch64, ConvertRegsX86_64};
 pub use converter::Converter;
 pub use event_interpretation::{EventInterpretation, KnownEvent};
 pub use mmap_range_or_vec::MmapRangeOrVec;
+pub use svma_file_range::compute_vma_bias;
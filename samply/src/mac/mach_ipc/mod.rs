@@ -753,6 +753,22 @@ pub fn mach_task_self() -> mach_port_t {
     unsafe { mach_task_self_ }
 }
 
+/// Obtains the task port of the process with the given pid.
+///
+/// This only succeeds if the calling process runs as root or has the
+/// `com.apple.security.cs.debugger` entitlement. Even then, it fails for processes
+/// which are protected by System Integrity Protection or which were signed with the
+/// hardened runtime without the `com.apple.security.get-task-allow` entitlement.
+pub fn task_for_pid(pid: u32) -> Result<mach_port_t, KernelError> {
+    let mut task: mach_port_t = MACH_PORT_NULL;
+    let os_result =
+        unsafe { mach_sys::task_for_pid(mach_task_self(), pid as libc::c_int, &mut task) };
+    if os_result == KERN_SUCCESS {
+        return Ok(task);
+    }
+    Err(os_result.into())
+}
+
 #[repr(C)]
 struct Message {
     header: mach_msg_header_t,
@@ -850,6 +866,10 @@ pub enum MachError {
     SendNoBuffer,
     SendTimedOut,
     SendTooLarge,
+    /// No launched process checked in, and the launched executable is protected by
+    /// System Integrity Protection, which strips `DYLD_INSERT_LIBRARIES` from its
+    /// environment. Not a Mach error code; produced by `TaskAccepter::next_message`.
+    PreloadBlockedBySip,
     Unknown(mach_msg_return_t),
 }
 
@@ -1079,6 +1099,10 @@ impl From<MachError> for Error {
                 ErrorKind::ConnectionReset,
                 "No senders exist for this port.",
             ),
+            MachError::PreloadBlockedBySip => Error::new(
+                ErrorKind::PermissionDenied,
+                "The launched executable is protected by System Integrity Protection, which removed DYLD_INSERT_LIBRARIES from its environment.",
+            ),
             MachError::Unknown(mach_error_number) => Error::new(
                 ErrorKind::Other,
                 format!("Unknown Mach error: {mach_error_number:x}"),
@@ -8,6 +8,7 @@ mod proc_maps;
 mod process_launcher;
 pub mod profiler;
 mod sampler;
+mod task_memory_file_contents;
 mod task_profiler;
 pub mod thread_act;
 pub mod thread_info;
@@ -1,84 +1,428 @@
-use std::ffi::{OsStr, OsString};
+use std::ffi::{CString, OsStr, OsString};
 use std::fs::File;
-use std::io::Write;
+use std::io::{Read, Write};
 use std::mem;
 use std::os::unix::prelude::OsStrExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 pub use super::mach_ipc::{mach_port_t, MachError, OsIpcSender};
-use super::mach_ipc::{BlockingMode, OsIpcMultiShotServer, MACH_PORT_NULL};
+use super::mach_ipc::{task_for_pid, BlockingMode, OsIpcMultiShotServer, MACH_PORT_NULL};
 use flate2::write::GzDecoder;
 use tempfile::tempdir;
 
+use crate::shared::exit_status::ChildExitStatus;
+
 pub struct TaskLauncher {
     program: OsString,
     args: Vec<OsString>,
     child_env: Vec<(OsString, OsString)>,
-    _temp_dir: Arc<tempfile::TempDir>,
+    clear_env: bool,
+    current_dir: Option<PathBuf>,
+    _temp_dir: Option<Arc<tempfile::TempDir>>,
+}
+
+/// Controls the environment variables of the launched child process.
+///
+/// Regardless of this configuration, the child always gets the `DYLD_INSERT_LIBRARIES`
+/// and `SAMPLY_BOOTSTRAP_SERVER_NAME` variables which samply needs in order to
+/// obtain the child's task.
+#[derive(Debug, Clone)]
+pub struct EnvConfig {
+    /// Variables to set in the child. If `inherit_parent` is true, these are applied
+    /// on top of samply's own environment and override variables of the same name.
+    pub vars: Vec<(OsString, OsString)>,
+    /// Whether the child should inherit samply's environment variables.
+    pub inherit_parent: bool,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        Self {
+            vars: Vec::new(),
+            inherit_parent: true,
+        }
+    }
+}
+
+/// A child process which was launched by `TaskLauncher::launch_child`.
+pub struct RootChild {
+    child: Child,
+    killed_by_samply: bool,
+}
+
+impl RootChild {
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Kills the child. A subsequent `wait` reports `ChildExitStatus::KilledBySamply`,
+    /// unless the child had already exited on its own.
+    pub fn kill(&mut self) -> std::io::Result<()> {
+        self.child.kill()?;
+        self.killed_by_samply = true;
+        Ok(())
+    }
+
+    /// Waits for the child to terminate and returns how it terminated.
+    pub fn wait(&mut self) -> std::io::Result<ChildExitStatus> {
+        let exit_status = self.child.wait()?;
+        Ok(ChildExitStatus::from_exit_status(
+            exit_status,
+            self.killed_by_samply,
+        ))
+    }
+
+    /// Returns how the child terminated if it has terminated, without blocking.
+    pub fn try_wait(&mut self) -> std::io::Result<Option<ChildExitStatus>> {
+        let exit_status = self.child.try_wait()?;
+        Ok(exit_status.map(|exit_status| {
+            ChildExitStatus::from_exit_status(exit_status, self.killed_by_samply)
+        }))
+    }
+}
+
+/// The reasons why `TaskAccepter::new` or `TaskLauncher::launch_child` can fail.
+#[derive(Debug, thiserror::Error)]
+pub enum LaunchError {
+    #[error("Could not create the server for the launched processes to check in: {0:?}")]
+    Mach(MachError),
+
+    #[error("Could not find an executable with the name {0}.")]
+    NotFound(String),
+
+    #[error("The working directory {0} does not exist or is not a directory.")]
+    InvalidWorkingDirectory(String),
+
+    #[error("Could not launch child process: {0}")]
+    SpawnFailed(#[source] std::io::Error),
+
+    #[error("The preload library {0} does not exist or is not a file.")]
+    PreloadLibNotFound(String),
+
+    #[error("Could not write the preload library to {0}: {1}")]
+    PreloadLibExtractionFailed(String, #[source] std::io::Error),
+
+    #[error("The temporary directory {0} is on a file system which is mounted with noexec, so the preload library can't be loaded from it. Set SAMPLY_PRELOAD_DIR to a directory on a file system which allows executable files.")]
+    TempDirIsNoexec(String),
+}
+
+impl From<MachError> for LaunchError {
+    fn from(err: MachError) -> Self {
+        LaunchError::Mach(err)
+    }
 }
 
 impl TaskLauncher {
-    pub fn launch_child(&self) -> Child {
-        match Command::new(&self.program)
+    pub fn launch_child(&self) -> Result<RootChild, LaunchError> {
+        let mut command = Command::new(&self.program);
+        if self.clear_env {
+            command.env_clear();
+        }
+        if let Some(current_dir) = &self.current_dir {
+            if !current_dir.is_dir() {
+                return Err(LaunchError::InvalidWorkingDirectory(
+                    current_dir.to_string_lossy().into(),
+                ));
+            }
+            command.current_dir(current_dir);
+        }
+        match command
             .args(&self.args)
             .envs(self.child_env.clone())
             .spawn()
         {
-            Ok(child) => child,
+            Ok(child) => Ok(RootChild {
+                child,
+                killed_by_samply: false,
+            }),
             Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-                eprintln!(
-                    "Error: Could not find an executable with the name {}.",
-                    self.program.to_string_lossy()
-                );
-                std::process::exit(1)
-            }
-            Err(err) => {
-                eprintln!("Error: Could not launch child process: {err}");
-                std::process::exit(1)
+                Err(LaunchError::NotFound(self.program.to_string_lossy().into()))
             }
+            Err(err) => Err(LaunchError::SpawnFailed(err)),
         }
     }
 }
 
 pub struct TaskAccepter {
     server: OsIpcMultiShotServer,
-    _temp_dir: Arc<tempfile::TempDir>,
+    _temp_dir: Option<Arc<tempfile::TempDir>>,
+    /// Whether the launched executable is protected by System Integrity Protection.
+    program_is_sip_protected: bool,
+    has_checked_in_task: bool,
+    accepted_pids: Vec<u32>,
+    task_filter: Option<Box<TaskFilter>>,
+    proceed_immediately: bool,
+}
+
+/// Decides, given its pid, whether a process which checked in should be profiled.
+pub type TaskFilter = dyn FnMut(u32) -> bool + Send;
+
+/// Returns a task filter which only accepts the main process of each launch.
+///
+/// Set `new_launch` before launching a process. The first process which checks in
+/// after that is the launched process, because the processes it launches can only
+/// check in after it. Processes which exec keep their pid, so they stay accepted.
+pub fn main_process_filter(new_launch: Arc<AtomicBool>) -> Box<TaskFilter> {
+    let mut main_pid = None;
+    Box::new(move |pid| {
+        if new_launch.swap(false, Ordering::SeqCst) {
+            main_pid = Some(pid);
+        }
+        main_pid == Some(pid)
+    })
 }
 
 static PRELOAD_LIB_CONTENTS: &[u8] =
     include_bytes!("../../resources/libsamply_mac_preload.dylib.gz");
 
+/// Writes out the embedded preload library and returns its path.
+///
+/// We would like to ship with libsamply_mac_preload.dylib as a separate resource file.
+/// But this won't work with cargo install. So we write out libsamply_mac_preload.dylib
+/// to samply's cache directory, under a name which contains a hash of its contents,
+/// and reuse it in later runs. If the cache directory isn't usable, we fall back to
+/// a temporary directory, which is returned so that it can be kept alive for as long
+/// as the path is used.
+///
+/// If `preload_dir` is set, the library is written to that directory instead, and
+/// there is no fallback: if the directory can't be used, this fails with
+/// `LaunchError::PreloadLibExtractionFailed`.
+fn extract_preload_lib(
+    preload_dir: Option<&Path>,
+) -> Result<(PathBuf, Option<tempfile::TempDir>), LaunchError> {
+    let extraction_failed = |dir: &Path, err| {
+        LaunchError::PreloadLibExtractionFailed(dir.to_string_lossy().into(), err)
+    };
+    if let Some(preload_dir) = preload_dir {
+        let preload_lib_path = extract_preload_lib_to_dir(preload_dir)
+            .map_err(|err| extraction_failed(preload_dir, err))?;
+        return Ok((preload_lib_path, None));
+    }
+    if let Some(cache_dir) = dirs::cache_dir() {
+        if let Ok(preload_lib_path) = extract_preload_lib_to_dir(&cache_dir.join("samply")) {
+            return Ok((preload_lib_path, None));
+        }
+    }
+    let dir = tempdir().map_err(|err| extraction_failed(&std::env::temp_dir(), err))?;
+    if is_on_noexec_mount(dir.path()) {
+        return Err(LaunchError::TempDirIsNoexec(
+            dir.path().to_string_lossy().into(),
+        ));
+    }
+    let preload_lib_path = dir.path().join("libsamply_mac_preload.dylib");
+    write_preload_lib_atomically(&preload_lib_path)
+        .map_err(|err| extraction_failed(dir.path(), err))?;
+    Ok((preload_lib_path, Some(dir)))
+}
+
+/// Writes the preload library into `dir`, under a name which contains a hash of its
+/// contents, unless it's already there. Creates `dir` if needed.
+fn extract_preload_lib_to_dir(dir: &Path) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    if is_on_noexec_mount(dir) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "the file system is mounted with noexec, so dyld can't load libraries from it",
+        ));
+    }
+    let hash = fxhash::hash64(PRELOAD_LIB_CONTENTS);
+    let preload_lib_path = dir.join(format!("libsamply_mac_preload-{hash:016x}.dylib"));
+    if !preload_lib_path.is_file() {
+        write_preload_lib_atomically(&preload_lib_path)?;
+    }
+    Ok(preload_lib_path)
+}
+
+/// Returns whether the file system which contains `path` is mounted with `noexec`.
+fn is_on_noexec_mount(path: &Path) -> bool {
+    let path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    let mut stats: libc::statfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stats) } != 0 {
+        return false;
+    }
+    stats.f_flags & libc::MNT_NOEXEC as u32 != 0
+}
+
+/// Decompresses the preload library into a temporary file next to `path` and then
+/// renames it to `path`, so that a concurrently launched process never sees a
+/// partially written dylib.
+fn write_preload_lib_atomically(path: &Path) -> std::io::Result<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let temp_file = tempfile::NamedTempFile::new_in(dir)?;
+    let mut decoder = GzDecoder::new(temp_file);
+    decoder.write_all(PRELOAD_LIB_CONTENTS)?;
+    let temp_file = decoder.finish()?;
+    temp_file.persist(path)?;
+    Ok(())
+}
+
+const FAT_MAGIC: u32 = 0xcafebabe;
+const MH_MAGIC: u32 = 0xfeedface;
+const MH_MAGIC_64: u32 = 0xfeedfacf;
+
+/// Returns the CPU types contained in the Mach-O file whose first bytes are `header`.
+/// For a universal binary, this returns the CPU type of every slice. Returns an empty
+/// Vec if the file isn't a Mach-O file, for example if it's a shell script.
+fn macho_cpu_types(header: &[u8]) -> Vec<u32> {
+    let read_u32_be = |offset: usize| -> Option<u32> {
+        let bytes = header.get(offset..offset + 4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    let magic = match read_u32_be(0) {
+        Some(magic) => magic,
+        None => return Vec::new(),
+    };
+    if magic == FAT_MAGIC {
+        // Java class files share this magic number; they have a large version number
+        // where the slice count would be.
+        let slice_count = read_u32_be(4).unwrap_or(0);
+        if slice_count > 30 {
+            return Vec::new();
+        }
+        return (0..slice_count as usize)
+            .filter_map(|i| read_u32_be(8 + i * 20))
+            .collect();
+    }
+    // Thin Mach-O files are little-endian on all architectures macOS runs on.
+    match magic.swap_bytes() {
+        MH_MAGIC | MH_MAGIC_64 => read_u32_be(4).map(u32::swap_bytes).into_iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn read_macho_cpu_types(path: &Path) -> Vec<u32> {
+    let mut header = Vec::new();
+    match File::open(path) {
+        Ok(file) => match file.take(4096).read_to_end(&mut header) {
+            Ok(_) => macho_cpu_types(&header),
+            Err(_) => Vec::new(),
+        },
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Resolves `program` the same way `Command::spawn` does: names without a slash are
+/// looked up in `PATH`.
+fn find_executable(program: &OsStr) -> Option<PathBuf> {
+    if program.as_bytes().contains(&b'/') {
+        return Some(PathBuf::from(program));
+    }
+    let path_var = std::env::var_os("PATH")?;
+    std::env::split_paths(&path_var)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// Prints a warning if `program` is a Mach-O executable and the preload library has
+/// no slice for any of its architectures.
+///
+/// The embedded preload library is a universal binary with arm64 and x86_64 slices,
+/// and dyld picks the matching slice itself, so this only triggers for a
+/// `preload_lib_override` which was built for a single architecture. If `program`
+/// isn't a Mach-O file, for example if it's a shell script which launches the actual
+/// executable, nothing is checked: the processes it launches can have either
+/// architecture (for example an x86_64 executable run under Rosetta from an arm64
+/// shell), which is why the embedded library contains both slices.
+fn check_preload_lib_arch(program: &OsStr, preload_lib_path: &Path) {
+    let program_cpu_types = match find_executable(program) {
+        Some(path) => read_macho_cpu_types(&path),
+        None => return,
+    };
+    if program_cpu_types.is_empty() {
+        return;
+    }
+    let preload_lib_cpu_types = read_macho_cpu_types(preload_lib_path);
+    if !program_cpu_types
+        .iter()
+        .any(|cpu_type| preload_lib_cpu_types.contains(cpu_type))
+    {
+        eprintln!(
+            "Warning: The preload library {} has no code for the architecture of {}. The launched process will not be profiled.",
+            preload_lib_path.to_string_lossy(),
+            program.to_string_lossy()
+        );
+    }
+}
+
+/// Returns whether `path` is in a location which is protected by System Integrity
+/// Protection. dyld ignores `DYLD_INSERT_LIBRARIES` for executables in these
+/// locations, and removes it from the environment of any process they launch.
+fn is_sip_protected_path(path: &Path) -> bool {
+    if path.starts_with("/usr/local") {
+        return false;
+    }
+    ["/System", "/usr", "/bin", "/sbin"]
+        .iter()
+        .any(|protected_dir| path.starts_with(protected_dir))
+}
+
 impl TaskAccepter {
-    pub fn new<I, S>(program: S, args: I) -> Result<(Self, TaskLauncher), MachError>
+    /// Creates the accepter and a launcher for `program`. If `env_config` is `None`,
+    /// the child inherits samply's full environment. If `current_dir` is `None`, the
+    /// child inherits samply's working directory; if it names a directory which
+    /// doesn't exist, launching the child fails with
+    /// `LaunchError::InvalidWorkingDirectory`.
+    ///
+    /// If `preload_lib_override` is `None`, the embedded preload library is extracted
+    /// into `preload_dir`, or, if that's `None`, into samply's cache directory or a
+    /// temporary directory. Otherwise the given dylib is used as-is; if it doesn't
+    /// exist, this fails with `LaunchError::PreloadLibNotFound`. Extracting fails with
+    /// an error if the library can't be written, or if the directory is on a file
+    /// system which is mounted with `noexec`.
+    pub fn new<I, S>(
+        program: S,
+        args: I,
+        env_config: Option<EnvConfig>,
+        current_dir: Option<PathBuf>,
+        preload_lib_override: Option<PathBuf>,
+        preload_dir: Option<PathBuf>,
+    ) -> Result<(Self, TaskLauncher), LaunchError>
     where
         I: IntoIterator<Item = S>,
         S: Into<OsString>,
     {
         let (server, server_name) = OsIpcMultiShotServer::new()?;
+        let program: OsString = program.into();
 
         // Launch the child with DYLD_INSERT_LIBRARIES set to libsamply_mac_preload.dylib.
+        let (preload_lib_path, dir) = match preload_lib_override {
+            Some(preload_lib_path) => {
+                if !preload_lib_path.is_file() {
+                    return Err(LaunchError::PreloadLibNotFound(
+                        preload_lib_path.to_string_lossy().into(),
+                    ));
+                }
+                (preload_lib_path, None)
+            }
+            None => {
+                let (preload_lib_path, dir) = extract_preload_lib(preload_dir.as_deref())?;
+                (preload_lib_path, dir.map(Arc::new))
+            }
+        };
+        check_preload_lib_arch(&program, &preload_lib_path);
+        let program_is_sip_protected = find_executable(&program)
+            .and_then(|path| path.canonicalize().ok())
+            .map_or(false, |path| is_sip_protected_path(&path));
 
-        // We would like to ship with libsamply_mac_preload.dylib as a separate resource file.
-        // But this won't work with cargo install. So we write out libsamply_mac_preload.dylib
-        // to a temporary directory.
-        let dir = tempdir().expect("Couldn't create temporary directory for preload-lib");
-        let preload_lib_path = dir.path().join("libsamply_mac_preload.dylib");
-        let file =
-            File::create(&preload_lib_path).expect("Couldn't create libsamply_mac_preload.dylib");
-        let mut decoder = GzDecoder::new(file);
-        decoder
-            .write_all(PRELOAD_LIB_CONTENTS)
-            .expect("Couldn't write libsamply_mac_preload.dylib (error during write_all)");
-        decoder
-            .finish()
-            .expect("Couldn't write libsamply_mac_preload.dylib (error during finish)");
-
-        // Take this process's environment variables and add DYLD_INSERT_LIBRARIES
-        // and SAMPLY_BOOTSTRAP_SERVER_NAME.
-        let child_env: Vec<(OsString, OsString)> = std::env::vars_os()
+        // Take the configured environment variables (by default, this process's
+        // environment variables) and add DYLD_INSERT_LIBRARIES and
+        // SAMPLY_BOOTSTRAP_SERVER_NAME.
+        let env_config = env_config.unwrap_or_default();
+        let clear_env = !env_config.inherit_parent;
+        let base_env: Vec<(OsString, OsString)> = if env_config.inherit_parent {
+            std::env::vars_os().chain(env_config.vars).collect()
+        } else {
+            env_config.vars
+        };
+        let child_env: Vec<(OsString, OsString)> = base_env
+            .into_iter()
             .chain(std::iter::once((
                 "DYLD_INSERT_LIBRARIES".into(),
                 preload_lib_path.into(),
@@ -90,31 +434,104 @@ impl TaskAccepter {
             .collect();
 
         let args: Vec<OsString> = args.into_iter().map(|a| a.into()).collect();
-        let program: OsString = program.into();
-        let dir = Arc::new(dir);
 
         Ok((
             TaskAccepter {
                 server,
                 _temp_dir: dir.clone(),
+                program_is_sip_protected,
+                has_checked_in_task: false,
+                accepted_pids: Vec::new(),
+                task_filter: None,
+                proceed_immediately: false,
             },
             TaskLauncher {
                 program,
                 args,
                 child_env,
+                clear_env,
+                current_dir,
                 _temp_dir: dir,
             },
         ))
     }
 
-    pub fn next_message(&mut self, timeout: Duration) -> Result<ReceivedStuff, MachError> {
+    /// Obtains the task of an already-running process, instead of launching a new
+    /// process with the preload library.
+    ///
+    /// This uses `task_for_pid`, which requires root privileges (or the debugger
+    /// entitlement), and which fails for processes protected by System Integrity
+    /// Protection. Since the process isn't paused, calling `start_execution` on the
+    /// returned task does nothing.
+    pub fn attach_to_pid(pid: u32) -> Result<AcceptedTask, MachError> {
+        let task = task_for_pid(pid)?;
+        Ok(AcceptedTask {
+            task,
+            pid,
+            sender_channel: None,
+        })
+    }
+
+    /// Sets a filter which decides whether a process which checks in should be
+    /// profiled. Processes for which the filter returns `false` are resumed right
+    /// away by `next_message`, which then returns `ReceivedStuff::SkippedTask`.
+    pub fn set_task_filter(&mut self, task_filter: Box<TaskFilter>) {
+        self.task_filter = Some(task_filter);
+    }
+
+    /// Makes `next_message` resume every process as soon as it checks in, instead of
+    /// leaving it paused until the caller calls `start_execution` on the accepted task.
+    ///
+    /// This saves the round trip through the caller, so the profiled program starts
+    /// sooner. The tradeoff is sample coverage at process start: the process already
+    /// runs while the caller is still handing its task to the sampler, so whatever
+    /// the process does in that window is missing from the profile. Calling
+    /// `start_execution` on a task which was resumed this way does nothing.
+    pub fn set_proceed_immediately(&mut self, proceed_immediately: bool) {
+        self.proceed_immediately = proceed_immediately;
+    }
+
+    /// The pids of the processes which checked in and were accepted for profiling,
+    /// in the order in which they checked in.
+    pub fn accepted_pids(&self) -> &[u32] {
+        &self.accepted_pids
+    }
+
+    /// Waits up to `timeout` for the next message from a launched process.
+    ///
+    /// Returns `NextMessageResult::Timeout` if no message arrived in time, which can
+    /// mean that no process has checked in yet, or that all of them have exited. The
+    /// caller can decide whether to keep waiting, for example based on whether the
+    /// root child is still running. `NextMessageResult::ChannelClosed` means that no
+    /// further messages can arrive.
+    ///
+    /// If the launched executable is protected by System Integrity Protection and no
+    /// process has checked in by the time the wait times out, this returns
+    /// `MachError::PreloadBlockedBySip`: the preload library was never loaded, so no
+    /// process will ever check in.
+    pub fn next_message(&mut self, timeout: Duration) -> Result<NextMessageResult, MachError> {
         // Wait until the child is ready
-        let (res, mut channels, _) = self
+        let (res, mut channels, _) = match self
             .server
-            .accept(BlockingMode::BlockingWithTimeout(timeout))?;
+            .accept(BlockingMode::BlockingWithTimeout(timeout))
+        {
+            Ok(message) => message,
+            Err(MachError::RcvTimedOut) => {
+                if self.program_is_sip_protected && !self.has_checked_in_task {
+                    return Err(MachError::PreloadBlockedBySip);
+                }
+                return Ok(NextMessageResult::Timeout);
+            }
+            Err(MachError::RcvPortDied | MachError::RcvPortChanged) => {
+                return Ok(NextMessageResult::ChannelClosed)
+            }
+            Err(err) => return Err(err),
+        };
+        if res.len() < 7 {
+            return Ok(NextMessageResult::UnexpectedMessage(res));
+        }
         let received_stuff = match res.split_at(7) {
-            (b"My task", pid_bytes) => {
-                assert!(pid_bytes.len() == 4);
+            (b"My task", pid_bytes) if pid_bytes.len() == 4 && channels.len() >= 2 => {
                 let pid =
                     u32::from_le_bytes([pid_bytes[0], pid_bytes[1], pid_bytes[2], pid_bytes[3]]);
                 let task_channel = channels.pop().unwrap();
@@ -122,14 +539,33 @@ impl TaskAccepter {
                 let sender_channel = sender_channel.into_sender();
 
                 let task = task_channel.into_port();
+                self.has_checked_in_task = true;
 
-                ReceivedStuff::AcceptedTask(AcceptedTask {
+                let mut accepted_task = AcceptedTask {
                     task,
                     pid,
-                    sender_channel,
-                })
+                    sender_channel: Some(sender_channel),
+                };
+                let should_profile = match &mut self.task_filter {
+                    Some(task_filter) => task_filter(pid),
+                    None => true,
+                };
+                if should_profile {
+                    if self.proceed_immediately {
+                        accepted_task.start_execution();
+                    }
+                    self.accepted_pids.push(pid);
+                    ReceivedStuff::AcceptedTask(accepted_task)
+                } else {
+                    // Let the process run without profiling it.
+                    accepted_task.start_execution();
+                    ReceivedStuff::SkippedTask(pid)
+                }
             }
-            (b"Jitdump", jitdump_info) => {
+            (b"Jitdump", jitdump_info)
+                if jitdump_info.len() >= 5
+                    && jitdump_info.len() >= 5 + jitdump_info[4] as usize =>
+            {
                 let pid_bytes = &jitdump_info[0..4];
                 let pid =
                     u32::from_le_bytes([pid_bytes[0], pid_bytes[1], pid_bytes[2], pid_bytes[3]]);
@@ -137,23 +573,47 @@ impl TaskAccepter {
                 let path = &jitdump_info[5..][..len];
                 ReceivedStuff::JitdumpPath(pid, OsStr::from_bytes(path).into())
             }
-            (other, _) => {
-                panic!("Unexpected message: {:?}", other);
+            (b"My task", _) if !channels.is_empty() => {
+                // A check-in which we can't parse. The process is blocked until we
+                // reply, so let it run without profiling it. The first channel is
+                // the process's reply channel.
+                let sender_channel = channels.remove(0).into_sender();
+                let _ = sender_channel.send(b"Proceed", vec![]);
+                return Ok(NextMessageResult::UnexpectedMessage(res));
             }
+            _ => return Ok(NextMessageResult::UnexpectedMessage(res)),
         };
-        Ok(received_stuff)
+        Ok(NextMessageResult::Received(received_stuff))
     }
 }
 
+pub enum NextMessageResult {
+    /// A message arrived.
+    Received(ReceivedStuff),
+    /// No message arrived within the timeout.
+    Timeout,
+    /// The server port is gone, no more messages will arrive.
+    ChannelClosed,
+    /// A message arrived but it couldn't be understood, for example because the
+    /// preload library is from a different samply version. Contains the raw message
+    /// bytes, whose first seven bytes are usually the message tag. If the message
+    /// was a malformed task check-in, the process has already been resumed.
+    UnexpectedMessage(Vec<u8>),
+}
+
 pub enum ReceivedStuff {
     AcceptedTask(AcceptedTask),
+    /// The process with this pid checked in, but the task filter rejected it. It has
+    /// already been resumed.
+    SkippedTask(u32),
     JitdumpPath(u32, PathBuf),
 }
 
 pub struct AcceptedTask {
     task: mach_port_t,
     pid: u32,
-    sender_channel: OsIpcSender,
+    /// `None` for tasks which were obtained with `TaskAccepter::attach_to_pid`.
+    sender_channel: Option<OsIpcSender>,
 }
 
 impl AcceptedTask {
@@ -165,7 +625,109 @@ impl AcceptedTask {
         self.pid
     }
 
-    pub fn start_execution(&self) {
-        self.sender_channel.send(b"Proceed", vec![]).unwrap();
+    /// Lets the paused process continue. This only has an effect the first time
+    /// it's called.
+    pub fn start_execution(&mut self) {
+        if let Some(sender_channel) = self.sender_channel.take() {
+            sender_channel.send(b"Proceed", vec![]).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cpu_types() {
+        const CPU_TYPE_X86_64: u32 = 0x01000007;
+        const CPU_TYPE_ARM64: u32 = 0x0100000c;
+
+        let mut thin = Vec::new();
+        thin.extend_from_slice(&MH_MAGIC_64.to_le_bytes());
+        thin.extend_from_slice(&CPU_TYPE_ARM64.to_le_bytes());
+        assert_eq!(macho_cpu_types(&thin), vec![CPU_TYPE_ARM64]);
+
+        let mut fat = Vec::new();
+        fat.extend_from_slice(&FAT_MAGIC.to_be_bytes());
+        fat.extend_from_slice(&2u32.to_be_bytes());
+        for cpu_type in [CPU_TYPE_X86_64, CPU_TYPE_ARM64] {
+            fat.extend_from_slice(&cpu_type.to_be_bytes());
+            fat.extend_from_slice(&[0; 16]);
+        }
+        assert_eq!(macho_cpu_types(&fat), vec![CPU_TYPE_X86_64, CPU_TYPE_ARM64]);
+
+        assert!(macho_cpu_types(b"#!/bin/sh\necho hello\n").is_empty());
+        assert!(macho_cpu_types(b"").is_empty());
+    }
+
+    #[test]
+    fn sip_protected_paths() {
+        assert!(is_sip_protected_path(Path::new("/bin/sleep")));
+        assert!(is_sip_protected_path(Path::new("/usr/bin/python3")));
+        assert!(is_sip_protected_path(Path::new(
+            "/System/Applications/Calculator.app/Contents/MacOS/Calculator"
+        )));
+        assert!(!is_sip_protected_path(Path::new("/usr/local/bin/node")));
+        assert!(!is_sip_protected_path(Path::new(
+            "/opt/homebrew/bin/python3"
+        )));
+        assert!(!is_sip_protected_path(Path::new(
+            "/Users/me/code/target/release/app"
+        )));
+    }
+
+    #[test]
+    fn proceed_immediately_resumes_the_process() {
+        // Launch this test binary, which just lists its tests and exits.
+        let program = std::env::current_exe().unwrap().into_os_string();
+        let (mut task_accepter, task_launcher) =
+            TaskAccepter::new(program, [OsString::from("--list")], None, None, None, None).unwrap();
+        task_accepter.set_proceed_immediately(true);
+        let mut root_child = task_launcher.launch_child().unwrap();
+        let pid = root_child.id();
+
+        let accepted_task = loop {
+            match task_accepter.next_message(Duration::from_secs(10)).unwrap() {
+                NextMessageResult::Received(ReceivedStuff::AcceptedTask(task)) => break task,
+                NextMessageResult::Received(_) => {}
+                _ => {
+                    root_child.kill().unwrap();
+                    panic!("The launched process did not check in");
+                }
+            }
+        };
+        assert_eq!(accepted_task.get_id(), pid);
+        assert_eq!(task_accepter.accepted_pids(), &[pid]);
+
+        // We never call start_execution, so the process can only exit if
+        // next_message has already resumed it.
+        for _ in 0..100 {
+            if root_child.try_wait().unwrap().is_some() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        root_child.kill().unwrap();
+        panic!("The launched process was not resumed");
+    }
+
+    #[test]
+    fn main_process_filter_accepts_each_launched_process() {
+        let new_launch = Arc::new(AtomicBool::new(false));
+        let mut filter = main_process_filter(new_launch.clone());
+        assert!(!filter(100));
+
+        new_launch.store(true, Ordering::SeqCst);
+        assert!(filter(100));
+        assert!(!filter(101));
+        assert!(!filter(102));
+        // The main process checks in again after an exec.
+        assert!(filter(100));
+
+        new_launch.store(true, Ordering::SeqCst);
+        assert!(filter(200));
+        assert!(!filter(100));
+        assert!(!filter(201));
     }
 }
@@ -6,31 +6,82 @@ use std::collections::HashMap;
 use std::ffi::OsString;
 use std::fs::File;
 use std::io::BufWriter;
-use std::path::Path;
-use std::process::ExitStatus;
-use std::sync::atomic::AtomicBool;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use super::error::SamplingError;
-use super::process_launcher::{MachError, ReceivedStuff, TaskAccepter};
+use super::process_launcher::{
+    main_process_filter, MachError, NextMessageResult, ReceivedStuff, RootChild, TaskAccepter,
+};
 use super::sampler::{Sampler, TaskInit};
 use super::time::get_monotonic_timestamp;
 use crate::server::{start_server_main, ServerProps};
-use crate::ConversionArgs;
+use crate::shared::exit_status::ChildExitStatus;
+use crate::{ConversionArgs, LaunchArgs};
 
 pub fn start_profiling_pid(
-    _output_file: &Path,
-    _pid: u32,
-    _time_limit: Option<Duration>,
-    _interval: Duration,
-    _server_props: Option<ServerProps>,
-    _conversion_args: &ConversionArgs,
+    output_file: &Path,
+    pid: u32,
+    time_limit: Option<Duration>,
+    interval: Duration,
+    server_props: Option<ServerProps>,
+    conversion_args: &ConversionArgs,
 ) {
-    eprintln!("Profiling existing processes is currently not supported on macOS.");
-    eprintln!("You can only profile processes which you launch via samply.");
-    std::process::exit(1)
+    let mut accepted_task = match TaskAccepter::attach_to_pid(pid) {
+        Ok(accepted_task) => accepted_task,
+        Err(err) => {
+            eprintln!("Could not obtain the task for pid {pid}: {err:?}");
+            eprintln!();
+            eprintln!("Attaching to a running process on macOS requires root privileges, so you may need to run samply with sudo. Processes which are protected by System Integrity Protection, or which use the hardened runtime, cannot be profiled this way.");
+            std::process::exit(1)
+        }
+    };
+
+    // Stop sampling when the user presses Ctrl+C, and then save the profile.
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGINT, stop_flag.clone())
+        .expect("cannot register signal handler");
+
+    let (task_sender, task_receiver) = unbounded();
+    let (_jitdump_path_sender, jitdump_path_receiver) = unbounded();
+    task_sender
+        .send(TaskInit {
+            start_time_mono: get_monotonic_timestamp(),
+            task: accepted_task.take_task(),
+            pid,
+            jitdump_path_receiver,
+        })
+        .expect("the receiver is still alive");
+    drop(task_sender);
+
+    eprintln!("Profiling pid {pid}, press Ctrl+C to stop.");
+    let sampler = Sampler::new(
+        format!("pid {pid}"),
+        task_receiver,
+        interval,
+        time_limit,
+        conversion_args,
+    )
+    .with_stop_flag(stop_flag);
+    let profile = match sampler.run() {
+        Ok(profile) => profile,
+        Err(e) => {
+            eprintln!("An error occurred during profiling: {e}");
+            std::process::exit(1)
+        }
+    };
+
+    let file = File::create(output_file).unwrap();
+    let writer = BufWriter::new(file);
+    to_writer(writer, &profile).expect("Couldn't write JSON");
+
+    if let Some(server_props) = server_props {
+        start_server_main(output_file, server_props);
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -42,8 +93,9 @@ pub fn start_recording(
     interval: Duration,
     server_props: Option<ServerProps>,
     conversion_args: &ConversionArgs,
+    launch_args: &LaunchArgs,
     iteration_count: u32,
-) -> Result<ExitStatus, MachError> {
+) -> Result<ChildExitStatus, MachError> {
     let (task_sender, task_receiver) = unbounded();
     let command_name_copy = command_name.to_string_lossy().to_string();
     let conversion_args = conversion_args.clone();
@@ -69,7 +121,31 @@ pub fn start_recording(
     )
     .expect("cannot register signal handler");
 
-    let (mut task_accepter, task_launcher) = TaskAccepter::new(&command_name, command_args)?;
+    // SAMPLY_PRELOAD_LIB can point to a prebuilt libsamply_mac_preload.dylib, which
+    // is then used instead of the embedded one.
+    let preload_lib_override = std::env::var_os("SAMPLY_PRELOAD_LIB").map(PathBuf::from);
+    // SAMPLY_PRELOAD_DIR sets the directory into which the embedded library is
+    // extracted, for systems where the cache and temp directories aren't usable.
+    let preload_dir = std::env::var_os("SAMPLY_PRELOAD_DIR").map(PathBuf::from);
+    let (mut task_accepter, task_launcher) = match TaskAccepter::new(
+        &command_name,
+        command_args,
+        None,
+        None,
+        preload_lib_override,
+        preload_dir,
+    ) {
+        Ok(accepter_and_launcher) => accepter_and_launcher,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            std::process::exit(1)
+        }
+    };
+    let new_launch = Arc::new(AtomicBool::new(false));
+    if launch_args.main_process_only {
+        task_accepter.set_task_filter(main_process_filter(new_launch.clone()));
+    }
+    task_accepter.set_proceed_immediately(launch_args.proceed_immediately);
 
     let (accepter_sender, accepter_receiver) = unbounded();
     let accepter_thread = thread::spawn(move || {
@@ -79,6 +155,7 @@ pub fn start_recording(
         // paths. Having the mapping here lets us deliver the path to the right
         // task even in cases where a process execs into a new task with the same pid.
         let mut jitdump_path_senders_per_pid = HashMap::new();
+        let mut skipped_task_count = 0;
 
         loop {
             if let Ok(()) = accepter_receiver.try_recv() {
@@ -86,7 +163,7 @@ pub fn start_recording(
             }
             let timeout = Duration::from_secs_f64(1.0);
             match task_accepter.next_message(timeout) {
-                Ok(ReceivedStuff::AcceptedTask(mut accepted_task)) => {
+                Ok(NextMessageResult::Received(ReceivedStuff::AcceptedTask(mut accepted_task))) => {
                     let pid = accepted_task.get_id();
                     let (jitdump_path_sender, jitdump_path_receiver) = unbounded();
                     let send_result = task_sender.send(TaskInit {
@@ -101,7 +178,10 @@ pub fn start_recording(
                     }
                     accepted_task.start_execution();
                 }
-                Ok(ReceivedStuff::JitdumpPath(pid, path)) => {
+                Ok(NextMessageResult::Received(ReceivedStuff::SkippedTask(_pid))) => {
+                    skipped_task_count += 1;
+                }
+                Ok(NextMessageResult::Received(ReceivedStuff::JitdumpPath(pid, path))) => {
                     match jitdump_path_senders_per_pid.entry(pid) {
                         Entry::Occupied(mut entry) => {
                             let send_result = entry.get_mut().send(path);
@@ -117,18 +197,59 @@ pub fn start_recording(
                         }
                     }
                 }
-                Err(MachError::RcvTimedOut) => {
-                    // TODO: give status back via task_sender
+                Ok(NextMessageResult::Timeout) => {
+                    // Keep waiting until the main thread tells us to stop, which
+                    // it does once the root child has exited.
+                }
+                Ok(NextMessageResult::UnexpectedMessage(message)) => {
+                    let tag = &message[..message.len().min(7)];
+                    eprintln!(
+                        "Ignoring unexpected message from the preload library (tag {:?}, {} bytes). Is the preload library from a different samply version?",
+                        String::from_utf8_lossy(tag),
+                        message.len()
+                    );
+                }
+                Ok(NextMessageResult::ChannelClosed) => {
+                    eprintln!(
+                        "The task accepter channel was closed, no more tasks can be accepted."
+                    );
+                    break;
+                }
+                Err(MachError::PreloadBlockedBySip) => {
+                    // No process will ever check in. Dropping task_sender lets the
+                    // sampler know that there won't be a root task.
+                    return Err(MachError::PreloadBlockedBySip);
                 }
                 Err(err) => {
                     eprintln!("Encountered error while waiting for task port: {err:?}");
                 }
             }
         }
+        if skipped_task_count != 0 {
+            let accepted_pids = task_accepter.accepted_pids();
+            eprintln!(
+                "Profiled {} processes ({accepted_pids:?}) and skipped {skipped_task_count} other processes.",
+                accepted_pids.len()
+            );
+        }
+        Ok(())
     });
 
-    let mut root_child = task_launcher.launch_child();
-    let mut exit_status = root_child.wait().expect("couldn't wait for child");
+    let launch_child = || {
+        new_launch.store(true, Ordering::SeqCst);
+        match task_launcher.launch_child() {
+            Ok(root_child) => root_child,
+            Err(err) => {
+                eprintln!("Error: {err}");
+                std::process::exit(1)
+            }
+        }
+    };
+    // The sampler stops at the time limit, so there's no point in letting the
+    // launched process run any longer.
+    let deadline = time_limit.map(|time_limit| Instant::now() + time_limit);
+    let mut root_child = launch_child();
+    let mut exit_status = wait_for_root_child(&mut root_child, deadline);
 
     for i in 2..=iteration_count {
         if !exit_status.success() {
@@ -139,8 +260,8 @@ pub fn start_recording(
             break;
         }
         eprintln!("Running iteration {i} of {iteration_count}...");
-        let mut root_child = task_launcher.launch_child();
-        exit_status = root_child.wait().expect("couldn't wait for child");
+        let mut root_child = launch_child();
+        exit_status = wait_for_root_child(&mut root_child, deadline);
     }
 
     // The launched subprocess is done. From now on, we want to terminate if the user presses Ctrl+C.
@@ -149,7 +270,7 @@ pub fn start_recording(
     accepter_sender
         .send(())
         .expect("couldn't tell accepter thread to stop");
-    accepter_thread
+    let accepter_result = accepter_thread
         .join()
         .expect("couldn't join accepter thread");
 
@@ -159,6 +280,19 @@ pub fn start_recording(
 
     let profile = match profile_result {
         Ok(profile) => profile,
+        Err(SamplingError::CouldNotObtainRootTask)
+            if accepter_result == Err(MachError::PreloadBlockedBySip) =>
+        {
+            eprintln!(
+                "Profiling failed: {} is protected by System Integrity Protection.",
+                command_name.to_string_lossy()
+            );
+            eprintln!();
+            eprintln!("macOS removes the DYLD_INSERT_LIBRARIES environment variable when launching executables in /usr, /bin, /sbin or /System, so samply's preload library was never loaded and the process never checked in. This also applies to everything launched by such an executable, for example by /bin/sh when running a shell script.");
+            eprintln!();
+            eprintln!("Suggested remedy: Launch a copy of the executable which is outside of these directories, or profile an executable that you've compiled yourself or installed with Homebrew.");
+            std::process::exit(1)
+        }
         Err(SamplingError::CouldNotObtainRootTask) => {
             eprintln!("Profiling failed: Could not obtain the root task.");
             eprintln!();
@@ -183,3 +317,22 @@ pub fn start_recording(
 
     Ok(exit_status)
 }
+
+/// Waits for the launched process to terminate. If `deadline` passes first, the
+/// process is killed, and the returned status is `ChildExitStatus::KilledBySamply`.
+fn wait_for_root_child(root_child: &mut RootChild, deadline: Option<Instant>) -> ChildExitStatus {
+    if let Some(deadline) = deadline {
+        while Instant::now() < deadline {
+            if let Some(exit_status) = root_child.try_wait().expect("couldn't wait for child") {
+                return exit_status;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        let pid = root_child.id();
+        eprintln!("The time limit has elapsed, killing the launched process (pid {pid}).");
+        if let Err(err) = root_child.kill() {
+            eprintln!("Could not kill the launched process: {err}");
+        }
+    }
+    root_child.wait().expect("couldn't wait for child")
+}
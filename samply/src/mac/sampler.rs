@@ -4,6 +4,8 @@ use mach::port::mach_port_t;
 
 use std::mem;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 use std::time::SystemTime;
@@ -32,6 +34,7 @@ pub struct Sampler {
     time_limit: Option<Duration>,
     fold_recursive_prefix: bool,
     merge_threads: bool,
+    stop_flag: Option<Arc<AtomicBool>>,
 }
 
 impl Sampler {
@@ -62,9 +65,18 @@ impl Sampler {
             time_limit,
             fold_recursive_prefix,
             merge_threads,
+            stop_flag: None,
         }
     }
 
+    /// Stop sampling once `stop_flag` is set, even if the sampled tasks are still
+    /// alive. This is used when attaching to running processes, which can outlive
+    /// the profiler.
+    pub fn with_stop_flag(mut self, stop_flag: Arc<AtomicBool>) -> Self {
+        self.stop_flag = Some(stop_flag);
+        self
+    }
+
     pub fn run(self) -> Result<Profile, SamplingError> {
         let reference_mono = get_monotonic_timestamp();
         let reference_system_time = SystemTime::now();
@@ -147,6 +159,12 @@ impl Sampler {
                 break;
             }
 
+            if let Some(stop_flag) = &self.stop_flag {
+                if stop_flag.load(Ordering::SeqCst) {
+                    break;
+                }
+            }
+
             let sample_mono = get_monotonic_timestamp();
             if let Some(time_limit) = self.time_limit {
                 if sample_mono - reference_mono >= time_limit.as_nanos() as u64 {
@@ -0,0 +1,526 @@
+use fxprof_processed_profile::{Symbol, SymbolTable};
+use mach::port::mach_port_t;
+use object::macho::{self, MachHeader64, SegmentCommand64};
+use object::read::macho::{MachHeader, MachOFile64, Segment};
+use object::{LittleEndian, Object, ObjectSegment, ObjectSymbol, SymbolKind};
+use wholesym::samply_symbols::{
+    self, object, FileAndPathHelperResult, FileContents, FileContentsWrapper,
+};
+
+use std::mem;
+use std::ops::{Deref, Range};
+
+use super::kernel_error::{self, KernelError};
+use super::proc_maps::{ForeignMemory, VmData};
+
+/// The bytes of a region of the image.
+enum RegionData {
+    /// A copy, used for the header and load commands, whose offsets are rewritten.
+    Owned(Vec<u8>),
+    /// Memory which was read from the task.
+    Task(VmData),
+}
+
+impl Deref for RegionData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            RegionData::Owned(vec) => vec,
+            RegionData::Task(vm_data) => vm_data.get_full_slice(),
+        }
+    }
+}
+
+/// A region of the image, with the file offsets it covers and its data.
+struct Region {
+    file_range: Range<u64>,
+    data: RegionData,
+}
+
+/// A [`FileContents`] implementation for a mach-O image which is mapped into a
+/// running task, for example a library from the dyld shared cache, whose bytes
+/// don't exist as a separate file on disk.
+///
+/// The contents are presented as if they were the image's file, with the mach
+/// header at offset zero and every segment at its address relative to the
+/// `__TEXT` segment. The file offsets in the load commands are rewritten to
+/// match, because the load commands of images in the dyld shared cache contain
+/// offsets into the cache files, and because `__LINKEDIT` doesn't start at the
+/// same distance from `__TEXT` in the file as in memory.
+///
+/// All segments except `__LINKEDIT` are read with `mach_vm_read` when the object
+/// is created; the kernel makes this a copy-on-write mapping, so this doesn't
+/// copy the memory. In the dyld shared cache, `__LINKEDIT` is shared by all
+/// images, so only the symbol table, the string table, the function starts and
+/// the exports trie are read from it. File ranges which aren't covered by any
+/// region can't be read.
+pub struct TaskMemoryFileContents {
+    regions: Vec<Region>,
+    len: u64,
+}
+
+impl TaskMemoryFileContents {
+    /// Reads the image whose mach header is at `header_address` in `task`. The
+    /// task can be obtained from `AcceptedTask::take_task`.
+    pub fn read_from_task(task: mach_port_t, header_address: u64) -> kernel_error::Result<Self> {
+        let mut memory = ForeignMemory::new(task);
+        let header = {
+            let header: &MachHeader64<LittleEndian> =
+                unsafe { memory.get_type_ref_at_address(header_address) }?;
+            *header
+        };
+        let commands_start = header_address + mem::size_of::<MachHeader64<LittleEndian>>() as u64;
+        let commands_end = commands_start + header.sizeofcmds(LittleEndian) as u64;
+        let header_and_commands = memory.get_slice(header_address..commands_end)?.to_vec();
+        Self::from_header_and_commands(header_and_commands, |text_relative_address, size| {
+            let address = header_address + text_relative_address;
+            let data = VmData::read_from_task(task, address, size)?;
+            if data.len() < size {
+                return Err(KernelError::InvalidAddress);
+            }
+            Ok(RegionData::Task(data))
+        })
+    }
+
+    /// Creates the contents from the mach header and load commands of the image,
+    /// with `read_memory` reading the image's memory at an address relative to
+    /// the start of the `__TEXT` segment.
+    fn from_header_and_commands(
+        mut header_and_commands: Vec<u8>,
+        mut read_memory: impl FnMut(u64, u64) -> kernel_error::Result<RegionData>,
+    ) -> kernel_error::Result<Self> {
+        let segments = segments(&header_and_commands).ok_or(KernelError::InvalidValue)?;
+        let text_vmaddr = segments
+            .iter()
+            .find(|segment| segment.name == macho::SEG_TEXT.as_bytes())
+            .map(|segment| segment.vmaddr)
+            .ok_or(KernelError::InvalidValue)?;
+        let linkedit_index = segments
+            .iter()
+            .position(|segment| segment.name == macho::SEG_LINKEDIT.as_bytes());
+        rewrite_file_offsets(&mut header_and_commands, |owner, offset| {
+            let segment = match owner {
+                OffsetOwner::Segment(index) => &segments[index],
+                OffsetOwner::Linkedit => &segments[linkedit_index?],
+            };
+            let offset_in_segment = offset.checked_sub(segment.fileoff)?;
+            let new_fileoff = segment.vmaddr.checked_sub(text_vmaddr)?;
+            Some(new_fileoff + offset_in_segment)
+        })
+        .ok_or(KernelError::InvalidValue)?;
+
+        let mut file_ranges: Vec<Range<u64>> = segments
+            .iter()
+            .enumerate()
+            .filter(|(index, segment)| Some(*index) != linkedit_index && segment.filesize != 0)
+            .filter_map(|(_, segment)| {
+                let start = segment.vmaddr.checked_sub(text_vmaddr)?;
+                Some(start..start + segment.filesize)
+            })
+            .collect();
+        file_ranges.extend(linkedit_ranges(&header_and_commands));
+
+        let mut regions = vec![Region {
+            file_range: 0..header_and_commands.len() as u64,
+            data: RegionData::Owned(header_and_commands),
+        }];
+        for file_range in file_ranges {
+            let size = file_range.end - file_range.start;
+            if size == 0 {
+                continue;
+            }
+            let data = read_memory(file_range.start, size)?;
+            regions.push(Region { file_range, data });
+        }
+        let len = regions.iter().map(|r| r.file_range.end).max().unwrap_or(0);
+        Ok(Self { regions, len })
+    }
+
+    fn region_bytes_at(&self, offset: u64, size: u64) -> FileAndPathHelperResult<&[u8]> {
+        let end = offset.checked_add(size).ok_or("Read range overflows")?;
+        let region = self
+            .regions
+            .iter()
+            .find(|r| r.file_range.start <= offset && end <= r.file_range.end)
+            .ok_or_else(|| {
+                format!("The range {offset:#x}..{end:#x} is not covered by a single region")
+            })?;
+        let start = (offset - region.file_range.start) as usize;
+        Ok(&region.data[start..][..size as usize])
+    }
+}
+
+impl FileContents for TaskMemoryFileContents {
+    fn len(&self) -> u64 {
+        self.len
+    }
+
+    fn read_bytes_at(&self, offset: u64, size: u64) -> FileAndPathHelperResult<&[u8]> {
+        self.region_bytes_at(offset, size)
+    }
+
+    fn read_bytes_at_until(
+        &self,
+        range: Range<u64>,
+        delimiter: u8,
+    ) -> FileAndPathHelperResult<&[u8]> {
+        // Only search up to the end of the region which contains the start of the range.
+        let region_end = self
+            .regions
+            .iter()
+            .find(|r| r.file_range.contains(&range.start))
+            .map(|r| r.file_range.end)
+            .ok_or("The range start is not covered by a region")?;
+        let end = range.end.min(region_end);
+        let bytes = self.region_bytes_at(range.start, end.saturating_sub(range.start))?;
+        match memchr::memchr(delimiter, bytes) {
+            Some(pos) => Ok(&bytes[..pos]),
+            None => Err("Could not find delimiter".into()),
+        }
+    }
+
+    fn read_bytes_into(
+        &self,
+        buffer: &mut Vec<u8>,
+        offset: u64,
+        size: usize,
+    ) -> FileAndPathHelperResult<()> {
+        buffer.extend_from_slice(self.region_bytes_at(offset, size as u64)?);
+        Ok(())
+    }
+}
+
+/// Returns the symbol table of the image, with addresses relative to the start of
+/// its `__TEXT` segment.
+pub fn symbol_table_for_image(contents: TaskMemoryFileContents) -> Option<SymbolTable> {
+    let contents = FileContentsWrapper::new(contents);
+    let file = MachOFile64::<LittleEndian, _>::parse(&contents).ok()?;
+    let text_vmaddr = file
+        .segments()
+        .find(|segment| segment.name() == Ok(Some(macho::SEG_TEXT)))?
+        .address();
+    let symbols = file
+        .symbols()
+        .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.is_definition())
+        .filter_map(|symbol| {
+            let address = u32::try_from(symbol.address().checked_sub(text_vmaddr)?).ok()?;
+            let name = samply_symbols::demangle_any(symbol.name().ok()?);
+            Some(Symbol {
+                address,
+                size: None,
+                name,
+            })
+        })
+        .collect();
+    Some(SymbolTable::new(symbols))
+}
+
+/// Returns whether the image at `header_address` in `task` is part of the dyld
+/// shared cache.
+pub fn is_in_dyld_shared_cache(task: mach_port_t, header_address: u64) -> bool {
+    let mut memory = ForeignMemory::new(task);
+    match unsafe { memory.get_type_ref_at_address::<MachHeader64<LittleEndian>>(header_address) } {
+        Ok(header) => header.flags(LittleEndian) & macho::MH_DYLIB_IN_CACHE != 0,
+        Err(_) => false,
+    }
+}
+
+struct SegmentInfo {
+    name: Vec<u8>,
+    vmaddr: u64,
+    fileoff: u64,
+    filesize: u64,
+}
+
+fn load_commands(
+    header_and_commands: &[u8],
+) -> Option<object::read::macho::LoadCommandIterator<'_, LittleEndian>> {
+    let header = MachHeader64::<LittleEndian>::parse(header_and_commands, 0).ok()?;
+    header
+        .load_commands(LittleEndian, header_and_commands, 0)
+        .ok()
+}
+
+fn segments(header_and_commands: &[u8]) -> Option<Vec<SegmentInfo>> {
+    let mut commands = load_commands(header_and_commands)?;
+    let mut segments = Vec::new();
+    while let Ok(Some(command)) = commands.next() {
+        if let Ok(Some((segment, _))) = SegmentCommand64::from_command(command) {
+            segments.push(SegmentInfo {
+                name: segment.name().to_owned(),
+                vmaddr: segment.vmaddr(LittleEndian),
+                fileoff: segment.fileoff(LittleEndian),
+                filesize: segment.filesize(LittleEndian),
+            });
+        }
+    }
+    Some(segments)
+}
+
+/// Returns the file ranges in `__LINKEDIT` which are needed for the symbol table:
+/// the symbols, their names, the function starts and the exports trie.
+fn linkedit_ranges(header_and_commands: &[u8]) -> Vec<Range<u64>> {
+    let mut ranges = Vec::new();
+    let mut commands = match load_commands(header_and_commands) {
+        Some(commands) => commands,
+        None => return ranges,
+    };
+    let mut push_range = |offset: u32, size: u64| {
+        if offset != 0 && size != 0 {
+            ranges.push(offset as u64..offset as u64 + size);
+        }
+    };
+    while let Ok(Some(command)) = commands.next() {
+        if let Ok(Some(symtab)) = command.symtab() {
+            let nlist_size = mem::size_of::<macho::Nlist64<LittleEndian>>() as u64;
+            push_range(
+                symtab.symoff.get(LittleEndian),
+                symtab.nsyms.get(LittleEndian) as u64 * nlist_size,
+            );
+            push_range(
+                symtab.stroff.get(LittleEndian),
+                symtab.strsize.get(LittleEndian) as u64,
+            );
+        } else if let Ok(Some(dyld_info)) = command.dyld_info() {
+            push_range(
+                dyld_info.export_off.get(LittleEndian),
+                dyld_info.export_size.get(LittleEndian) as u64,
+            );
+        } else if command.cmd() == macho::LC_FUNCTION_STARTS
+            || command.cmd() == macho::LC_DYLD_EXPORTS_TRIE
+        {
+            if let Ok(linkedit_data) = command.data::<macho::LinkeditDataCommand<LittleEndian>>() {
+                push_range(
+                    linkedit_data.dataoff.get(LittleEndian),
+                    linkedit_data.datasize.get(LittleEndian) as u64,
+                );
+            }
+        }
+    }
+    ranges
+}
+
+/// Which segment a file offset in a load command points into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OffsetOwner {
+    /// The segment with this index among the `LC_SEGMENT_64` commands; used for the
+    /// segment's own file offset and for the file offsets of its sections.
+    Segment(usize),
+    /// The `__LINKEDIT` segment.
+    Linkedit,
+}
+
+/// Replaces every non-zero file offset in the load commands with the result of
+/// `new_offset`. Offsets for which `new_offset` returns `None` are set to zero,
+/// i.e. treated as absent. Returns `None` if the load commands are malformed.
+fn rewrite_file_offsets(
+    header_and_commands: &mut [u8],
+    mut new_offset: impl FnMut(OffsetOwner, u64) -> Option<u64>,
+) -> Option<()> {
+    let header_size = mem::size_of::<MachHeader64<LittleEndian>>();
+    let ncmds = read_u32(header_and_commands, 16)?;
+    let mut command_start = header_size;
+    let mut segment_index = 0;
+    for _ in 0..ncmds {
+        let cmd = read_u32(header_and_commands, command_start)?;
+        let cmdsize = read_u32(header_and_commands, command_start + 4)? as usize;
+        if cmdsize < 8 || command_start + cmdsize > header_and_commands[..].len() {
+            return None;
+        }
+        let command = &mut header_and_commands[command_start..][..cmdsize];
+        match cmd {
+            macho::LC_SEGMENT_64 => {
+                let owner = OffsetOwner::Segment(segment_index);
+                segment_index += 1;
+                let fileoff = read_u64(command, 40)?;
+                let filesize = read_u64(command, 48)?;
+                if filesize != 0 {
+                    let fileoff = new_offset(owner, fileoff).unwrap_or(0);
+                    command[40..48].copy_from_slice(&fileoff.to_le_bytes());
+                }
+                let nsects = read_u32(command, 64)? as usize;
+                for section_index in 0..nsects {
+                    let section_start = 72 + section_index * 80;
+                    rewrite_u32(command, &mut new_offset, section_start + 48, owner)?;
+                    // There are no relocations in linked images.
+                    command
+                        .get_mut(section_start + 56..section_start + 64)?
+                        .fill(0);
+                }
+            }
+            macho::LC_SYMTAB => {
+                rewrite_u32(command, &mut new_offset, 8, OffsetOwner::Linkedit)?;
+                rewrite_u32(command, &mut new_offset, 16, OffsetOwner::Linkedit)?;
+            }
+            macho::LC_DYSYMTAB => {
+                for field_offset in [32, 40, 48, 56, 64, 72] {
+                    rewrite_u32(
+                        command,
+                        &mut new_offset,
+                        field_offset,
+                        OffsetOwner::Linkedit,
+                    )?;
+                }
+            }
+            macho::LC_DYLD_INFO | macho::LC_DYLD_INFO_ONLY => {
+                for field_offset in [8, 16, 24, 32, 40] {
+                    rewrite_u32(
+                        command,
+                        &mut new_offset,
+                        field_offset,
+                        OffsetOwner::Linkedit,
+                    )?;
+                }
+            }
+            macho::LC_CODE_SIGNATURE
+            | macho::LC_SEGMENT_SPLIT_INFO
+            | macho::LC_FUNCTION_STARTS
+            | macho::LC_DATA_IN_CODE
+            | macho::LC_DYLIB_CODE_SIGN_DRS
+            | macho::LC_LINKER_OPTIMIZATION_HINT
+            | macho::LC_DYLD_EXPORTS_TRIE
+            | macho::LC_DYLD_CHAINED_FIXUPS => {
+                rewrite_u32(command, &mut new_offset, 8, OffsetOwner::Linkedit)?;
+            }
+            _ => {}
+        }
+        command_start += cmdsize;
+    }
+    Some(())
+}
+
+/// Replaces the non-zero u32 file offset at `field_offset` in `command`, see
+/// `rewrite_file_offsets`.
+fn rewrite_u32(
+    command: &mut [u8],
+    new_offset: &mut impl FnMut(OffsetOwner, u64) -> Option<u64>,
+    field_offset: usize,
+    owner: OffsetOwner,
+) -> Option<()> {
+    let offset = read_u32(command, field_offset)?;
+    if offset != 0 {
+        let offset = new_offset(owner, offset as u64)
+            .and_then(|offset| u32::try_from(offset).ok())
+            .unwrap_or(0);
+        command[field_offset..][..4].copy_from_slice(&offset.to_le_bytes());
+    }
+    Some(())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Creates the contents for the image in `file_data` as if it was mapped into a
+    /// task, with the given header and load commands. Returns the contents and the
+    /// number of bytes which were read from `__LINKEDIT`.
+    fn image_from_file(
+        file_data: &[u8],
+        header_and_commands: Vec<u8>,
+    ) -> (TaskMemoryFileContents, u64) {
+        let segments = segments(file_data).unwrap();
+        let text_vmaddr = segments
+            .iter()
+            .find(|segment| segment.name == macho::SEG_TEXT.as_bytes())
+            .unwrap()
+            .vmaddr;
+        let mut linkedit_bytes_read = 0;
+        let contents = TaskMemoryFileContents::from_header_and_commands(
+            header_and_commands,
+            |address, size| {
+                let vmaddr = text_vmaddr + address;
+                let segment = segments
+                    .iter()
+                    .find(|s| s.vmaddr <= vmaddr && vmaddr + size <= s.vmaddr + s.filesize)
+                    .ok_or(KernelError::InvalidAddress)?;
+                if segment.name == macho::SEG_LINKEDIT.as_bytes() {
+                    linkedit_bytes_read += size;
+                }
+                let offset = (segment.fileoff + vmaddr - segment.vmaddr) as usize;
+                Ok(RegionData::Owned(
+                    file_data[offset..offset + size as usize].to_vec(),
+                ))
+            },
+        )
+        .unwrap();
+        (contents, linkedit_bytes_read)
+    }
+
+    fn symbols<'data, R: object::ReadRef<'data>>(
+        file: &MachOFile64<'data, LittleEndian, R>,
+    ) -> Vec<(u64, String)> {
+        file.symbols()
+            .map(|symbol| (symbol.address(), symbol.name().unwrap().to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn image_in_task_memory() {
+        let file_data = std::fs::read(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../fixtures/macos-ci/libmozglue.dylib"),
+        )
+        .unwrap();
+        let file = MachOFile64::<LittleEndian, _>::parse(&file_data[..]).unwrap();
+        let expected_symbols = symbols(&file);
+        let function = file
+            .symbols()
+            .find(|symbol| symbol.kind() == SymbolKind::Text && symbol.is_definition())
+            .unwrap();
+        let header = MachHeader64::<LittleEndian>::parse(&file_data[..], 0).unwrap();
+        let header_and_commands = file_data[..mem::size_of::<MachHeader64<LittleEndian>>()
+            + header.sizeofcmds(LittleEndian) as usize]
+            .to_vec();
+        let segments = segments(&file_data).unwrap();
+        let segment = |name: &str| {
+            segments
+                .iter()
+                .find(|segment| segment.name == name.as_bytes())
+                .unwrap()
+        };
+        let text_vmaddr = segment(macho::SEG_TEXT).vmaddr;
+        let linkedit_size = segment(macho::SEG_LINKEDIT).filesize;
+
+        let (contents, linkedit_bytes_read) =
+            image_from_file(&file_data, header_and_commands.clone());
+        assert!(linkedit_bytes_read < linkedit_size);
+        let contents = FileContentsWrapper::new(contents);
+        let image = MachOFile64::<LittleEndian, _>::parse(&contents).unwrap();
+        assert_eq!(symbols(&image), expected_symbols);
+
+        // Images in the dyld shared cache have load commands with offsets into the
+        // cache file.
+        let mut cache_header_and_commands = header_and_commands;
+        rewrite_file_offsets(&mut cache_header_and_commands, |_, offset| {
+            Some(offset + 0x123_0000)
+        })
+        .unwrap();
+        let (contents, _) = image_from_file(&file_data, cache_header_and_commands.clone());
+        let contents = FileContentsWrapper::new(contents);
+        let image = MachOFile64::<LittleEndian, _>::parse(&contents).unwrap();
+        assert_eq!(symbols(&image), expected_symbols);
+
+        let (contents, _) = image_from_file(&file_data, cache_header_and_commands);
+        let symbol_table = symbol_table_for_image(contents).unwrap();
+        let symbol = symbol_table
+            .lookup((function.address() - text_vmaddr) as u32)
+            .unwrap();
+        assert_eq!(
+            symbol.name,
+            samply_symbols::demangle_any(function.name().unwrap())
+        );
+    }
+}
@@ -4,7 +4,9 @@ use framehop::{
     Unwinder, UnwinderNative,
 };
 use fxprof_processed_profile::debugid::DebugId;
-use fxprof_processed_profile::{LibraryInfo, ProcessHandle, Profile, ThreadHandle, Timestamp};
+use fxprof_processed_profile::{
+    LibraryInfo, ProcessHandle, Profile, SymbolTable, ThreadHandle, Timestamp,
+};
 use mach::mach_types::thread_act_port_array_t;
 use mach::mach_types::thread_act_t;
 use mach::message::mach_msg_type_number_t;
@@ -14,7 +16,7 @@ use mach::traps::mach_task_self;
 use mach::vm::mach_vm_deallocate;
 use mach::vm_types::{mach_vm_address_t, mach_vm_size_t};
 use object::{CompressedFileRange, CompressionFormat, Object, ObjectSection};
-use samply_symbols::{object, DebugIdExt};
+use samply_symbols::{object, DebugIdExt, FileContentsWrapper};
 use wholesym::samply_symbols;
 
 use std::collections::hash_map::Entry;
@@ -22,6 +24,7 @@ use std::collections::{HashMap, HashSet};
 use std::mem;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use crate::shared::jit_category_manager::JitCategoryManager;
 use crate::shared::jit_function_recycler::JitFunctionRecycler;
@@ -39,6 +42,9 @@ use super::error::SamplingError;
 use super::kernel_error::{IntoResult, KernelError};
 use super::proc_maps::{DyldInfo, DyldInfoManager, Modification, StackwalkerRef, VmSubData};
 use super::sampler::TaskInit;
+use super::task_memory_file_contents::{
+    is_in_dyld_shared_cache, symbol_table_for_image, TaskMemoryFileContents,
+};
 use super::thread_profiler::{get_thread_id, get_thread_name, ThreadProfiler};
 
 pub enum UnwindSectionBytes {
@@ -391,6 +397,7 @@ impl TaskProfiler {
             match change {
                 Modification::Added(mut lib) => {
                     self.add_lib_to_unwinder_and_ensure_debug_id(&mut lib);
+                    let symbol_table = self.symbol_table_from_task_memory(&lib);
 
                     let path = Path::new(&lib.file);
                     if let Some(name) = path.file_name() {
@@ -404,7 +411,7 @@ impl TaskProfiler {
                             debug_id: lib.debug_id.unwrap(),
                             code_id: lib.code_id.map(|ci| ci.to_string()),
                             arch: lib.arch.map(ToOwned::to_owned),
-                            symbol_table: None,
+                            symbol_table,
                         });
                         self.lib_mapping_ops.push(
                             now_mono,
@@ -430,6 +437,21 @@ impl TaskProfiler {
         }
     }
 
+    /// Returns the symbol table of `lib` from the task's memory if its file on disk
+    /// can't be used for symbolication, because the file no longer exists or has
+    /// been replaced with a different build. Images from the dyld shared cache are
+    /// symbolicated from the cache file on disk instead.
+    fn symbol_table_from_task_memory(&self, lib: &DyldInfo) -> Option<Arc<SymbolTable>> {
+        let debug_id = lib.debug_id?;
+        if is_in_dyld_shared_cache(self.task, lib.base_avma)
+            || file_on_disk_has_debug_id(Path::new(&lib.file), debug_id)
+        {
+            return None;
+        }
+        let contents = TaskMemoryFileContents::read_from_task(self.task, lib.base_avma).ok()?;
+        symbol_table_for_image(contents).map(Arc::new)
+    }
+
     fn add_lib_to_unwinder_and_ensure_debug_id(&mut self, lib: &mut DyldInfo) {
         let base_svma = lib.svma_info.base_svma;
         let base_avma = lib.base_avma;
@@ -634,6 +656,26 @@ fn get_debug_frame(file_path: &str) -> Option<UnwindSectionBytes> {
     }
 }
 
+/// Returns whether the mach-O file at `path`, or one of the members of the universal
+/// binary at `path`, has the UUID of `debug_id`.
+fn file_on_disk_has_debug_id(path: &Path, debug_id: DebugId) -> bool {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(_) => return false,
+    };
+    let mmap = match unsafe { memmap2::MmapOptions::new().map(&file) } {
+        Ok(mmap) => mmap,
+        Err(_) => return false,
+    };
+    let file_contents = FileContentsWrapper::new(mmap);
+    match samply_symbols::list_fat_archive_members(&file_contents) {
+        Ok(members) => members
+            .iter()
+            .any(|member| member.uuid.map(DebugId::from_uuid) == Some(debug_id)),
+        Err(_) => false,
+    }
+}
+
 fn get_thread_list(task: mach_port_t) -> Result<Vec<thread_act_t>, SamplingError> {
     let mut thread_list: thread_act_port_array_t = std::ptr::null_mut();
     let mut thread_count: mach_msg_type_number_t = Default::default();
@@ -50,6 +50,9 @@ EXAMPLES:
 
     # Import perf.data files from Linux perf:
     samply load perf.data
+
+    # Only symbolicate the sampled addresses of a perf.data file:
+    samply symbolicate perf.data
 "#
 )]
 struct Opt {
@@ -65,6 +68,10 @@ enum Action {
     #[cfg(any(target_os = "macos", target_os = "linux"))]
     /// Record a profile and display it.
     Record(RecordArgs),
+
+    /// Look up the symbols for the sampled addresses of a perf.data file, and print
+    /// one symbolication response (JSON) per module.
+    Symbolicate(SymbolicateArgs),
 }
 
 #[derive(Debug, Args)]
@@ -79,6 +86,12 @@ struct LoadArgs {
     server_args: ServerArgs,
 }
 
+#[derive(Debug, Args)]
+struct SymbolicateArgs {
+    /// Path to the perf.data file.
+    file: PathBuf,
+}
+
 #[cfg(any(target_os = "macos", target_os = "linux"))]
 #[derive(Debug, Args)]
 struct RecordArgs {
@@ -105,6 +118,9 @@ struct RecordArgs {
     #[command(flatten)]
     conversion_args: ConversionArgs,
 
+    #[command(flatten)]
+    launch_args: LaunchArgs,
+
     #[command(flatten)]
     server_args: ServerArgs,
 
@@ -117,7 +133,7 @@ struct RecordArgs {
     )]
     command: Vec<std::ffi::OsString>,
 
-    /// Process ID of existing process to attach to (Linux only).
+    /// Process ID of existing process to attach to. On macOS, this requires root privileges.
     #[arg(short, long)]
     pid: Option<u32>,
 }
@@ -137,6 +153,23 @@ struct ServerArgs {
     verbose: bool,
 }
 
+/// Options for the processes launched by `samply record`.
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+#[derive(Debug, Args, Clone)]
+pub struct LaunchArgs {
+    /// Only profile the launched process, not the processes it launches. Only
+    /// supported on macOS.
+    #[arg(long)]
+    main_process_only: bool,
+
+    /// Let each launched process run as soon as it checks in, without waiting for
+    /// samply to start sampling it. The process starts sooner, but whatever it does
+    /// in the first moments is missing from the profile.
+    #[cfg(target_os = "macos")]
+    #[arg(long)]
+    proceed_immediately: bool,
+}
+
 #[derive(Debug, Args, Clone)]
 pub struct ConversionArgs {
     /// Merge non-overlapping threads of the same name.
@@ -168,6 +201,25 @@ fn main() {
             start_server_main(filename, load_args.server_args.server_props());
         }
 
+        Action::Symbolicate(symbolicate_args) => {
+            let input_file = match File::open(&symbolicate_args.file) {
+                Ok(file) => file,
+                Err(err) => {
+                    eprintln!("Could not open file {:?}: {}", symbolicate_args.file, err);
+                    std::process::exit(1)
+                }
+            };
+            let reader = BufReader::new(input_file);
+            let queries = match import::perf_data::symbolication_queries(reader) {
+                Ok(queries) => queries,
+                Err(err) => {
+                    eprintln!("Could not read {:?}: {}", symbolicate_args.file, err);
+                    std::process::exit(1)
+                }
+            };
+            import::perf_data::run_and_print_queries(&queries);
+        }
+
         #[cfg(any(target_os = "macos", target_os = "linux"))]
         Action::Record(record_args) => {
             use std::time::Duration;
@@ -206,6 +258,7 @@ fn main() {
                     interval,
                     server_props,
                     &record_args.conversion_args,
+                    &record_args.launch_args,
                     record_args.iteration_count,
                 ) {
                     Ok(exit_status) => exit_status,
@@ -214,7 +267,7 @@ fn main() {
                         std::process::exit(1);
                     }
                 };
-                std::process::exit(exit_status.code().unwrap_or(0));
+                std::process::exit(exit_status.exit_code());
             }
         }
     }
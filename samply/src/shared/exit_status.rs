@@ -0,0 +1,92 @@
+use std::fmt;
+use std::process::ExitStatus;
+
+/// How the profiled root process terminated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildExitStatus {
+    /// The process exited normally with this exit code.
+    Exited(i32),
+    /// The process was terminated by this signal, which wasn't sent by samply.
+    Signaled(i32),
+    /// samply killed the process.
+    KilledBySamply,
+}
+
+impl ChildExitStatus {
+    /// Converts the `ExitStatus` of a process that samply waited for.
+    ///
+    /// `killed_by_samply` should be true if samply sent the process a kill signal
+    /// before it terminated.
+    pub fn from_exit_status(exit_status: ExitStatus, killed_by_samply: bool) -> Self {
+        if let Some(code) = exit_status.code() {
+            return ChildExitStatus::Exited(code);
+        }
+        if killed_by_samply {
+            return ChildExitStatus::KilledBySamply;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = exit_status.signal() {
+                return ChildExitStatus::Signaled(signal);
+            }
+        }
+        ChildExitStatus::Exited(1)
+    }
+
+    pub fn success(&self) -> bool {
+        matches!(self, ChildExitStatus::Exited(0))
+    }
+
+    /// The exit code that samply should exit with, so that scripts which invoke
+    /// `samply record -- mytool` see the same code as when running `mytool` directly.
+    /// Like shells, this uses 128 + signal number for processes terminated by a signal.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ChildExitStatus::Exited(code) => *code,
+            ChildExitStatus::Signaled(signal) => 128 + signal,
+            ChildExitStatus::KilledBySamply => 128 + 9,
+        }
+    }
+}
+
+impl From<ExitStatus> for ChildExitStatus {
+    fn from(exit_status: ExitStatus) -> Self {
+        Self::from_exit_status(exit_status, false)
+    }
+}
+
+impl fmt::Display for ChildExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChildExitStatus::Exited(code) => write!(f, "exit status: {code}"),
+            ChildExitStatus::Signaled(signal) => write!(f, "terminated by signal {signal}"),
+            ChildExitStatus::KilledBySamply => write!(f, "killed by samply"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn exit_codes() {
+        use std::os::unix::process::ExitStatusExt;
+
+        // Wait statuses: the exit code is in the second byte, the signal in the low bits.
+        let exited = ChildExitStatus::from(ExitStatus::from_raw(3 << 8));
+        assert_eq!(exited, ChildExitStatus::Exited(3));
+        assert_eq!(exited.exit_code(), 3);
+        assert!(!exited.success());
+
+        let signaled = ChildExitStatus::from(ExitStatus::from_raw(15));
+        assert_eq!(signaled, ChildExitStatus::Signaled(15));
+        assert_eq!(signaled.exit_code(), 143);
+
+        let killed = ChildExitStatus::from_exit_status(ExitStatus::from_raw(9), true);
+        assert_eq!(killed, ChildExitStatus::KilledBySamply);
+        assert!(ChildExitStatus::from(ExitStatus::from_raw(0)).success());
+    }
+}
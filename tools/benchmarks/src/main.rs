@@ -1,5 +1,5 @@
 use bzip2::read::BzDecoder;
-use dump_table::get_table_for_debug_name_and_id;
+use dump_table::{get_symbol_map_for_debug_name_and_id, get_table_for_debug_name_and_id};
 use flate2::read::GzDecoder;
 use query_api::{query_api, DebugId};
 use std::collections::HashSet;
@@ -11,6 +11,13 @@ use tar::Archive;
 use tempfile::tempdir;
 
 fn main() -> anyhow::Result<()> {
+    // The address lookup benchmarks only use the fixtures in the repository, so
+    // they can be run on their own without downloading the big fixtures.
+    if std::env::args().nth(1).as_deref() == Some("lookups") {
+        print_timings(address_lookup_timings()?);
+        return Ok(());
+    }
+
     prepare(
         big_fixtures_dir().join("win64-ci").join("xul.pdb"),
         "https://storage.googleapis.com/profiler-get-symbols-fixtures/win64-ci-xul.pdb.gz",
@@ -51,7 +58,7 @@ fn main() -> anyhow::Result<()> {
         FileType::Gzip,
     )?;
 
-    let timings = vec![
+    let mut timings = vec![
         // Windows
         Timing {
             platform: "win64",
@@ -190,6 +197,13 @@ fn main() -> anyhow::Result<()> {
         },
     ];
 
+    timings.extend(address_lookup_timings()?);
+    print_timings(timings);
+
+    Ok(())
+}
+
+fn print_timings(timings: Vec<Timing>) {
     eprintln!();
     eprintln!("Results:");
     for Timing {
@@ -200,8 +214,42 @@ fn main() -> anyhow::Result<()> {
     {
         eprintln!("  - {platform:12} {action:16} {duration:?}");
     }
+}
 
-    Ok(())
+/// Runs the address lookup benchmark with and without the address cache, on the
+/// fixtures in the repository.
+fn address_lookup_timings() -> anyhow::Result<Vec<Timing>> {
+    let libs = [
+        (
+            "macos",
+            "libmozglue.dylib",
+            "64EC2645330C3A0BA6E4EBCD28A1B5940",
+            "macos-ci",
+        ),
+        (
+            "win64",
+            "mozglue.pdb",
+            "63C609072D3499F64C4C44205044422E1",
+            "win64-ci",
+        ),
+    ];
+    let mut timings = Vec::new();
+    for (platform, debug_name, breakpad_id, dir) in libs {
+        for (action, address_cache_capacity) in [("lookups", None), ("lookups, cached", Some(1000))]
+        {
+            timings.push(Timing {
+                platform,
+                action,
+                duration: run_address_lookup_benchmark(
+                    debug_name,
+                    breakpad_id,
+                    fixtures_dir().join(dir),
+                    address_cache_capacity,
+                )?,
+            });
+        }
+    }
+    Ok(timings)
 }
 
 struct Timing {
@@ -225,6 +273,64 @@ fn run_api_query_benchmark(
     Ok(duration)
 }
 
+/// Looks up the addresses of a synthetic profile in which the 100 hottest addresses
+/// account for 90% of the samples, like in a typical profile. The remaining samples
+/// are spread over the rest of the library.
+fn run_address_lookup_benchmark(
+    debug_name: &str,
+    breakpad_id: &str,
+    symbol_directory: PathBuf,
+    address_cache_capacity: Option<usize>,
+) -> anyhow::Result<Duration> {
+    const SAMPLE_COUNT: usize = 200_000;
+    const HOT_ADDRESS_COUNT: usize = 100;
+
+    eprintln!(
+        "Starting address lookup benchmark for {debug_name}, {breakpad_id}, cache capacity {address_cache_capacity:?}."
+    );
+    let mut symbol_map = futures::executor::block_on(get_symbol_map_for_debug_name_and_id(
+        debug_name,
+        Some(DebugId::from_breakpad(breakpad_id)?),
+        symbol_directory,
+    ))?;
+    symbol_map.set_address_cache_capacity(address_cache_capacity);
+
+    // Use an address a few bytes into each function, so that the lookups have to
+    // find the line and inline information for an address in the middle of a function.
+    let function_addresses: Vec<u32> = symbol_map
+        .iter_symbols()
+        .map(|(address, _)| address + 4)
+        .collect();
+    // A simple linear congruential generator, so that every run looks up the same
+    // addresses.
+    let mut state: u64 = 0x853c_49e6_748f_ea9b;
+    let mut next_random = move || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 33) as usize
+    };
+    let samples: Vec<u32> = (0..SAMPLE_COUNT)
+        .map(|_| {
+            let index = if next_random() % 10 != 0 {
+                next_random() % HOT_ADDRESS_COUNT
+            } else {
+                next_random() % function_addresses.len()
+            };
+            function_addresses[index]
+        })
+        .collect();
+
+    let start = Instant::now();
+    for address in samples {
+        let _info = symbol_map.lookup_relative_address(address);
+    }
+    let duration = start.elapsed();
+    eprintln!("Finished address lookup benchmark for {debug_name}, {breakpad_id}.");
+    eprintln!("Elapsed time: {duration:?}");
+    Ok(duration)
+}
+
 fn run_dump_table_benchmark(
     debug_name: &str,
     breakpad_id: Option<String>,
@@ -3,6 +3,7 @@ use samply_symbols::debugid::DebugId;
 use samply_symbols::{
     self, CandidatePathInfo, CompactSymbolTable, Error, FileAndPathHelper, FileAndPathHelperResult,
     FileLocation, LibraryInfo, MultiArchDisambiguator, OptionallySendFuture, SymbolManager,
+    SymbolMap,
 };
 use std::fs::File;
 use std::io::{BufWriter, Write};
@@ -65,6 +66,16 @@ pub async fn get_table_for_debug_name_and_id(
     debug_id: Option<DebugId>,
     symbol_directory: PathBuf,
 ) -> Result<CompactSymbolTable, Error> {
+    let symbol_map =
+        get_symbol_map_for_debug_name_and_id(debug_name, debug_id, symbol_directory).await?;
+    Ok(CompactSymbolTable::from_symbol_map(&symbol_map))
+}
+
+pub async fn get_symbol_map_for_debug_name_and_id(
+    debug_name: &str,
+    debug_id: Option<DebugId>,
+    symbol_directory: PathBuf,
+) -> Result<SymbolMap<FileLocationType>, Error> {
     let helper = Helper { symbol_directory };
     let symbol_manager = SymbolManager::with_helper(&helper);
     let info = LibraryInfo {
@@ -72,8 +83,7 @@ pub async fn get_table_for_debug_name_and_id(
         debug_id,
         ..Default::default()
     };
-    let symbol_map = symbol_manager.load_symbol_map(&info).await?;
-    Ok(CompactSymbolTable::from_symbol_map(&symbol_map))
+    symbol_manager.load_symbol_map(&info).await
 }
 
 pub fn dump_table(w: &mut impl Write, table: CompactSymbolTable, full: bool) -> anyhow::Result<()> {
@@ -275,7 +285,7 @@ impl<'h> FileAndPathHelper<'h> for Helper {
 }
 
 #[derive(Clone)]
-struct FileLocationType(PathBuf);
+pub struct FileLocationType(PathBuf);
 
 impl FileLocationType {
     pub fn new(path: impl Into<PathBuf>) -> Self {
@@ -311,4 +321,14 @@ impl FileLocation for FileLocationType {
     fn location_for_breakpad_symindex(&self) -> Option<Self> {
         Some(Self(self.0.with_extension("symindex")))
     }
+
+    fn location_for_dwp(&self) -> Option<Self> {
+        let mut path = self.0.clone().into_os_string();
+        path.push(".dwp");
+        Some(Self(path.into()))
+    }
+
+    fn location_for_dwo(&self, dwo_path: &str) -> Option<Self> {
+        Some(Self(dwo_path.into()))
+    }
 }
@@ -206,4 +206,14 @@ impl FileLocation for FileLocationType {
     fn location_for_breakpad_symindex(&self) -> Option<Self> {
         Some(Self(self.0.with_extension("symindex")))
     }
+
+    fn location_for_dwp(&self) -> Option<Self> {
+        let mut path = self.0.clone().into_os_string();
+        path.push(".dwp");
+        Some(Self(path.into()))
+    }
+
+    fn location_for_dwo(&self, dwo_path: &str) -> Option<Self> {
+        Some(Self(dwo_path.into()))
+    }
 }
@@ -1,5 +1,9 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
+use samply_api::samply_symbols::FunctionNameStyle;
 use symsrv::{parse_nt_symbol_path, NtSymbolPathEntry};
 
 /// The configuration of a [`SymbolManager`](crate::SymbolManager).
@@ -9,6 +13,7 @@ use symsrv::{parse_nt_symbol_path, NtSymbolPathEntry};
 pub struct SymbolManagerConfig {
     pub(crate) verbose: bool,
     pub(crate) redirect_paths: HashMap<PathBuf, PathBuf>,
+    pub(crate) path_prefix_remappings: Vec<(PathBuf, PathBuf)>,
     pub(crate) respect_nt_symbol_path: bool,
     pub(crate) default_nt_symbol_path: Option<String>,
     pub(crate) breakpad_directories_readonly: Vec<PathBuf>,
@@ -19,6 +24,8 @@ pub struct SymbolManagerConfig {
     pub(crate) use_spotlight: bool,
     pub(crate) debuginfod_cache_dir_if_not_installed: Option<PathBuf>,
     pub(crate) debuginfod_servers: Vec<(String, PathBuf)>,
+    pub(crate) address_cache_capacity: Option<usize>,
+    pub(crate) function_name_style: FunctionNameStyle,
 }
 
 impl SymbolManagerConfig {
@@ -47,6 +54,25 @@ impl SymbolManagerConfig {
         self
     }
 
+    /// Add a rule which replaces the path prefix `from` with `to` in the paths of
+    /// external object files, which debug info records with absolute paths from the
+    /// build machine. For example, this can map `/Users/buildbot/build` to the local
+    /// checkout. The remapped path is only tried if the object file isn't found at
+    /// its recorded path. Paths of binaries and debug files are never remapped.
+    ///
+    /// This method can be called multiple times; the first rule whose prefix matches
+    /// is applied. Prefixes are matched on whole path components.
+    pub fn remap_path_prefix(mut self, from: impl Into<PathBuf>, to: impl Into<PathBuf>) -> Self {
+        self.path_prefix_remappings.push((from.into(), to.into()));
+        self
+    }
+
+    pub(crate) fn remapped_path(&self, path: &Path) -> Option<PathBuf> {
+        self.path_prefix_remappings
+            .iter()
+            .find_map(|(from, to)| Some(to.join(path.strip_prefix(from).ok()?)))
+    }
+
     /// Whether to import Windows symbol path configuration from the
     /// `_NT_SYMBOL_PATH` environment variable.
     pub fn respect_nt_symbol_path(mut self, respect: bool) -> Self {
@@ -175,4 +201,48 @@ impl SymbolManagerConfig {
         self.use_spotlight = use_spotlight;
         self
     }
+
+    /// Cache the lookup results of up to `capacity` addresses in each [`SymbolMap`](crate::SymbolMap).
+    ///
+    /// This speeds up symbolicating profiles, which usually contain the same hot
+    /// addresses many times. When the cache is full, the least recently used address
+    /// is evicted. `None` disables the cache; this is the default.
+    pub fn address_cache_capacity(mut self, capacity: Option<usize>) -> Self {
+        self.address_cache_capacity = capacity;
+        self
+    }
+
+    /// Shorten the function names in the lookup results of each [`SymbolMap`](crate::SymbolMap),
+    /// for example by removing the parameter lists from C++ function signatures.
+    /// The default style keeps the full names.
+    pub fn function_name_style(mut self, function_name_style: FunctionNameStyle) -> Self {
+        self.function_name_style = function_name_style;
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn remap_path_prefix() {
+        let config = SymbolManagerConfig::new()
+            .remap_path_prefix("/Users/buildbot/build", "/home/me/checkout")
+            .remap_path_prefix("/Users/buildbot", "/home/me/other");
+        assert_eq!(
+            config.remapped_path(Path::new("/Users/buildbot/build/obj/foo.o")),
+            Some(PathBuf::from("/home/me/checkout/obj/foo.o"))
+        );
+        assert_eq!(
+            config.remapped_path(Path::new("/Users/buildbot/elsewhere/bar.o")),
+            Some(PathBuf::from("/home/me/other/elsewhere/bar.o"))
+        );
+        // Prefixes only match whole components.
+        assert_eq!(
+            config.remapped_path(Path::new("/Users/buildbot/build2/foo.o")),
+            Some(PathBuf::from("/home/me/other/build2/foo.o"))
+        );
+        assert_eq!(config.remapped_path(Path::new("/Users/other/foo.o")), None);
+    }
 }
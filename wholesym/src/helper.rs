@@ -3,8 +3,8 @@ use samply_api::samply_symbols::{
     self, BreakpadIndex, BreakpadIndexParser, ElfBuildId, LibraryInfo, PeCodeId,
 };
 use samply_symbols::{
-    CandidatePathInfo, CodeId, FileAndPathHelper, FileAndPathHelperResult, FileLocation,
-    OptionallySendFuture,
+    CandidatePathInfo, CodeId, DecompressingFileContents, FileAndPathHelper,
+    FileAndPathHelperResult, FileLocation, OptionallySendFuture,
 };
 use symsrv::{memmap2, FileContents, SymbolCache};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -25,7 +25,9 @@ pub enum WholesymFileLocation {
     LocalFile(PathBuf),
     SymsrvFile(String),
     LocalBreakpadFile(PathBuf, String),
-    BreakpadSymbolServerFile(String),
+    /// A breakpad .sym file on the server with the given base URL, at the given
+    /// relative path.
+    BreakpadSymbolServerFile(String, String),
     BreakpadSymindexFile(String),
     DebuginfodDebugFile(ElfBuildId),
     DebuginfodExecutable(ElfBuildId),
@@ -88,12 +90,32 @@ impl FileLocation for WholesymFileLocation {
 
     fn location_for_breakpad_symindex(&self) -> Option<Self> {
         match self {
-            Self::BreakpadSymbolServerFile(rel_path) | Self::LocalBreakpadFile(_, rel_path) => {
+            Self::BreakpadSymbolServerFile(_, rel_path) | Self::LocalBreakpadFile(_, rel_path) => {
                 Some(Self::BreakpadSymindexFile(rel_path.clone()))
             }
             _ => None,
         }
     }
+
+    fn location_for_dwp(&self) -> Option<Self> {
+        match self {
+            Self::LocalFile(path) => {
+                let mut dwp_path = path.clone().into_os_string();
+                dwp_path.push(".dwp");
+                Some(Self::LocalFile(dwp_path.into()))
+            }
+            _ => None,
+        }
+    }
+
+    fn location_for_dwo(&self, dwo_path: &str) -> Option<Self> {
+        // Like external object files, .dwo files are referred to by absolute file
+        // path, so we only load them if those paths were found in a local file.
+        match self {
+            Self::LocalFile(_) => Some(Self::LocalFile(dwo_path.into())),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for WholesymFileLocation {
@@ -176,6 +198,10 @@ struct KnownLibs {
 }
 
 impl Helper {
+    pub(crate) fn config(&self) -> &SymbolManagerConfig {
+        &self.config
+    }
+
     pub fn with_config(config: SymbolManagerConfig) -> Self {
         let win_symbol_cache = match config.effective_nt_symbol_path() {
             Some(nt_symbol_path) => Some(SymbolCache::new(nt_symbol_path, config.verbose)),
@@ -260,11 +286,21 @@ impl Helper {
                     .get_file(Path::new(&path))
                     .await?)
             }
-            WholesymFileLocation::BreakpadSymbolServerFile(path) => {
+            WholesymFileLocation::BreakpadSymbolServerFile(server_base_url, path) => {
                 if self.config.verbose {
-                    eprintln!("Trying to get file {path:?} from breakpad symbol server");
+                    eprintln!(
+                        "Trying to get file {path:?} from breakpad symbol server {server_base_url}"
+                    );
                 }
-                self.get_bp_sym_file(&path).await
+                let cache_dir = self
+                    .config
+                    .breakpad_servers
+                    .iter()
+                    .find(|(url, _)| *url == server_base_url)
+                    .map(|(_, cache_dir)| cache_dir)
+                    .ok_or("Unknown breakpad symbol server")?;
+                self.get_bp_sym_file_from_server(&path, &server_base_url, cache_dir)
+                    .await
             }
             WholesymFileLocation::BreakpadSymindexFile(rel_path) => {
                 if let Some(symindex_path) = self.symindex_path(&rel_path) {
@@ -296,18 +332,6 @@ impl Helper {
         }
     }
 
-    async fn get_bp_sym_file(&self, rel_path: &str) -> FileAndPathHelperResult<FileContents> {
-        for (server_base_url, cache_dir) in &self.config.breakpad_servers {
-            if let Ok(file) = self
-                .get_bp_sym_file_from_server(rel_path, server_base_url, cache_dir)
-                .await
-            {
-                return Ok(file);
-            }
-        }
-        Err("No breakpad sym file on server".into())
-    }
-
     async fn get_bp_sym_file_from_server(
         &self,
         rel_path: &str,
@@ -449,7 +473,7 @@ impl Helper {
 }
 
 impl<'h> FileAndPathHelper<'h> for Helper {
-    type F = FileContents;
+    type F = DecompressingFileContents<FileContents>;
     type FL = WholesymFileLocation;
     type OpenFileFuture =
         Pin<Box<dyn OptionallySendFuture<Output = FileAndPathHelperResult<Self::F>> + 'h>>;
@@ -567,10 +591,15 @@ impl<'h> FileAndPathHelper<'h> for Helper {
             // TODO: Check symsrv local cache before checking breakpad servers
             // but still check breakpad server before checking symsrv server
 
-            if !self.config.breakpad_servers.is_empty() {
-                // We might find a .sym file on a symbol server.
+            // We might find a .sym file on a symbol server. Each server is a separate
+            // candidate, so that the symbol manager moves on to the next server if a
+            // server is unreachable or doesn't have the file.
+            for (server_base_url, _cache_dir) in &self.config.breakpad_servers {
                 paths.push(CandidatePathInfo::SingleFile(
-                    WholesymFileLocation::BreakpadSymbolServerFile(rel_path),
+                    WholesymFileLocation::BreakpadSymbolServerFile(
+                        server_base_url.clone(),
+                        rel_path.clone(),
+                    ),
                 ));
             }
 
@@ -707,7 +736,10 @@ impl<'h> FileAndPathHelper<'h> for Helper {
         &'h self,
         location: WholesymFileLocation,
     ) -> Pin<Box<dyn OptionallySendFuture<Output = FileAndPathHelperResult<Self::F>> + 'h>> {
-        Box::pin(self.load_file_impl(location))
+        // Symbol servers can serve gzip or zstd compressed files.
+        Box::pin(
+            async move { DecompressingFileContents::new(self.load_file_impl(location).await?) },
+        )
     }
 
     fn get_candidate_paths_for_supplementary_debug_file(
@@ -749,6 +781,32 @@ impl<'h> FileAndPathHelper<'h> for Helper {
 
         Ok(paths)
     }
+
+    fn get_candidate_paths_for_external_object_file(
+        &self,
+        original_file_location: &WholesymFileLocation,
+        object_file: &str,
+    ) -> FileAndPathHelperResult<Vec<WholesymFileLocation>> {
+        // Object file paths were recorded on the build machine. If the user has
+        // told us where that build directory lives on this machine, try there too.
+        let remapped_path = match original_file_location {
+            WholesymFileLocation::LocalFile(_) => self.config.remapped_path(Path::new(object_file)),
+            _ => None,
+        };
+        if let Some(remapped_path) = &remapped_path {
+            if self.config.verbose {
+                eprintln!(
+                    "Remapped {:?} to {:?}",
+                    object_file,
+                    remapped_path.to_string_lossy()
+                );
+            }
+        }
+        Ok(remapped_path
+            .map(WholesymFileLocation::LocalFile)
+            .into_iter()
+            .collect())
+    }
 }
 
 fn get_dyld_shared_cache_paths(arch: Option<&str>) -> Vec<WholesymFileLocation> {
@@ -770,6 +828,10 @@ fn get_dyld_shared_cache_paths(arch: Option<&str>) -> Vec<WholesymFileLocation>
                 "/System/Volumes/Preboot/Cryptexes/OS/System/Library/dyld/dyld_shared_cache_arm64e"
                     .into(),
             ),
+            WholesymFileLocation::LocalFile(
+                "/System/Volumes/Preboot/Cryptexes/OS/System/Library/dyld/dyld_shared_cache_x86_64h"
+                    .into(),
+            ),
             WholesymFileLocation::LocalFile(
                 "/System/Volumes/Preboot/Cryptexes/OS/System/Library/dyld/dyld_shared_cache_x86_64"
                     .into(),
@@ -38,6 +38,15 @@
 //!                 .lookup_external(&symbol_map.symbol_file_origin(), &ext_ref)
 //!                 .await
 //!         }
+//!         FramesLookupResult::NeedDwo(dwo_ref) => {
+//!             let origin = symbol_map.symbol_file_origin();
+//!             let contents = symbol_manager.load_dwo_file(&origin, &dwo_ref).await.ok();
+//!             symbol_map.add_dwo_file(&dwo_ref, contents);
+//!             match symbol_map.lookup_relative_address(0xd6f4).map(|info| info.frames) {
+//!                 Some(FramesLookupResult::Available(frames)) => Some(frames),
+//!                 _ => None,
+//!             }
+//!         }
 //!         FramesLookupResult::Unavailable => None,
 //!     };
 //!     if let Some(frames) = frames {
@@ -115,10 +124,7 @@
 //!  - [x] Breakpad symbol files, local or on a server
 //!  - [x] Symbols from the regular symbol table
 //!  - [x] Fallback symbols from exported functions and function start addresses
-//!
-//! Unsupported for now (patches accepted):
-//!
-//!  - [ ] Split DWARF (.dwo and .dwp files)
+//!  - [x] Split DWARF (.dwo and .dwp files)
 //!
 //! # Performance
 //!
@@ -151,8 +157,9 @@ mod symbol_manager;
 pub use config::SymbolManagerConfig;
 pub use samply_api::samply_symbols;
 pub use samply_api::samply_symbols::{
-    AddressInfo, CodeId, ElfBuildId, Error, ExternalFileAddressInFileRef, ExternalFileAddressRef,
-    ExternalFileRef, ExternalFileSymbolMap, FrameDebugInfo, FramesLookupResult, LibraryInfo,
-    MappedPath, MultiArchDisambiguator, PeCodeId, SourceFilePath, SymbolInfo,
+    AddressInfo, CodeId, DwoRef, ElfBuildId, Error, ExternalFileAddressInFileRef,
+    ExternalFileAddressRef, ExternalFileRef, ExternalFileSymbolMap, FrameDebugInfo,
+    FramesLookupResult, FunctionNameStyle, LibraryInfo, MappedPath, MultiArchDisambiguator,
+    PeCodeId, SourceFilePath, SymbolInfo,
 };
 pub use symbol_manager::{SymbolFileOrigin, SymbolManager, SymbolMap};
@@ -8,6 +8,7 @@ use samply_api::samply_symbols;
 use samply_symbols::object;
 use std::fs;
 use std::path::{Path, PathBuf};
+use symsrv::memmap2;
 use uuid::Uuid;
 
 #[cfg(target_os = "macos")]
@@ -86,14 +87,48 @@ fn try_match_dsym(dsym_dir: &Path, uuid: Uuid) -> Option<PathBuf> {
         return None; // There should only be one file in the `DWARF` directory.
     }
 
-    // Parse inner object file.
-    let file = fs::read(&debug_file_name).ok()?;
-    let dsym = object::File::parse(&file[..]).ok()?;
+    // Map the inner object file. dSYM files can be very large, so avoid reading
+    // the whole file into memory just to check the UUID.
+    let file = fs::File::open(&debug_file_name).ok()?;
+    let mmap = unsafe { memmap2::MmapOptions::new().map(&file).ok()? };
 
     // Make sure the dSYM file matches the object file to find debuginfo for.
-    if dsym.mach_uuid() == Ok(Some(*uuid.as_bytes())) {
+    if macho_file_has_uuid(&mmap[..], uuid) {
         Some(debug_file_name)
     } else {
         None
     }
 }
+
+/// Checks whether the mach-O file in `data` has the given UUID. dSYMs for
+/// multi-arch builds are universal binaries, so for those, every member is checked.
+fn macho_file_has_uuid(data: &[u8], uuid: Uuid) -> bool {
+    use object::read::macho::{FatArch, FatHeader};
+
+    let member_ranges: Vec<(u64, u64)> = match object::FileKind::parse(data) {
+        Ok(object::FileKind::MachOFat32) => match FatHeader::parse_arch32(data) {
+            Ok(arches) => arches.iter().map(FatArch::file_range).collect(),
+            Err(_) => return false,
+        },
+        Ok(object::FileKind::MachOFat64) => match FatHeader::parse_arch64(data) {
+            Ok(arches) => arches.iter().map(FatArch::file_range).collect(),
+            Err(_) => return false,
+        },
+        Ok(_) => vec![(0, data.len() as u64)],
+        Err(_) => return false,
+    };
+
+    member_ranges.into_iter().any(|(start, size)| {
+        let member_data = match data
+            .get(start as usize..)
+            .and_then(|d| d.get(..size as usize))
+        {
+            Some(member_data) => member_data,
+            None => return false,
+        };
+        match object::File::parse(member_data) {
+            Ok(member) => member.mach_uuid() == Ok(Some(*uuid.as_bytes())),
+            Err(_) => false,
+        }
+    })
+}
@@ -4,8 +4,8 @@ use std::{future::Future, pin::Pin};
 
 use debugid::DebugId;
 use samply_api::samply_symbols::{
-    self, AddressInfo, Error, ExternalFileAddressRef, ExternalFileRef, ExternalFileSymbolMap,
-    FrameDebugInfo, LibraryInfo, MultiArchDisambiguator,
+    self, AddressInfo, DwoRef, Error, ExternalFileAddressRef, ExternalFileRef,
+    ExternalFileSymbolMap, FrameDebugInfo, LibraryInfo, MultiArchDisambiguator,
 };
 use samply_api::Api;
 use yoke::{Yoke, Yokeable};
@@ -13,7 +13,8 @@ use yoke::{Yoke, Yokeable};
 use crate::config::SymbolManagerConfig;
 use crate::helper::{FileReadOnlyHelper, Helper, WholesymFileLocation};
 
-/// Used in [`SymbolManager::lookup_external`] and [`SymbolManager::load_external_file`].
+/// Used in [`SymbolManager::lookup_external`], [`SymbolManager::load_external_file`]
+/// and [`SymbolManager::load_dwo_file`].
 #[derive(Debug, Clone)]
 pub struct SymbolFileOrigin(WholesymFileLocation);
 
@@ -83,6 +84,16 @@ impl SymbolMap {
         SymbolFileOrigin(self.0.debug_file_location().clone())
     }
 
+    /// Provide the contents of a `.dwo` file for which a lookup returned
+    /// [`FramesLookupResult::NeedDwo`](crate::FramesLookupResult::NeedDwo).
+    ///
+    /// The contents can be obtained with [`SymbolManager::load_dwo_file`]. Pass `None`
+    /// if the file couldn't be loaded; lookups in that file's units then fall back to
+    /// the information in the binary.
+    pub fn add_dwo_file(&self, dwo_ref: &DwoRef, contents: Option<Vec<u8>>) {
+        self.0.add_dwo_file(dwo_ref, contents)
+    }
+
     /// The Debug ID of the binary that is described by the symbol information in this `SymbolMap`.
     pub fn debug_id(&self) -> debugid::DebugId {
         self.0.debug_id()
@@ -93,12 +104,29 @@ impl SymbolMap {
         self.0.symbol_count()
     }
 
+    /// The error which makes all lookups in this `SymbolMap` fail, if there is one.
+    /// See [`samply_symbols::SymbolMap::lookup_error`].
+    pub fn lookup_error(&self) -> Option<Error> {
+        self.0.lookup_error()
+    }
+
     /// Iterate over all symbols in this `SymbolMap`.
     ///
     /// This iterator yields the relative address and the name of each symbol.
     pub fn iter_symbols(&self) -> Box<dyn Iterator<Item = (u32, Cow<'_, str>)> + '_> {
         self.0.iter_symbols()
     }
+
+    /// Iterate over all symbols in this `SymbolMap`, along with their sizes.
+    ///
+    /// This iterator yields the relative address, the size (if known) and the name
+    /// of each symbol. If the symbol file doesn't record a symbol's size, the size
+    /// is the distance to the next symbol.
+    pub fn iter_symbols_with_size(
+        &self,
+    ) -> Box<dyn Iterator<Item = (u32, Option<u32>, Cow<'_, str>)> + '_> {
+        self.0.iter_symbols_with_size()
+    }
 }
 
 /// Allows obtaining [`SymbolMap`]s.
@@ -224,8 +252,6 @@ impl SymbolManager {
     /// `FramesLookupResult::External` from the lookups. Then the address needs to be
     /// looked up in the external file.
     ///
-    /// In the future, this may also be used for loading `.dwo` or `.dwp` files on Linux.
-    ///
     /// The `SymbolManager` keeps the most recent external file cached, so that repeated
     /// calls to `lookup_external` for the same external file are fast. If the set of
     /// addresses for lookup is known ahead-of-time, sorting these addresses upfront can
@@ -257,6 +283,24 @@ impl SymbolManager {
             .await
     }
 
+    /// Load the `.dwo` file for which a lookup returned
+    /// [`FramesLookupResult::NeedDwo`](crate::FramesLookupResult::NeedDwo).
+    ///
+    /// This is used on Linux for binaries compiled with `-gsplit-dwarf`, if the
+    /// split debug info hasn't been packaged into a `.dwp` file. Hand the contents
+    /// to [`SymbolMap::add_dwo_file`] and look up the address again.
+    pub async fn load_dwo_file(
+        &self,
+        symbol_file_origin: &SymbolFileOrigin,
+        dwo_ref: &DwoRef,
+    ) -> Result<Vec<u8>, Error> {
+        self.helper_with_symbol_manager
+            .get()
+            .0
+            .load_dwo_file(symbol_file_origin, dwo_ref)
+            .await
+    }
+
     /// Run a symbolication query with the "Tecken" JSON API.
     ///
     /// In the future, this will be a feature on this crate and not enabled by default.
@@ -305,6 +349,12 @@ trait SymbolManagerTrait {
         external_file_ref: &'a ExternalFileRef,
     ) -> Pin<Box<dyn Future<Output = Result<ExternalFileSymbolMap, Error>> + 'a + Send>>;
 
+    fn load_dwo_file<'a>(
+        &'a self,
+        symbol_file_origin: &'a SymbolFileOrigin,
+        dwo_ref: &'a DwoRef,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>, Error>> + 'a + Send>>;
+
     fn query_json_api<'a>(
         &'a self,
         path: &'a str,
@@ -326,7 +376,11 @@ impl<'h> SymbolManagerWrapper<'h> {
     }
 
     async fn load_symbol_map(&self, info: LibraryInfo) -> Result<SymbolMap, Error> {
-        Ok(SymbolMap(self.0.load_symbol_map(&info).await?))
+        let mut symbol_map = self.0.load_symbol_map(&info).await?;
+        let config = self.0.helper().config();
+        symbol_map.set_address_cache_capacity(config.address_cache_capacity);
+        symbol_map.set_function_name_style(config.function_name_style);
+        Ok(SymbolMap(symbol_map))
     }
 }
 
@@ -375,6 +429,14 @@ impl<'h> SymbolManagerTrait for SymbolManagerWrapper<'h> {
         )
     }
 
+    fn load_dwo_file<'a>(
+        &'a self,
+        symbol_file_origin: &'a SymbolFileOrigin,
+        dwo_ref: &'a DwoRef,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>, Error>> + 'a + Send>> {
+        Box::pin(self.0.load_dwo_file(&symbol_file_origin.0, dwo_ref))
+    }
+
     fn query_json_api<'a>(
         &'a self,
         path: &'a str,
@@ -50,7 +50,7 @@ fn for_docs() {
                         .lookup_external(&symbol_map.symbol_file_origin(), &ext_ref)
                         .await
                 }
-                FramesLookupResult::Unavailable => None,
+                FramesLookupResult::NeedDwo(_) | FramesLookupResult::Unavailable => None,
             };
             if let Some(frames) = frames {
                 for (i, frame) in frames.into_iter().enumerate() {
@@ -191,3 +191,24 @@ fn disassemble_libcrypto() {
         r#"{"startAddress":"0x5844","size":"0x1c","instructions":[[0,"hint #0x1b"],[4,"stp x29, x30, [sp, #-0x10]!"],[8,"mov x29, sp"],[12,"adrp x0, $+0x593f3000"],[16,"add x0, x0, #0x340"],[20,"ldr x8, [x0]"],[24,"blraaz x8"]]}"#
     );
 }
+
+#[test]
+fn address_cache() {
+    let binary_path = fixtures_dir().join("other").join("example-linux");
+    let load_symbol_map = |config: wholesym::SymbolManagerConfig| {
+        let symbol_manager = wholesym::SymbolManager::with_config(config);
+        futures::executor::block_on(
+            symbol_manager.load_symbol_map_for_binary_at_path(&binary_path, None),
+        )
+        .unwrap()
+    };
+    let uncached = load_symbol_map(Default::default());
+    let cached =
+        load_symbol_map(wholesym::SymbolManagerConfig::default().address_cache_capacity(Some(2)));
+    for address in [0x1160, 0x1165, 0x1156, 0x1160, 0x1158, 0x1165] {
+        assert_eq!(
+            cached.lookup_relative_address(address),
+            uncached.lookup_relative_address(address)
+        );
+    }
+}